@@ -1,7 +1,16 @@
-use std::{any::TypeId, pin::Pin, sync::Arc, time::Duration};
+use std::{
+    any::{Any, TypeId},
+    collections::{BTreeMap, HashMap, HashSet},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
+use crate::{CancellationToken, Format, RetryPolicy, Scratchpad};
+
 /// Public because macros need it.
 #[doc(hidden)]
 pub trait State: Clone + Send + Sync + 'static {}
@@ -12,17 +21,125 @@ impl<T> State for T where T: Clone + Send + Sync + 'static {}
 #[doc(hidden)]
 pub trait NodeBuilder<S: State> {
     fn node() -> Node<S>;
+    /// Turns the output of this node back into its type, so it can be passed to dependents.
+    ///
+    /// # Errors
+    /// If the payload can not be turned into the type, with a description of why.
+    fn try_decode(payload: Payload) -> std::result::Result<Self, String>
+    where
+        Self: Sized;
+    /// Like [`NodeBuilder::try_decode`].
+    ///
+    /// # Panics
+    /// If the payload can not be turned into the type.
+    #[must_use]
+    fn decode(payload: Payload) -> Self
+    where
+        Self: Sized,
+    {
+        Self::try_decode(payload).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Like [`NodeBuilder::try_decode`], but shares the output with other dependents where it
+    /// can, instead of cloning it. The `producer` macro does this for transient nodes.
+    ///
+    /// # Errors
+    /// If the payload can not be turned into the type, with a description of why.
+    fn try_decode_shared(payload: Payload) -> std::result::Result<Arc<Self>, String>
+    where
+        Self: Sized,
+    {
+        Self::try_decode(payload).map(Arc::new)
+    }
 }
 
-/// An actual node.
-/// Public because macros need it.
-#[doc(hidden)]
+/// A node in a job. Usually created by the `producer` macro, but can also be defined at runtime
+/// with [`Node::builder`].
 #[derive(Clone)]
 pub struct Node<S: State> {
+    /// Name of the node. Must be unique within a job.
     pub name: &'static str,
-    pub id: TypeId,
+    /// Identifies the node. See [`NodeId`].
+    pub id: NodeId,
+    /// Creates the nodes this node depends on.
     pub deps: Arc<dyn Fn() -> Vec<Node<S>> + Send + Sync + 'static>,
+    /// Dependencies that are only used if something else puts them in the job (or their data is
+    /// provided). They are never added to a job just because this node needs them.
+    pub optional: HashSet<NodeId>,
+    /// Nodes that must be done before this one starts, if they are part of the job. Their
+    /// outputs are not passed to the producer, and they are never added to a job for this node.
+    pub after: HashSet<NodeId>,
+    /// Runs the node.
     pub producer: Producer<S>,
+    /// Output is only kept in memory, and never serialized.
+    pub transient: bool,
+    /// Give up on the node if a single attempt takes longer than this. The node then fails.
+    pub timeout: Option<Duration>,
+    /// Retry the node at most this many times. Overrides [`crate::Worker::max_retries`].
+    pub max_retries: Option<u32>,
+    /// The producer does heavy CPU work, so it is run away from the other nodes. See
+    /// [`crate::Worker::blocking_runtime`].
+    pub blocking: bool,
+    /// How long to wait between retries, instead of what the error asks for.
+    pub retry_policy: Option<RetryPolicy>,
+    /// When several nodes are ready at the same time, the ones with the highest priority are
+    /// started first. Defaults to `0`.
+    pub priority: i32,
+    /// Names this node as an alternative producer for its output. See
+    /// [`crate::JobBuilder::select`].
+    pub variant: Option<&'static str>,
+    /// Checks that data provided for the node can be turned into its output, when the job is
+    /// built. See [`crate::NodeDef::validate_data`].
+    pub validate: Option<Validator>,
+    /// Nodes that use the same resource (say `"gpu"`) never run at the same time, even if they
+    /// could. See [`crate::NodeDef::exclusive`].
+    pub exclusive: Option<&'static str>,
+    /// The version of the shape of the output. Values in [`crate::Worker::data`] carry it, and
+    /// provided data of another version is rejected, unless it can be migrated. See
+    /// [`crate::NodeDef::version`].
+    pub version: Option<u32>,
+    /// Starts of nodes with the same tag (say `"openai"`) are limited by the rate set with
+    /// [`crate::Worker::rate_limit`]. See [`crate::NodeDef::rate`].
+    pub rate: Option<&'static str>,
+    /// Checked when the job starts, before any node runs. See [`crate::NodeDef::requires`].
+    pub requires: Vec<crate::Requirement<S>>,
+}
+
+/// Identifies a node within a job. It's the type of the output, and the namespace the node was
+/// mounted in, if it is part of a [`crate::Subgraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    ty: TypeId,
+    namespace: Option<&'static str>,
+}
+
+impl NodeId {
+    /// The id of the node with output `T`, outside of any namespace.
+    #[must_use]
+    pub fn of<T: ?Sized + 'static>() -> Self {
+        Self {
+            ty: TypeId::of::<T>(),
+            namespace: None,
+        }
+    }
+
+    /// The namespace the node was mounted in, if any.
+    #[must_use]
+    pub fn namespace(&self) -> Option<&'static str> {
+        self.namespace
+    }
+
+    /// The same node, mounted in `namespace`. Namespaces nest, so a node that already has one
+    /// ends up in `namespace.<the old one>`.
+    pub(crate) fn in_namespace(self, namespace: &'static str) -> Self {
+        let namespace = match self.namespace {
+            Some(inner) => crate::subgraph::intern(format!("{namespace}.{inner}")),
+            None => namespace,
+        };
+        Self {
+            ty: self.ty,
+            namespace: Some(namespace),
+        }
+    }
 }
 
 impl<S: State> std::fmt::Debug for Node<S> {
@@ -33,12 +150,151 @@ impl<S: State> std::fmt::Debug for Node<S> {
 
 type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
+/// Checks that a value can be turned into the output of a node. See [`Node::validate`].
+pub type Validator = fn(&Value) -> std::result::Result<(), String>;
+
 /// Public because macros need it.
 #[doc(hidden)]
 pub type Producer<S> = Arc<
-    dyn Fn(Context<S>, Vec<Value>) -> BoxFuture<'static, Result<Value>> + Send + Sync + 'static,
+    dyn Fn(Context<S>, Vec<Payload>) -> BoxFuture<'static, Result<Payload>> + Send + Sync + 'static,
 >;
 
+/// The output of a node, as it is passed on to the nodes that depend on it.
+#[derive(Clone)]
+pub enum Payload {
+    /// The serialized output. This is what ends up in [`crate::Worker::data`]. Shared between
+    /// the nodes that depend on it, which each deserialize their own copy.
+    Json(Arc<Value>),
+    /// The output, serialized as CBOR (see [`crate::Format::Cbor`]).
+    Cbor(Arc<[u8]>),
+    /// The output of a raw node (see [`crate::NodeDef::raw_producer`]), as it is. It is not part
+    /// of [`crate::Worker::data`], snapshots or caches.
+    Raw(Arc<[u8]>),
+    /// The output of a `transient` node. It is only kept in memory, and is never serialized.
+    Transient(Arc<dyn Any + Send + Sync>),
+    /// Passed to a producer in place of an optional dependency that is not part of the job.
+    Missing,
+    /// The output of a streaming node, along with the rest of its producer, which the worker
+    /// keeps running. Dependents get the output as [`Payload::Transient`].
+    Streaming(Arc<dyn Any + Send + Sync>, Rest),
+}
+
+/// The rest of a streaming producer, after it has handed out its stream. Only taken once.
+#[doc(hidden)]
+pub type Rest = Arc<std::sync::Mutex<Option<BoxFuture<'static, Result<()>>>>>;
+
+impl Payload {
+    /// The serialized value, unless the payload is transient.
+    #[must_use]
+    pub fn json(&self) -> Option<&Value> {
+        match self {
+            Payload::Json(value) => Some(value.as_ref()),
+            Payload::Cbor(_)
+            | Payload::Raw(_)
+            | Payload::Transient(_)
+            | Payload::Missing
+            | Payload::Streaming(..) => None,
+        }
+    }
+
+    /// The serialized value as JSON, whatever format it was serialized in. `None` if the payload
+    /// is transient.
+    #[must_use]
+    pub fn to_json(&self) -> Option<Value> {
+        match self {
+            Payload::Json(value) => Some(Value::clone(value)),
+            Payload::Cbor(bytes) => ciborium::from_reader(&bytes[..]).ok(),
+            Payload::Raw(_) | Payload::Transient(_) | Payload::Missing | Payload::Streaming(..) => {
+                None
+            }
+        }
+    }
+
+    /// Deserializes the payload, whatever format it was serialized in.
+    ///
+    /// # Panics
+    /// If the payload is transient or can not be deserialized into `T`.
+    #[must_use]
+    pub fn deserialize<T: DeserializeOwned>(self) -> T {
+        self.try_deserialize().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`Payload::deserialize`], but describes what went wrong instead of panicking.
+    ///
+    /// # Errors
+    /// If the payload is transient or can not be deserialized into `T`.
+    pub fn try_deserialize<T: DeserializeOwned>(self) -> std::result::Result<T, String> {
+        match self {
+            Payload::Json(value) => T::deserialize(&*value).map_err(|e| e.to_string()),
+            Payload::Cbor(bytes) => ciborium::from_reader(&bytes[..]).map_err(|e| e.to_string()),
+            payload => Err(format!("Expected a serialized payload, got {payload:?}")),
+        }
+    }
+
+    /// Deserializes the payload.
+    ///
+    /// # Panics
+    /// If the payload is transient or can not be deserialized into `T`.
+    #[must_use]
+    pub fn from_json<T: DeserializeOwned>(self) -> T {
+        let Payload::Json(value) = self else {
+            panic!("Expected a serialized payload");
+        };
+        T::deserialize(&*value).unwrap()
+    }
+
+    /// Turns the bytes of a raw payload back into a `T`.
+    ///
+    /// # Panics
+    /// If the payload is not raw.
+    #[must_use]
+    pub fn from_raw<T: From<Vec<u8>>>(self) -> T {
+        let Payload::Raw(bytes) = self else {
+            panic!("Expected a raw payload");
+        };
+        T::from(bytes.to_vec())
+    }
+
+    /// Gets the in-memory value of a transient payload.
+    ///
+    /// # Panics
+    /// If the payload is not transient or is not a `T`.
+    #[must_use]
+    pub fn from_transient<T: Clone + 'static>(self) -> T {
+        let Payload::Transient(value) = self else {
+            panic!("Expected a transient payload");
+        };
+        value.downcast_ref::<T>().unwrap().clone()
+    }
+
+    /// Like [`Payload::from_transient`], but shares the value instead of cloning it.
+    ///
+    /// # Panics
+    /// If the payload is not transient or is not a `T`.
+    #[must_use]
+    pub fn from_transient_shared<T: Send + Sync + 'static>(self) -> Arc<T> {
+        let Payload::Transient(value) = self else {
+            panic!("Expected a transient payload");
+        };
+        value
+            .downcast::<T>()
+            .unwrap_or_else(|_| panic!("Transient payload is not a T"))
+    }
+}
+
+impl std::fmt::Debug for Payload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Payload::Json(value) => write!(f, "Json({value})"),
+            Payload::Cbor(bytes) => write!(f, "Cbor({} bytes)", bytes.len()),
+            Payload::Raw(bytes) => write!(f, "Raw({} bytes)", bytes.len()),
+            Payload::Transient(_) => write!(f, "Transient"),
+            Payload::Missing => write!(f, "Missing"),
+            Payload::Streaming(..) => write!(f, "Streaming"),
+        }
+    }
+}
+
 /// First argument of a producer function. It's just some basic meta data (that I might later
 /// expand on) about running the node.
 ///
@@ -47,11 +303,79 @@ pub type Producer<S> = Arc<
 pub struct Context<S: State> {
     /// Your state as passed into the [`crate::Job`].
     pub state: S,
+    /// Information about the current attempt at running the node.
+    pub attempt: AttemptInfo,
+    /// Identifies the job the node is part of. It is also on the tracing span of the job, so it
+    /// can be used to tie your own logs to the job.
+    pub job_id: u64,
+    /// How the output of the node is serialized. See [`crate::Worker::format`].
+    pub format: Format,
+    /// Set on the job with [`crate::JobBuilder::label`].
+    pub labels: Arc<BTreeMap<String, String>>,
+    /// Cancelled when the job is stopped, drained or times out. See [`Context::cancelled`].
+    pub cancellation: CancellationToken,
+    /// Shared by every node in the job. See [`Context::scratch`].
+    pub scratchpad: Scratchpad,
+}
+
+impl<S: State> Context<S> {
     /// Retry count. First time this is run, it will be `0`.
-    pub retry: u32,
-    /// The start time for this node.
+    #[must_use]
+    pub fn retry(&self) -> u32 {
+        self.attempt.attempt
+    }
+
+    /// Completes when the job is stopped, drained (see [`crate::Worker::drain`]) or times out,
+    /// so a long running producer can wrap up, instead of being aborted halfway through.
+    ///
+    /// When the job is stopped or times out, the node is aborted right away anyway, but work the
+    /// producer spawned itself can still be told to stop. When draining, the node is allowed to
+    /// finish, so it can return early. Return an error with a retry to have it run again once the
+    /// job is restored.
+    pub fn cancelled(&self) -> impl Future<Output = ()> + Send + '_ {
+        self.cancellation.cancelled()
+    }
+
+    /// Whether the job is being stopped. See [`Context::cancelled`].
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// The value of label `key`, if it was set on the job (see [`crate::JobBuilder::label`]).
+    #[must_use]
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels.get(key).map(String::as_str)
+    }
+
+    /// Values shared by every node in the job, for things that do not fit as nodes, like
+    /// counters or flags. See [`Scratchpad`].
+    #[must_use]
+    pub fn scratch(&self) -> &Scratchpad {
+        &self.scratchpad
+    }
+
+    /// The start time for this attempt.
     /// All "times" are defined as an offset of when the job started.
-    pub start: Duration,
+    #[must_use]
+    pub fn start(&self) -> Duration {
+        self.attempt.attempt_started
+    }
+}
+
+/// Describes the current attempt at running a node. Useful if a producer wants to budget its work.
+///
+/// All "times" are defined as an offset of when the job started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AttemptInfo {
+    /// Retry count. First time this is run, it will be `0`.
+    pub attempt: u32,
+    /// When the first attempt was started.
+    pub first_started: Duration,
+    /// When this attempt was started.
+    pub attempt_started: Duration,
+    /// When this attempt will be stopped, if there is a limit.
+    pub deadline: Option<Duration>,
 }
 
 /// Return value for producers.
@@ -62,6 +386,19 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub struct Error {
     pub(crate) message: String,
     pub(crate) retry_in: Option<Duration>,
+    pub(crate) details: Option<Value>,
+    pub(crate) kind: ErrorKind,
+}
+
+/// Where an [`Error`] came from. See [`Error::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Returned by the producer.
+    Producer,
+    /// The output of node `node` could not be serialized, or deserialized for a dependent. Say,
+    /// because provided data (or a value from an earlier run) has a different schema.
+    Serde { node: &'static str },
 }
 
 impl Error {
@@ -69,48 +406,178 @@ impl Error {
     pub fn fatal(message: impl Into<String>) -> Self {
         let message = message.into();
         let retry_in = None;
-        Self { message, retry_in }
+        let details = None;
+        Self {
+            message,
+            retry_in,
+            details,
+            kind: ErrorKind::Producer,
+        }
     }
 
     /// Node has failed and should be retried after some time.
     pub fn with_retry(message: impl Into<String>, retry_in: Duration) -> Self {
         let message = message.into();
         let retry_in = Some(retry_in);
-        Self { message, retry_in }
+        let details = None;
+        Self {
+            message,
+            retry_in,
+            details,
+            kind: ErrorKind::Producer,
+        }
+    }
+
+    /// The output of `node` could not be serialized or deserialized. Never retried, since it
+    /// would fail the same way again.
+    pub(crate) fn serde(node: &'static str, source: impl std::fmt::Display) -> Self {
+        Self {
+            kind: ErrorKind::Serde { node },
+            ..Self::fatal(format!(
+                "Could not (de)serialize the output of {node}: {source}"
+            ))
+        }
+    }
+
+    /// Attach an error code, or any other data, so whoever gets the error back (see
+    /// [`Output::NodeFailed`]) can act on it without parsing the message.
+    #[must_use]
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// The error message.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// How long the node asked to wait before being retried, if at all.
+    #[must_use]
+    pub fn retry_in(&self) -> Option<Duration> {
+        self.retry_in
+    }
+
+    /// The details attached with [`Error::with_details`].
+    #[must_use]
+    pub fn details(&self) -> Option<&Value> {
+        self.details.as_ref()
+    }
+
+    /// Where the error came from.
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
     }
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
 /// Output of running a job. Describes how and if the job was finished. Use [`crate::Worker::data`]
-/// to get the results out.
+/// to get the results out. Unless the job is done, the output also has the data collected so far.
 #[derive(Debug, Clone)]
 pub enum Output {
     /// Job finished successfully.
     Done {
         /// It took this long to finish the job.
         duration: Duration,
+        /// When the job was started.
+        started_at: SystemTime,
+        /// When the job finished.
+        finished_at: SystemTime,
     },
     /// Jab finished because a node failed.
     NodeFailed {
         /// Node failed after this much time.
         duration: Duration,
+        /// When the job was started.
+        started_at: SystemTime,
+        /// When the job finished.
+        finished_at: SystemTime,
         /// Name of the node that failed.
         name: &'static str,
-        /// The error message from the node.
-        error: String,
+        /// Number of times the node was retried before giving up.
+        retries: u32,
+        /// The error returned by the node, on its last attempt.
+        error: Error,
+        /// Values of the nodes that were provided or done by then, like [`crate::Worker::data`].
+        /// Enough to resume the job later.
+        data: Arc<HashMap<String, Value>>,
     },
     /// Job finished because a node panicked.
     NodePanic {
         /// The node panicked at this time.
         duration: Duration,
+        /// When the job was started.
+        started_at: SystemTime,
+        /// When the job finished.
+        finished_at: SystemTime,
         /// Name of the node that panicked.
         name: &'static str,
         /// Error message of the node panicking.
         error: String,
+        /// Values of the nodes that were provided or done by then, like [`crate::Worker::data`].
+        /// Enough to resume the job later.
+        data: Arc<HashMap<String, Value>>,
     },
     /// Job was manually stopped.
     Stopped {
         /// Job was stopped after this time.
         duration: Duration,
+        /// When the job was started.
+        started_at: SystemTime,
+        /// When the job finished.
+        finished_at: SystemTime,
+        /// Values of the nodes that were provided or done by then, like [`crate::Worker::data`].
+        /// Enough to resume the job later.
+        data: Arc<HashMap<String, Value>>,
+    },
+    /// Job ran everything it could, but some nodes failed. Only with
+    /// [`crate::FailurePolicy::CollectAll`].
+    Finished {
+        /// It took this long to finish the job.
+        duration: Duration,
+        /// When the job was started.
+        started_at: SystemTime,
+        /// When the job finished.
+        finished_at: SystemTime,
+        /// Every node that failed or panicked, in the order they did.
+        failures: Vec<NodeFailure>,
+        /// Values of the nodes that were provided or done, like [`crate::Worker::data`].
+        data: Arc<HashMap<String, Value>>,
+    },
+    /// Job never ran, because requirements of its nodes were not met. See
+    /// [`crate::Requirement`].
+    Unmet {
+        /// It took this long to check the requirements.
+        duration: Duration,
+        /// When the job was started.
+        started_at: SystemTime,
+        /// When the job finished.
+        finished_at: SystemTime,
+        /// The requirements that were not met, sorted by name.
+        unmet: Vec<crate::UnmetRequirement>,
+        /// Values of the nodes that were provided, like [`crate::Worker::data`].
+        data: Arc<HashMap<String, Value>>,
+    },
+    /// Job did not finish in time. See [`crate::Worker::run_with_timeout`].
+    TimedOut {
+        /// Job was stopped after this time.
+        duration: Duration,
+        /// When the job was started.
+        started_at: SystemTime,
+        /// When the job finished.
+        finished_at: SystemTime,
+        /// Values of the nodes that were provided or done by then, like [`crate::Worker::data`].
+        /// Enough to resume the job later.
+        data: Arc<HashMap<String, Value>>,
     },
 }
 
@@ -119,10 +586,41 @@ impl Output {
     #[must_use]
     pub fn duration(&self) -> Duration {
         match self {
-            Output::Stopped { duration }
+            Output::Stopped { duration, .. }
+            | Output::TimedOut { duration, .. }
+            | Output::Unmet { duration, .. }
             | Output::NodePanic { duration, .. }
             | Output::NodeFailed { duration, .. }
-            | Output::Done { duration } => *duration,
+            | Output::Finished { duration, .. }
+            | Output::Done { duration, .. } => *duration,
+        }
+    }
+
+    /// When the job was started.
+    #[must_use]
+    pub fn started_at(&self) -> SystemTime {
+        match self {
+            Output::Stopped { started_at, .. }
+            | Output::TimedOut { started_at, .. }
+            | Output::Unmet { started_at, .. }
+            | Output::NodePanic { started_at, .. }
+            | Output::NodeFailed { started_at, .. }
+            | Output::Finished { started_at, .. }
+            | Output::Done { started_at, .. } => *started_at,
+        }
+    }
+
+    /// When the job finished.
+    #[must_use]
+    pub fn finished_at(&self) -> SystemTime {
+        match self {
+            Output::Stopped { finished_at, .. }
+            | Output::TimedOut { finished_at, .. }
+            | Output::Unmet { finished_at, .. }
+            | Output::NodePanic { finished_at, .. }
+            | Output::NodeFailed { finished_at, .. }
+            | Output::Finished { finished_at, .. }
+            | Output::Done { finished_at, .. } => *finished_at,
         }
     }
     #[must_use]
@@ -134,6 +632,10 @@ impl Output {
         matches!(self, Self::Stopped { .. })
     }
     #[must_use]
+    pub fn is_timed_out(&self) -> bool {
+        matches!(self, Self::TimedOut { .. })
+    }
+    #[must_use]
     pub fn is_node_failed(&self) -> bool {
         matches!(self, Self::NodeFailed { .. })
     }
@@ -141,4 +643,25 @@ impl Output {
     pub fn is_node_panic(&self) -> bool {
         matches!(self, Self::NodePanic { .. })
     }
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Finished { .. })
+    }
+    #[must_use]
+    pub fn is_unmet(&self) -> bool {
+        matches!(self, Self::Unmet { .. })
+    }
+}
+
+/// A node that failed. See [`Output::Finished`].
+#[derive(Debug, Clone)]
+pub struct NodeFailure {
+    /// Name of the node.
+    pub name: &'static str,
+    /// Number of times the node was retried before giving up.
+    pub retries: u32,
+    /// The error returned by the node, on its last attempt. If it panicked, the panic message.
+    pub error: Error,
+    /// The node panicked, rather than return an error.
+    pub panicked: bool,
 }
@@ -0,0 +1,241 @@
+//! Run the same job over many inputs.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+use crate::{
+    FailurePolicy, JobBuilder, JobError, NodeState, Output, State, Worker,
+    rt::{self, Tasks},
+};
+
+/// Options for [`run_all`].
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Maximum number of jobs running at the same time.
+    pub concurrency: usize,
+    /// A file to keep track of which inputs completed successfully. If set, inputs already in
+    /// the manifest are skipped, so a batch that was interrupted (or had failures) can be run
+    /// again, and only the inputs that did not complete are run.
+    ///
+    /// The file has one JSON encoded key per line, and is appended to as jobs complete.
+    pub manifest: Option<PathBuf>,
+    /// What each job does when a node fails. With [`FailurePolicy::CollectAll`], a job makes as
+    /// much progress as it can, and its output lists every failure.
+    pub failure_policy: FailurePolicy,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            manifest: None,
+            failure_policy: FailurePolicy::default(),
+        }
+    }
+}
+
+/// The result of running a batch.
+#[derive(Debug)]
+pub struct Report {
+    /// One item per input that was run, in the same order as the inputs.
+    pub items: Vec<Item>,
+    /// Statistics for the whole batch.
+    pub stats: Stats,
+}
+
+/// The result of running the job for a single input.
+#[derive(Debug)]
+pub struct Item {
+    /// The key of the input.
+    pub key: String,
+    /// How the job went, or why it could not be built.
+    pub output: Result<Output, JobError>,
+    /// The data collected from running the job.
+    pub data: HashMap<String, Value>,
+}
+
+/// Statistics for a batch.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// Number of inputs that were run.
+    pub total: usize,
+    /// Number of inputs that were skipped, because the manifest says they already completed.
+    pub skipped: usize,
+    /// Number of jobs that finished successfully.
+    pub succeeded: usize,
+    /// Share of jobs that finished successfully, between `0` and `1`.
+    pub success_rate: f64,
+    /// The node that took the longest to run, in any of the jobs, and how long it took.
+    pub slowest: Option<(&'static str, Duration)>,
+    /// How long it took to run the whole batch.
+    pub duration: Duration,
+}
+
+/// Runs the job described by `template` once per input, with the input as provided data (see
+/// [`crate::Job::builder_with_data`]). Inputs are given as `(key, data)`, where the key is only
+/// used to identify the input in the report.
+///
+/// At most `options.concurrency` jobs run at the same time.
+///
+/// If `options.manifest` is set, inputs that completed in an earlier run are skipped. Problems
+/// reading or writing the manifest are logged, but do not stop the batch (worst case, an input
+/// is run again).
+///
+/// # Panics
+/// If `options.concurrency` is `0`.
+pub async fn run_all<S, I>(template: JobBuilder<S>, inputs: I, state: S, options: Options) -> Report
+where
+    S: State,
+    I: IntoIterator<Item = (String, HashMap<String, Value>)>,
+{
+    assert!(options.concurrency > 0, "Concurrency must be at least 1");
+    let t0 = rt::now();
+    let permits = Arc::new(Semaphore::new(options.concurrency));
+    let mut handles = Tasks::new();
+    let mut summary = Stats::default();
+    let completed = options.manifest.as_deref().map(load).unwrap_or_default();
+    let mut manifest = options.manifest.as_deref().and_then(|path| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .inspect_err(|error| warn!(?path, %error, "Could not open manifest"))
+            .ok()
+    });
+
+    for (i, (key, data)) in inputs.into_iter().enumerate() {
+        if completed.contains(&key) {
+            summary.skipped += 1;
+            continue;
+        }
+        let builder = template.clone().with_data(data);
+        let state = state.clone();
+        let permits = permits.clone();
+        let policy = options.failure_policy;
+        handles.spawn(async move {
+            let _permit = permits.acquire().await.expect("Semaphore is never closed");
+            let (item, slowest) = run_one(key, builder, state, policy).await;
+            (i, item, slowest)
+        });
+    }
+
+    let mut items = vec![];
+    while let Some((i, item, slowest)) = handles.next().await {
+        if item.output.as_ref().is_ok_and(Output::is_done) {
+            summary.succeeded += 1;
+            if let Some(file) = &mut manifest
+                && let Err(error) = record(file, &item.key)
+            {
+                warn!(key = item.key, %error, "Could not write to manifest");
+            }
+        }
+        if let Some((name, duration)) = slowest
+            && summary.slowest.is_none_or(|(_, d)| duration > d)
+        {
+            summary.slowest = Some((name, duration));
+        }
+        items.push((i, item));
+    }
+    items.sort_by_key(|(i, _)| *i);
+
+    summary.total = items.len();
+    #[allow(clippy::cast_precision_loss)]
+    if summary.total > 0 {
+        summary.success_rate = summary.succeeded as f64 / summary.total as f64;
+    }
+    summary.duration = t0.elapsed();
+    info!(
+        total = summary.total,
+        succeeded = summary.succeeded,
+        skipped = summary.skipped,
+        "Batch done"
+    );
+
+    let items = items.into_iter().map(|(_, item)| item).collect();
+    Report {
+        items,
+        stats: summary,
+    }
+}
+
+/// Runs a single job, and returns its report item and its slowest node.
+async fn run_one<S: State>(
+    key: String,
+    builder: JobBuilder<S>,
+    state: S,
+    policy: FailurePolicy,
+) -> (Item, Option<(&'static str, Duration)>) {
+    let job = match builder.build() {
+        Ok(job) => job,
+        Err(e) => {
+            let data = HashMap::new();
+            return (
+                Item {
+                    key,
+                    output: Err(e),
+                    data,
+                },
+                None,
+            );
+        }
+    };
+    let mut worker = Worker::new(job, state).on_failure(policy);
+    worker.run().await.expect("Worker was just created");
+    let output = worker.get_output().await.expect("Worker is running");
+    let slowest = worker
+        .status()
+        .await
+        .into_iter()
+        .filter_map(|(name, state)| match state {
+            NodeState::Done { duration, .. } => Some((name, duration)),
+            _ => None,
+        })
+        .max_by_key(|(_, duration)| *duration);
+    let data = worker.data().await;
+    (
+        Item {
+            key,
+            output: Ok(output),
+            data,
+        },
+        slowest,
+    )
+}
+
+/// Reads the keys of the inputs that have completed. A missing manifest is the same as an empty
+/// one.
+fn load(path: &Path) -> HashSet<String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return HashSet::new(),
+        Err(error) => {
+            warn!(?path, %error, "Could not read manifest");
+            return HashSet::new();
+        }
+    };
+    contents
+        .lines()
+        .filter_map(|line| {
+            serde_json::from_str(line)
+                .inspect_err(|error| warn!(?path, line, %error, "Invalid line in manifest"))
+                .ok()
+        })
+        .collect()
+}
+
+/// Adds a key to the manifest.
+fn record(file: &mut File, key: &str) -> io::Result<()> {
+    let line = serde_json::to_string(key)?;
+    writeln!(file, "{line}")?;
+    file.flush()
+}
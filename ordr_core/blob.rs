@@ -0,0 +1,160 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::UNIX_EPOCH,
+};
+
+use serde_json::{Map, Value};
+
+use crate::{Error, Payload, rt};
+
+/// Keeps outputs that are too large to keep in memory. Configured with
+/// [`crate::Worker::with_blob_store`].
+///
+/// Once an output is stored, the worker only keeps a reference to it, `{"$blob": "..."}`, which
+/// is what shows up in [`crate::Worker::data`] and snapshots. Dependents get the output loaded
+/// back from the store.
+pub trait BlobStore: Send + Sync + 'static {
+    /// Store the output of node `name`, and return a reference to it, for [`BlobStore::get`].
+    ///
+    /// # Errors
+    /// If the output could not be stored. The worker logs the error, and keeps the output in
+    /// memory instead.
+    fn put(&self, name: &str, value: &Value) -> io::Result<String>;
+
+    /// The output stored under `reference`.
+    ///
+    /// # Errors
+    /// If the output could not be loaded. The node that needed it then fails.
+    fn get(&self, reference: &str) -> io::Result<Value>;
+}
+
+/// Lets a blob store be shared between workers.
+impl<B: BlobStore> BlobStore for Arc<B> {
+    fn put(&self, name: &str, value: &Value) -> io::Result<String> {
+        (**self).put(name, value)
+    }
+
+    fn get(&self, reference: &str) -> io::Result<Value> {
+        (**self).get(reference)
+    }
+}
+
+/// A [`BlobStore`] that keeps every output as a JSON file in a directory. The reference is the
+/// path of the file.
+#[derive(Debug)]
+pub struct FileBlobStore {
+    dir: PathBuf,
+    /// Tells apart outputs of the same node.
+    next: AtomicU64,
+}
+
+impl FileBlobStore {
+    /// Use the directory at `dir`, and create it if it does not exist.
+    ///
+    /// # Errors
+    /// If the directory can not be created.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            next: AtomicU64::new(0),
+        })
+    }
+}
+
+impl BlobStore for FileBlobStore {
+    fn put(&self, name: &str, value: &Value) -> io::Result<String> {
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        // Other processes may use the same directory.
+        let nanos = rt::system_now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = self.dir.join(format!("{name}-{nanos}-{n}.json"));
+        fs::write(&path, serde_json::to_vec(value)?)?;
+        path.into_os_string()
+            .into_string()
+            .map_err(|_| io::Error::other("Blob directory is not valid UTF-8"))
+    }
+
+    fn get(&self, reference: &str) -> io::Result<Value> {
+        Ok(serde_json::from_slice(&fs::read(reference)?)?)
+    }
+}
+
+const BLOB: &str = "$blob";
+
+/// The reference, if `value` is one.
+fn reference(value: &Value) -> Option<&str> {
+    match value {
+        Value::Object(map) if map.len() == 1 => map.get(BLOB)?.as_str(),
+        _ => None,
+    }
+}
+
+/// Stores `payload` in `store` if it serializes to more than `limit` bytes, and returns a
+/// reference to it. `None` if it is small enough to keep in memory, or is not serialized.
+pub(crate) fn spill(
+    store: &dyn BlobStore,
+    limit: usize,
+    name: &str,
+    payload: &Payload,
+) -> io::Result<Option<Payload>> {
+    let size = match payload {
+        Payload::Json(value) => {
+            let mut counter = Counter(0);
+            serde_json::to_writer(&mut counter, &**value)?;
+            counter.0
+        }
+        Payload::Cbor(bytes) => bytes.len(),
+        _ => return Ok(None),
+    };
+    if size <= limit {
+        return Ok(None);
+    }
+    let Some(value) = payload.to_json() else {
+        return Ok(None);
+    };
+    let mut map = Map::new();
+    map.insert(BLOB.into(), store.put(name, &value)?.into());
+    Ok(Some(Payload::Json(Arc::new(Value::Object(map)))))
+}
+
+/// Replaces references among `payloads` with what they refer to.
+pub(crate) fn load(store: &dyn BlobStore, payloads: Vec<Payload>) -> Result<Vec<Payload>, Error> {
+    payloads
+        .into_iter()
+        .map(|payload| {
+            let Payload::Json(value) = &payload else {
+                return Ok(payload);
+            };
+            let Some(reference) = reference(value) else {
+                return Ok(payload);
+            };
+            let value = store
+                .get(reference)
+                .map_err(|e| Error::fatal(format!("Could not load blob {reference}: {e}")))?;
+            Ok(Payload::Json(Arc::new(value)))
+        })
+        .collect()
+}
+
+/// Counts the bytes written to it.
+struct Counter(usize);
+
+impl io::Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
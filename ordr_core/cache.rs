@@ -0,0 +1,146 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::Value;
+
+use crate::Payload;
+
+/// Keeps the outputs of nodes, so a node that is run again with the same inputs can be skipped.
+/// Configured with [`crate::Worker::with_cache`].
+///
+/// Outputs are stored by the name of the node, and a key made from its inputs (see
+/// [`cache_key`]). Transient nodes, and nodes that depend on them, are never cached.
+pub trait Cache: Send + Sync + 'static {
+    /// The output of node `name` for the inputs described by `key`, if it has been stored.
+    ///
+    /// # Errors
+    /// If the cache could not be read. The worker logs the error, and runs the node.
+    fn get(&self, name: &str, key: &str) -> io::Result<Option<Value>>;
+
+    /// Store the output of node `name` for the inputs described by `key`.
+    ///
+    /// # Errors
+    /// If the output could not be stored. The worker logs the error and carries on.
+    fn put(&self, name: &str, key: &str, value: &Value) -> io::Result<()>;
+}
+
+/// Lets a cache be shared between workers.
+impl<C: Cache> Cache for Arc<C> {
+    fn get(&self, name: &str, key: &str) -> io::Result<Option<Value>> {
+        (**self).get(name, key)
+    }
+
+    fn put(&self, name: &str, key: &str, value: &Value) -> io::Result<()> {
+        (**self).put(name, key, value)
+    }
+}
+
+/// The cache key for a node with these inputs. `None` if any of them are transient, since those
+/// can not be compared between runs.
+///
+/// The key is a hash of the serialized inputs, and does not change between runs (or builds).
+#[must_use]
+pub fn cache_key(payloads: &[Payload]) -> Option<String> {
+    // FNV-1a, which unlike the hasher in std, is stable.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for payload in payloads {
+        let bytes = match payload {
+            Payload::Json(value) => value.to_string().into_bytes(),
+            Payload::Cbor(bytes) | Payload::Raw(bytes) => bytes.to_vec(),
+            Payload::Missing => vec![],
+            Payload::Transient(_) | Payload::Streaming(..) => return None,
+        };
+        // Separate the inputs, so `["ab", "c"]` and `["a", "bc"]` differ.
+        for byte in bytes.into_iter().chain([0xff]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    Some(format!("{hash:016x}"))
+}
+
+/// A [`Cache`] that only lives as long as the process. Wrap it in an `Arc` to share it between
+/// workers.
+#[derive(Debug, Default)]
+pub struct InMemoryCache {
+    values: Mutex<HashMap<(String, String), Value>>,
+}
+
+impl InMemoryCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of stored outputs.
+    ///
+    /// # Panics
+    /// If a previous call panicked while holding the lock.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+
+    /// Returns `true` if nothing has been stored.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Cache for InMemoryCache {
+    fn get(&self, name: &str, key: &str) -> io::Result<Option<Value>> {
+        let values = self.values.lock().unwrap();
+        Ok(values.get(&(name.to_string(), key.to_string())).cloned())
+    }
+
+    fn put(&self, name: &str, key: &str, value: &Value) -> io::Result<()> {
+        let mut values = self.values.lock().unwrap();
+        values.insert((name.to_string(), key.to_string()), value.clone());
+        Ok(())
+    }
+}
+
+/// A [`Cache`] that keeps every output as a JSON file in a directory, so it survives restarts.
+#[derive(Debug, Clone)]
+pub struct FileCache {
+    dir: PathBuf,
+}
+
+impl FileCache {
+    /// Use the directory at `dir`, and create it if it does not exist.
+    ///
+    /// # Errors
+    /// If the directory can not be created.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, name: &str, key: &str) -> PathBuf {
+        self.dir.join(format!("{name}-{key}.json"))
+    }
+}
+
+impl Cache for FileCache {
+    fn get(&self, name: &str, key: &str) -> io::Result<Option<Value>> {
+        match fs::read(self.path(name, key)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, name: &str, key: &str, value: &Value) -> io::Result<()> {
+        let path = self.path(name, key);
+        // Write to a temporary file first, so a crash never leaves a half written file behind.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(value)?)?;
+        fs::rename(tmp, path)
+    }
+}
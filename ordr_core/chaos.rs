@@ -0,0 +1,173 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use crate::{Context, Error, Job, Payload, Result, State};
+
+/// The faults to inject into a job. Start with one of [`ChaosConfig::fail`],
+/// [`ChaosConfig::panic`] or [`ChaosConfig::delay`], narrow down which attempts it hits, combine
+/// with others using [`ChaosConfig::and`], and apply it to a job with [`ChaosConfig::wrap`]:
+///
+/// ```
+/// # use ordr_core::ChaosConfig;
+/// # use std::time::Duration;
+/// // Fail the first two attempts of `DocMeta`, in a way that is retried, and slow down `Pages`.
+/// let chaos = ChaosConfig::fail("DocMeta")
+///     .retry_in(Duration::ZERO)
+///     .times(2)
+///     .and(ChaosConfig::delay("Pages", Duration::from_millis(50)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChaosConfig {
+    faults: Vec<Fault>,
+}
+
+#[derive(Debug, Clone)]
+struct Fault {
+    node: String,
+    kind: FaultKind,
+    /// Attempts before this one are left alone.
+    after_attempts: u32,
+    /// Number of attempts it hits. `None` for all of them.
+    times: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FaultKind {
+    Fail { retry_in: Option<Duration> },
+    Panic,
+    Delay(Duration),
+}
+
+impl Fault {
+    fn hits(&self, attempt: u32) -> bool {
+        attempt >= self.after_attempts
+            && self
+                .times
+                .is_none_or(|times| attempt - self.after_attempts < times)
+    }
+}
+
+impl ChaosConfig {
+    fn new(node: &str, kind: FaultKind) -> Self {
+        Self {
+            faults: vec![Fault {
+                node: node.to_string(),
+                kind,
+                after_attempts: 0,
+                times: None,
+            }],
+        }
+    }
+
+    /// Make node `node` return an error instead of running. The error is not retried, unless
+    /// [`ChaosConfig::retry_in`] is set.
+    #[must_use]
+    pub fn fail(node: &str) -> Self {
+        Self::new(node, FaultKind::Fail { retry_in: None })
+    }
+
+    /// Make node `node` panic instead of running.
+    #[must_use]
+    pub fn panic(node: &str) -> Self {
+        Self::new(node, FaultKind::Panic)
+    }
+
+    /// Wait this long before running node `node`.
+    #[must_use]
+    pub fn delay(node: &str, delay: Duration) -> Self {
+        Self::new(node, FaultKind::Delay(delay))
+    }
+
+    /// Leave the first `attempts` attempts of the node alone (see [`Context::retry`]). Applies to
+    /// the last fault added.
+    #[must_use]
+    pub fn after_attempts(mut self, attempts: u32) -> Self {
+        self.last().after_attempts = attempts;
+        self
+    }
+
+    /// Only hit this many attempts of the node, after which it runs as normal. Applies to the
+    /// last fault added.
+    #[must_use]
+    pub fn times(mut self, times: u32) -> Self {
+        self.last().times = Some(times);
+        self
+    }
+
+    /// Make the error of the last [`ChaosConfig::fail`] one that is retried after `retry_in`.
+    ///
+    /// # Panics
+    /// If the last fault added is not a failure.
+    #[must_use]
+    pub fn retry_in(mut self, retry_in: Duration) -> Self {
+        let FaultKind::Fail { retry_in: r } = &mut self.last().kind else {
+            panic!("retry_in only applies to failures");
+        };
+        *r = Some(retry_in);
+        self
+    }
+
+    /// Inject the faults of `other` as well. Faults on the same node are applied in the order
+    /// they were added, and the first failure or panic wins.
+    #[must_use]
+    pub fn and(mut self, other: ChaosConfig) -> Self {
+        self.faults.extend(other.faults);
+        self
+    }
+
+    /// Replace the producers of the nodes in `job` that have faults, with ones that inject them.
+    ///
+    /// # Panics
+    /// If a fault is for a node that the job does not run.
+    #[must_use]
+    pub fn wrap<S: State>(&self, mut job: Job<S>) -> Job<S> {
+        for fault in &self.faults {
+            assert!(
+                job.nodes.values().any(|node| node.name == fault.node),
+                "{} is not run by the job",
+                fault.node
+            );
+        }
+        for node in job.nodes.values_mut() {
+            let faults: Arc<[Fault]> = self
+                .faults
+                .iter()
+                .filter(|fault| fault.node == node.name)
+                .cloned()
+                .collect();
+            if faults.is_empty() {
+                continue;
+            }
+            let name = node.name;
+            let producer = node.producer.clone();
+            node.producer = Arc::new(move |ctx: Context<S>, payloads: Vec<Payload>| {
+                let faults = faults.clone();
+                let producer = producer.clone();
+                let run = async move {
+                    let attempt = ctx.retry();
+                    for fault in faults.iter().filter(|fault| fault.hits(attempt)) {
+                        match fault.kind {
+                            FaultKind::Delay(delay) => crate::rt::sleep(delay).await,
+                            FaultKind::Fail { retry_in: None } => {
+                                return Err(Error::fatal(format!("Injected failure in {name}")));
+                            }
+                            FaultKind::Fail {
+                                retry_in: Some(retry_in),
+                            } => {
+                                let message = format!("Injected failure in {name}");
+                                return Err(Error::with_retry(message, retry_in));
+                            }
+                            FaultKind::Panic => panic!("Injected panic in {name}"),
+                        }
+                    }
+                    producer(ctx, payloads).await
+                };
+                Box::pin(run) as Pin<Box<dyn Future<Output = Result<Payload>> + Send>>
+            });
+        }
+        job
+    }
+
+    fn last(&mut self) -> &mut Fault {
+        self.faults.last_mut().expect("Created with a fault")
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::BuildHasher,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// How the data of two runs differs. Created with [`diff`], or [`crate::Worker::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DataDiff {
+    /// Nodes that only have a value in the second run.
+    pub added: BTreeMap<String, Value>,
+    /// Nodes that only have a value in the first run.
+    pub removed: BTreeMap<String, Value>,
+    /// Nodes whose value differs between the runs, with every place it differs.
+    pub changed: BTreeMap<String, Vec<Change>>,
+}
+
+impl DataDiff {
+    /// Returns `true` if both runs have the same data.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// A single difference within the value of a node.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    /// Where in the value it is, as a JSON pointer, say `"/items/0/price"`. Empty for the value
+    /// as a whole.
+    pub path: String,
+    /// The value in the first run. `None` if it was not there.
+    pub before: Option<Value>,
+    /// The value in the second run. `None` if it is not there.
+    pub after: Option<Value>,
+}
+
+/// Compares the data of two runs (as returned by [`crate::Worker::data`]), node by node. Objects
+/// and arrays are compared member by member, so a change deep within a value is reported where
+/// it is, rather than as the whole value.
+#[must_use]
+pub fn diff<H: BuildHasher>(
+    data_a: &HashMap<String, Value, H>,
+    data_b: &HashMap<String, Value, H>,
+) -> DataDiff {
+    let mut diff = DataDiff::default();
+    for (name, a) in data_a {
+        let Some(b) = data_b.get(name) else {
+            diff.removed.insert(name.clone(), a.clone());
+            continue;
+        };
+        let mut changes = vec![];
+        compare(String::new(), a, b, &mut changes);
+        if !changes.is_empty() {
+            diff.changed.insert(name.clone(), changes);
+        }
+    }
+    for (name, b) in data_b {
+        if !data_a.contains_key(name) {
+            diff.added.insert(name.clone(), b.clone());
+        }
+    }
+    diff
+}
+
+/// Adds the differences between `a` and `b`, found at `path`, to `changes`.
+fn compare(path: String, a: &Value, b: &Value, changes: &mut Vec<Change>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<_> = a.keys().chain(b.keys()).collect();
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys {
+                let path = format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+                member(path, a.get(key), b.get(key), changes);
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                member(format!("{path}/{i}"), a.get(i), b.get(i), changes);
+            }
+        }
+        _ if a == b => {}
+        _ => changes.push(Change {
+            path,
+            before: Some(a.clone()),
+            after: Some(b.clone()),
+        }),
+    }
+}
+
+fn member(path: String, a: Option<&Value>, b: Option<&Value>, changes: &mut Vec<Change>) {
+    match (a, b) {
+        (Some(a), Some(b)) => compare(path, a, b, changes),
+        (a, b) => changes.push(Change {
+            path,
+            before: a.cloned(),
+            after: b.cloned(),
+        }),
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use crate::{
+    DecisionKind, Error, Job, Node, NodeFailure, NodeId, Payload, State,
+    rate::{RateLimit, RateLimiter},
+    rt::Instant,
+};
+
+/// Decides which nodes of a job to start, and what to do once they finish, without running
+/// anything itself. Shared by the run loop of a [`crate::Worker`] and by [`crate::Driver`], so
+/// both schedule nodes the same way.
+pub(crate) struct Dispatcher<S: State> {
+    pub(crate) nodes: HashMap<NodeId, Node<S>>,
+    /// Outputs of the nodes that are provided or done (or streaming).
+    pub(crate) results: HashMap<NodeId, Payload>,
+    adj: HashMap<NodeId, Vec<NodeId>>,
+    inputs: HashMap<NodeId, Vec<NodeId>>,
+    /// The nodes that depend on each node, to find the ones a failed node blocks.
+    dependents: HashMap<NodeId, Vec<NodeId>>,
+    /// Nodes that have not been started yet.
+    pending: HashSet<NodeId>,
+    /// Nodes that have been started, and have not finished, with their retry count.
+    running: HashMap<NodeId, u32>,
+    /// Nodes that are done waiting, and should be retried, with their new retry count.
+    retries_due: VecDeque<(NodeId, u32)>,
+    /// The resources used by running nodes, and the node using each. See `Node::exclusive`.
+    held: HashMap<&'static str, NodeId>,
+    /// When nodes with each rate tag were started.
+    limiter: RateLimiter,
+    /// When each node was first started.
+    first_started: HashMap<NodeId, Instant>,
+    /// The nodes that failed, and were not retried, in the order they did.
+    failures: Vec<NodeFailure>,
+    /// How many nodes may run at the same time.
+    pub(crate) max_concurrency: Option<usize>,
+    /// How many times a node may be retried, unless the node says otherwise.
+    pub(crate) max_retries: Option<u32>,
+}
+
+/// What may be started now. See [`Dispatcher::admit`].
+pub(crate) struct Admitted {
+    /// Retries that were due, with their retry count. They count as running.
+    pub(crate) retries: Vec<(NodeId, u32)>,
+    /// Ready nodes that may be started, in the order they were given. They do not count as
+    /// running until they are [`Dispatcher::start`]ed.
+    pub(crate) ready: Vec<NodeId>,
+    /// Ready nodes that were held back, and why.
+    pub(crate) held_back: Vec<(NodeId, DecisionKind)>,
+    /// When a node that is held back by its rate limit can start.
+    pub(crate) wake_at: Option<Instant>,
+}
+
+impl<S: State> Dispatcher<S> {
+    /// A dispatcher for the nodes of `job`. The provided values are left in `job`.
+    pub(crate) fn new(job: &mut Job<S>, rate_limits: HashMap<String, RateLimit>) -> Self {
+        let mut dependents: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        for (id, deps) in &job.adj {
+            for dep in deps {
+                dependents.entry(*dep).or_default().push(*id);
+            }
+        }
+        let nodes = std::mem::take(&mut job.nodes);
+        Self {
+            pending: nodes.keys().copied().collect(),
+            nodes,
+            results: HashMap::new(),
+            adj: std::mem::take(&mut job.adj),
+            inputs: std::mem::take(&mut job.inputs),
+            dependents,
+            running: HashMap::new(),
+            retries_due: VecDeque::new(),
+            held: HashMap::new(),
+            limiter: RateLimiter::new(rate_limits),
+            first_started: HashMap::new(),
+            failures: vec![],
+            max_concurrency: None,
+            max_retries: None,
+        }
+    }
+
+    /// Start at most `limit.starts` nodes tagged `tag` within any `limit.per`.
+    pub(crate) fn rate_limit(&mut self, tag: &str, limit: RateLimit) {
+        self.limiter.insert(tag, limit);
+    }
+
+    /// The nodes whose dependencies are all done, and have not been started yet. Sorted by
+    /// priority, and then by name, so the order is the same between runs.
+    pub(crate) fn ready(&self) -> Vec<NodeId> {
+        let mut ready: Vec<_> = self
+            .pending
+            .iter()
+            .copied()
+            .filter(|id| self.adj[id].iter().all(|id| self.results.contains_key(id)))
+            .collect();
+        ready.sort_by_key(|id| (Reverse(self.nodes[id].priority), self.nodes[id].name));
+        ready
+    }
+
+    /// The nodes that have not been started, since some of their dependencies are not done,
+    /// with those dependencies. Both sorted by name.
+    pub(crate) fn waiting(&self) -> Vec<(NodeId, Vec<&'static str>)> {
+        let mut waiting: Vec<_> = self
+            .pending
+            .iter()
+            .filter_map(|id| {
+                let mut deps: Vec<_> = self.adj[id]
+                    .iter()
+                    .filter(|id| !self.results.contains_key(id))
+                    .map(|id| self.nodes[id].name)
+                    .collect();
+                deps.sort_unstable();
+                (!deps.is_empty()).then_some((*id, deps))
+            })
+            .collect();
+        waiting.sort_by_key(|(id, _)| self.nodes[id].name);
+        waiting
+    }
+
+    /// Picks what may be started now, out of the retries that are due and the `ready` nodes,
+    /// without going over the concurrency limit, the rate limits, or starting two nodes that use
+    /// the same resource. Retries go first, and are started right away.
+    pub(crate) fn admit(&mut self, mut ready: Vec<NodeId>, now: Instant) -> Admitted {
+        let mut wake_at: Option<Instant> = None;
+        let mut capacity = self
+            .max_concurrency
+            .map_or(usize::MAX, |max| max.saturating_sub(self.running.len()));
+        let mut retries = vec![];
+        let mut waiting = VecDeque::new();
+        while capacity > 0
+            && let Some((id, retry)) = self.retries_due.pop_front()
+        {
+            let node = &self.nodes[&id];
+            if node
+                .exclusive
+                .is_some_and(|resource| self.held.contains_key(resource))
+            {
+                waiting.push_back((id, retry));
+                continue;
+            }
+            if let Some(tag) = node.rate
+                && let Err(at) = self.limiter.acquire(tag, now)
+            {
+                wake_at = Some(wake_at.map_or(at, |wake_at| wake_at.min(at)));
+                waiting.push_back((id, retry));
+                continue;
+            }
+            capacity -= 1;
+            self.start(id, retry, now);
+            retries.push((id, retry));
+        }
+        waiting.append(&mut self.retries_due);
+        self.retries_due = waiting;
+
+        // Only one node at a time gets to use each resource.
+        let mut held_back = vec![];
+        let mut taken: HashSet<_> = self.held.keys().copied().collect();
+        ready.retain(|id| match self.nodes[id].exclusive {
+            Some(resource) if !taken.insert(resource) => {
+                held_back.push((*id, DecisionKind::Exclusive(resource)));
+                false
+            }
+            _ => true,
+        });
+        if ready.len() > capacity {
+            for id in ready.drain(capacity..) {
+                held_back.push((id, DecisionKind::ConcurrencyLimit));
+            }
+        }
+        // Nodes that would go over their rate limit wait until they can start.
+        ready.retain(|id| match self.nodes[id].rate {
+            Some(tag) => match self.limiter.acquire(tag, now) {
+                Ok(()) => true,
+                Err(at) => {
+                    wake_at = Some(wake_at.map_or(at, |wake_at| wake_at.min(at)));
+                    held_back.push((*id, DecisionKind::RateLimited(tag)));
+                    false
+                }
+            },
+            None => true,
+        });
+        Admitted {
+            retries,
+            ready,
+            held_back,
+            wake_at,
+        }
+    }
+
+    /// Counts node `id` as running, from `now`, and takes its resource.
+    pub(crate) fn start(&mut self, id: NodeId, retry: u32, now: Instant) {
+        self.pending.remove(&id);
+        self.running.insert(id, retry);
+        self.first_started.entry(id).or_insert(now);
+        if let Some(resource) = self.nodes[&id].exclusive {
+            self.held.insert(resource, id);
+        }
+    }
+
+    /// The retry count of node `id`, if it is running.
+    pub(crate) fn running(&self, id: NodeId) -> Option<u32> {
+        self.running.get(&id).copied()
+    }
+
+    /// When node `id` was first started.
+    pub(crate) fn first_started(&self, id: NodeId) -> Instant {
+        self.first_started[&id]
+    }
+
+    /// The inputs of node `id`, in the order its producer takes them.
+    pub(crate) fn payloads(&self, id: NodeId) -> Vec<Payload> {
+        self.inputs[&id]
+            .iter()
+            .map(|id| self.results.get(id).cloned().unwrap_or(Payload::Missing))
+            .collect()
+    }
+
+    /// Node `id` was not started, since its output was already known (say, from a cache).
+    pub(crate) fn skip(&mut self, id: NodeId, payload: Payload) {
+        self.pending.remove(&id);
+        self.results.insert(id, payload);
+    }
+
+    /// Node `id` started streaming `payload`. Its dependents can read from it, while it keeps
+    /// running.
+    pub(crate) fn stream(&mut self, id: NodeId, payload: Payload) {
+        self.results.insert(id, payload);
+    }
+
+    /// Node `id` is done, with `payload`.
+    pub(crate) fn done(&mut self, id: NodeId, payload: Payload) {
+        self.finish(id);
+        self.results.insert(id, payload);
+    }
+
+    /// Node `id` stopped running. Gives back its resource.
+    pub(crate) fn finish(&mut self, id: NodeId) {
+        self.running.remove(&id);
+        if let Some(resource) = self.nodes[&id].exclusive
+            && self.held.get(resource) == Some(&id)
+        {
+            self.held.remove(resource);
+        }
+    }
+
+    /// Node `id` failed with `error`, after `retry` retries. Returns how long to wait before
+    /// retrying it, or `None` to give up on it (see [`Dispatcher::give_up`]). A policy decides
+    /// how long to wait, but the error decides whether to retry.
+    pub(crate) fn failed(
+        &mut self,
+        id: NodeId,
+        retry: u32,
+        error: &Error,
+        now: Instant,
+    ) -> Option<Duration> {
+        self.finish(id);
+        let node = &self.nodes[&id];
+        let max_retries = node.max_retries.or(self.max_retries);
+        let retry_in = error
+            .retry_in
+            .filter(|_| max_retries.is_none_or(|max| retry < max));
+        match node.retry_policy {
+            Some(policy) => retry_in.and_then(|_| {
+                let elapsed = now.saturating_duration_since(self.first_started[&id]);
+                policy.delay(retry + 1, elapsed)
+            }),
+            None => retry_in,
+        }
+    }
+
+    /// Node `id` is due to be retried, for the `retry`th time.
+    pub(crate) fn retry(&mut self, id: NodeId, retry: u32) {
+        self.retries_due.push_back((id, retry));
+    }
+
+    /// Takes the retries that are due, to not run them after all.
+    pub(crate) fn drain_retries(&mut self) -> Vec<(NodeId, u32)> {
+        self.retries_due.drain(..).collect()
+    }
+
+    /// Gives up on a node that failed, and the nodes that (directly or not) depend on it.
+    /// Returns those nodes.
+    pub(crate) fn give_up(&mut self, id: NodeId, failure: NodeFailure) -> Vec<NodeId> {
+        self.failures.push(failure);
+        let mut blocked = vec![];
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            for dependent in self.dependents.get(&id).into_iter().flatten() {
+                if self.pending.remove(dependent) {
+                    blocked.push(*dependent);
+                    stack.push(*dependent);
+                }
+            }
+        }
+        blocked
+    }
+
+    /// The nodes that were given up on, in the order they failed.
+    pub(crate) fn failures(&self) -> &[NodeFailure] {
+        &self.failures
+    }
+
+    pub(crate) fn take_failures(&mut self) -> Vec<NodeFailure> {
+        std::mem::take(&mut self.failures)
+    }
+
+    /// Nothing is running, and nothing more can be started, not counting retries that are
+    /// waiting to be due.
+    pub(crate) fn is_done(&self) -> bool {
+        self.running.is_empty() && self.retries_due.is_empty() && self.pending.is_empty()
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::{
+    AttemptInfo, Cache, Context, Error, Format, Job, NodeFailure, NodeId, Payload, Producer,
+    Result, Scratchpad, State, UnmetRequirement, cache_key,
+    dispatch::Dispatcher,
+    rate::RateLimit,
+    rt::{self, Instant},
+    worker::{NEXT_JOB_ID, with_version},
+};
+
+/// Decides what to run next, without running anything itself, so a job can be run by an
+/// executor other than tokio's (say, in WASM). Ask it what is [`Driver::ready`], run those nodes
+/// however you like, and tell it when each is [`Driver::complete`], until [`Driver::is_done`].
+///
+/// ```
+/// # use ordr_core::{Driver, Job};
+/// # async fn run<S: ordr_core::State>(job: Job<S>, state: S) {
+/// let mut driver = Driver::new(job);
+/// while !driver.is_done() {
+///     for run in driver.ready() {
+///         let id = run.id;
+///         let result = run.run(state.clone()).await;
+///         driver.complete(id, result).unwrap();
+///     }
+/// }
+/// # }
+/// ```
+///
+/// Nodes are picked the same way a [`crate::Worker`] picks them: by priority, within the
+/// concurrency and rate limits, one at a time for each resource, with retries first and retry
+/// policies given the time since the node was first started. Waiting (for retries, and rate
+/// limits) and timeouts are left to the caller, though producers are given the deadline of their
+/// node, as with a worker. Streaming nodes need a [`crate::Worker`].
+///
+/// On wasm, a [`crate::Worker`] runs its nodes on the event loop of the browser. A driver lets
+/// you run them some other way there, say one at a time from a web worker.
+pub struct Driver<S: State> {
+    dispatch: Dispatcher<S>,
+    /// Names of the provided nodes, for [`Driver::data`].
+    provided: HashMap<NodeId, &'static str>,
+    /// How long to wait before running each node that is to be retried.
+    delays: HashMap<NodeId, Duration>,
+    /// When a node that is held back by its rate limit can start.
+    wake_at: Option<Instant>,
+    cache: Option<Arc<dyn Cache>>,
+    /// The cache keys of the running nodes, so their outputs can be cached once they are done.
+    cache_keys: HashMap<NodeId, String>,
+    versions: HashMap<&'static str, u32>,
+    format: Format,
+    /// Given to every producer. See [`Driver::cancel`].
+    cancellation: CancellationToken,
+    t0: Instant,
+    job_id: u64,
+    labels: Arc<BTreeMap<String, String>>,
+    scratchpad: Scratchpad,
+}
+
+impl<S: State> Driver<S> {
+    #[must_use]
+    pub fn new(mut job: Job<S>) -> Self {
+        let mut dispatch = Dispatcher::new(&mut job, HashMap::new());
+        let mut provided = HashMap::new();
+        for (id, (name, value)) in job.provided {
+            provided.insert(id, name);
+            dispatch.results.insert(id, Payload::Json(Arc::new(value)));
+        }
+        Self {
+            dispatch,
+            provided,
+            delays: HashMap::new(),
+            wake_at: None,
+            cache: None,
+            cache_keys: HashMap::new(),
+            versions: job.versions,
+            format: Format::default(),
+            cancellation: CancellationToken::new(),
+            t0: rt::now(),
+            job_id: NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed),
+            labels: Arc::new(job.labels),
+            scratchpad: Scratchpad::default(),
+        }
+    }
+
+    /// Retry a node at most `max_retries` times, unless the node says otherwise. Like
+    /// [`crate::Worker::max_retries`].
+    #[must_use]
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.dispatch.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Hand out at most `max_concurrency` nodes that have not been completed. Like
+    /// [`crate::Worker::max_concurrency`].
+    ///
+    /// # Panics
+    /// If `max_concurrency` is `0`.
+    #[must_use]
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        assert!(max_concurrency > 0, "Max concurrency must be at least 1");
+        self.dispatch.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// Hand out at most `starts` nodes tagged `tag` within any `per`. Like
+    /// [`crate::Worker::rate_limit`]. See [`Driver::rate_limited_for`].
+    ///
+    /// # Panics
+    /// If `starts` is `0`.
+    #[must_use]
+    pub fn rate_limit(mut self, tag: &str, starts: u32, per: Duration) -> Self {
+        assert!(starts > 0, "A rate limit must allow at least 1 start");
+        self.dispatch.rate_limit(tag, RateLimit { starts, per });
+        self
+    }
+
+    /// Serialize the outputs of nodes with `format`, instead of as JSON. Like
+    /// [`crate::Worker::format`].
+    #[must_use]
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Look for the output of each node in `cache` before handing it out, and store outputs
+    /// there once they are complete. Like [`crate::Worker::with_cache`].
+    #[must_use]
+    pub fn with_cache(mut self, cache: impl Cache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Check the requirements of the nodes (see [`crate::Requirement`]), each once, with
+    /// `state`. Returns the ones that were not met, sorted by name. A [`crate::Worker`] does
+    /// this before starting anything, and does not run the job if any are unmet.
+    pub async fn preflight(&self, state: &S) -> Vec<UnmetRequirement> {
+        crate::preflight(self.dispatch.nodes.values(), state).await
+    }
+
+    /// The nodes that can be run now: retries first, then the nodes whose dependencies are all
+    /// done, by priority and then by name. They are counted as running until they are
+    /// [`Driver::complete`]. Nodes found in the cache are not handed out, but done right away.
+    pub fn ready(&mut self) -> Vec<NodeRun<S>> {
+        let mut runs = vec![];
+        loop {
+            let now = rt::now();
+            let ready = self.dispatch.ready();
+            let admitted = self.dispatch.admit(ready, now);
+            self.wake_at = admitted.wake_at;
+            for (id, retry) in admitted.retries {
+                let delay = self.delays.remove(&id).unwrap_or_default();
+                runs.push(self.run(id, retry, delay, now));
+            }
+            let mut cached = false;
+            for id in admitted.ready {
+                if let Some(payload) = self.cached(id) {
+                    self.dispatch.skip(id, payload);
+                    cached = true;
+                    continue;
+                }
+                self.dispatch.start(id, 0, now);
+                runs.push(self.run(id, 0, Duration::ZERO, now));
+            }
+            // Cached nodes may have made others ready.
+            if !cached {
+                return runs;
+            }
+        }
+    }
+
+    /// Record the result of running node `id`. A node that fails is handed out again by
+    /// [`Driver::ready`] if it asks to be retried and may be, or else the nodes that depend on
+    /// it are given up on.
+    ///
+    /// # Errors
+    /// If the node is not running, say because it was already completed. The result is ignored.
+    pub fn complete(
+        &mut self,
+        id: NodeId,
+        result: Result<Payload>,
+    ) -> std::result::Result<(), &'static str> {
+        let Some(retry) = self.dispatch.running(id) else {
+            return Err("Node is not running");
+        };
+        let name = self.dispatch.nodes[&id].name;
+        let error = match result {
+            Ok(Payload::Streaming(..)) => Error::fatal("Streaming nodes need a Worker"),
+            Ok(payload) => {
+                if let Some(cache) = &self.cache
+                    && let Some(key) = self.cache_keys.remove(&id)
+                    && let Some(value) = payload.to_json()
+                    && let Err(error) = cache.put(name, &key, &value)
+                {
+                    warn!(name, %error, "Could not write to cache");
+                }
+                self.dispatch.done(id, payload);
+                return Ok(());
+            }
+            Err(error) => error,
+        };
+        if let Some(delay) = self.dispatch.failed(id, retry, &error, rt::now()) {
+            self.delays.insert(id, delay);
+            self.dispatch.retry(id, retry + 1);
+            return Ok(());
+        }
+        let failure = NodeFailure {
+            name,
+            retries: retry,
+            error,
+            panicked: false,
+        };
+        self.dispatch.give_up(id, failure);
+        Ok(())
+    }
+
+    /// Tell the producers of the nodes that were (or will be) handed out to wrap up, through
+    /// [`Context::cancelled`], like stopping a [`crate::Worker`] does. The driver keeps handing
+    /// out nodes, so stop asking for them.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    /// Nothing is running, and nothing more can be run. Either every node is done, or some
+    /// failed (see [`Driver::failures`]).
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.dispatch.is_done()
+    }
+
+    /// How long until a node that is held back by its rate limit can be run, if one is. Ask
+    /// [`Driver::ready`] again after that.
+    #[must_use]
+    pub fn rate_limited_for(&self) -> Option<Duration> {
+        self.wake_at
+            .map(|at| at.saturating_duration_since(rt::now()))
+    }
+
+    /// The nodes that failed, and were not retried, in the order they did.
+    #[must_use]
+    pub fn failures(&self) -> &[NodeFailure] {
+        self.dispatch.failures()
+    }
+
+    /// The values of the nodes that were provided or done, by name. Like
+    /// [`crate::Worker::data`].
+    #[must_use]
+    pub fn data(&self) -> HashMap<String, Value> {
+        self.dispatch
+            .results
+            .iter()
+            .filter_map(|(id, payload)| {
+                let name = self
+                    .dispatch
+                    .nodes
+                    .get(id)
+                    .map_or_else(|| self.provided[id], |n| n.name);
+                let value = with_version(name, payload.to_json()?, &self.versions);
+                Some((name.to_string(), value))
+            })
+            .collect()
+    }
+
+    /// The output of node `id` from the cache, if there is one. Otherwise, remembers the key
+    /// to store its output under, once it is complete.
+    fn cached(&mut self, id: NodeId) -> Option<Payload> {
+        let cache = self.cache.as_ref()?;
+        let node = &self.dispatch.nodes[&id];
+        if node.transient {
+            return None;
+        }
+        let key = cache_key(&self.dispatch.payloads(id))?;
+        match cache.get(node.name, &key) {
+            Ok(Some(value)) => return Some(Payload::Json(Arc::new(value))),
+            Ok(None) => {}
+            Err(error) => warn!(name = node.name, %error, "Could not read from cache"),
+        }
+        self.cache_keys.insert(id, key);
+        None
+    }
+
+    fn run(&self, id: NodeId, retry: u32, delay: Duration, now: Instant) -> NodeRun<S> {
+        let node = &self.dispatch.nodes[&id];
+        NodeRun {
+            id,
+            name: node.name,
+            retry,
+            delay,
+            timeout: node.timeout,
+            producer: node.producer.clone(),
+            payloads: self.dispatch.payloads(id),
+            first_started: self.dispatch.first_started(id).duration_since(self.t0),
+            attempt_started: now.duration_since(self.t0),
+            job_id: self.job_id,
+            format: self.format,
+            labels: self.labels.clone(),
+            cancellation: self.cancellation.clone(),
+            scratchpad: self.scratchpad.clone(),
+        }
+    }
+}
+
+/// A node that can be run. See [`Driver::ready`].
+pub struct NodeRun<S: State> {
+    /// Identifies the node. Pass it to [`Driver::complete`].
+    pub id: NodeId,
+    pub name: &'static str,
+    /// Retry count. `0` the first time the node is run.
+    pub retry: u32,
+    /// How long to wait before running it, if it is a retry.
+    pub delay: Duration,
+    /// How long it may run, if the node has a timeout. The producer is given the deadline, but
+    /// stopping it once it is over is up to the caller.
+    pub timeout: Option<Duration>,
+    producer: Producer<S>,
+    payloads: Vec<Payload>,
+    first_started: Duration,
+    attempt_started: Duration,
+    job_id: u64,
+    format: Format,
+    labels: Arc<BTreeMap<String, String>>,
+    cancellation: CancellationToken,
+    scratchpad: Scratchpad,
+}
+
+impl<S: State> NodeRun<S> {
+    /// Run the producer of the node, with `state`, on whatever polls it. Plain (blocking)
+    /// producers, and producers with `map_over`, hand their work to the runtime: tokio, or
+    /// async-std or smol outside of it (see their features). On wasm, plain producers are
+    /// called in place, and `map_over` runs on the event loop of the browser.
+    ///
+    /// The producer's [`Context`] is filled in as by a worker: with the format of the driver,
+    /// the deadline of the node (see [`NodeRun::timeout`]), and cancelled by [`Driver::cancel`].
+    pub fn run(self, state: S) -> impl Future<Output = Result<Payload>> + Send + 'static {
+        let context = Context {
+            state,
+            attempt: AttemptInfo {
+                attempt: self.retry,
+                first_started: self.first_started,
+                attempt_started: self.attempt_started,
+                deadline: self.timeout.map(|timeout| self.attempt_started + timeout),
+            },
+            job_id: self.job_id,
+            format: self.format,
+            labels: self.labels,
+            cancellation: self.cancellation,
+            scratchpad: self.scratchpad,
+        };
+        (self.producer)(context, self.payloads)
+    }
+}
+
+impl<S: State> std::fmt::Debug for NodeRun<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NodeRun<{}, {}>", self.name, self.retry)
+    }
+}
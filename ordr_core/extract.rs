@@ -0,0 +1,57 @@
+use std::{collections::HashMap, fmt, hash::BuildHasher};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::unversioned_ref;
+
+/// Pulls typed values out of the data of a job (see [`crate::Worker::data`]), usually with
+/// `#[derive(ordr::FromData)]`. See [`crate::Worker::extract`].
+pub trait FromData: Sized {
+    /// # Errors
+    /// If a value is missing, or can not be deserialized.
+    fn from_data(data: &HashMap<String, Value>) -> Result<Self, ExtractError>;
+}
+
+/// Why [`FromData::from_data`] failed.
+#[derive(Debug)]
+pub enum ExtractError {
+    /// There is no value for the node with this name.
+    Missing(&'static str),
+    /// The value of a node can not be deserialized into the field. Has the name of the node, and
+    /// why.
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::Missing(name) => write!(f, "No value for {name}"),
+            ExtractError::Invalid(name, error) => write!(f, "Invalid value for {name}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+/// Deserializes the value of node `name`, without its version, if it has one (see
+/// [`crate::NodeDef::version`]). Used by `#[derive(FromData)]`.
+#[doc(hidden)]
+pub fn extract_field<T: DeserializeOwned, H: BuildHasher>(
+    data: &HashMap<String, Value, H>,
+    name: &'static str,
+) -> Result<T, ExtractError> {
+    extract_optional_field(data, name)?.ok_or(ExtractError::Missing(name))
+}
+
+/// Like [`extract_field`], but for `Option` fields, which are `None` if there is no value.
+#[doc(hidden)]
+pub fn extract_optional_field<T: DeserializeOwned, H: BuildHasher>(
+    data: &HashMap<String, Value, H>,
+    name: &'static str,
+) -> Result<Option<T>, ExtractError> {
+    data.get(name)
+        .map(|value| T::deserialize(unversioned_ref(value)))
+        .transpose()
+        .map_err(|e| ExtractError::Invalid(name, e.to_string()))
+}
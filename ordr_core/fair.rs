@@ -0,0 +1,154 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::oneshot;
+
+/// What a job with a weight of `1` pays for each slot. Heavier jobs pay less, so they get their
+/// turn more often.
+const STRIDE: u64 = 1 << 16;
+
+/// Hands out a fixed number of slots to the nodes of many jobs. When nodes of several jobs are
+/// waiting, the slots go round between the jobs, in proportion to their weights (stride
+/// scheduling): every job has a pass, that grows by `STRIDE / weight` each time it gets a slot,
+/// and the waiting job with the lowest pass goes next.
+#[derive(Debug)]
+pub(crate) struct FairQueue {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    free: usize,
+    /// The pass of the last job to get a slot. A job that starts waiting catches up to it, so it
+    /// can not save up turns while it is idle.
+    clock: u64,
+    jobs: HashMap<usize, Turns>,
+}
+
+/// Where a job is in the rotation, and its nodes that wait for a slot.
+#[derive(Debug)]
+struct Turns {
+    pass: u64,
+    stride: u64,
+    waiting: VecDeque<oneshot::Sender<Slot>>,
+}
+
+impl FairQueue {
+    pub(crate) fn new(slots: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                free: slots,
+                clock: 0,
+                jobs: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Wait for a slot for a node of `job`. It is given back when the [`Slot`] is dropped.
+    ///
+    /// # Panics
+    /// If the lock is poisoned.
+    async fn acquire(self: &Arc<Self>, job: usize, weight: u32) -> Slot {
+        let rx = {
+            let mut inner = self.inner.lock().unwrap();
+            let Inner { free, clock, jobs } = &mut *inner;
+            let nobody_waiting = jobs.values().all(|share| share.waiting.is_empty());
+            let share = jobs.entry(job).or_insert_with(|| Turns {
+                pass: 0,
+                stride: STRIDE / u64::from(weight.max(1)),
+                waiting: VecDeque::new(),
+            });
+            if share.waiting.is_empty() {
+                share.pass = share.pass.max(*clock);
+            }
+            if nobody_waiting && *free > 0 {
+                *free -= 1;
+                share.charge(clock);
+                return Slot {
+                    queue: Some(self.clone()),
+                };
+            }
+            let (tx, rx) = oneshot::channel();
+            share.waiting.push_back(tx);
+            rx
+        };
+        // The sender is only dropped once it has sent the slot, or the job is removed.
+        rx.await.expect("A waiting node is given a slot")
+    }
+
+    /// Forget about `job`, once it is done.
+    ///
+    /// # Panics
+    /// If the lock is poisoned.
+    pub(crate) fn remove(&self, job: usize) {
+        self.inner.lock().unwrap().jobs.remove(&job);
+    }
+
+    /// Hand a slot that was given back to the next waiting node, or keep it for later.
+    fn release(self: &Arc<Self>) {
+        let mut inner = self.inner.lock().unwrap();
+        let Inner { free, clock, jobs } = &mut *inner;
+        loop {
+            let next = jobs
+                .iter_mut()
+                .filter(|(_, share)| !share.waiting.is_empty())
+                .min_by_key(|(job, share)| (share.pass, **job));
+            let Some((_, share)) = next else {
+                *free += 1;
+                return;
+            };
+            let tx = share.waiting.pop_front().expect("Only jobs that wait");
+            let slot = Slot {
+                queue: Some(self.clone()),
+            };
+            // A node that is no longer waiting (it was aborted) does not get it. If it stops
+            // waiting after this, the slot is dropped along with the channel, and given back.
+            match tx.send(slot) {
+                Ok(()) => {
+                    share.charge(clock);
+                    return;
+                }
+                Err(mut slot) => slot.queue = None,
+            }
+        }
+    }
+}
+
+impl Turns {
+    fn charge(&mut self, clock: &mut u64) {
+        *clock = self.pass;
+        self.pass += self.stride;
+    }
+}
+
+/// The slots of a [`FairQueue`] that a job of a pool takes its turns at. See
+/// [`crate::WorkerPool::max_nodes`].
+#[derive(Debug, Clone)]
+pub(crate) struct PoolShare {
+    pub(crate) queue: Arc<FairQueue>,
+    pub(crate) job: usize,
+    pub(crate) weight: u32,
+}
+
+impl PoolShare {
+    /// Wait for the job's turn to run a node.
+    pub(crate) async fn acquire(&self) -> Slot {
+        self.queue.acquire(self.job, self.weight).await
+    }
+}
+
+/// A slot of a [`FairQueue`]. Given back when dropped.
+#[derive(Debug)]
+pub(crate) struct Slot {
+    queue: Option<Arc<FairQueue>>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.release();
+        }
+    }
+}
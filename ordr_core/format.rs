@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::Payload;
+
+/// How the outputs of nodes are serialized. Set with [`crate::Worker::format`].
+///
+/// Whatever the format, [`crate::Worker::data`], snapshots, stores and caches see the values as
+/// JSON. A format that is not self-describing (like bincode) can not be turned back into JSON
+/// without knowing the type, so it is not offered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    /// Outputs are kept as a [`serde_json::Value`].
+    #[default]
+    Json,
+    /// Outputs are kept as CBOR bytes, which are smaller, and handle binary data (like
+    /// `serde_bytes`) without blowing it up into a list of numbers.
+    Cbor,
+}
+
+impl Format {
+    /// Serialize `value` into a payload.
+    ///
+    /// # Panics
+    /// If `value` can not be serialized.
+    pub fn serialize<T: Serialize + ?Sized>(self, value: &T) -> Payload {
+        self.try_serialize(value).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`Format::serialize`], but describes what went wrong instead of panicking.
+    pub(crate) fn try_serialize<T: Serialize + ?Sized>(self, value: &T) -> Result<Payload, String> {
+        match self {
+            Format::Json => serde_json::to_value(value)
+                .map(|value| Payload::Json(Arc::new(value)))
+                .map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut bytes = vec![];
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(Payload::Cbor(Arc::from(bytes)))
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{pin::Pin, sync::Arc, time::Duration};
+
+use crate::{Context, Error, Payload, Result, State};
+
+/// The future returned by the methods of a [`Hook`].
+pub type HookFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Gets called around every node a worker runs, say to report progress to a job tracker without
+/// touching the producers. Registered with [`crate::Worker::add_hook`].
+///
+/// Every method does nothing by default. They are awaited in the task running the node, so a slow
+/// hook holds up the node, but not the rest of the job. A hook that panics fails the job like a
+/// producer that panics.
+pub trait Hook<S: State>: Send + Sync + 'static {
+    /// Called right before every attempt at running node `name`.
+    fn before_node<'a>(&'a self, name: &'static str, context: &'a Context<S>) -> HookFuture<'a> {
+        let _ = (name, context);
+        Box::pin(async {})
+    }
+
+    /// Called after every attempt at running node `name`, with what the producer returned and
+    /// how long it took.
+    fn after_node<'a>(
+        &'a self,
+        name: &'static str,
+        result: &'a Result<Payload>,
+        duration: Duration,
+    ) -> HookFuture<'a> {
+        let _ = (name, result, duration);
+        Box::pin(async {})
+    }
+
+    /// Called when node `name` failed with `error`, and will be tried again (as retry number
+    /// `retry`) after `delay`.
+    fn on_retry<'a>(
+        &'a self,
+        name: &'static str,
+        retry: u32,
+        error: &'a Error,
+        delay: Duration,
+    ) -> HookFuture<'a> {
+        let _ = (name, retry, error, delay);
+        Box::pin(async {})
+    }
+}
+
+/// Lets a hook be shared between workers, or kept around to look at afterwards.
+impl<S: State, H: Hook<S>> Hook<S> for Arc<H> {
+    fn before_node<'a>(&'a self, name: &'static str, context: &'a Context<S>) -> HookFuture<'a> {
+        (**self).before_node(name, context)
+    }
+
+    fn after_node<'a>(
+        &'a self,
+        name: &'static str,
+        result: &'a Result<Payload>,
+        duration: Duration,
+    ) -> HookFuture<'a> {
+        (**self).after_node(name, result, duration)
+    }
+
+    fn on_retry<'a>(
+        &'a self,
+        name: &'static str,
+        retry: u32,
+        error: &'a Error,
+        delay: Duration,
+    ) -> HookFuture<'a> {
+        (**self).on_retry(name, retry, error, delay)
+    }
+}
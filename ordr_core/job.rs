@@ -1,22 +1,40 @@
 use ::std::hash::BuildHasher;
 use std::{
-    any::TypeId,
-    collections::{HashMap, HashSet, hash_map::Entry},
+    collections::{BTreeMap, HashMap, HashSet, hash_map::Entry},
     fmt,
+    sync::Arc,
+    time::Duration,
 };
 
+use serde::Serialize;
 use serde_json::Value;
-use tracing::warn;
+use tracing::{info, warn};
 
-use crate::{Node, NodeBuilder, State};
+use crate::{
+    Checkpoint, Migrator, Node, NodeBuilder, NodeId, State, Subgraph, intern,
+    manifest::{JobManifest, NodeRegistry},
+    unversioned, versioned,
+};
 
 /// Describes what needs to be done, and how to do it. Pass it to a [`crate::Worker`] to have it
 /// executed.
 #[derive(Debug, Clone)]
 pub struct Job<S: State> {
-    pub(crate) nodes: HashMap<TypeId, Node<S>>,
-    pub(crate) adj: HashMap<TypeId, Vec<TypeId>>,
-    pub(crate) provided: HashMap<TypeId, (&'static str, Value)>,
+    pub(crate) nodes: HashMap<NodeId, Node<S>>,
+    pub(crate) adj: HashMap<NodeId, Vec<NodeId>>,
+    /// All dependencies of each node, in the order its producer takes them. Unlike `adj`, this
+    /// includes optional dependencies that are not part of the job.
+    pub(crate) inputs: HashMap<NodeId, Vec<NodeId>>,
+    pub(crate) provided: HashMap<NodeId, (&'static str, Value)>,
+    pub(crate) targets: HashSet<NodeId>,
+    /// Nodes left out of the job because data was provided for the nodes that needed them.
+    pub(crate) pruned: HashMap<NodeId, (&'static str, Vec<&'static str>)>,
+    /// Set with [`JobBuilder::label`].
+    pub(crate) labels: BTreeMap<String, String>,
+    /// Names in the provided data that did not match any node.
+    pub(crate) discarded: Vec<String>,
+    /// The version of every node that has one, provided or not. See [`Node::version`].
+    pub(crate) versions: HashMap<&'static str, u32>,
 }
 
 impl<S: State> Default for Job<S> {
@@ -24,7 +42,13 @@ impl<S: State> Default for Job<S> {
         Job {
             nodes: HashMap::new(),
             adj: HashMap::new(),
+            inputs: HashMap::new(),
             provided: HashMap::new(),
+            targets: HashSet::new(),
+            pruned: HashMap::new(),
+            labels: BTreeMap::new(),
+            discarded: vec![],
+            versions: HashMap::new(),
         }
     }
 }
@@ -32,17 +56,107 @@ impl<S: State> Default for Job<S> {
 impl<S: State> Job<S> {
     #[must_use]
     pub fn builder() -> JobBuilder<S> {
-        JobBuilder {
-            data: HashMap::new(),
-            job: Job::default(),
-        }
+        Self::builder_with_data(HashMap::new())
     }
 
     #[must_use]
     pub fn builder_with_data(data: HashMap<String, Value>) -> JobBuilder<S> {
         JobBuilder {
             data,
-            job: Job::default(),
+            targets: vec![],
+            selected: vec![],
+            forced: HashSet::new(),
+            invalidated: HashSet::new(),
+            timeouts: HashMap::new(),
+            priorities: HashMap::new(),
+            labels: BTreeMap::new(),
+            variants: HashMap::new(),
+            selections: HashMap::new(),
+            migrators: HashMap::new(),
+            names: HashMap::new(),
+            strict: false,
+        }
+    }
+
+    /// Continue a job from a [`Checkpoint`]. Its targets are looked up by name in `nodes`, and
+    /// its values and labels are provided, so only what was left is run.
+    ///
+    /// # Errors
+    /// If one of the targets is not in `nodes`.
+    pub fn builder_from_checkpoint(
+        checkpoint: Checkpoint,
+        nodes: &[Node<S>],
+    ) -> Result<JobBuilder<S>, JobError> {
+        let mut builder = Self::builder_with_data(checkpoint.values);
+        for target in checkpoint.targets {
+            let Some(node) = nodes.iter().find(|node| node.name == target) else {
+                return Err(JobError::UnknownTarget(target));
+            };
+            builder = builder.add_node(node.clone());
+        }
+        builder.labels = checkpoint.labels;
+        Ok(builder)
+    }
+
+    /// Build the job described by `manifest` (see [`Job::to_manifest`]), with the nodes in
+    /// `registry`. Its nodes are added, and its targets solved for, with its data provided.
+    ///
+    /// # Errors
+    /// If a node or target is not in `registry`, or the job can not be built.
+    pub fn from_manifest(
+        manifest: JobManifest,
+        registry: &NodeRegistry<S>,
+    ) -> Result<Job<S>, JobError> {
+        let mut builder = Self::builder_with_data(manifest.provided.into_iter().collect());
+        for name in manifest.nodes {
+            let Some(node) = registry.get(&name) else {
+                return Err(JobError::UnknownNode(name));
+            };
+            builder = builder.add_node(node.clone());
+        }
+        for target in manifest.targets {
+            let Some(node) = registry.get(&target) else {
+                return Err(JobError::UnknownTarget(target));
+            };
+            builder.selected.push(node.clone());
+        }
+        builder.labels = manifest.labels;
+        builder.build()
+    }
+
+    /// Describe the job, so it can be stored or sent elsewhere, and built again with
+    /// [`Job::from_manifest`].
+    #[must_use]
+    pub fn to_manifest(&self) -> JobManifest {
+        let name = |id: &NodeId| {
+            let node = self.nodes.get(id).map(|node| node.name);
+            node.or_else(|| self.provided.get(id).map(|(name, _)| *name))
+        };
+        let mut nodes: Vec<_> = self.nodes.values().map(|n| n.name.to_string()).collect();
+        nodes.sort();
+        let mut targets: Vec<_> = self
+            .targets
+            .iter()
+            .filter_map(name)
+            .map(String::from)
+            .collect();
+        targets.sort();
+        let provided = self
+            .provided
+            .values()
+            .map(|(name, value)| {
+                let value = match self.versions.get(name) {
+                    Some(version) => versioned(*version, value.clone()),
+                    None => value.clone(),
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        JobManifest {
+            nodes,
+            targets,
+            provided,
+            labels: self.labels.clone(),
         }
     }
 
@@ -58,74 +172,833 @@ impl<S: State> Job<S> {
         self.nodes.is_empty()
     }
 
+    /// The labels set with [`JobBuilder::label`].
     #[must_use]
-    pub fn name(&self, id: &TypeId) -> &'static str {
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        &self.labels
+    }
+
+    #[must_use]
+    pub fn name(&self, id: &NodeId) -> &'static str {
         self.nodes[id].name
     }
+
+    /// Combine two jobs into one, that solves for the targets of both. Nodes they share are
+    /// only run once. Data provided to either is used by both, so a node that one job runs, but
+    /// the other was given data for, is not run (nor what only it needed). Where both were given
+    /// data for a node, or set the same label, `self` wins.
+    ///
+    /// # Errors
+    /// If the jobs have nodes with the same name, but different outputs, or the combined job has
+    /// a cycle.
+    pub fn merge(mut self, other: Job<S>) -> Result<Job<S>, JobError> {
+        let names = |job: &Job<S>| -> HashMap<&'static str, NodeId> {
+            let nodes = job.nodes.iter().map(|(id, node)| (node.name, *id));
+            let provided = job.provided.iter().map(|(id, (name, _))| (*name, *id));
+            nodes.chain(provided).collect()
+        };
+        let ours = names(&self);
+        for (name, id) in names(&other) {
+            if ours.get(name).is_some_and(|ours| *ours != id) {
+                return Err(JobError::DuplicateName(name));
+            }
+        }
+        for (id, provided) in other.provided {
+            self.provided.entry(id).or_insert(provided);
+        }
+        for (id, node) in other.nodes {
+            if let Entry::Vacant(entry) = self.nodes.entry(id) {
+                self.inputs.insert(id, other.inputs[&id].clone());
+                entry.insert(node);
+            }
+        }
+        self.targets.extend(other.targets);
+        for (id, (name, by)) in other.pruned {
+            self.pruned.entry(id).or_insert((name, vec![])).1.extend(by);
+        }
+        for (key, value) in other.labels {
+            self.labels.entry(key).or_insert(value);
+        }
+        self.discarded.extend(other.discarded);
+        self.versions.extend(other.versions);
+        self.discarded.sort();
+        self.discarded.dedup();
+
+        // Nodes that are provided now, and whatever only they needed, do not have to run.
+        for id in self.provided.keys() {
+            if let Some(node) = self.nodes.remove(id) {
+                self.inputs.remove(id);
+                record_pruned(&mut self.pruned, &node);
+            }
+        }
+        let mut needed = HashSet::new();
+        let mut stack: Vec<_> = self.targets.iter().copied().collect();
+        while let Some(id) = stack.pop() {
+            if self.nodes.contains_key(&id) && needed.insert(id) {
+                stack.extend(self.inputs[&id].iter().copied());
+            }
+        }
+        self.nodes.retain(|id, _| needed.contains(id));
+        self.inputs.retain(|id, _| needed.contains(id));
+        self.targets
+            .retain(|id| self.nodes.contains_key(id) || self.provided.contains_key(id));
+        self.pruned
+            .retain(|id, _| !self.nodes.contains_key(id) && !self.provided.contains_key(id));
+        self.adj.clear();
+        link(&mut self);
+        check(&self)?;
+        Ok(self)
+    }
+
+    /// Explains why node `N` will or won't run in this job.
+    #[must_use]
+    pub fn explain<N: NodeBuilder<S>>(&self) -> Explanation {
+        let id = N::node().id;
+        if self.provided.contains_key(&id) {
+            return Explanation::Provided;
+        }
+        if let Some(deps) = self.adj.get(&id) {
+            let mut waiting_for: Vec<_> = deps
+                .iter()
+                .filter(|id| !self.provided.contains_key(id))
+                .map(|id| self.name(id))
+                .collect();
+            waiting_for.sort_unstable();
+            return Explanation::Pending { waiting_for };
+        }
+        match self.pruned.get(&id) {
+            Some((_, by)) => {
+                let mut by = by.clone();
+                by.sort_unstable();
+                Explanation::Pruned { by }
+            }
+            None => Explanation::Unreachable,
+        }
+    }
+
+    /// The nodes that were left out of the job because data was provided for the nodes that
+    /// depend on them. Each node comes with the names of the provided nodes that made it
+    /// unnecessary. Sorted by name.
+    #[must_use]
+    pub fn pruned(&self) -> Vec<(&'static str, Vec<&'static str>)> {
+        let mut pruned: Vec<_> = self
+            .pruned
+            .values()
+            .map(|(name, by)| {
+                let mut by = by.clone();
+                by.sort_unstable();
+                (*name, by)
+            })
+            .collect();
+        pruned.sort_unstable();
+        pruned
+    }
+
+    /// Every node in the job, including provided ones, with what they depend on. Provided nodes
+    /// come first, then the rest in topological order (dependencies first), sorted by name within
+    /// each level. Same order as [`crate::mermaid`].
+    #[must_use]
+    pub fn nodes(&self) -> Vec<NodeInfo> {
+        let mut provided: Vec<_> = self
+            .provided
+            .iter()
+            .map(|(id, (name, _))| (*name, *id))
+            .collect();
+        provided.sort_unstable_by_key(|(name, _)| *name);
+        let provided = provided.into_iter().map(|(name, id)| NodeInfo {
+            name,
+            deps: vec![],
+            provided: true,
+            target: self.targets.contains(&id),
+        });
+        let rest = self.levels().into_iter().flatten().map(|id| NodeInfo {
+            name: self.name(&id),
+            deps: self.dep_names(&id),
+            provided: false,
+            target: self.targets.contains(&id),
+        });
+        provided.chain(rest).collect()
+    }
+
+    /// Names of the nodes that node `name` depends on, sorted. Only dependencies that are part of
+    /// the job (or provided) are included. `None` if there is no such node in the job.
+    #[must_use]
+    pub fn dependencies_of(&self, name: &str) -> Option<Vec<&'static str>> {
+        if self.provided.values().any(|(n, _)| *n == name) {
+            return Some(vec![]);
+        }
+        let (id, _) = self.nodes.iter().find(|(_, node)| node.name == name)?;
+        Some(self.dep_names(id))
+    }
+
+    fn dep_names(&self, id: &NodeId) -> Vec<&'static str> {
+        let name = |id| self.provided.get(id).map_or_else(|| self.name(id), |p| p.0);
+        let mut deps: Vec<_> = self.adj[id].iter().map(name).collect();
+        deps.sort_unstable();
+        deps
+    }
+
+    /// The nodes that will run, grouped in waves that can run in parallel: the first wave only
+    /// depends on provided data, the next only on the first, and so forth. Provided and pruned
+    /// nodes are left out. Each wave is sorted by name.
+    ///
+    /// This is how the job would run with unlimited concurrency and equally fast nodes. In
+    /// practice, a node starts as soon as its own dependencies are done.
+    #[must_use]
+    pub fn execution_plan(&self) -> Vec<Vec<&'static str>> {
+        self.levels()
+            .into_iter()
+            .map(|level| level.iter().map(|id| self.name(id)).collect())
+            .collect()
+    }
+
+    /// What running the job would do, without running anything. Handy for checking a job, and
+    /// the data provided for it, in CI.
+    #[must_use]
+    pub fn plan(&self) -> Plan {
+        let mut provided: Vec<_> = self.provided.values().map(|(name, _)| *name).collect();
+        provided.sort_unstable();
+        Plan {
+            waves: self.execution_plan(),
+            provided,
+            pruned: self.pruned(),
+            discarded: self.discarded.clone(),
+        }
+    }
+
+    /// The nodes grouped in levels: the first level has no dependencies (except provided data),
+    /// the next only depends on the first, and so forth. Each level is sorted by name.
+    pub(crate) fn levels(&self) -> Vec<Vec<NodeId>> {
+        let mut remaining: HashSet<_> = self.adj.keys().copied().collect();
+        let mut levels: Vec<Vec<NodeId>> = vec![];
+        while !remaining.is_empty() {
+            let mut level: Vec<_> = remaining
+                .iter()
+                .copied()
+                .filter(|id| self.adj[id].iter().all(|dep| !remaining.contains(dep)))
+                .collect();
+            // Only happens with cycles, which `build` does not allow.
+            if level.is_empty() {
+                break;
+            }
+            level.sort_by_key(|id| self.name(id));
+            for id in &level {
+                remaining.remove(id);
+            }
+            levels.push(level);
+        }
+        levels
+    }
+
+    /// Turn a node into provided data. Nodes that are then no longer needed by any target are
+    /// removed. Returns `false` if there is no node with that name.
+    pub(crate) fn provide(&mut self, name: &str, value: Value) -> bool {
+        if let Some((_, v)) = self.provided.values_mut().find(|(n, _)| *n == name) {
+            *v = value;
+            return true;
+        }
+        let Some((&id, node)) = self.nodes.iter().find(|(_, node)| node.name == name) else {
+            return false;
+        };
+        let name = node.name;
+        self.provided.insert(id, (name, value));
+        self.nodes.remove(&id);
+        self.adj.remove(&id);
+        self.inputs.remove(&id);
+        for (id, node) in self.prune() {
+            let (_, by) = self.pruned.entry(id).or_insert((node.name, vec![]));
+            by.push(name);
+        }
+        true
+    }
+
+    /// Removes nodes that can not be reached from the targets, and returns them.
+    fn prune(&mut self) -> Vec<(NodeId, Node<S>)> {
+        let mut seen = HashSet::new();
+        let mut stack: Vec<_> = self.targets.iter().copied().collect();
+        while let Some(id) = stack.pop() {
+            if seen.insert(id) {
+                stack.extend(self.adj.get(&id).into_iter().flatten());
+            }
+        }
+        self.adj.retain(|id, _| seen.contains(id));
+        // Only nodes to run after can be removed from under a node that is kept.
+        for deps in self.adj.values_mut() {
+            deps.retain(|id| seen.contains(id));
+        }
+        self.inputs.retain(|id, _| seen.contains(id));
+        self.nodes.extract_if(|id, _| !seen.contains(id)).collect()
+    }
 }
 
 /// Builds a job. Created with [`Job::builder()`]. Call `.build()` on it to create a [`Job`].
+#[derive(Clone)]
 pub struct JobBuilder<S: State> {
     data: HashMap<String, Value>,
-    job: Job<S>,
+    targets: Vec<Node<S>>,
+    /// Set with [`JobBuilder::target`]. If empty, all of `targets` are solved for.
+    selected: Vec<Node<S>>,
+    forced: HashSet<NodeId>,
+    /// Set with [`JobBuilder::invalidate`].
+    invalidated: HashSet<NodeId>,
+    timeouts: HashMap<NodeId, Duration>,
+    priorities: HashMap<NodeId, i32>,
+    labels: BTreeMap<String, String>,
+    /// Alternative producers, by the node they produce, and their variant name.
+    variants: HashMap<NodeId, HashMap<&'static str, Node<S>>>,
+    /// The variant to use for a node. Set with [`JobBuilder::select`].
+    selections: HashMap<NodeId, String>,
+    /// Set with [`JobBuilder::migrate`].
+    migrators: HashMap<NodeId, Migrator>,
+    /// Set with [`JobBuilder::rename`].
+    names: HashMap<NodeId, &'static str>,
+    /// Set with [`JobBuilder::strict`].
+    strict: bool,
 }
 
 impl<S: State> JobBuilder<S> {
+    /// Adds more provided data. See [`Job::builder_with_data`].
+    #[must_use]
+    pub fn with_data(mut self, data: HashMap<String, Value>) -> Self {
+        self.data.extend(data);
+        self
+    }
+
+    /// Provides the value of node `T`, so it does not have to be produced. Like
+    /// [`JobBuilder::with_data`], but typed.
+    ///
+    /// # Panics
+    /// If `value` can not be serialized.
+    #[must_use]
+    pub fn with_input<T: Serialize + NodeBuilder<S>>(mut self, value: T) -> Self {
+        let node = T::node();
+        let name = self.names.get(&node.id).copied().unwrap_or(node.name);
+        let value = serde_json::to_value(value).expect("Input can be serialized");
+        self.data.insert(name.to_string(), value);
+        self
+    }
+
+    /// Provides the value of node `T` from a value of another shape, `X`, converted with `f`.
+    /// Meant for data fetched elsewhere, that is close to, but not quite, what the job expects:
+    /// `provide_as::<T, _>(x, |x| T(..))`.
+    ///
+    /// # Panics
+    /// If the converted value can not be serialized.
+    #[must_use]
+    pub fn provide_as<T, X>(self, value: X, f: impl FnOnce(X) -> T) -> Self
+    where
+        T: Serialize + NodeBuilder<S>,
+    {
+        self.with_input(f(value))
+    }
+
     /// Adds a node to the job. All dependencies of the node will be automatically added as well.
     #[must_use]
     pub fn add<N: NodeBuilder<S>>(mut self) -> Self {
-        // Use a stack to recursively add dependencies.
-        let mut stack = vec![N::node()];
+        self.targets.push(N::node());
+        self
+    }
+
+    /// Adds a node that was defined at runtime (see [`Node::builder`]). Like with
+    /// [`JobBuilder::add`], its dependencies are added as well.
+    #[must_use]
+    pub fn add_node(mut self, node: Node<S>) -> Self {
+        self.targets.push(node);
+        self
+    }
+
+    /// Adds the node called `name`, which the macros registered with the `registry` feature,
+    /// without knowing its type. Like with [`JobBuilder::add`], its dependencies are added as
+    /// well.
+    ///
+    /// # Errors
+    /// If no node with that name, and this state, was registered.
+    #[cfg(feature = "registry")]
+    pub fn add_by_name(mut self, name: &str) -> Result<Self, JobError> {
+        let Some(node) = crate::registry::registered_node(name) else {
+            return Err(JobError::UnknownNode(name.to_string()));
+        };
+        self.targets.push(node);
+        Ok(self)
+    }
+
+    /// Registers an alternative producer for a node, defined at runtime with a variant name (see
+    /// [`crate::NodeDef::variant`]). It is only used if it is picked with [`JobBuilder::select`].
+    ///
+    /// # Panics
+    /// If the node has no variant name.
+    #[must_use]
+    pub fn add_variant(mut self, node: Node<S>) -> Self {
+        let variant = node.variant.expect("Variants must have a name");
+        self.variants
+            .entry(node.id)
+            .or_default()
+            .insert(variant, node);
+        self
+    }
+
+    /// Produce node `N` with the producer called `variant` (see [`JobBuilder::add_variant`]),
+    /// instead of its usual one. Selecting again replaces the earlier choice.
+    #[must_use]
+    pub fn select<N: NodeBuilder<S>>(mut self, variant: &str) -> Self {
+        self.selections.insert(N::node().id, variant.to_string());
+        self
+    }
+
+    /// Attach `value` to the job under `key`, say the id of the tenant the job runs for.
+    /// Producers get the labels through [`crate::Context::labels`], and they are on the tracing
+    /// span of the job.
+    #[must_use]
+    pub fn label(mut self, key: &str, value: &str) -> Self {
+        self.labels.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Adds the nodes of `subgraph` to the job, in `namespace`. Mount the same subgraph under
+    /// different namespaces to run it more than once in a job. See [`Subgraph`].
+    #[must_use]
+    pub fn mount(mut self, namespace: &str, subgraph: &Subgraph<S>) -> Self {
+        self.targets.extend(subgraph.mount(namespace));
+        self
+    }
+
+    /// Solve for node `N`. Once a target is set, only the targets (and what they need) are part
+    /// of the job. Nodes added with [`JobBuilder::add`] or [`JobBuilder::add_node`] are then
+    /// only registered: they are used in place of `N` if they have the same output, but are
+    /// otherwise left out. Without any targets, every added node is solved for.
+    #[must_use]
+    pub fn target<N: NodeBuilder<S>>(mut self) -> Self {
+        self.selected.push(N::node());
+        self
+    }
+
+    /// Always run node `N`, even if data was provided for it. Its dependencies will be added to
+    /// the job as needed. Useful when you know one cached value is bad, but want to keep the rest.
+    #[must_use]
+    pub fn force<N: NodeBuilder<S>>(mut self) -> Self {
+        self.forced.insert(N::node().id);
+        self
+    }
+
+    /// Run node `N` again, along with every node that (directly or not) depends on it, even if
+    /// data was provided for them. Data provided for anything else is still used. Useful when
+    /// the value of `N` is known to be stale, and so is everything computed from it.
+    #[must_use]
+    pub fn invalidate<N: NodeBuilder<S>>(mut self) -> Self {
+        self.invalidated.insert(N::node().id);
+        self
+    }
+
+    /// Fail node `N` if a single attempt takes longer than `timeout`. Overrides the timeout set
+    /// on the producer, if any.
+    #[must_use]
+    pub fn timeout_for<N: NodeBuilder<S>>(mut self, timeout: Duration) -> Self {
+        self.timeouts.insert(N::node().id, timeout);
+        self
+    }
+
+    /// Start node `N` before other ready nodes with a lower priority. Overrides the priority set
+    /// on the producer, if any.
+    #[must_use]
+    pub fn priority<N: NodeBuilder<S>>(mut self, priority: i32) -> Self {
+        self.priorities.insert(N::node().id, priority);
+        self
+    }
+
+    /// Call node `N` `name` in this job, say because a node from another crate has the same
+    /// name. Its data is then provided, and shows up in [`crate::Worker::data`], under the new
+    /// name.
+    #[must_use]
+    pub fn rename<N: NodeBuilder<S>>(mut self, name: &str) -> Self {
+        let node = N::node();
+        let name = intern(name.to_string());
+        // Data given with `with_input` before the rename.
+        if let Some(value) = self.data.remove(node.name) {
+            self.data.insert(name.to_string(), value);
+        }
+        self.names.insert(node.id, name);
+        self
+    }
+
+    /// Upgrade data provided for node `N` with `migrator`, when it has another version than the
+    /// node (see [`crate::NodeDef::version`]). The migrator is given the version of the data
+    /// (`None` if it has none), and the value, and returns the value as it is now.
+    #[must_use]
+    pub fn migrate<N, F>(mut self, migrator: F) -> Self
+    where
+        N: NodeBuilder<S>,
+        F: Fn(Option<u32>, Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.migrators.insert(N::node().id, Arc::new(migrator));
+        self
+    }
+
+    /// Fail the build, rather than log a warning, when provided data is not used: when it does
+    /// not match any node ([`JobError::UnknownData`]), when it is for a node that is not part of
+    /// the job ([`JobError::UnreachableData`]), or when it is for a target, so the target is not
+    /// produced by the job ([`JobError::ProvidedTarget`]).
+    #[must_use]
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Names of every node the builder knows of: the added nodes, their variants, and
+    /// (recursively) their dependencies.
+    fn known_names(&self) -> HashSet<&'static str> {
+        let mut seen = HashSet::new();
+        let mut names = HashSet::new();
+        let mut stack: Vec<_> = (self.targets.iter())
+            .chain(&self.selected)
+            .chain(self.variants.values().flat_map(HashMap::values))
+            .cloned()
+            .collect();
         while let Some(node) = stack.pop() {
-            // If we already have it `data`, then we promote the data item to actual provided data
-            // under its id.
-            if let Some(data) = self.data.remove(node.name) {
-                self.job.provided.insert(node.id, (node.name, data));
+            if seen.insert(node.id) {
+                names.insert(self.names.get(&node.id).copied().unwrap_or(node.name));
+                stack.extend((node.deps)());
+            }
+        }
+        names
+    }
+
+    /// Takes the data provided for `node`, unless the node has to run anyway.
+    fn take_data(&mut self, node: &Node<S>) -> Result<Option<Value>, JobError> {
+        let Some(data) = self.data.remove(node.name) else {
+            return Ok(None);
+        };
+        if self.forced.contains(&node.id) {
+            info!(
+                name = node.name,
+                "Ignoring provided data, since node is forced"
+            );
+            return Ok(None);
+        }
+        if depends_on(node, &self.invalidated) {
+            info!(
+                name = node.name,
+                "Ignoring provided data, since node is invalidated"
+            );
+            return Ok(None);
+        }
+        self.accept(node, data).map(Some)
+    }
+
+    /// Checks data provided for `node`, and takes it out of its version.
+    fn accept(&self, node: &Node<S>, data: Value) -> Result<Value, JobError> {
+        let data = match node.version {
+            Some(version) => match unversioned(data) {
+                (Some(found), data) if found == version => data,
+                (found, data) => {
+                    let Some(migrator) = self.migrators.get(&node.id) else {
+                        return Err(JobError::StaleData(node.name, found, version));
+                    };
+                    info!(name = node.name, ?found, version, "Migrating provided data");
+                    migrator(found, data)
+                        .map_err(|e| JobError::InvalidProvidedData(node.name, e))?
+                }
+            },
+            None => data,
+        };
+        validate(node, &data)?;
+        Ok(data)
+    }
+
+    /// Creates and validates the Job.
+    ///
+    /// # Errors
+    /// If the graph contains any cycles, or if there is a name collision. In a strict build (see
+    /// [`JobBuilder::strict`]), if provided data is not used.
+    pub fn build(mut self) -> Result<Job<S>, JobError> {
+        let known = self.strict.then(|| self.known_names());
+        let mut job = Job {
+            labels: std::mem::take(&mut self.labels),
+            ..Job::default()
+        };
+        let targets = std::mem::take(&mut self.targets);
+        let selected = std::mem::take(&mut self.selected);
+        // Use a stack to recursively add dependencies.
+        let mut stack = if selected.is_empty() {
+            targets
+        } else {
+            let mut registered: HashMap<_, _> =
+                targets.into_iter().map(|node| (node.id, node)).collect();
+            selected
+                .into_iter()
+                .map(|node| registered.remove(&node.id).unwrap_or(node))
+                .collect()
+        };
+        let mut optional = vec![];
+        job.targets.extend(stack.iter().map(|node| node.id));
+        while let Some(mut node) = stack.pop() {
+            node = selected_variant(node, &self.variants, &self.selections)?;
+            if let Some(name) = self.names.get(&node.id) {
+                node.name = name;
+            }
+            if let Some(timeout) = self.timeouts.get(&node.id) {
+                node.timeout = Some(*timeout);
+            }
+            if let Some(priority) = self.priorities.get(&node.id) {
+                node.priority = *priority;
+            }
+            if let Some(version) = node.version {
+                job.versions.insert(node.name, version);
+            }
+            if let Some(data) = self.take_data(&node)? {
+                // If we already have it `data`, then we promote the data item to actual provided
+                // data under its id.
+                job.provided.insert(node.id, (node.name, data));
+                record_pruned(&mut job.pruned, &node);
                 continue;
             }
             // If it was already promoted, then we should just ignore it.
-            if self.job.provided.contains_key(&node.id) {
+            if job.provided.contains_key(&node.id) {
                 continue;
             }
             // Only add node if we don't already have it.
-            if let Entry::Vacant(entry) = self.job.nodes.entry(node.id) {
+            if let Entry::Vacant(entry) = job.nodes.entry(node.id) {
                 let deps = (node.deps)();
-                let dep_ids = deps.iter().map(|n| n.id).collect();
-                self.job.adj.insert(node.id, dep_ids);
-                stack.extend(deps);
+                job.inputs
+                    .insert(node.id, deps.iter().map(|n| n.id).collect());
+                for dep in deps {
+                    if node.optional.contains(&dep.id) {
+                        optional.push(dep);
+                    } else {
+                        stack.push(dep);
+                    }
+                }
                 entry.insert(node);
             }
         }
+        // Optional dependencies are only used if they ended up in the job anyway, or if their
+        // data was provided.
+        for mut node in optional {
+            if job.nodes.contains_key(&node.id) || job.provided.contains_key(&node.id) {
+                continue;
+            }
+            if let Some(name) = self.names.get(&node.id) {
+                node.name = name;
+            }
+            if let Some(data) = self.data.remove(node.name) {
+                let data = self.accept(&node, data)?;
+                job.versions.extend(node.version.map(|v| (node.name, v)));
+                job.provided.insert(node.id, (node.name, data));
+            }
+        }
+        link(&mut job);
+        for name in self.data.keys() {
+            warn!(
+                name,
+                "Did not find {name} from the provided data. Discarding."
+            );
+        }
+        job.discarded = self.data.into_keys().collect();
+        job.discarded.sort();
+        if let Some(known) = known {
+            check_strict(&job, &known)?;
+        }
+        // Some of the pruned nodes may be needed by other nodes after all.
+        job.pruned
+            .retain(|id, _| !job.nodes.contains_key(id) && !job.provided.contains_key(id));
+        for (name, provided) in job.pruned() {
+            info!(
+                name,
+                ?provided,
+                "Skipping producer, since data was provided"
+            );
+        }
+        check(&job)?;
+        Ok(job)
+    }
+
+    /// Adds everything from `other`: its nodes, targets, provided data, labels and settings.
+    /// Where both set the same thing (say, the timeout of a node), `other` wins. Nodes with the
+    /// same name, but different outputs, fail the build with [`JobError::DuplicateName`].
+    #[must_use]
+    pub fn extend(mut self, other: JobBuilder<S>) -> Self {
+        self.data.extend(other.data);
+        self.targets.extend(other.targets);
+        self.selected.extend(other.selected);
+        self.forced.extend(other.forced);
+        self.invalidated.extend(other.invalidated);
+        self.timeouts.extend(other.timeouts);
+        self.priorities.extend(other.priorities);
+        self.labels.extend(other.labels);
+        for (id, variants) in other.variants {
+            self.variants.entry(id).or_default().extend(variants);
+        }
+        self.selections.extend(other.selections);
+        self.migrators.extend(other.migrators);
+        self.names.extend(other.names);
+        self.strict |= other.strict;
         self
     }
+}
 
-    /// Creates and validates the Job.
-    ///
-    /// # Errors
-    /// If the graph contains any cycles, or if there is a name collision.
-    pub fn build(self) -> Result<Job<S>, JobError> {
-        for name in self.data.keys() {
-            warn!("Did not find {name} from the provided data. Discarding.");
+/// Fails if the job has a cycle, or two nodes with the same name.
+fn check<S: State>(job: &Job<S>) -> Result<(), JobError> {
+    if let Some(cycle) = find_cycle(&job.adj) {
+        let names: Vec<_> = cycle.iter().map(|id| job.nodes[id].name).collect();
+        return Err(JobError::Cycle(names));
+    }
+    let mut seen = HashSet::new();
+    for node in job.nodes.values() {
+        if seen.contains(node.name) {
+            return Err(JobError::DuplicateName(node.name));
+        }
+        seen.insert(node.name);
+    }
+    Ok(())
+}
+
+/// Fails if provided data was not used. See [`JobBuilder::strict`]. `known` has the names of all
+/// nodes the builder knew of.
+fn check_strict<S: State>(job: &Job<S>, known: &HashSet<&'static str>) -> Result<(), JobError> {
+    if let Some(name) = job.discarded.first() {
+        return Err(if known.contains(name.as_str()) {
+            JobError::UnreachableData(name.clone())
+        } else {
+            JobError::UnknownData(name.clone())
+        });
+    }
+    let mut provided: Vec<_> = (job.targets.iter())
+        .filter_map(|id| job.provided.get(id))
+        .map(|(name, _)| *name)
+        .collect();
+    provided.sort_unstable();
+    match provided.first() {
+        Some(name) => Err(JobError::ProvidedTarget(name)),
+        None => Ok(()),
+    }
+}
+
+/// Sets the edges the worker waits on: the inputs of each node that are part of the job, and the
+/// nodes it runs after, if they run in this job.
+fn link<S: State>(job: &mut Job<S>) {
+    for (id, inputs) in &job.inputs {
+        let present =
+            |dep: &&NodeId| job.nodes.contains_key(*dep) || job.provided.contains_key(*dep);
+        let mut deps: Vec<_> = inputs.iter().filter(present).copied().collect();
+        let after: Vec<_> = job.nodes[id]
+            .after
+            .iter()
+            .filter(|after| job.nodes.contains_key(*after) && !deps.contains(*after))
+            .copied()
+            .collect();
+        deps.extend(after);
+        job.adj.insert(*id, deps);
+    }
+}
+
+/// Checks `data` provided for `node`, if the node knows how.
+fn validate<S: State>(node: &Node<S>, data: &Value) -> Result<(), JobError> {
+    match node.validate.map(|validate| validate(data)) {
+        Some(Err(error)) => Err(JobError::InvalidProvidedData(node.name, error)),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `node` is one of `ids`, or (recursively) depends on one of them.
+fn depends_on<S: State>(node: &Node<S>, ids: &HashSet<NodeId>) -> bool {
+    if ids.is_empty() {
+        return false;
+    }
+    let mut seen = HashSet::new();
+    let mut stack = vec![node.clone()];
+    while let Some(node) = stack.pop() {
+        if ids.contains(&node.id) {
+            return true;
         }
-        if let Some(cycle) = find_cycle(&self.job.adj) {
-            let names: Vec<_> = cycle.iter().map(|id| self.job.nodes[id].name).collect();
-            return Err(JobError::Cycle(names));
+        if seen.insert(node.id) {
+            stack.extend((node.deps)());
         }
-        let mut seen = HashSet::new();
-        for node in self.job.nodes.values() {
-            if seen.contains(node.name) {
-                return Err(JobError::DuplicateName(node.name));
-            }
-            seen.insert(node.name);
+    }
+    false
+}
+
+/// Records all (recursive) dependencies of a provided node as pruned by it.
+fn record_pruned<S: State>(
+    pruned: &mut HashMap<NodeId, (&'static str, Vec<&'static str>)>,
+    provided: &Node<S>,
+) {
+    let mut seen = HashSet::new();
+    let mut stack = (provided.deps)();
+    while let Some(node) = stack.pop() {
+        if seen.insert(node.id) {
+            let (_, by) = pruned.entry(node.id).or_insert((node.name, vec![]));
+            by.push(provided.name);
+            stack.extend((node.deps)());
         }
-        Ok(self.job)
     }
 }
 
+/// A node in a job, as seen from the outside. Created with [`Job::nodes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub name: &'static str,
+    /// Names of the nodes it depends on, sorted. Empty for provided nodes.
+    pub deps: Vec<&'static str>,
+    /// Data was provided for the node, so it will not run.
+    pub provided: bool,
+    /// The node was added to the job, rather than just being a dependency.
+    pub target: bool,
+}
+
+/// What running a job would do. Created with [`Job::plan`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Plan {
+    /// The nodes that would run, in waves. See [`Job::execution_plan`].
+    pub waves: Vec<Vec<&'static str>>,
+    /// Nodes that would not run, since data was provided for them. Sorted.
+    pub provided: Vec<&'static str>,
+    /// Nodes that would not run, since data was provided for the nodes that need them. See
+    /// [`Job::pruned`].
+    pub pruned: Vec<(&'static str, Vec<&'static str>)>,
+    /// Names in the provided data that did not match any node, so were thrown away. Often a
+    /// typo. Sorted.
+    pub discarded: Vec<String>,
+}
+
+/// Why a node will or won't run in a job. Created with [`Job::explain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Explanation {
+    /// Data was provided for the node, so it will not run.
+    Provided,
+    /// The node will not run, because data was provided for these nodes that depend on it.
+    Pruned { by: Vec<&'static str> },
+    /// The node is not part of the job. None of the targets depend on it.
+    Unreachable,
+    /// The node will run once these dependencies are done. If empty, it starts right away.
+    Pending { waiting_for: Vec<&'static str> },
+}
+
 #[derive(Debug)]
 pub enum JobError {
     Cycle(Vec<&'static str>),
     DuplicateName(&'static str),
+    /// A target of a [`Checkpoint`], a [`crate::manifest::Manifest`] or a [`JobManifest`], was
+    /// not among the nodes given.
+    UnknownTarget(String),
+    /// A node of a [`JobManifest`] was not in the [`NodeRegistry`] given, or a node added with
+    /// `JobBuilder::add_by_name` was never registered.
+    UnknownNode(String),
+    /// The variant selected for a node (see [`JobBuilder::select`]) was never added.
+    UnknownVariant(&'static str, String),
+    /// The data provided for a node can not be turned into its output. Has the name of the node,
+    /// and why.
+    InvalidProvidedData(&'static str, String),
+    /// The data provided for a node has another version than the node, and there is no way to
+    /// migrate it (see [`JobBuilder::migrate`]). Has the name of the node, the version of the
+    /// data, if any, and the version of the node.
+    StaleData(&'static str, Option<u32>, u32),
+    /// In a strict build (see [`JobBuilder::strict`]), data was provided under a name that no
+    /// node has. Often a typo.
+    UnknownData(String),
+    /// In a strict build, data was provided for a node that is not part of the job, say one that
+    /// only a provided node depends on.
+    UnreachableData(String),
+    /// In a strict build, data was provided for a target, so the job does not produce it.
+    ProvidedTarget(&'static str),
 }
 
 impl fmt::Display for JobError {
@@ -135,6 +1008,33 @@ impl fmt::Display for JobError {
             JobError::DuplicateName(name) => {
                 write!(f, "Found two nodes with the same name: {name}")
             }
+            JobError::UnknownTarget(name) => write!(f, "Unknown target: {name}"),
+            JobError::UnknownNode(name) => write!(f, "Unknown node: {name}"),
+            JobError::UnknownVariant(name, variant) => {
+                write!(f, "Unknown variant of {name}: {variant}")
+            }
+            JobError::InvalidProvidedData(name, error) => {
+                write!(f, "Invalid data provided for {name}: {error}")
+            }
+            JobError::StaleData(name, Some(found), version) => {
+                write!(
+                    f,
+                    "Data provided for {name} is version {found}, not {version}"
+                )
+            }
+            JobError::StaleData(name, None, version) => {
+                write!(
+                    f,
+                    "Data provided for {name} has no version, expected {version}"
+                )
+            }
+            JobError::UnknownData(name) => write!(f, "Data provided for unknown node: {name}"),
+            JobError::UnreachableData(name) => {
+                write!(f, "Data provided for {name}, which is not part of the job")
+            }
+            JobError::ProvidedTarget(name) => {
+                write!(f, "Data provided for target {name}, so it is not produced")
+            }
         }
     }
 }
@@ -142,7 +1042,7 @@ impl fmt::Display for JobError {
 impl std::error::Error for JobError {}
 
 #[must_use]
-fn find_cycle<S: BuildHasher>(adj: &HashMap<TypeId, Vec<TypeId>, S>) -> Option<Vec<TypeId>> {
+fn find_cycle<S: BuildHasher>(adj: &HashMap<NodeId, Vec<NodeId>, S>) -> Option<Vec<NodeId>> {
     // Keep track of the nodes: None = not seen, Some(false) = visiting, Some(true) = done.
     let mut state = HashMap::new();
     // Used to build the path if we find a cycle.
@@ -198,17 +1098,40 @@ fn find_cycle<S: BuildHasher>(adj: &HashMap<TypeId, Vec<TypeId>, S>) -> Option<V
     None
 }
 
+/// `node`, or the variant of it that was selected.
+fn selected_variant<S: State>(
+    node: Node<S>,
+    variants: &HashMap<NodeId, HashMap<&'static str, Node<S>>>,
+    selections: &HashMap<NodeId, String>,
+) -> Result<Node<S>, JobError> {
+    let Some(variant) = selections.get(&node.id) else {
+        return Ok(node);
+    };
+    let Some(selected) = variants
+        .get(&node.id)
+        .and_then(|variants| variants.get(variant.as_str()))
+    else {
+        return Err(JobError::UnknownVariant(node.name, variant.clone()));
+    };
+    // Keep the name, so provided data and lookups still find it.
+    Ok(Node {
+        name: node.name,
+        ..selected.clone()
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{any::TypeId, collections::HashMap};
+    use std::collections::HashMap;
 
     use super::find_cycle;
+    use crate::NodeId;
 
     #[test]
     fn can_find_simple_cycle() {
-        let a = TypeId::of::<u16>();
-        let b = TypeId::of::<u32>();
-        let c = TypeId::of::<u64>();
+        let a = NodeId::of::<u16>();
+        let b = NodeId::of::<u32>();
+        let c = NodeId::of::<u64>();
         let mut adj = HashMap::new();
         adj.insert(a, vec![b]);
         adj.insert(b, vec![c]);
@@ -219,9 +1142,9 @@ mod tests {
 
     #[test]
     fn can_not_find_a_not_cycle() {
-        let a = TypeId::of::<u16>();
-        let b = TypeId::of::<u32>();
-        let c = TypeId::of::<u64>();
+        let a = NodeId::of::<u16>();
+        let b = NodeId::of::<u32>();
+        let c = NodeId::of::<u64>();
         let mut adj = HashMap::new();
         adj.insert(a, vec![c]);
         adj.insert(b, vec![c]);
@@ -232,12 +1155,12 @@ mod tests {
 
     #[test]
     fn can_find_cycle() {
-        let node_a = TypeId::of::<u16>();
-        let node_b = TypeId::of::<u32>();
-        let node_c = TypeId::of::<u64>();
-        let node_d = TypeId::of::<i64>();
-        let node_e = TypeId::of::<i64>();
-        let node_f = TypeId::of::<i64>();
+        let node_a = NodeId::of::<u16>();
+        let node_b = NodeId::of::<u32>();
+        let node_c = NodeId::of::<u64>();
+        let node_d = NodeId::of::<i64>();
+        let node_e = NodeId::of::<i64>();
+        let node_f = NodeId::of::<i64>();
         let mut adj = HashMap::new();
         adj.insert(node_a, vec![node_b, node_c, node_f]);
         adj.insert(node_b, vec![node_d]);
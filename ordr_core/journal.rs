@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{Error, Payload};
+
+/// One attempt at running a node, as kept by a [`Recorder`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// The name of the node.
+    pub name: String,
+    /// Which attempt this was. The first one is `0`.
+    pub retry: u32,
+    /// The inputs given to the producer, in order. `None` for inputs that are not serialized
+    /// (transient, raw or streaming), and optional inputs that were missing.
+    pub inputs: Vec<Option<Value>>,
+    /// The output of the producer. `None` if it failed, or the output is not serialized.
+    pub output: Option<Value>,
+    /// Why the producer failed, if it did.
+    pub error: Option<String>,
+    /// How long the producer asked to wait before a retry, if it failed.
+    pub retry_in: Option<Duration>,
+    /// How long the attempt took.
+    pub duration: Duration,
+}
+
+/// Keeps a [`Record`] of every attempt at running a node, so the job can later be run again
+/// from exactly the same data with a [`Replayer`]. Configured with [`crate::Worker::record`].
+///
+/// Clones share the records, so keep one around to look at them once the job is done.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    records: Arc<Mutex<Vec<Record>>>,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything recorded so far, in the order the attempts finished.
+    ///
+    /// # Panics
+    /// If a previous call panicked while holding the lock.
+    #[must_use]
+    pub fn records(&self) -> Vec<Record> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Write the records to `path`, as JSON. Read them back with [`Replayer::open`].
+    ///
+    /// # Errors
+    /// If the file can not be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec(&self.records())?)
+    }
+
+    pub(crate) fn record(
+        &self,
+        name: &str,
+        retry: u32,
+        inputs: Vec<Option<Value>>,
+        result: &Result<Payload, Error>,
+        duration: Duration,
+    ) {
+        let (output, error, retry_in) = match result {
+            Ok(payload) => (payload.to_json(), None, None),
+            Err(e) => (None, Some(e.message.clone()), e.retry_in),
+        };
+        self.records.lock().unwrap().push(Record {
+            name: name.to_string(),
+            retry,
+            inputs,
+            output,
+            error,
+            retry_in,
+            duration,
+        });
+    }
+}
+
+/// Runs a job from the [`Record`]s of an earlier run, instead of running the producers. Every
+/// attempt at a node gets what the same attempt got when it was recorded, so failures and
+/// retries happen the same way. Configured with [`crate::Worker::replay`].
+///
+/// An attempt that was not recorded, or whose output is not serialized, fails the node. If a
+/// node is given other inputs than when it was recorded, a warning is logged.
+#[derive(Debug, Clone, Default)]
+pub struct Replayer {
+    records: Arc<HashMap<(String, u32), Record>>,
+}
+
+impl Replayer {
+    #[must_use]
+    pub fn new(records: impl IntoIterator<Item = Record>) -> Self {
+        let records = records
+            .into_iter()
+            .map(|record| ((record.name.clone(), record.retry), record))
+            .collect();
+        Self {
+            records: Arc::new(records),
+        }
+    }
+
+    /// Read records written by [`Recorder::save`].
+    ///
+    /// # Errors
+    /// If the file can not be read, or does not hold records.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let records: Vec<Record> = serde_json::from_slice(&fs::read(path)?)?;
+        Ok(Self::new(records))
+    }
+
+    pub(crate) fn replay(
+        &self,
+        name: &str,
+        retry: u32,
+        payloads: &[Payload],
+    ) -> Result<Payload, Error> {
+        let Some(record) = self.records.get(&(name.to_string(), retry)) else {
+            return Err(Error::fatal(format!(
+                "Retry {retry} of node {name} was not recorded"
+            )));
+        };
+        let inputs: Vec<_> = payloads.iter().map(Payload::to_json).collect();
+        if inputs != record.inputs {
+            warn!(name, retry, "Inputs differ from the recording");
+        }
+        match (&record.error, record.retry_in, &record.output) {
+            (Some(message), Some(retry_in), _) => Err(Error::with_retry(message, retry_in)),
+            (Some(message), None, _) => Err(Error::fatal(message)),
+            (None, _, Some(value)) => Ok(Payload::Json(Arc::new(value.clone()))),
+            (None, _, None) => Err(Error::fatal(format!(
+                "The output of node {name} was not recorded"
+            ))),
+        }
+    }
+}
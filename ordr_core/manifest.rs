@@ -0,0 +1,226 @@
+//! Run a job described by a JSON file, so services that embed ordr do not each need their own
+//! harness for it. Or save the definition of a job as a [`JobManifest`], say in a queue, and build
+//! it again in another process.
+
+use std::{
+    collections::{BTreeMap, HashMap, hash_map::Entry},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use crate::{Job, JobError, Node, NodeBuilder, Output, State, Worker};
+
+/// What to run, and how. Usually read from a file with [`Manifest::open`]:
+///
+/// ```json
+/// {
+///     "targets": ["Report"],
+///     "data": "input.json",
+///     "output": "output.json",
+///     "timeout_secs": 60,
+///     "max_concurrency": 4,
+///     "labels": { "tenant": "acme" }
+/// }
+/// ```
+///
+/// Everything but `targets` can be left out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    /// The names of the nodes to solve for. Looked up among the nodes given to [`run_from_manifest`].
+    pub targets: Vec<String>,
+    /// A JSON file with the provided data, as an object keyed by node name (see
+    /// [`Job::builder_with_data`]).
+    pub data: Option<PathBuf>,
+    /// Where to write the data collected from the job, in the same format as `data`.
+    pub output: Option<PathBuf>,
+    /// Stop the job if it has not finished within this many seconds (see
+    /// [`Worker::run_with_timeout`]).
+    pub timeout_secs: Option<f64>,
+    /// See [`Worker::max_concurrency`].
+    pub max_concurrency: Option<usize>,
+    /// See [`crate::JobBuilder::label`].
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Read a manifest from a JSON file. Relative paths in it are relative to the directory of
+    /// the file.
+    ///
+    /// # Errors
+    /// If the file can not be read, or is not a manifest.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut manifest: Self = serde_json::from_slice(&fs::read(path)?)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for file in [&mut manifest.data, &mut manifest.output]
+            .into_iter()
+            .flatten()
+        {
+            *file = dir.join(&*file);
+        }
+        Ok(manifest)
+    }
+}
+
+/// The definition of a job: what it solves for, and the data it was given. Created with
+/// [`Job::to_manifest`], and turned back into a job with [`Job::from_manifest`].
+///
+/// Settings like timeouts, priorities and selected variants are not part of it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobManifest {
+    /// Names of the nodes the job runs. Sorted.
+    pub nodes: Vec<String>,
+    /// Names of the nodes the job solves for. Sorted.
+    pub targets: Vec<String>,
+    /// Values of the provided nodes, by name. Versioned, for nodes that have a version.
+    pub provided: BTreeMap<String, Value>,
+    /// See [`crate::JobBuilder::label`].
+    pub labels: BTreeMap<String, String>,
+}
+
+/// The nodes a [`JobManifest`] can be built from, by name. Registering a node registers its
+/// dependencies as well.
+#[derive(Clone)]
+pub struct NodeRegistry<S: State> {
+    nodes: HashMap<&'static str, Node<S>>,
+}
+
+impl<S: State> Default for NodeRegistry<S> {
+    fn default() -> Self {
+        Self {
+            nodes: HashMap::new(),
+        }
+    }
+}
+
+impl<S: State> NodeRegistry<S> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every node the macros registered with the `registry` feature, for this state.
+    #[cfg(feature = "registry")]
+    #[must_use]
+    pub fn registered() -> Self {
+        crate::registry::registered_nodes().fold(Self::new(), Self::register_node)
+    }
+
+    /// Registers node `N`, and (recursively) its dependencies.
+    #[must_use]
+    pub fn register<N: NodeBuilder<S>>(self) -> Self {
+        self.register_node(N::node())
+    }
+
+    /// Like [`NodeRegistry::register`], for a node created at runtime. A node registered under
+    /// a name that is already taken replaces the old one, but not its dependencies.
+    #[must_use]
+    pub fn register_node(mut self, node: Node<S>) -> Self {
+        let mut stack = (node.deps)();
+        self.nodes.insert(node.name, node);
+        while let Some(node) = stack.pop() {
+            if let Entry::Vacant(entry) = self.nodes.entry(node.name) {
+                stack.extend((node.deps)());
+                entry.insert(node);
+            }
+        }
+        self
+    }
+
+    /// The node called `name`, if it is registered.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Node<S>> {
+        self.nodes.get(name)
+    }
+}
+
+/// Why [`run_from_manifest`] could not run the job.
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The provided data could not be read, or the output could not be written.
+    Io(io::Error),
+    /// The job could not be built. A target that is not among the nodes given is a
+    /// [`JobError::UnknownTarget`].
+    Job(JobError),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(e) => write!(f, "{e}"),
+            ManifestError::Job(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+impl From<io::Error> for ManifestError {
+    fn from(e: io::Error) -> Self {
+        ManifestError::Io(e)
+    }
+}
+
+impl From<JobError> for ManifestError {
+    fn from(e: JobError) -> Self {
+        ManifestError::Job(e)
+    }
+}
+
+/// Runs the job described by `manifest`, with `state`, and returns its output once it is done.
+/// The targets are looked up by name in `nodes`, along the lines of
+/// [`Job::builder_from_checkpoint`]. If the manifest has an `output`, the data collected from
+/// the job is written there, however the job went.
+///
+/// # Errors
+/// If the provided data can not be read, the job can not be built, or the output can not be
+/// written.
+///
+/// # Panics
+/// If `max_concurrency` is `0`, or `timeout_secs` is negative.
+pub async fn run_from_manifest<S: State>(
+    manifest: &Manifest,
+    nodes: &[Node<S>],
+    state: S,
+) -> Result<Output, ManifestError> {
+    let data: HashMap<String, Value> = match &manifest.data {
+        Some(path) => serde_json::from_slice(&fs::read(path)?).map_err(io::Error::from)?,
+        None => HashMap::new(),
+    };
+    let mut builder = Job::builder_with_data(data);
+    for target in &manifest.targets {
+        let Some(node) = nodes.iter().find(|node| node.name == target) else {
+            return Err(JobError::UnknownTarget(target.clone()).into());
+        };
+        builder = builder.add_node(node.clone());
+    }
+    for (key, value) in &manifest.labels {
+        builder = builder.label(key, value);
+    }
+    let mut worker = Worker::new(builder.build()?, state);
+    if let Some(max_concurrency) = manifest.max_concurrency {
+        worker = worker.max_concurrency(max_concurrency);
+    }
+    match manifest.timeout_secs {
+        Some(secs) => worker.run_with_timeout(Duration::from_secs_f64(secs)).await,
+        None => worker.run().await,
+    }
+    .expect("Worker was just created");
+    let output = worker.get_output().await.expect("Worker is running");
+    if let Some(path) = &manifest.output {
+        let data: BTreeMap<_, _> = worker.data().await.into_iter().collect();
+        fs::write(
+            path,
+            serde_json::to_vec_pretty(&data).map_err(io::Error::from)?,
+        )?;
+        info!(?path, "Wrote output");
+    }
+    Ok(output)
+}
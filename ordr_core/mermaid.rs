@@ -1,25 +1,101 @@
 use std::collections::HashMap;
 
-use crate::{Job, State};
+use crate::{Job, JobReport, NodeId, NodeStatus, State};
 
 /// Builds a simple mermaid diagram of the nodes that will be executed when running this job.
+///
+/// Nodes are given a class depending on their role: `target` for the nodes that were added to the
+/// job, `provided` for the nodes that will not run because data was provided for them, and
+/// `pending` for the rest (dependencies that will run).
+///
+/// The output is stable: provided nodes come first, then the rest in topological order
+/// (dependencies first), sorted by name within each level. So the same job always gives the same
+/// diagram.
 #[must_use]
 pub fn mermaid<S: State>(job: &Job<S>) -> String {
-    let idx: HashMap<_, _> = job.adj.keys().enumerate().map(|(i, id)| (*id, i)).collect();
-    let n = |id| format!("n{}", idx[id]);
+    let mut provided: Vec<_> = job.provided.keys().copied().collect();
+    provided.sort_by_key(|id| job.provided[id].0);
+    let order: Vec<_> = provided
+        .iter()
+        .copied()
+        .chain(job.levels().into_iter().flatten())
+        .collect();
+    let idx: HashMap<_, _> = order.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let n = |id: &NodeId| format!("n{}", idx[id]);
+    let name = |id| job.provided.get(id).map_or_else(|| job.name(id), |p| p.0);
     let mut lines = vec!["flowchart LR".into()];
 
-    for id in job.adj.keys() {
-        lines.push(format!("{}[{}]", n(id), job.name(id)));
+    for id in &order {
+        lines.push(format!("{}[{}]", n(id), name(id)));
     }
 
-    for (id, deps) in &job.adj {
+    for (id, deps) in order.iter().filter_map(|id| Some((id, job.adj.get(id)?))) {
         if deps.is_empty() {
             continue;
         }
+        let mut deps = deps.clone();
+        deps.sort_by_key(|id| idx[id]);
         let deps = deps.iter().map(n).collect::<Vec<_>>().join(" & ");
         lines.push(format!("{deps} --> {}", n(id)));
     }
 
+    lines.push("classDef target stroke-width:3px".into());
+    lines.push("classDef provided fill:#eee,stroke-dasharray:5 5".into());
+    lines.push("classDef pending fill:#fff".into());
+    for id in &order {
+        let class = if job.provided.contains_key(id) {
+            "provided"
+        } else if job.targets.contains(id) {
+            "target"
+        } else {
+            "pending"
+        };
+        lines.push(format!("class {} {class}", n(id)));
+    }
+
+    lines.join("\n    ")
+}
+
+/// Builds a mermaid `gantt` diagram of a job that has run, from its [`JobReport`], with a bar for
+/// each node that ran, from when it started to when it finished. Shows how much ran in parallel,
+/// and where the job stalled.
+///
+/// Times are in milliseconds from the start of the job. Nodes on the
+/// [`JobReport::critical_path`] are marked `crit`, and nodes that are done `done`. Nodes that were
+/// provided, or never finished, are left out.
+#[must_use]
+pub fn mermaid_gantt(report: &JobReport) -> String {
+    let critical: Vec<_> = report
+        .critical_path()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    let mut bars: Vec<_> = report
+        .nodes
+        .iter()
+        .filter_map(|(name, node)| Some((node.start?, node.end?, name, node)))
+        .collect();
+    bars.sort_by_key(|(start, end, name, _)| (*start, *end, *name));
+    let mut lines = vec![
+        "gantt".to_string(),
+        format!("title Job {}", report.job_id),
+        "dateFormat x".into(),
+        "axisFormat %M:%S.%L".into(),
+    ];
+    for (start, end, name, node) in bars {
+        let mut tags = String::new();
+        if critical.contains(&name.as_str()) {
+            tags.push_str("crit, ");
+        }
+        if node.state == NodeStatus::Done {
+            tags.push_str("done, ");
+        }
+        let start = start.as_millis();
+        // Bars that take no time at all are not drawn.
+        let end = end.as_millis().max(start + 1);
+        // A colon would end the name of the task.
+        let name = name.replace(':', "#58;");
+        lines.push(format!("{name} :{tags}{start}, {end}"));
+    }
     lines.join("\n    ")
 }
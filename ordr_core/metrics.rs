@@ -0,0 +1,27 @@
+//! Exports what the nodes do through the `metrics` crate. Every metric has a `job` label (see
+//! [`crate::Worker::label`]) and a `node` label with the name of the node:
+//!
+//! * `ordr_node_started_total`: Nodes started (not counting retries).
+//! * `ordr_node_retries_total`: Nodes retried, or set to be retried when draining.
+//! * `ordr_node_done_total`: Nodes that finished successfully.
+//! * `ordr_node_failures_total`: Nodes that failed, and were not retried.
+//! * `ordr_node_duration_seconds`: Histogram of how long successful nodes took.
+
+use metrics::{counter, histogram};
+
+use crate::NodeState;
+
+/// Records a node changing to `state`.
+pub(crate) fn record(job: &str, node: &'static str, state: &NodeState) {
+    let labels = [("job", job.to_string()), ("node", node.to_string())];
+    match state {
+        NodeState::Provided { .. } | NodeState::Blocked { .. } => {}
+        NodeState::Running { .. } => counter!("ordr_node_started_total", &labels).increment(1),
+        NodeState::Retrying { .. } => counter!("ordr_node_retries_total", &labels).increment(1),
+        NodeState::Done { duration, .. } => {
+            counter!("ordr_node_done_total", &labels).increment(1);
+            histogram!("ordr_node_duration_seconds", &labels).record(duration.as_secs_f64());
+        }
+        NodeState::Failed { .. } => counter!("ordr_node_failures_total", &labels).increment(1),
+    }
+}
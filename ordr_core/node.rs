@@ -0,0 +1,681 @@
+use std::{
+    any::Any, collections::HashSet, marker::PhantomData, pin::Pin, sync::Arc, time::Duration,
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{
+    Check, Context, Emitter, Error, Format, Node, NodeBuilder, NodeId, Payload, Requirement,
+    Result, RetryPolicy, State, Stream, Validator,
+    rt::{self, Tasks},
+    stream,
+};
+
+impl<S: State> Node<S> {
+    /// Define a node at runtime, without the `producer` macro. `T` is the output of the node,
+    /// and like with the macro, it is what identifies the node.
+    #[must_use]
+    pub fn builder<T>(name: &'static str) -> NodeDef<S, T, ()> {
+        NodeDef {
+            name,
+            deps: vec![],
+            after: HashSet::new(),
+            timeout: None,
+            max_retries: None,
+            blocking: false,
+            retry_policy: None,
+            priority: 0,
+            variant: None,
+            validate: None,
+            exclusive: None,
+            version: None,
+            rate: None,
+            requires: vec![],
+            _types: PhantomData,
+        }
+    }
+}
+
+/// Defines a [`Node`] at runtime. Created with [`Node::builder`].
+///
+/// `T` is the output of the node, and `D` is a tuple of the dependencies added so far.
+pub struct NodeDef<S: State, T, D> {
+    name: &'static str,
+    deps: Vec<Dep<S>>,
+    after: HashSet<NodeId>,
+    timeout: Option<Duration>,
+    max_retries: Option<u32>,
+    blocking: bool,
+    retry_policy: Option<RetryPolicy>,
+    priority: i32,
+    variant: Option<&'static str>,
+    validate: Option<Validator>,
+    exclusive: Option<&'static str>,
+    version: Option<u32>,
+    rate: Option<&'static str>,
+    requires: Vec<Requirement<S>>,
+    _types: PhantomData<fn() -> (T, D)>,
+}
+
+/// A dependency of a node defined at runtime.
+struct Dep<S: State> {
+    id: NodeId,
+    /// Only used if it is part of the job anyway.
+    optional: bool,
+    /// Creates the dependency. Only called when the job is built, so cycles can be found.
+    node: Arc<dyn Fn() -> Node<S> + Send + Sync + 'static>,
+    /// Turns the output of the dependency into its type, or describes why it could not.
+    decode: fn(Payload) -> std::result::Result<Box<dyn Any + Send>, String>,
+}
+
+impl<S: State, T, D> NodeDef<S, T, D> {
+    /// Add a dependency on a node created with the `producer` macro. Its value is passed
+    /// to the producer as the next element of the tuple.
+    #[must_use]
+    pub fn dep<A>(self) -> NodeDef<S, T, D::Out>
+    where
+        A: NodeBuilder<S> + Send + 'static,
+        D: Append<A>,
+    {
+        self.push(Dep {
+            id: NodeId::of::<A>(),
+            optional: false,
+            node: Arc::new(A::node),
+            decode: |payload| Ok(Box::new(A::try_decode(payload)?)),
+        })
+    }
+
+    /// Add an optional dependency on a node created with the `producer` macro. The producer gets
+    /// `Some` value if the node is part of the job anyway (because something else needs it, or
+    /// its data was provided), and `None` otherwise. It is never run just for this node.
+    #[must_use]
+    pub fn optional_dep<A>(self) -> NodeDef<S, T, D::Out>
+    where
+        A: NodeBuilder<S> + Send + 'static,
+        D: Append<Option<A>>,
+    {
+        self.push(Dep {
+            id: NodeId::of::<A>(),
+            optional: true,
+            node: Arc::new(A::node),
+            decode: |payload| match payload {
+                Payload::Missing => Ok(Box::new(None::<A>)),
+                payload => Ok(Box::new(Some(A::try_decode(payload)?))),
+            },
+        })
+    }
+
+    /// Like [`NodeDef::dep`], but if the value can not be deserialized (say, it was saved by an
+    /// older version of the node), the producer gets `A::default()` instead of failing.
+    #[must_use]
+    pub fn lenient_dep<A>(self) -> NodeDef<S, T, D::Out>
+    where
+        A: NodeBuilder<S> + Default + Send + 'static,
+        D: Append<A>,
+    {
+        self.push(Dep {
+            id: NodeId::of::<A>(),
+            optional: false,
+            node: Arc::new(A::node),
+            decode: |payload| Ok(Box::new(lenient::<S, A>(payload).unwrap_or_default())),
+        })
+    }
+
+    /// Like [`NodeDef::optional_dep`], but the producer also gets `None` if the value can not be
+    /// deserialized, instead of failing.
+    #[must_use]
+    pub fn lenient_optional_dep<A>(self) -> NodeDef<S, T, D::Out>
+    where
+        A: NodeBuilder<S> + Send + 'static,
+        D: Append<Option<A>>,
+    {
+        self.push(Dep {
+            id: NodeId::of::<A>(),
+            optional: true,
+            node: Arc::new(A::node),
+            decode: |payload| match payload {
+                Payload::Missing => Ok(Box::new(None::<A>)),
+                payload => Ok(Box::new(lenient::<S, A>(payload))),
+            },
+        })
+    }
+
+    /// Add a dependency on a node created with the `producer` macro, which is passed to the
+    /// producer as an `Arc`. Transient outputs are then shared between dependents, instead of
+    /// cloned for each of them. See [`NodeBuilder::try_decode_shared`].
+    #[must_use]
+    pub fn shared_dep<A>(self) -> NodeDef<S, T, D::Out>
+    where
+        A: NodeBuilder<S> + Send + Sync + 'static,
+        D: Append<Arc<A>>,
+    {
+        self.push(Dep {
+            id: NodeId::of::<A>(),
+            optional: false,
+            node: Arc::new(A::node),
+            decode: |payload| Ok(Box::new(A::try_decode_shared(payload)?)),
+        })
+    }
+
+    /// Add a dependency on a node that was defined at runtime.
+    #[must_use]
+    pub fn dep_on<A>(self, node: Node<S>) -> NodeDef<S, T, D::Out>
+    where
+        A: DeserializeOwned + Clone + Send + 'static,
+        D: Append<A>,
+    {
+        self.push(Dep {
+            id: node.id,
+            optional: false,
+            node: Arc::new(move || node.clone()),
+            decode: |payload| match payload {
+                Payload::Transient(_) => Ok(Box::new(payload.from_transient::<A>())),
+                _ => Ok(Box::new(payload.try_deserialize::<A>()?)),
+            },
+        })
+    }
+
+    /// Add a dependency on a streaming node (see [`NodeDef::stream_producer`]). The producer gets
+    /// the [`Stream`] to read from, before the streaming node is done.
+    #[must_use]
+    pub fn stream_dep_on<I>(self, node: Node<S>) -> NodeDef<S, T, D::Out>
+    where
+        I: Send + 'static,
+        D: Append<Stream<I>>,
+    {
+        self.push(Dep {
+            id: node.id,
+            optional: false,
+            node: Arc::new(move || node.clone()),
+            decode: |payload| Ok(Box::new(payload.from_transient::<Stream<I>>())),
+        })
+    }
+
+    /// Add a dependency on a raw node that was defined at runtime (see
+    /// [`NodeDef::raw_producer`]).
+    #[must_use]
+    pub fn raw_dep_on<A>(self, node: Node<S>) -> NodeDef<S, T, D::Out>
+    where
+        A: From<Vec<u8>> + Send + 'static,
+        D: Append<A>,
+    {
+        self.push(Dep {
+            id: node.id,
+            optional: false,
+            node: Arc::new(move || node.clone()),
+            decode: |payload| Ok(Box::new(payload.from_raw::<A>())),
+        })
+    }
+
+    /// Run the node after node `A`, if `A` is part of the job. Unlike a dependency, `A` is not
+    /// added to the job for this node, and its output is not passed to the producer. Meant for
+    /// ordering side effects, like sending a notification after an upload.
+    #[must_use]
+    pub fn after<A: NodeBuilder<S>>(self) -> Self {
+        self.after_node(&A::node())
+    }
+
+    /// Like [`NodeDef::after`], but for a node that was defined at runtime.
+    #[must_use]
+    pub fn after_node(mut self, node: &Node<S>) -> Self {
+        self.after.insert(node.id);
+        self
+    }
+
+    /// Fail the node if a single attempt takes longer than `timeout`. See [`Node::timeout`].
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retry the node at most `max_retries` times. See [`Node::max_retries`].
+    #[must_use]
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// The producer does heavy CPU work, so run it away from the other nodes. See
+    /// [`Node::blocking`].
+    #[must_use]
+    pub fn blocking(mut self) -> Self {
+        self.blocking = true;
+        self
+    }
+
+    /// Decide how long to wait between retries with `policy`. See [`RetryPolicy`].
+    #[must_use]
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Start the node before other ready nodes with a lower priority. See [`Node::priority`].
+    #[must_use]
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Make the node an alternative producer for `T`, called `name`. Register it with
+    /// [`crate::JobBuilder::add_variant`], and pick it with [`crate::JobBuilder::select`].
+    #[must_use]
+    pub fn variant(mut self, name: &'static str) -> Self {
+        self.variant = Some(name);
+        self
+    }
+
+    /// Check that data provided for the node can be deserialized into `T`, when the job is
+    /// built, instead of failing once a dependent runs. The `producer` macro does this for every
+    /// node that is serialized.
+    #[must_use]
+    pub fn validate_data(mut self) -> Self
+    where
+        T: DeserializeOwned,
+    {
+        self.validate = Some(|value| {
+            T::deserialize(value)
+                .map(drop)
+                .map_err(|error| error.to_string())
+        });
+        self
+    }
+
+    /// Never run the node at the same time as other nodes using `resource`, say `"gpu"`. See
+    /// [`Node::exclusive`].
+    #[must_use]
+    pub fn exclusive(mut self, resource: &'static str) -> Self {
+        self.exclusive = Some(resource);
+        self
+    }
+
+    /// Set the version of the shape of the output. Bump it when the output changes in a way
+    /// that old values no longer fit. Values in [`crate::Worker::data`] are then stored as
+    /// `{"__v": version, "value": ...}`, and provided data of another version is rejected,
+    /// unless a migration is registered with [`crate::JobBuilder::migrate`].
+    #[must_use]
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Tag the node as using a rate limited resource, say `"openai"`. Nodes with the tag are
+    /// started no faster than the limit set with [`crate::Worker::rate_limit`] allows. Without a
+    /// limit for the tag, it does nothing.
+    #[must_use]
+    pub fn rate(mut self, tag: &'static str) -> Self {
+        self.rate = Some(tag);
+        self
+    }
+
+    /// Require something of the environment, like a credential. `check` is run with the state
+    /// once when the job starts, and if it fails, the job does not run at all (see
+    /// [`crate::Output::Unmet`]). Nodes that require the same `name` share a single check.
+    #[must_use]
+    pub fn requires<F, Fut>(mut self, name: &'static str, check: F) -> Self
+    where
+        F: Fn(S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let check: Check<S> = Arc::new(move |state| Box::pin(check(state)));
+        self.requires.push(Requirement { name, check });
+        self
+    }
+
+    fn push<D2>(mut self, dep: Dep<S>) -> NodeDef<S, T, D2> {
+        self.deps.push(dep);
+        NodeDef {
+            name: self.name,
+            deps: self.deps,
+            after: self.after,
+            timeout: self.timeout,
+            max_retries: self.max_retries,
+            blocking: self.blocking,
+            retry_policy: self.retry_policy,
+            priority: self.priority,
+            variant: self.variant,
+            validate: self.validate,
+            exclusive: self.exclusive,
+            version: self.version,
+            rate: self.rate,
+            requires: self.requires,
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<S: State, T: Send + 'static, D: Deps> NodeDef<S, T, D> {
+    /// Set the producer, and create the node. The producer is given the context and a tuple of
+    /// the dependencies. It can be a closure that captures whatever it needs (clients, config,
+    /// etc.), as long as it can be shared between threads.
+    ///
+    /// # Panics
+    /// The producer panics if its output can not be serialized.
+    pub fn producer<F, Fut>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Serialize,
+    {
+        self.build(f, false, |value, format| format.try_serialize(&value))
+    }
+
+    /// Like [`NodeDef::producer`], but every run gets its own clone of `captured`. Handy for
+    /// producers that need a client or some config, since the returned future can then own it.
+    ///
+    /// # Panics
+    /// The producer panics if its output can not be serialized.
+    pub fn producer_with<C, F, Fut>(self, captured: C, f: F) -> Node<S>
+    where
+        C: Clone + Send + Sync + 'static,
+        F: Fn(C, Context<S>, D) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Serialize,
+    {
+        self.producer(move |context, deps| f(captured.clone(), context, deps))
+    }
+
+    /// Like [`NodeDef::producer`], but the output is only kept in memory, and never serialized.
+    pub fn transient_producer<F, Fut>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Sync,
+    {
+        self.build(f, true, |value, _| Ok(Payload::Transient(Arc::new(value))))
+    }
+
+    /// Like [`NodeDef::producer`], but the output is kept as the bytes it turns into, and is
+    /// never serialized. Meant for large binary outputs, like images or archives. Raw outputs
+    /// are not part of [`crate::Worker::data`], snapshots or caches.
+    pub fn raw_producer<F, Fut>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Into<Vec<u8>>,
+    {
+        self.build(f, false, |value, _| {
+            Ok(Payload::Raw(Arc::from(value.into())))
+        })
+    }
+
+    /// Like [`NodeDef::producer`], but for nodes that only mark that something was done (say,
+    /// `DocUploaded`), so the output does not have to be serializable. It is kept as `null`, and
+    /// any value is accepted when provided. Dependents get it from [`NodeBuilder::try_decode`],
+    /// which the `producer` macro implements by creating the (unit) struct.
+    pub fn marker_producer<F, Fut>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        self.build(f, false, |_, _| Ok(Payload::Json(Arc::new(Value::Null))))
+    }
+
+    /// Like [`NodeDef::producer`], but for a plain function, that is run on the blocking threads
+    /// of the runtime (tokio's blocking thread pool, by default), so CPU heavy work doesn't hold
+    /// up other nodes.
+    ///
+    /// # Panics
+    /// The producer panics if its output can not be serialized.
+    pub fn blocking_producer<F>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Result<T> + Send + Sync + 'static,
+        T: Serialize,
+    {
+        self.producer(blocking(f))
+    }
+
+    /// Like [`NodeDef::blocking_producer`], but the output is only kept in memory, and never
+    /// serialized.
+    pub fn transient_blocking_producer<F>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Result<T> + Send + Sync + 'static,
+        T: Sync,
+    {
+        self.transient_producer(blocking(f))
+    }
+
+    /// Like [`NodeDef::blocking_producer`], but for a marker. See [`NodeDef::marker_producer`].
+    pub fn marker_blocking_producer<F>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Result<T> + Send + Sync + 'static,
+    {
+        self.marker_producer(blocking(f))
+    }
+
+    /// Like [`NodeDef::blocking_producer`], but the output is kept as bytes. See
+    /// [`NodeDef::raw_producer`].
+    pub fn raw_blocking_producer<F>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Result<T> + Send + Sync + 'static,
+        T: Into<Vec<u8>>,
+    {
+        self.raw_producer(blocking(f))
+    }
+
+    fn build<O: 'static, F, Fut>(
+        self,
+        f: F,
+        transient: bool,
+        encode: fn(O, Format) -> std::result::Result<Payload, String>,
+    ) -> Node<S>
+    where
+        F: Fn(Context<S>, D) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O>> + Send + 'static,
+    {
+        let optional = self
+            .deps
+            .iter()
+            .filter(|d| d.optional)
+            .map(|d| d.id)
+            .collect();
+        let (nodes, decoders): (Vec<_>, Vec<_>) =
+            self.deps.into_iter().map(|d| (d.node, d.decode)).unzip();
+        let dep_nodes = nodes.clone();
+        let name = self.name;
+        Node {
+            name: self.name,
+            id: NodeId::of::<T>(),
+            deps: Arc::new(move || nodes.iter().map(|node| node()).collect()),
+            optional,
+            after: self.after,
+            producer: Arc::new(move |context, payloads| {
+                let values = payloads
+                    .into_iter()
+                    .zip(&decoders)
+                    .zip(&dep_nodes)
+                    .map(|((payload, decode), node)| {
+                        decode(payload).map_err(|e| Error::serde(node().name, e))
+                    })
+                    .collect::<Result<_>>();
+                let values = match values {
+                    Ok(values) => values,
+                    Err(e) => return Box::pin(std::future::ready(Err(e))),
+                };
+                let format = context.format;
+                let fut = f(context, D::from_values(values));
+                Box::pin(async move {
+                    let value = fut.await?;
+                    encode(value, format).map_err(|e| Error::serde(name, e))
+                })
+            }),
+            transient,
+            timeout: self.timeout,
+            max_retries: self.max_retries,
+            blocking: self.blocking,
+            retry_policy: self.retry_policy,
+            priority: self.priority,
+            variant: self.variant,
+            validate: self.validate,
+            exclusive: self.exclusive,
+            version: self.version,
+            rate: self.rate,
+            requires: self.requires,
+        }
+    }
+}
+
+impl<S: State, I: Send + 'static, D: Deps> NodeDef<S, Stream<I>, D> {
+    /// Set a producer that emits its output as a stream of items. Unlike other nodes, its
+    /// dependents start as soon as the producer does, and read the items (see [`Stream::next`])
+    /// as they are emitted, so they never have to be held in memory all at once.
+    ///
+    /// The node is done once the producer returns. If it fails, the job fails. It is never
+    /// retried, since the items it emitted have already been read. A timeout on the node only
+    /// covers getting the producer started.
+    pub fn stream_producer<F, Fut>(self, f: F) -> Node<S>
+    where
+        F: Fn(Context<S>, D, Emitter<I>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let producer = move |context, deps| {
+            let (emitter, stream) = stream::channel::<I>();
+            let rest = f(context, deps, emitter);
+            async move { Ok((stream, rest)) }
+        };
+        self.build(producer, true, |(stream, rest), _| {
+            let rest = Arc::new(std::sync::Mutex::new(Some(Box::pin(rest) as _)));
+            Ok(Payload::Streaming(Arc::new(stream), rest))
+        })
+    }
+}
+
+impl<S: State, T: Send + 'static, Items: Send + 'static> NodeDef<S, T, (Items,)> {
+    /// Set a producer that runs once for every item of the single dependency, in parallel, and
+    /// collects the results into the output of the node. If any of them fail, the node fails
+    /// (and is retried as a whole, if asked to).
+    ///
+    /// # Panics
+    /// If one of the runs panics, the node panics.
+    pub fn map_producer<F, Fut, O>(self, f: F) -> Node<S>
+    where
+        Items: IntoIterator,
+        Items::Item: Send + 'static,
+        F: Fn(Context<S>, Items::Item) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O>> + Send + 'static,
+        O: Send + 'static,
+        T: FromIterator<O> + Serialize,
+    {
+        self.producer(move |context, (items,)| {
+            let runs: Vec<_> = items
+                .into_iter()
+                .map(|item| f(context.clone(), item))
+                .collect();
+            async move {
+                let mut set = Tasks::new();
+                for (i, run) in runs.into_iter().enumerate() {
+                    set.spawn(async move { (i, run.await) });
+                }
+                let mut outputs = Vec::with_capacity(set.len());
+                while let Some((i, output)) = set.next().await {
+                    // Returning drops the rest of the runs, which aborts them.
+                    outputs.push((i, output?));
+                }
+                outputs.sort_by_key(|(i, _)| *i);
+                Ok(outputs.into_iter().map(|(_, output)| output).collect())
+            }
+        })
+    }
+}
+
+impl<S: State, T: Send + 'static> NodeDef<S, T, ()> {
+    /// Create the node as one of several outputs of a single producer. `whole` is a transient
+    /// node whose output holds them all (say, a tuple `(A, B)`), and `part` picks this node's
+    /// output out of it. The producer of `whole` then runs once, however many of its outputs the
+    /// job needs. The `producer` macro does this for producers that return a tuple.
+    ///
+    /// The settings of the node (timeout, retries and so on) belong on `whole`, since that is
+    /// where the work is done.
+    pub fn output_of<W>(self, whole: Node<S>, part: fn(&W) -> &T) -> Node<S>
+    where
+        W: Send + Sync + 'static,
+        T: Serialize,
+    {
+        let name = self.name;
+        let def = self.push::<(Arc<W>,)>(Dep {
+            id: whole.id,
+            optional: false,
+            node: Arc::new(move || whole.clone()),
+            decode: |payload| Ok(Box::new(payload.from_transient_shared::<W>())),
+        });
+        // Serializes the part where it is, since the whole is shared with the other parts.
+        let producer = move |context: Context<S>, (whole,): (Arc<W>,)| {
+            let payload = context.format.try_serialize(part(&whole));
+            std::future::ready(payload.map_err(|e| Error::serde(name, e)))
+        };
+        def.build(producer, false, |payload, _| Ok(payload))
+    }
+}
+
+/// Turns a plain function into a producer that runs it on the blocking thread pool (or in place,
+/// on wasm).
+fn blocking<S: State, D: Deps, T: Send + 'static, F>(
+    f: F,
+) -> impl Fn(Context<S>, D) -> Pin<Box<dyn Future<Output = Result<T>> + Send>> + Send + Sync + 'static
+where
+    F: Fn(Context<S>, D) -> Result<T> + Send + Sync + 'static,
+{
+    let f = Arc::new(f);
+    move |context, deps| {
+        let f = f.clone();
+        Box::pin(rt::run_blocking(move || f(context, deps)))
+    }
+}
+
+/// Decodes the value of a lenient dependency, or logs why it could not.
+fn lenient<S: State, A: NodeBuilder<S>>(payload: Payload) -> Option<A> {
+    A::try_decode(payload)
+        .inspect_err(|error| {
+            let name = A::node().name;
+            warn!(
+                name,
+                error, "Could not decode dependency, passing it on as missing"
+            );
+        })
+        .ok()
+}
+
+/// A tuple of dependencies, as given to a producer defined with [`Node::builder`].
+#[doc(hidden)]
+pub trait Deps: Send + 'static {
+    fn from_values(values: Vec<Box<dyn Any + Send>>) -> Self;
+}
+
+/// Adds a type to the end of a tuple.
+#[doc(hidden)]
+pub trait Append<A> {
+    type Out;
+}
+
+macro_rules! tuple {
+    ($($ty:ident)*) => {
+        impl<$($ty: Send + 'static),*> Deps for ($($ty,)*) {
+            #[allow(unused_variables, unused_mut, clippy::unused_unit)]
+            fn from_values(values: Vec<Box<dyn Any + Send>>) -> Self {
+                let mut values = values.into_iter();
+                ($(*values.next().unwrap().downcast::<$ty>().unwrap(),)*)
+            }
+        }
+
+        impl<$($ty,)* Z> Append<Z> for ($($ty,)*) {
+            type Out = ($($ty,)* Z,);
+        }
+    };
+}
+
+tuple!();
+tuple!(A);
+tuple!(A B);
+tuple!(A B C);
+tuple!(A B C D);
+tuple!(A B C D E);
+tuple!(A B C D E F);
+tuple!(A B C D E F G);
+tuple!(A B C D E F G H);
+tuple!(A B C D E F G H I);
+tuple!(A B C D E F G H I J);
+tuple!(A B C D E F G H I J K);
+tuple!(A B C D E F G H I J K L);
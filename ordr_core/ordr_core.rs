@@ -1,14 +1,103 @@
 pub use serde;
 pub use serde_json;
+pub use tokio_util::sync::CancellationToken;
 
 mod base;
 pub use base::*;
 
+mod node;
+pub use node::*;
+
 mod job;
 pub use job::*;
 
 mod worker;
 pub use worker::*;
 
+mod subgraph;
+pub use subgraph::*;
+
+mod hook;
+pub use hook::*;
+
+mod pool;
+pub use pool::*;
+
+mod scheduler;
+pub use scheduler::*;
+
+mod driver;
+pub use driver::*;
+
+mod quarantine;
+pub use quarantine::*;
+
+mod store;
+pub use store::*;
+
+mod retry;
+pub use retry::*;
+
+mod stream;
+pub use stream::*;
+
+mod format;
+pub use format::*;
+
+mod cache;
+pub use cache::*;
+
+mod blob;
+pub use blob::*;
+
+mod journal;
+pub use journal::*;
+
+mod version;
+pub use version::*;
+
+mod diff;
+pub use diff::*;
+
+mod report;
+pub use report::*;
+
+mod scratch;
+pub use scratch::*;
+
+mod extract;
+pub use extract::*;
+
 mod mermaid;
 pub use mermaid::*;
+
+mod preflight;
+pub use preflight::*;
+
+mod dispatch;
+mod fair;
+mod rate;
+
+mod rt;
+pub use rt::{Task, TaskError};
+
+pub mod batch;
+pub mod manifest;
+
+#[cfg(feature = "metrics")]
+mod metrics;
+
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "serve")]
+pub use serve::*;
+
+#[cfg(feature = "registry")]
+mod registry;
+#[cfg(feature = "registry")]
+pub use registry::*;
+
+#[cfg(feature = "test-util")]
+mod chaos;
+#[cfg(feature = "test-util")]
+pub use chaos::*;
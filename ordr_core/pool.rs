@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use serde_json::Value;
+use tokio::sync::{Semaphore, watch};
+
+use crate::{
+    Job, NodeState, Output, State, Worker,
+    fair::{FairQueue, PoolShare},
+    rt,
+};
+
+/// Runs many jobs, with the same state, at most `max_jobs` at a time. Jobs are started in the
+/// order they are submitted.
+#[derive(Clone)]
+pub struct WorkerPool<S: State> {
+    state: S,
+    permits: Arc<Semaphore>,
+    counters: Arc<PoolCounters>,
+    /// The node slots shared by the running jobs. See [`WorkerPool::max_nodes`].
+    nodes: Option<Arc<FairQueue>>,
+}
+
+/// How big a share of the nodes of a [`WorkerPool`] a job gets, when they are limited with
+/// [`WorkerPool::max_nodes`]. A job of each priority gets to start twice as many nodes as a job of
+/// the one below it, while they both have nodes waiting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// For jobs that can wait, like backfills.
+    Low,
+    #[default]
+    Normal,
+    /// For jobs someone is waiting for.
+    High,
+}
+
+impl Priority {
+    fn weight(self) -> u32 {
+        match self {
+            Self::Low => 1,
+            Self::Normal => 2,
+            Self::High => 4,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolCounters {
+    submitted: AtomicUsize,
+    running: AtomicUsize,
+    succeeded: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+/// Numbers for all jobs submitted to a [`WorkerPool`]. Created with [`WorkerPool::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Jobs submitted so far.
+    pub submitted: usize,
+    /// Jobs waiting for one of the running jobs to finish.
+    pub queued: usize,
+    /// Jobs currently running.
+    pub running: usize,
+    /// Jobs that finished successfully.
+    pub succeeded: usize,
+    /// Jobs that finished any other way (failed, panicked, were stopped or timed out).
+    pub failed: usize,
+}
+
+impl<S: State> WorkerPool<S> {
+    /// Create a pool, where every job gets a clone of `state`.
+    ///
+    /// # Panics
+    /// If `max_jobs` is `0`.
+    pub fn new(state: S, max_jobs: usize) -> Self {
+        assert!(max_jobs > 0, "Max jobs must be at least 1");
+        Self {
+            state,
+            permits: Arc::new(Semaphore::new(max_jobs)),
+            counters: Arc::default(),
+            nodes: None,
+        }
+    }
+
+    /// Run at most `max_nodes` nodes at the same time, across all running jobs. When nodes of
+    /// several jobs are waiting, the jobs take turns (weighted round-robin), so a job with many
+    /// nodes does not hold up the others. See [`WorkerPool::submit_with_priority`].
+    ///
+    /// Nodes waiting for their turn count as running, in their job.
+    ///
+    /// # Panics
+    /// If `max_nodes` is `0`.
+    #[must_use]
+    pub fn max_nodes(mut self, max_nodes: usize) -> Self {
+        assert!(max_nodes > 0, "Max nodes must be at least 1");
+        self.nodes = Some(Arc::new(FairQueue::new(max_nodes)));
+        self
+    }
+
+    /// Queue a job. It starts as soon as fewer than `max_jobs` jobs are running.
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime, and no other runtime is enabled.
+    pub fn submit(&self, job: Job<S>) -> JobHandle<S> {
+        self.submit_with_priority(job, Priority::Normal)
+    }
+
+    /// Like [`WorkerPool::submit`], but the job gets a bigger (or smaller) share of the nodes,
+    /// if they are limited with [`WorkerPool::max_nodes`].
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime, and no other runtime is enabled.
+    pub fn submit_with_priority(&self, job: Job<S>, priority: Priority) -> JobHandle<S> {
+        let id = self.counters.submitted.fetch_add(1, Ordering::Relaxed);
+        let mut worker = Worker::new(job, self.state.clone());
+        if let Some(queue) = &self.nodes {
+            worker = worker.share(PoolShare {
+                queue: queue.clone(),
+                job: id,
+                weight: priority.weight(),
+            });
+        }
+        let (tx, output) = watch::channel(None);
+        let permits = self.permits.clone();
+        let counters = self.counters.clone();
+        let nodes = self.nodes.clone();
+        let mut running = worker.clone();
+        rt::spawn(async move {
+            let _permit = permits.acquire().await.expect("Semaphore is never closed");
+            counters.running.fetch_add(1, Ordering::Relaxed);
+            running.run().await.expect("Worker was just created");
+            let result = running.get_output().await.expect("Worker is running");
+            if let Some(nodes) = nodes {
+                nodes.remove(id);
+            }
+            counters.running.fetch_sub(1, Ordering::Relaxed);
+            if result.is_done() {
+                counters.succeeded.fetch_add(1, Ordering::Relaxed);
+            } else {
+                counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            // Nobody waiting for the output is fine.
+            let _ = tx.send(Some(result));
+        });
+        JobHandle { id, worker, output }
+    }
+
+    /// Counts of the jobs in the pool, by how far they have come.
+    #[must_use]
+    pub fn stats(&self) -> PoolStats {
+        let submitted = self.counters.submitted.load(Ordering::Relaxed);
+        let running = self.counters.running.load(Ordering::Relaxed);
+        let succeeded = self.counters.succeeded.load(Ordering::Relaxed);
+        let failed = self.counters.failed.load(Ordering::Relaxed);
+        PoolStats {
+            submitted,
+            queued: submitted.saturating_sub(running + succeeded + failed),
+            running,
+            succeeded,
+            failed,
+        }
+    }
+}
+
+/// A job submitted to a [`WorkerPool`].
+pub struct JobHandle<S: State> {
+    id: usize,
+    worker: Worker<S>,
+    output: watch::Receiver<Option<Output>>,
+}
+
+impl<S: State> JobHandle<S> {
+    /// Identifies the job within its pool. Jobs are numbered from `0`, in the order they were
+    /// submitted.
+    #[must_use]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The worker running the job. Note that it is only started once the pool gets to it.
+    #[must_use]
+    pub fn worker(&self) -> &Worker<S> {
+        &self.worker
+    }
+
+    /// Returns `true` once the job has finished.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.output.borrow().is_some()
+    }
+
+    /// Wait for the job to finish, and return the [`Output`].
+    ///
+    /// # Panics
+    /// If the pool's task running the job panicked.
+    pub async fn output(&mut self) -> Output {
+        let output = self
+            .output
+            .wait_for(Option::is_some)
+            .await
+            .expect("Pool sends the output before it is done");
+        output.clone().expect("Waited for it")
+    }
+
+    /// The state of every node. See [`Worker::status`].
+    pub async fn status(&self) -> HashMap<&'static str, NodeState> {
+        self.worker.status().await
+    }
+
+    /// The data collected from running the job. See [`Worker::data`].
+    pub async fn data(&self) -> HashMap<String, Value> {
+        self.worker.data().await
+    }
+}
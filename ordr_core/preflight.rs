@@ -0,0 +1,84 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    pin::Pin,
+    sync::Arc,
+};
+
+use tracing::{error, info};
+
+use crate::{Error, Node, Result, State, rt::Tasks};
+
+/// Something a node needs from its environment, like a credential or access to a bucket. It is
+/// checked once when the job starts, before any node runs, and the job is not run if it is not
+/// met. See [`crate::NodeDef::requires`], or `#[producer(requires = check_s3_access)]`.
+#[derive(Clone)]
+pub struct Requirement<S: State> {
+    /// Name of the requirement. Nodes that share a requirement (by name) have it checked once.
+    pub name: &'static str,
+    /// Checks the requirement, with the state of the job.
+    pub check: Check<S>,
+}
+
+/// Checks a [`Requirement`]. Returns an error describing why it is not met.
+pub type Check<S> =
+    Arc<dyn Fn(S) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync + 'static>;
+
+impl<S: State> std::fmt::Debug for Requirement<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Requirement<{}>", self.name)
+    }
+}
+
+/// A [`Requirement`] that was not met. See [`crate::Output::Unmet`].
+#[derive(Debug, Clone)]
+pub struct UnmetRequirement {
+    /// Name of the requirement.
+    pub name: &'static str,
+    /// The nodes that need it, sorted.
+    pub nodes: Vec<&'static str>,
+    /// Why it was not met. If the check panicked, the panic message.
+    pub error: Error,
+}
+
+/// Checks the requirements of `nodes`, each once, at the same time. Returns the ones that were
+/// not met, sorted by name.
+pub(crate) async fn preflight<'a, S: State>(
+    nodes: impl IntoIterator<Item = &'a Node<S>>,
+    state: &S,
+) -> Vec<UnmetRequirement> {
+    let mut requirements: BTreeMap<&'static str, (Check<S>, Vec<&'static str>)> = BTreeMap::new();
+    for node in nodes {
+        for requirement in &node.requires {
+            let (_, needed_by) = requirements
+                .entry(requirement.name)
+                .or_insert_with(|| (requirement.check.clone(), vec![]));
+            needed_by.push(node.name);
+        }
+    }
+    if requirements.is_empty() {
+        return vec![];
+    }
+
+    let mut checks = Tasks::new();
+    let mut names = HashMap::new();
+    for (name, (check, _)) in &requirements {
+        let id = checks.spawn(check(state.clone()));
+        names.insert(id, *name);
+    }
+    let mut unmet = vec![];
+    while let Some((id, result)) = checks.join_next().await {
+        let name = names[&id];
+        let result = result.unwrap_or_else(|e| Err(Error::fatal(e.to_string())));
+        match result {
+            Ok(()) => info!(name, "Requirement met"),
+            Err(error) => {
+                error!(name, error = error.message, "Requirement not met");
+                let mut nodes = requirements[name].1.clone();
+                nodes.sort_unstable();
+                unmet.push(UnmetRequirement { name, nodes, error });
+            }
+        }
+    }
+    unmet.sort_by_key(|unmet| unmet.name);
+    unmet
+}
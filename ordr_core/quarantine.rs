@@ -0,0 +1,84 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use tracing::{info, warn};
+
+/// Keeps track of nodes that keep failing, across jobs. Once a node has failed a job
+/// `threshold` times in a row, it is quarantined, and jobs that would run it fail right away,
+/// until it is cleared with [`Quarantine::clear`] (or [`crate::Worker::unquarantine`]).
+///
+/// It is cheap to clone, and clones share their state. Attach the same one to every worker with
+/// [`crate::Worker::with_quarantine`].
+#[derive(Debug, Clone)]
+pub struct Quarantine {
+    threshold: u32,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Number of jobs in a row that each node failed.
+    failures: HashMap<String, u32>,
+    quarantined: HashSet<String>,
+}
+
+impl Quarantine {
+    /// Quarantine nodes after `threshold` failed jobs in a row. A node only counts as failed once
+    /// it has run out of retries.
+    ///
+    /// # Panics
+    /// If `threshold` is `0`.
+    #[must_use]
+    pub fn new(threshold: u32) -> Self {
+        assert!(threshold > 0, "Threshold must be at least 1");
+        Self {
+            threshold,
+            inner: Arc::default(),
+        }
+    }
+
+    /// Whether the node is quarantined.
+    #[must_use]
+    pub fn is_quarantined(&self, name: &str) -> bool {
+        self.lock().quarantined.contains(name)
+    }
+
+    /// Names of the quarantined nodes, sorted.
+    #[must_use]
+    pub fn quarantined(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.lock().quarantined.iter().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Let the node run again, with a clean slate. Returns `false` if it was not quarantined.
+    pub fn clear(&self, name: &str) -> bool {
+        let mut inner = self.lock();
+        inner.failures.remove(name);
+        let cleared = inner.quarantined.remove(name);
+        if cleared {
+            info!(name, "Node no longer quarantined");
+        }
+        cleared
+    }
+
+    pub(crate) fn succeeded(&self, name: &str) {
+        self.lock().failures.remove(name);
+    }
+
+    pub(crate) fn failed(&self, name: &str) {
+        let mut inner = self.lock();
+        let failures = inner.failures.entry(name.to_string()).or_default();
+        *failures += 1;
+        if *failures >= self.threshold && inner.quarantined.insert(name.to_string()) {
+            warn!(name, threshold = self.threshold, "Node quarantined");
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // The lock is never held across anything that can panic.
+        self.inner.lock().unwrap()
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use crate::rt::Instant;
+
+/// At most `starts` nodes with a rate tag are started within any `per`. See
+/// [`crate::Worker::rate_limit`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RateLimit {
+    pub(crate) starts: u32,
+    pub(crate) per: Duration,
+}
+
+/// Keeps track of when nodes with a rate tag were started, to hold back the ones that would go
+/// over their [`RateLimit`].
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    started: HashMap<&'static str, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limits: HashMap<String, RateLimit>) -> Self {
+        Self {
+            limits,
+            started: HashMap::new(),
+        }
+    }
+
+    /// Limits how often nodes tagged `tag` are started, instead of any earlier limit.
+    pub(crate) fn insert(&mut self, tag: &str, limit: RateLimit) {
+        self.limits.insert(tag.to_string(), limit);
+    }
+
+    /// Counts a start of a node tagged `tag`, if it is allowed now. Otherwise, returns when it
+    /// will be. Tags without a limit are always allowed.
+    pub(crate) fn acquire(&mut self, tag: &'static str, now: Instant) -> Result<(), Instant> {
+        let Some(limit) = self.limits.get(tag) else {
+            return Ok(());
+        };
+        let started = self.started.entry(tag).or_default();
+        while started
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= limit.per)
+        {
+            started.pop_front();
+        }
+        if started.len() >= limit.starts as usize {
+            return Err(started[0] + limit.per);
+        }
+        started.push_back(now);
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use std::any::{Any, TypeId};
+
+use crate::{Node, NodeBuilder, State};
+
+#[doc(hidden)]
+pub use inventory;
+
+/// A node registered by the `producer` macro (and friends) with the `registry` feature, so it
+/// can be added to a job by its name. See [`crate::JobBuilder::add_by_name`].
+///
+/// Public because macros need it.
+#[doc(hidden)]
+pub struct RegisteredNode {
+    state: fn() -> TypeId,
+    node: fn() -> Box<dyn Any>,
+}
+
+impl RegisteredNode {
+    #[must_use]
+    pub const fn new<S: State, N: NodeBuilder<S>>() -> Self {
+        Self {
+            state: TypeId::of::<S>,
+            node: boxed_node::<S, N>,
+        }
+    }
+}
+
+inventory::collect!(RegisteredNode);
+
+fn boxed_node<S: State, N: NodeBuilder<S>>() -> Box<dyn Any> {
+    Box::new(N::node())
+}
+
+/// Every registered node with state `S`.
+pub(crate) fn registered_nodes<S: State>() -> impl Iterator<Item = Node<S>> {
+    inventory::iter::<RegisteredNode>
+        .into_iter()
+        .filter(|registered| (registered.state)() == TypeId::of::<S>())
+        .filter_map(|registered| (registered.node)().downcast().ok())
+        .map(|node| *node)
+}
+
+/// The registered node called `name`, with state `S`, if there is one.
+pub(crate) fn registered_node<S: State>(name: &str) -> Option<Node<S>> {
+    registered_nodes().find(|node| node.name == name)
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{NodeState, Output};
+
+/// A summary of a job and each of its nodes, that can be stored or sent elsewhere. Created with
+/// [`crate::Worker::report`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobReport {
+    /// See [`crate::Worker::job_id`].
+    pub job_id: u64,
+    /// See [`crate::JobBuilder::label`].
+    pub labels: BTreeMap<String, String>,
+    /// How the job ended. `None` if it has not.
+    pub outcome: Option<Outcome>,
+    /// When the job was started. `None` if it has not been.
+    pub started_at: Option<SystemTime>,
+    /// When the job ended. `None` if it has not.
+    pub finished_at: Option<SystemTime>,
+    /// How long the job ran. `None` if it has not ended.
+    pub duration: Option<Duration>,
+    /// Every node in the job, by name.
+    pub nodes: BTreeMap<String, NodeReport>,
+    /// What the nodes left in [`crate::Context::scratch`].
+    pub scratch: BTreeMap<String, Value>,
+    /// Provided nodes that no started node depended on, sorted. Stale resume data, or a
+    /// misnamed key. See [`crate::Worker::unused_inputs`].
+    #[serde(default)]
+    pub unused_inputs: Vec<String>,
+}
+
+/// How a job ended. Mirrors the variants of [`Output`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// See [`Output::Done`].
+    Done,
+    /// See [`Output::NodeFailed`].
+    NodeFailed,
+    /// See [`Output::NodePanic`].
+    NodePanic,
+    /// See [`Output::Stopped`].
+    Stopped,
+    /// See [`Output::Finished`].
+    Finished,
+    /// See [`Output::TimedOut`].
+    TimedOut,
+    /// See [`Output::Unmet`].
+    Unmet,
+}
+
+impl From<&Output> for Outcome {
+    fn from(output: &Output) -> Self {
+        match output {
+            Output::Done { .. } => Outcome::Done,
+            Output::NodeFailed { .. } => Outcome::NodeFailed,
+            Output::NodePanic { .. } => Outcome::NodePanic,
+            Output::Stopped { .. } => Outcome::Stopped,
+            Output::Finished { .. } => Outcome::Finished,
+            Output::TimedOut { .. } => Outcome::TimedOut,
+            Output::Unmet { .. } => Outcome::Unmet,
+        }
+    }
+}
+
+/// What happened to a single node. See [`JobReport::nodes`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeReport {
+    /// Where the node got to.
+    pub state: NodeStatus,
+    /// The value was provided when the job was created, rather than produced by the job.
+    pub provided: bool,
+    /// The nodes it depends on, sorted.
+    pub inputs: Vec<String>,
+    /// When the node (or its last attempt) started, as an offset from the start of the job.
+    /// `None` if it never ran, or it is not known.
+    pub start: Option<Duration>,
+    /// When the node finished, as an offset from the start of the job. `None` if it has not.
+    pub end: Option<Duration>,
+    /// Number of times the node was retried.
+    pub retries: u32,
+    /// Error message of the node, if it failed (or the panic message, if it panicked).
+    pub error: Option<String>,
+    /// The node that failed, if this one was blocked by it.
+    pub blocked_by: Option<String>,
+}
+
+/// Where a node got to. Mirrors [`NodeState`], with `Pending` for nodes that have not started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeStatus {
+    /// Has not started.
+    Pending,
+    /// See [`NodeState::Provided`].
+    Provided,
+    /// See [`NodeState::Running`].
+    Running,
+    /// See [`NodeState::Retrying`].
+    Retrying,
+    /// See [`NodeState::Done`].
+    Done,
+    /// See [`NodeState::Failed`].
+    Failed,
+    /// See [`NodeState::Blocked`].
+    Blocked,
+}
+
+impl JobReport {
+    /// Put together a report from what the worker knows. `deps` has the dependencies of every
+    /// node that is not provided.
+    pub(crate) fn new(
+        job_id: u64,
+        labels: BTreeMap<String, String>,
+        output: Option<&Output>,
+        started_at: Option<SystemTime>,
+        scratch: BTreeMap<String, Value>,
+        status: &HashMap<&'static str, NodeState>,
+        deps: &HashMap<&'static str, Vec<&'static str>>,
+    ) -> Self {
+        let mut nodes: BTreeMap<_, _> = deps
+            .iter()
+            .flat_map(|(name, deps)| deps.iter().chain([name]))
+            .chain(status.keys())
+            .map(|name| {
+                let node = match status.get(name) {
+                    Some(state) => NodeReport::new(state, started_at),
+                    None => NodeReport::pending(),
+                };
+                (name.to_string(), node)
+            })
+            .collect();
+        for (name, deps) in deps {
+            let mut inputs: Vec<_> = deps.iter().map(ToString::to_string).collect();
+            inputs.sort_unstable();
+            nodes.get_mut(*name).expect("Added above").inputs = inputs;
+        }
+        Self {
+            job_id,
+            labels,
+            outcome: output.map(Outcome::from),
+            started_at,
+            finished_at: output.map(Output::finished_at),
+            duration: output.map(Output::duration),
+            nodes,
+            scratch,
+            unused_inputs: unused_inputs(status, deps)
+                .into_iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// Names of the provided nodes that no started node depended on, sorted.
+pub(crate) fn unused_inputs<T: ::std::hash::BuildHasher>(
+    status: &HashMap<&'static str, NodeState, T>,
+    deps: &HashMap<&'static str, Vec<&'static str>>,
+) -> Vec<&'static str> {
+    let provided = |state: &NodeState| matches!(state, NodeState::Provided { .. });
+    let used: HashSet<_> = status
+        .iter()
+        .filter(|(_, state)| !provided(state))
+        .flat_map(|(name, _)| deps.get(name).into_iter().flatten().copied())
+        .collect();
+    let mut unused: Vec<_> = status
+        .iter()
+        .filter(|(name, state)| provided(state) && !used.contains(*name))
+        .map(|(name, _)| *name)
+        .collect();
+    unused.sort_unstable();
+    unused
+}
+
+impl JobReport {
+    /// The chain of nodes that decided how long the job took, from the first to start to the
+    /// last to finish, with how long each of them ran. Speeding up any other node would not
+    /// have made the job finish sooner.
+    ///
+    /// It starts from the node that finished last, and walks back through the input that
+    /// finished last, as that is the one it was waiting for. Only nodes that ran (see
+    /// [`NodeReport::duration`]) are part of it.
+    #[must_use]
+    pub fn critical_path(&self) -> Vec<(&str, Duration)> {
+        let ran = self.ran();
+        let mut path = vec![];
+        let mut next = ran.iter().max_by_key(|(_, (_, end))| *end);
+        while let Some((&name, &(start, end))) = next {
+            path.push((name, end.saturating_sub(start)));
+            next = self.nodes[name]
+                .inputs
+                .iter()
+                .filter_map(|input| ran.get_key_value(input.as_str()))
+                .max_by_key(|(_, (_, end))| *end);
+        }
+        path.reverse();
+        path
+    }
+
+    /// How much later each node that ran could have finished, without the job finishing any
+    /// later. Nodes on the [`JobReport::critical_path`] have next to none.
+    #[must_use]
+    pub fn slack(&self) -> BTreeMap<&str, Duration> {
+        let ran = self.ran();
+        let Some(job_end) = ran.values().map(|(_, end)| *end).max() else {
+            return BTreeMap::new();
+        };
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for name in ran.keys() {
+            for input in &self.nodes[*name].inputs {
+                dependents.entry(input).or_default().push(name);
+            }
+        }
+        // Latest each node could finish. A node must finish before the latest time any of its
+        // dependents could start.
+        let mut latest = HashMap::new();
+        ran.keys()
+            .map(|name| {
+                let end = latest_end(name, &ran, &dependents, job_end, &mut latest);
+                (*name, end.saturating_sub(ran[name].1))
+            })
+            .collect()
+    }
+
+    /// The start and end of every node that ran.
+    fn ran(&self) -> BTreeMap<&str, (Duration, Duration)> {
+        self.nodes
+            .iter()
+            .filter_map(|(name, node)| Some((name.as_str(), (node.start?, node.end?))))
+            .collect()
+    }
+}
+
+/// The latest node `name` could have finished without delaying the job, which ended at
+/// `job_end`.
+fn latest_end<'a>(
+    name: &'a str,
+    ran: &BTreeMap<&'a str, (Duration, Duration)>,
+    dependents: &HashMap<&'a str, Vec<&'a str>>,
+    job_end: Duration,
+    memo: &mut HashMap<&'a str, Duration>,
+) -> Duration {
+    if let Some(end) = memo.get(name) {
+        return *end;
+    }
+    let end = dependents
+        .get(name)
+        .into_iter()
+        .flatten()
+        .map(|dependent| {
+            let (start, end) = ran[dependent];
+            let took = end.saturating_sub(start);
+            latest_end(dependent, ran, dependents, job_end, memo).saturating_sub(took)
+        })
+        .min()
+        .unwrap_or(job_end);
+    memo.insert(name, end);
+    end
+}
+
+impl NodeReport {
+    /// How long the node ran, if it has started and finished.
+    #[must_use]
+    pub fn duration(&self) -> Option<Duration> {
+        Some(self.end?.saturating_sub(self.start?))
+    }
+
+    fn pending() -> Self {
+        Self {
+            state: NodeStatus::Pending,
+            provided: false,
+            inputs: vec![],
+            start: None,
+            end: None,
+            retries: 0,
+            error: None,
+            blocked_by: None,
+        }
+    }
+
+    /// `job_started` is used to place nodes that are done, whose state only has the time of day
+    /// they finished.
+    fn new(state: &NodeState, job_started: Option<SystemTime>) -> Self {
+        let pending = Self::pending();
+        match state {
+            NodeState::Provided { .. } => Self {
+                state: NodeStatus::Provided,
+                provided: true,
+                ..pending
+            },
+            NodeState::Running { start, .. } => Self {
+                state: NodeStatus::Running,
+                start: Some(*start),
+                ..pending
+            },
+            NodeState::Retrying { start, retries, .. } => Self {
+                state: NodeStatus::Retrying,
+                start: Some(*start),
+                retries: *retries,
+                ..pending
+            },
+            NodeState::Done {
+                duration,
+                retries,
+                finished_at,
+                ..
+            } => {
+                let end = job_started.and_then(|t0| finished_at.duration_since(t0).ok());
+                Self {
+                    state: NodeStatus::Done,
+                    start: end.map(|end| end.saturating_sub(*duration)),
+                    end,
+                    retries: *retries,
+                    ..pending
+                }
+            }
+            NodeState::Failed {
+                duration,
+                retries,
+                error,
+                ..
+            } => Self {
+                state: NodeStatus::Failed,
+                end: Some(*duration),
+                retries: *retries,
+                error: Some(error.message.clone()),
+                ..pending
+            },
+            NodeState::Blocked { by } => Self {
+                state: NodeStatus::Blocked,
+                blocked_by: Some(by.to_string()),
+                ..pending
+            },
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// Decides how long to wait before retrying a node, and when to give up. Set it on a node with
+/// `#[producer(retry = "exponential(100ms, 5)")]` or [`crate::NodeDef::retry_policy`].
+///
+/// With a policy, the `retry_in` of the error is ignored, and only tells whether the node should
+/// be retried at all. Fatal errors are never retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    delay: Duration,
+    factor: f64,
+    max_retries: u32,
+    max_delay: Option<Duration>,
+    max_elapsed: Option<Duration>,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Wait `delay` between attempts, and retry at most `max_retries` times.
+    #[must_use]
+    pub fn fixed(delay: Duration, max_retries: u32) -> Self {
+        Self {
+            delay,
+            factor: 1.0,
+            max_retries,
+            max_delay: None,
+            max_elapsed: None,
+            jitter: false,
+        }
+    }
+
+    /// Wait `delay` before the first retry, and twice as long before each of the next ones.
+    /// Retry at most `max_retries` times.
+    #[must_use]
+    pub fn exponential(delay: Duration, max_retries: u32) -> Self {
+        Self {
+            factor: 2.0,
+            ..Self::fixed(delay, max_retries)
+        }
+    }
+
+    /// Never wait longer than `max_delay` between attempts.
+    #[must_use]
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Give up once `max_elapsed` has passed since the node was first started, even if there
+    /// are retries left.
+    #[must_use]
+    pub fn max_elapsed(mut self, max_elapsed: Duration) -> Self {
+        self.max_elapsed = Some(max_elapsed);
+        self
+    }
+
+    /// Wait a random time between half and all of the delay, so nodes that fail together don't
+    /// all retry at the same time.
+    #[must_use]
+    pub fn with_jitter(mut self) -> Self {
+        self.jitter = true;
+        self
+    }
+
+    /// How long to wait before retry number `retry` (counting from `1`), given that `elapsed`
+    /// has passed since the node was first started. `None` means give up.
+    #[must_use]
+    pub fn delay(&self, retry: u32, elapsed: Duration) -> Option<Duration> {
+        if retry == 0 || retry > self.max_retries {
+            return None;
+        }
+        let exponent = i32::try_from(retry - 1).unwrap_or(i32::MAX);
+        let secs = self.delay.as_secs_f64() * self.factor.powi(exponent);
+        let mut delay = Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX);
+        if let Some(max_delay) = self.max_delay {
+            delay = delay.min(max_delay);
+        }
+        if self.jitter {
+            delay = delay / 2 + (delay / 2).mul_f64(random_fraction());
+        }
+        if self
+            .max_elapsed
+            .is_some_and(|max_elapsed| elapsed.saturating_add(delay) > max_elapsed)
+        {
+            return None;
+        }
+        Some(delay)
+    }
+}
+
+/// A random number between `0` and `1`. Good enough for jitter.
+fn random_fraction() -> f64 {
+    // `RandomState` is seeded randomly, so hashing nothing gives a random number.
+    let n = RandomState::new().build_hasher().finish();
+    #[allow(clippy::cast_precision_loss)]
+    let fraction = (n >> 11) as f64 / (1u64 << 53) as f64;
+    fraction
+}
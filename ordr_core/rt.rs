@@ -0,0 +1,428 @@
+//! The async runtime that jobs run on. It is tokio, unless there is no tokio runtime around, and
+//! the `async-std` or `smol` feature is enabled (if both are, async-std wins). On wasm, tasks run
+//! on the event loop of the browser, plain functions are called in place (there are no threads),
+//! and the time comes from the browser, since std has no clock there.
+
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::{
+    FutureExt, StreamExt,
+    future::{AbortHandle, Abortable},
+    stream::FuturesUnordered,
+};
+use tokio::sync::oneshot;
+
+// Calling `std::time::Instant::now` panics on wasm.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::Instant;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The current [`Instant`], to measure durations with.
+pub(crate) fn now() -> Instant {
+    Instant::now()
+}
+
+/// The current time of day.
+pub(crate) fn system_now() -> SystemTime {
+    #[cfg(target_arch = "wasm32")]
+    return std::time::UNIX_EPOCH
+        + web_time::SystemTime::now()
+            .duration_since(web_time::UNIX_EPOCH)
+            .unwrap_or_default();
+    #[cfg(not(target_arch = "wasm32"))]
+    SystemTime::now()
+}
+
+/// What the crate needs from an async runtime. Everything else (spawning tasks that can be
+/// awaited and aborted, timeouts) is built on top of it, and the channels and locks are tokio's,
+/// which work on any runtime.
+pub(crate) trait Runtime: Sync {
+    /// Runs `fut` in the background, until it is done.
+    fn spawn(&self, fut: BoxFuture<'static, ()>);
+    /// Runs `f` on a thread where it may block.
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>);
+    /// Runs `fut` on the current thread until it is done. Only called from within
+    /// [`Runtime::spawn_blocking`]. On wasm, futures are awaited in place instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn block_on(&self, fut: BoxFuture<'_, ()>);
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The runtime to run on, from where this is called.
+#[cfg(not(target_arch = "wasm32"))]
+fn runtime() -> &'static dyn Runtime {
+    #[cfg(feature = "async-std")]
+    if tokio::runtime::Handle::try_current().is_err() {
+        return &AsyncStd;
+    }
+    #[cfg(all(feature = "smol", not(feature = "async-std")))]
+    if tokio::runtime::Handle::try_current().is_err() {
+        return &Smol;
+    }
+    &Tokio
+}
+
+#[cfg(target_arch = "wasm32")]
+fn runtime() -> &'static dyn Runtime {
+    &Wasm
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Tokio;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Runtime for Tokio {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        tokio::spawn(fut);
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) {
+        tokio::task::spawn_blocking(f);
+    }
+
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        // Threads of the blocking pool know the runtime they belong to.
+        tokio::runtime::Handle::current().block_on(fut);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+#[cfg(feature = "async-std")]
+struct AsyncStd;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStd {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        // Dropping the handle detaches the task.
+        async_std::task::spawn(fut);
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) {
+        async_std::task::spawn_blocking(f);
+    }
+
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        async_std::task::block_on(fut);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+#[cfg(all(feature = "smol", not(feature = "async-std")))]
+struct Smol;
+
+#[cfg(all(feature = "smol", not(feature = "async-std")))]
+impl Runtime for Smol {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        smol::spawn(fut).detach();
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) {
+        smol::unblock(f).detach();
+    }
+
+    fn block_on(&self, fut: BoxFuture<'_, ()>) {
+        smol::block_on(fut);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+struct Wasm;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(callback: &js_sys::Function, ms: i32) -> wasm_bindgen::JsValue;
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Runtime for Wasm {
+    fn spawn(&self, fut: BoxFuture<'static, ()>) {
+        wasm_bindgen_futures::spawn_local(fut);
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) {
+        f();
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        // A promise can not be sent between threads, but the future has to be `Send`. So the
+        // timer is awaited in a task of its own, which says when it is done.
+        let (tx, rx) = oneshot::channel();
+        wasm_bindgen_futures::spawn_local(async move {
+            let ms = i32::try_from(duration.as_millis()).unwrap_or(i32::MAX);
+            let timer = js_sys::Promise::new(&mut |resolve, _| {
+                set_timeout(&resolve, ms);
+            });
+            let _ = wasm_bindgen_futures::JsFuture::from(timer).await;
+            let _ = tx.send(());
+        });
+        Box::pin(async {
+            let _ = rx.await;
+        })
+    }
+}
+
+static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Runs `fut` in the background, on the runtime. See [`Task`].
+///
+/// # Panics
+/// If called outside of a tokio runtime, and no other runtime is enabled.
+pub(crate) fn spawn<T: Send + 'static>(fut: impl Future<Output = T> + Send + 'static) -> Task<T> {
+    let (tx, output) = oneshot::channel();
+    let (abort, registration) = AbortHandle::new_pair();
+    let finished = Arc::new(AtomicBool::new(false));
+    let done = finished.clone();
+    let fut = Abortable::new(AssertUnwindSafe(fut).catch_unwind(), registration);
+    runtime().spawn(Box::pin(async move {
+        // An aborted task has nothing to send, and dropping the sender says as much.
+        if let Ok(output) = fut.await {
+            let _ = tx.send(output);
+        }
+        done.store(true, Ordering::Release);
+    }));
+    Task {
+        id: NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed),
+        output,
+        abort,
+        finished,
+    }
+}
+
+/// A task running in the background, on whichever runtime the crate runs on. Await it to get
+/// its output. Dropping it does not stop the task; [`Task::abort`] does.
+pub struct Task<T> {
+    id: u64,
+    output: oneshot::Receiver<thread::Result<T>>,
+    abort: AbortHandle,
+    finished: Arc<AtomicBool>,
+}
+
+impl<T> Task<T> {
+    /// Stop the task, the next time it yields. Awaiting it then gives [`TaskError::Aborted`].
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    /// The task is done, one way or another, so awaiting it will not wait.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = Result<T, TaskError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.output)
+            .poll(cx)
+            .map(|output| match output {
+                Ok(Ok(output)) => Ok(output),
+                Ok(Err(panic)) => Err(TaskError::Panicked(panic)),
+                Err(_) => Err(TaskError::Aborted),
+            })
+    }
+}
+
+impl<T> fmt::Debug for Task<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Task<{}>", self.id)
+    }
+}
+
+/// Why a [`Task`] did not finish.
+pub enum TaskError {
+    /// The task panicked. Holds what it panicked with.
+    Panicked(Box<dyn Any + Send>),
+    /// The task was aborted, or the runtime shut down before it finished.
+    Aborted,
+}
+
+impl TaskError {
+    /// Panic with what the task panicked with.
+    ///
+    /// # Panics
+    /// Always.
+    pub fn resume(self) -> ! {
+        match self {
+            Self::Panicked(panic) => std::panic::resume_unwind(panic),
+            Self::Aborted => panic!("The task was aborted"),
+        }
+    }
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panicked(panic) => {
+                let message = (panic.downcast_ref::<&str>().copied())
+                    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("Box<dyn Any>");
+                write!(f, "Panicked: {message}")
+            }
+            Self::Aborted => f.write_str("Aborted"),
+        }
+    }
+}
+
+impl fmt::Debug for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for TaskError {}
+
+/// Runs `f` where it will not hold up other nodes: the blocking threads of the runtime, or in
+/// place on wasm.
+///
+/// # Panics
+/// If `f` panics.
+pub(crate) async fn run_blocking<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    runtime().spawn_blocking(Box::new(move || {
+        let _ = tx.send(std::panic::catch_unwind(AssertUnwindSafe(f)));
+    }));
+    match rx.await {
+        Ok(Ok(output)) => output,
+        Ok(Err(panic)) => TaskError::Panicked(panic).resume(),
+        Err(_) => TaskError::Aborted.resume(),
+    }
+}
+
+/// Like [`run_blocking`], but for a future that may block the thread it is polled on.
+///
+/// # Panics
+/// If `fut` panics.
+pub(crate) async fn run_blocking_future<F>(fut: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(target_arch = "wasm32")]
+    return fut.await;
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let runtime = runtime();
+        run_blocking(move || {
+            let mut output = None;
+            runtime.block_on(Box::pin(async {
+                output = Some(fut.await);
+            }));
+            output.expect("The future is done")
+        })
+        .await
+    }
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+    runtime().sleep(duration).await;
+}
+
+pub(crate) async fn sleep_until(at: Instant) {
+    sleep(at.saturating_duration_since(now())).await;
+}
+
+/// The output of `fut`, unless it takes longer than `duration`. Then it is dropped.
+pub(crate) async fn timeout<F: Future>(duration: Duration, fut: F) -> Option<F::Output> {
+    tokio::select! {
+        output = fut => Some(output),
+        () = sleep(duration) => None,
+    }
+}
+
+/// Aborts the task when dropped, so a set of tasks takes them all down with it.
+struct Joined<T>(Task<T>);
+
+impl<T> Future for Joined<T> {
+    type Output = (u64, Result<T, TaskError>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let id = self.0.id;
+        Pin::new(&mut self.0).poll(cx).map(|output| (id, output))
+    }
+}
+
+impl<T> Drop for Joined<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// A set of futures that run at the same time, spawned on the runtime. Dropping it aborts
+/// whatever is left.
+pub(crate) struct Tasks<T> {
+    set: FuturesUnordered<Joined<T>>,
+}
+
+impl<T: Send + 'static> Tasks<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            set: FuturesUnordered::new(),
+        }
+    }
+
+    /// Adds `fut` to the set, and returns an id for it, to tell which one is done.
+    pub(crate) fn spawn(&mut self, fut: impl Future<Output = T> + Send + 'static) -> u64 {
+        let task = spawn(fut);
+        let id = task.id;
+        self.set.push(Joined(task));
+        id
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// The id and output of the next future to finish, or `None` if there are none left.
+    pub(crate) async fn join_next(&mut self) -> Option<(u64, Result<T, TaskError>)> {
+        self.set.next().await
+    }
+
+    /// The output of the next future to finish, or `None` if there are none left.
+    ///
+    /// # Panics
+    /// If the future panicked.
+    pub(crate) async fn next(&mut self) -> Option<T> {
+        let (_, output) = self.join_next().await?;
+        Some(output.unwrap_or_else(|e| e.resume()))
+    }
+}
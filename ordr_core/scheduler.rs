@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::sync::{Semaphore, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::{
+    Job, JobError, State, Task, Worker,
+    rt::{self, Instant},
+};
+
+/// When a job given to a [`Scheduler`] should run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum When {
+    /// Right away.
+    Now,
+    /// At this point in time.
+    At(Instant),
+    /// Once this much time has passed.
+    In(Duration),
+    /// Over and over, this much time apart, starting this much time from now. Runs never
+    /// overlap (see [`Overlap::Queue`]).
+    Every(Duration),
+}
+
+/// What a recurring job does when it is due, while its previous run is still going. See
+/// [`Scheduler::recurring`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Overlap {
+    /// Wait for the previous run to finish, and then start right away.
+    #[default]
+    Queue,
+    /// Do not run this time around.
+    Skip,
+    /// Start anyway, so several runs can be going at once.
+    Concurrent,
+}
+
+/// Builds the job for each run of a recurring job, from the data of the run before it (`None`
+/// for the first run). See [`Scheduler::recurring`].
+type Template<S> = Box<dyn Fn(Option<&HashMap<String, Value>>) -> Result<Job<S>, JobError> + Send>;
+
+/// Runs jobs once they are due, at most `max_jobs` at a time. Jobs that are due while
+/// `max_jobs` jobs are running wait for one of them to finish.
+///
+/// Everything is kept in memory, so scheduled jobs are gone if the process stops.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    permits: Arc<Semaphore>,
+    next_id: Arc<AtomicUsize>,
+    /// Cancelled by [`Scheduler::shutdown`]. Every scheduled job has a child of it.
+    shutdown: CancellationToken,
+}
+
+impl Scheduler {
+    /// # Panics
+    /// If `max_jobs` is `0`.
+    #[must_use]
+    pub fn new(max_jobs: usize) -> Self {
+        assert!(max_jobs > 0, "Max jobs must be at least 1");
+        Self {
+            permits: Arc::new(Semaphore::new(max_jobs)),
+            next_id: Arc::default(),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Run `job`, with `state`, `when` it is due. Every run gets a fresh [`Worker`], with a clone
+    /// of the job and the state.
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime, and no other runtime is enabled.
+    pub fn enqueue<S: State>(&self, job: Job<S>, state: S, when: When) -> ScheduledJob<S> {
+        let now = rt::now();
+        let (due, every) = match when {
+            When::Now => (now, None),
+            When::At(at) => (at, None),
+            When::In(delay) => (now + delay, None),
+            When::Every(interval) => (now + interval, Some((interval, Overlap::Queue))),
+        };
+        let template = Box::new(move |_: Option<&HashMap<String, Value>>| Ok(job.clone()));
+        self.schedule(state, due, every, template)
+    }
+
+    /// Run a job every `interval`, starting `interval` from now, until it is cancelled. Each run
+    /// gets the job that `template` builds, from the data of the run before it (`None` for the
+    /// first run), say to provide some of it with [`Job::builder_with_data`]. If `template`
+    /// fails, the error is logged, and there is no run this time around.
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime, and no other runtime is enabled.
+    pub fn recurring<S, F>(
+        &self,
+        interval: Duration,
+        overlap: Overlap,
+        state: S,
+        template: F,
+    ) -> ScheduledJob<S>
+    where
+        S: State,
+        F: Fn(Option<&HashMap<String, Value>>) -> Result<Job<S>, JobError> + Send + 'static,
+    {
+        let due = rt::now() + interval;
+        self.schedule(state, due, Some((interval, overlap)), Box::new(template))
+    }
+
+    fn schedule<S: State>(
+        &self,
+        state: S,
+        mut due: Instant,
+        every: Option<(Duration, Overlap)>,
+        template: Template<S>,
+    ) -> ScheduledJob<S> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = self.shutdown.child_token();
+        let (tx, runs) = watch::channel(None);
+        let tx = Arc::new(tx);
+        let permits = self.permits.clone();
+        let cancelled = cancel.clone();
+        rt::spawn(async move {
+            // The data of the last run that finished.
+            let previous = Arc::new(std::sync::Mutex::new(None));
+            let mut running: Vec<Task<()>> = vec![];
+            loop {
+                tokio::select! {
+                    () = rt::sleep_until(due) => {}
+                    () = cancelled.cancelled() => return,
+                }
+                running.retain(|run| !run.is_finished());
+                let overlap = every.map(|(_, overlap)| overlap).unwrap_or_default();
+                let start = match overlap {
+                    _ if running.is_empty() => true,
+                    Overlap::Queue => {
+                        for run in running.drain(..) {
+                            let _ = run.await;
+                        }
+                        true
+                    }
+                    Overlap::Skip => {
+                        info!(id, "Scheduled job skipped, since it is still running");
+                        false
+                    }
+                    Overlap::Concurrent => true,
+                };
+                let job = start.then(|| template(previous.lock().unwrap().as_ref()));
+                match job {
+                    Some(Ok(job)) => {
+                        let run = run(job, state.clone(), &permits, &cancelled, &tx, &previous);
+                        running.push(rt::spawn(run));
+                    }
+                    Some(Err(e)) => error!(id, error = %e, "Could not create scheduled job"),
+                    None => {}
+                }
+                // Runs hold on to the sender, so a run that is going still gets to send.
+                let Some((interval, overlap)) = every else {
+                    return;
+                };
+                due += interval;
+                if overlap == Overlap::Queue {
+                    due = due.max(rt::now());
+                }
+            }
+        });
+        ScheduledJob { id, cancel, runs }
+    }
+
+    /// Cancel every scheduled job. Jobs that are already running are allowed to finish.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// A single run of a scheduled job, once there is room for it.
+fn run<S: State>(
+    job: Job<S>,
+    state: S,
+    permits: &Arc<Semaphore>,
+    cancelled: &CancellationToken,
+    tx: &Arc<watch::Sender<Option<Worker<S>>>>,
+    previous: &Arc<std::sync::Mutex<Option<HashMap<String, Value>>>>,
+) -> impl Future<Output = ()> + Send + 'static {
+    let (permits, cancelled, tx, previous) = (
+        permits.clone(),
+        cancelled.clone(),
+        tx.clone(),
+        previous.clone(),
+    );
+    async move {
+        if cancelled.is_cancelled() {
+            return;
+        }
+        let _permit = tokio::select! {
+            permit = permits.acquire() => permit.expect("Semaphore is never closed"),
+            () = cancelled.cancelled() => return,
+        };
+        let mut worker = Worker::new(job, state);
+        worker.run().await.expect("Worker was just created");
+        worker.get_output().await.expect("Worker is running");
+        *previous.lock().unwrap() = Some(worker.data().await);
+        // Nobody waiting for the run is fine.
+        tx.send_replace(Some(worker));
+    }
+}
+
+/// A job given to a [`Scheduler`].
+pub struct ScheduledJob<S: State> {
+    id: usize,
+    cancel: CancellationToken,
+    /// The worker of the last run that finished.
+    runs: watch::Receiver<Option<Worker<S>>>,
+}
+
+impl<S: State> ScheduledJob<S> {
+    /// Identifies the job within its scheduler. Jobs are numbered from `0`, in the order they
+    /// were enqueued.
+    #[must_use]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Do not run the job (again). A run that has already started is allowed to finish.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Returns `true` if the job, or its scheduler, was cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Wait for the next run of the job to finish, and return the worker that ran it, to get
+    /// the output and data from. `None` once the job will not run again, because it was
+    /// cancelled, or did its only run already.
+    ///
+    /// If several runs have finished since the last call, only the latest is returned.
+    pub async fn next_run(&mut self) -> Option<Worker<S>> {
+        self.runs.changed().await.ok()?;
+        self.runs.borrow_and_update().clone()
+    }
+}
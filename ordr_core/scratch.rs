@@ -0,0 +1,59 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use serde_json::Value;
+
+/// Values shared by every node in a job, for things that are not worth a node of their own, like
+/// counters or flags. Producers get it with [`crate::Context::scratch`], and it is part of the
+/// [`crate::JobReport`] of the job.
+///
+/// It is cheap to clone, and clones share their values. Nothing is done to order the nodes that
+/// use it, so it is best kept to values that do not decide what a node produces.
+#[derive(Debug, Clone, Default)]
+pub struct Scratchpad {
+    values: Arc<Mutex<BTreeMap<String, Value>>>,
+}
+
+impl Scratchpad {
+    /// The value of `key`, if it is set.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.lock().get(key).cloned()
+    }
+
+    /// Set `key` to `value`, and return the old value, if any.
+    pub fn insert(&self, key: impl Into<String>, value: Value) -> Option<Value> {
+        self.lock().insert(key.into(), value)
+    }
+
+    /// Unset `key`, and return its value, if any.
+    #[allow(clippy::must_use_candidate)]
+    pub fn remove(&self, key: &str) -> Option<Value> {
+        self.lock().remove(key)
+    }
+
+    /// Set `key` to what `f` makes of its current value, without other nodes changing it in the
+    /// meantime. Say, to count something across nodes.
+    pub fn update(&self, key: impl Into<String>, f: impl FnOnce(Option<Value>) -> Value) {
+        let mut values = self.lock();
+        let key = key.into();
+        let value = f(values.remove(&key));
+        values.insert(key, value);
+    }
+
+    /// All values, by key.
+    #[must_use]
+    pub fn values(&self) -> BTreeMap<String, Value> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, Value>> {
+        // Values are only ever replaced whole, so they are fine even if a node panicked in
+        // `update`.
+        self.values
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
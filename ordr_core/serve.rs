@@ -0,0 +1,282 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json, Router,
+    extract::{self, Path},
+    http::StatusCode,
+    routing::{get, post},
+};
+use serde_json::{Value, json};
+use tokio::{net::ToSocketAddrs, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tower_http::timeout::TimeoutLayer;
+use tracing::{info, warn};
+
+use crate::{Output, State, StoredState, Worker};
+
+/// How long a request may take, from when its headers are read, before it is answered with
+/// `408 Request Timeout`.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A small HTTP server for looking at, and stopping, running jobs. Only with the `serve`
+/// feature.
+///
+/// Add workers with [`Server::add`], and start listening with [`Server::serve`]. Jobs are
+/// identified by [`Worker::job_id`]. Responses are JSON, except for the diagram.
+///
+/// - `GET /jobs`: The ids of every job.
+/// - `GET /jobs/:id`: Labels, [`crate::Progress`] and output (`null` until the job is done).
+/// - `GET /jobs/:id/nodes`: The state of every node that has one, like a [`crate::FileStore`]
+///   keeps it.
+/// - `GET /jobs/:id/data`: The data collected so far, like [`Worker::data`].
+/// - `GET /jobs/:id/mermaid`: A diagram of the job, like [`Worker::mermaid`].
+/// - `POST /jobs/:id/stop`: Stop the job, like [`Worker::stop`].
+///
+/// It is served with axum, on the tokio runtime it is started in. Every connection is served by
+/// a task of its own, so a slow client only holds up itself.
+///
+/// There is no authentication, so do not expose it to anyone who should not be able to stop
+/// jobs.
+pub struct Server<S: State> {
+    workers: Arc<Mutex<BTreeMap<u64, Worker<S>>>>,
+}
+
+impl<S: State> Clone for Server<S> {
+    fn clone(&self) -> Self {
+        Self {
+            workers: self.workers.clone(),
+        }
+    }
+}
+
+impl<S: State> Default for Server<S> {
+    fn default() -> Self {
+        Self {
+            workers: Arc::default(),
+        }
+    }
+}
+
+impl<S: State> Server<S> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `worker` available. Clones share the jobs, so workers can be added after the server
+    /// is started.
+    ///
+    /// # Panics
+    /// If a previous call panicked while holding the lock.
+    pub fn add(&self, worker: &Worker<S>) {
+        let mut workers = self.workers.lock().unwrap();
+        workers.insert(worker.job_id(), worker.clone());
+    }
+
+    /// Stop serving the job with this id. Returns `false` if there was no such job.
+    ///
+    /// # Panics
+    /// If a previous call panicked while holding the lock.
+    #[allow(clippy::must_use_candidate)]
+    pub fn remove(&self, id: u64) -> bool {
+        self.workers.lock().unwrap().remove(&id).is_some()
+    }
+
+    /// Listen on `addr`, on a task of its own, until [`Serving::stop`] is called.
+    ///
+    /// # Errors
+    /// If `addr` can not be listened on.
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime.
+    pub async fn serve(&self, addr: impl ToSocketAddrs) -> io::Result<Serving> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let router = Router::new()
+            .route("/jobs", get(jobs))
+            .route("/jobs/{id}", get(job))
+            .route("/jobs/{id}/nodes", get(nodes))
+            .route("/jobs/{id}/data", get(data))
+            .route("/jobs/{id}/mermaid", get(mermaid))
+            .route("/jobs/{id}/stop", post(stop))
+            .layer(TimeoutLayer::with_status_code(
+                StatusCode::REQUEST_TIMEOUT,
+                REQUEST_TIMEOUT,
+            ))
+            .with_state(self.clone());
+        let stop = CancellationToken::new();
+        let stopped = stop.clone().cancelled_owned();
+        info!(%addr, "Serving jobs");
+        let task = tokio::spawn(async move {
+            let result = axum::serve(listener, router)
+                .with_graceful_shutdown(stopped)
+                .await;
+            if let Err(e) = result {
+                warn!(error = %e, "Could not serve jobs");
+            }
+        });
+        Ok(Serving { addr, stop, task })
+    }
+
+    /// The worker of job `id`, or `404 Not Found`.
+    fn worker(&self, id: u64) -> Result<Worker<S>, StatusCode> {
+        let workers = self.workers.lock().unwrap();
+        workers.get(&id).cloned().ok_or(StatusCode::NOT_FOUND)
+    }
+}
+
+type Served<S> = extract::State<Server<S>>;
+
+async fn jobs<S: State>(extract::State(server): Served<S>) -> Json<Vec<u64>> {
+    let workers = server.workers.lock().unwrap();
+    Json(workers.keys().copied().collect())
+}
+
+async fn job<S: State>(
+    extract::State(server): Served<S>,
+    Path(id): Path<u64>,
+) -> Result<Json<Value>, StatusCode> {
+    let worker = server.worker(id)?;
+    Ok(Json(describe(&worker).await))
+}
+
+async fn nodes<S: State>(
+    extract::State(server): Served<S>,
+    Path(id): Path<u64>,
+) -> Result<Json<BTreeMap<&'static str, StoredState>>, StatusCode> {
+    let status = server.worker(id)?.status().await;
+    let nodes = status
+        .iter()
+        .map(|(name, state)| (*name, StoredState::from(state)))
+        .collect();
+    Ok(Json(nodes))
+}
+
+async fn data<S: State>(
+    extract::State(server): Served<S>,
+    Path(id): Path<u64>,
+) -> Result<Json<Value>, StatusCode> {
+    let data = server.worker(id)?.data().await;
+    Ok(Json(json!(data)))
+}
+
+async fn mermaid<S: State>(
+    extract::State(server): Served<S>,
+    Path(id): Path<u64>,
+) -> Result<String, StatusCode> {
+    Ok(server.worker(id)?.mermaid().await)
+}
+
+async fn stop<S: State>(
+    extract::State(server): Served<S>,
+    Path(id): Path<u64>,
+) -> Result<Json<Value>, StatusCode> {
+    let mut worker = server.worker(id)?;
+    worker.stop().await;
+    Ok(Json(describe(&worker).await))
+}
+
+/// The server of [`Server::serve`]. Dropping it does not stop the server.
+#[derive(Debug)]
+pub struct Serving {
+    addr: SocketAddr,
+    stop: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+impl Serving {
+    /// The address being listened on. Useful when serving on port `0`.
+    #[must_use]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop listening, and wait for the requests that are being handled to finish.
+    pub async fn stop(self) {
+        self.stop.cancel();
+        let _ = self.task.await;
+    }
+}
+
+/// Everything about a job, for `GET /jobs/:id`.
+async fn describe<S: State>(worker: &Worker<S>) -> Value {
+    let progress = worker.progress().await;
+    json!({
+        "id": worker.job_id(),
+        "labels": worker.labels(),
+        "progress": {
+            "total": progress.total,
+            "done": progress.done,
+            "running": progress.running,
+            "failed": progress.failed,
+            "eta": progress.eta.map(|eta| eta.as_secs_f64()),
+        },
+        "output": worker.output().as_ref().map(output),
+    })
+}
+
+fn output(output: &Output) -> Value {
+    let (state, duration) = match output {
+        Output::Done { duration, .. } => ("done", duration),
+        Output::NodeFailed { duration, .. } => ("node_failed", duration),
+        Output::NodePanic { duration, .. } => ("node_panic", duration),
+        Output::Stopped { duration, .. } => ("stopped", duration),
+        Output::Finished { duration, .. } => ("finished", duration),
+        Output::TimedOut { duration, .. } => ("timed_out", duration),
+        Output::Unmet { duration, .. } => ("unmet", duration),
+    };
+    let mut value = json!({
+        "state": state,
+        "duration": duration.as_secs_f64(),
+        "started_at": unix_secs(output.started_at()),
+        "finished_at": unix_secs(output.finished_at()),
+    });
+    let details = match output {
+        Output::NodeFailed {
+            name,
+            retries,
+            error,
+            ..
+        } => json!({ "node": name, "retries": retries, "error": error.message }),
+        Output::NodePanic { name, error, .. } => json!({ "node": name, "error": error }),
+        Output::Finished { failures, .. } => {
+            let failures: Vec<_> = failures
+                .iter()
+                .map(|f| {
+                    json!({
+                        "node": f.name,
+                        "retries": f.retries,
+                        "error": f.error.message,
+                        "panicked": f.panicked,
+                    })
+                })
+                .collect();
+            json!({ "failures": failures })
+        }
+        Output::Unmet { unmet, .. } => {
+            let unmet: Vec<_> = unmet
+                .iter()
+                .map(|u| json!({ "requirement": u.name, "nodes": u.nodes, "error": u.error.message }))
+                .collect();
+            json!({ "unmet": unmet })
+        }
+        _ => json!({}),
+    };
+    if let (Value::Object(value), Value::Object(details)) = (&mut value, details) {
+        value.extend(details);
+    }
+    value
+}
+
+/// Seconds since the unix epoch.
+fn unix_secs(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{NodeState, Snapshot};
+
+/// Persists the state of the nodes in a job, as it changes. Configured with
+/// [`crate::Worker::with_store`], so a job can be picked up again (with [`crate::Worker::restore`])
+/// if the process crashes.
+///
+/// [`JobStore::save`] is called by the worker every time a node changes state, so it should be
+/// reasonably quick.
+pub trait JobStore: Send + Sync + 'static {
+    /// Persist the new state of a node.
+    ///
+    /// # Errors
+    /// If the state could not be persisted. The worker logs the error and carries on.
+    fn save(&self, name: &str, state: &NodeState) -> io::Result<()>;
+
+    /// Everything needed to continue the job.
+    ///
+    /// # Errors
+    /// If the state could not be read.
+    fn load(&self) -> io::Result<Snapshot>;
+}
+
+/// The state of a node, as kept by a [`FileStore`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum StoredState {
+    /// Provided when the job was created.
+    Provided { value: Value },
+    /// Was running when last saved.
+    Running,
+    /// Finished successfully. The value is missing for transient nodes.
+    Done { retries: u32, value: Option<Value> },
+    /// Was being retried.
+    Retrying { retries: u32 },
+    /// Failed, and was not going to be retried.
+    Failed { retries: u32, error: String },
+    /// Was not going to run, since node `by` failed.
+    Blocked { by: String },
+}
+
+impl From<&NodeState> for StoredState {
+    fn from(state: &NodeState) -> Self {
+        match state {
+            NodeState::Provided { value } => Self::Provided {
+                value: value.clone(),
+            },
+            NodeState::Running { .. } => Self::Running,
+            NodeState::Done { retries, value, .. } => Self::Done {
+                retries: *retries,
+                value: value.to_json(),
+            },
+            NodeState::Retrying { retries, .. } => Self::Retrying { retries: *retries },
+            NodeState::Failed { retries, error, .. } => Self::Failed {
+                retries: *retries,
+                error: error.message.clone(),
+            },
+            NodeState::Blocked { by } => Self::Blocked { by: by.to_string() },
+        }
+    }
+}
+
+/// A [`JobStore`] that keeps the state of every node in a single JSON file. The file is
+/// rewritten on every change, so it is meant for jobs with a modest number of nodes.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    nodes: Mutex<HashMap<String, StoredState>>,
+}
+
+impl FileStore {
+    /// Use the file at `path`. If it already exists (say, from a run that crashed), its contents
+    /// are picked up, and can be resumed from with [`JobStore::load`].
+    ///
+    /// # Errors
+    /// If the file exists, but can not be read or parsed.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let nodes = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        let nodes = Mutex::new(nodes);
+        Ok(Self { path, nodes })
+    }
+
+    /// The stored state of every node seen so far.
+    ///
+    /// # Panics
+    /// If a previous call panicked while holding the lock.
+    #[must_use]
+    pub fn nodes(&self) -> HashMap<String, StoredState> {
+        self.nodes.lock().unwrap().clone()
+    }
+}
+
+impl JobStore for FileStore {
+    fn save(&self, name: &str, state: &NodeState) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        nodes.insert(name.to_string(), state.into());
+        // Write to a temporary file first, so a crash never leaves a half written file behind.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&*nodes)?)?;
+        fs::rename(tmp, &self.path)
+    }
+
+    fn load(&self) -> io::Result<Snapshot> {
+        let mut snapshot = Snapshot::default();
+        for (name, state) in self.nodes.lock().unwrap().iter() {
+            match state {
+                StoredState::Provided { value }
+                | StoredState::Done {
+                    value: Some(value), ..
+                } => {
+                    snapshot.values.insert(name.clone(), value.clone());
+                }
+                StoredState::Retrying { retries } | StoredState::Failed { retries, .. } => {
+                    snapshot.retries.insert(name.clone(), *retries);
+                    snapshot.pending.push(name.clone());
+                }
+                StoredState::Running
+                | StoredState::Done { value: None, .. }
+                | StoredState::Blocked { .. } => {
+                    snapshot.pending.push(name.clone());
+                }
+            }
+        }
+        snapshot.pending.sort();
+        Ok(snapshot)
+    }
+}
+
+/// Everything needed to run a job again, from where it got to: its targets, labels, and the
+/// values of the nodes that were provided or done. Created with [`crate::Worker::checkpoint`],
+/// and turned back into a job with [`crate::Job::builder_from_checkpoint`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Names of the nodes the job was solving for. Sorted.
+    pub targets: Vec<String>,
+    /// See [`crate::JobBuilder::label`].
+    pub labels: BTreeMap<String, String>,
+    /// Values of the nodes that were provided or done.
+    pub values: HashMap<String, Value>,
+}
+
+impl Checkpoint {
+    /// Write the checkpoint to `path`, as JSON.
+    ///
+    /// # Errors
+    /// If the file can not be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        // Write to a temporary file first, so a crash never leaves a half written file behind.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Read a checkpoint written with [`Checkpoint::save`].
+    ///
+    /// # Errors
+    /// If the file can not be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, mpsc};
+
+use crate::{Error, Result};
+
+/// How many items a streaming node can get ahead of its dependents.
+const STREAM_CAPACITY: usize = 64;
+
+/// The output of a streaming node (see [`crate::NodeDef::stream_producer`]). Dependents get it
+/// as soon as the producer has started, and read the items as they are emitted.
+///
+/// Clones share the same items, so if several dependents read from it, each item goes to only
+/// one of them. It is meant to have a single reader, that reads until the end.
+pub struct Stream<T> {
+    items: Arc<Mutex<mpsc::Receiver<T>>>,
+}
+
+// Derive would require `T: Clone`.
+impl<T> Clone for Stream<T> {
+    fn clone(&self) -> Self {
+        Self {
+            items: self.items.clone(),
+        }
+    }
+}
+
+impl<T> Stream<T> {
+    /// The next item, or `None` once the producer is done. A producer that fails also ends the
+    /// stream, but the job then fails, so there is no need to check for it.
+    pub async fn next(&self) -> Option<T> {
+        self.items.lock().await.recv().await
+    }
+
+    /// Reads the rest of the items.
+    pub async fn collect(&self) -> Vec<T> {
+        let mut items = self.items.lock().await;
+        let mut all = vec![];
+        while let Some(item) = items.recv().await {
+            all.push(item);
+        }
+        all
+    }
+}
+
+/// Sends the items of a streaming node. Given to the producer by
+/// [`crate::NodeDef::stream_producer`].
+pub struct Emitter<T> {
+    items: mpsc::Sender<T>,
+}
+
+impl<T> Emitter<T> {
+    /// Send the next item. Waits if the dependents are too far behind.
+    ///
+    /// # Errors
+    /// If nobody can read the stream anymore, which only happens when the job is stopping.
+    pub async fn emit(&self, item: T) -> Result<()> {
+        self.items
+            .send(item)
+            .await
+            .map_err(|_| Error::fatal("Stream closed"))
+    }
+}
+
+/// Creates a connected emitter and stream.
+pub(crate) fn channel<T>() -> (Emitter<T>, Stream<T>) {
+    let (tx, rx) = mpsc::channel(STREAM_CAPACITY);
+    let emitter = Emitter { items: tx };
+    let stream = Stream {
+        items: Arc::new(Mutex::new(rx)),
+    };
+    (emitter, stream)
+}
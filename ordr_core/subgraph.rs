@@ -0,0 +1,106 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use crate::{Node, NodeBuilder, NodeId, State};
+
+/// A group of nodes that can be mounted into a job several times, each time under a namespace of
+/// its own. See [`crate::JobBuilder::mount`].
+///
+/// Mounted nodes are named `"{namespace}.{name}"`, so data can be provided for each mount on its
+/// own. Only the nodes added to the subgraph are namespaced. Whatever else they depend on is
+/// shared with the rest of the job.
+#[derive(Clone)]
+pub struct Subgraph<S: State> {
+    nodes: Vec<Node<S>>,
+}
+
+impl<S: State> Default for Subgraph<S> {
+    fn default() -> Self {
+        Self { nodes: vec![] }
+    }
+}
+
+impl<S: State> Subgraph<S> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds node `N` to the subgraph. Like with [`crate::JobBuilder::add`], it is a target of
+    /// the job it is mounted in.
+    #[must_use]
+    pub fn add<N: NodeBuilder<S>>(self) -> Self {
+        self.add_node(N::node())
+    }
+
+    /// Adds a node that was defined at runtime (see [`Node::builder`]).
+    #[must_use]
+    pub fn add_node(mut self, node: Node<S>) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    /// The nodes of the subgraph, mounted in `namespace`.
+    pub(crate) fn mount(&self, namespace: &str) -> Vec<Node<S>> {
+        let namespace = intern(namespace.to_string());
+        let members: Arc<HashSet<_>> = Arc::new(self.nodes.iter().map(|node| node.id).collect());
+        self.nodes
+            .iter()
+            .map(|node| namespaced(node.clone(), namespace, &members))
+            .collect()
+    }
+}
+
+/// Moves `node` into `namespace`, along with the dependencies that are `members` of the subgraph.
+fn namespaced<S: State>(
+    node: Node<S>,
+    namespace: &'static str,
+    members: &Arc<HashSet<NodeId>>,
+) -> Node<S> {
+    let rename = |id: NodeId| {
+        if members.contains(&id) {
+            id.in_namespace(namespace)
+        } else {
+            id
+        }
+    };
+    let optional = node.optional.iter().copied().map(rename).collect();
+    let after = node.after.iter().copied().map(rename).collect();
+    let deps = node.deps.clone();
+    let inner = members.clone();
+    Node {
+        name: intern(format!("{namespace}.{}", node.name)),
+        id: node.id.in_namespace(namespace),
+        // Dependencies are only created when the job is built, so they are moved then.
+        deps: Arc::new(move || {
+            deps()
+                .into_iter()
+                .map(|dep| {
+                    if inner.contains(&dep.id) {
+                        namespaced(dep, namespace, &inner)
+                    } else {
+                        dep
+                    }
+                })
+                .collect()
+        }),
+        optional,
+        after,
+        ..node
+    }
+}
+
+/// Leaks `s`, unless an equal string was leaked before. Jobs that mount the same subgraph over
+/// and over (say, one per request) then don't keep using more memory.
+pub(crate) fn intern(s: String) -> &'static str {
+    static INTERNED: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(Mutex::default);
+    let mut interned = INTERNED.lock().unwrap();
+    if let Some(s) = interned.get(s.as_str()) {
+        return s;
+    }
+    let s = Box::leak(s.into_boxed_str());
+    interned.insert(s);
+    s
+}
@@ -0,0 +1,43 @@
+//! Runs a small job on wasm, where the clock and the event loop are the browser's. Run with
+//! `wasm-pack test --node ordr_core`.
+#![cfg(target_arch = "wasm32")]
+
+use std::time::Duration;
+
+use ordr_core::{Context, Driver, Error, Job, Node, Worker, serde_json::json};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// `B` fails the first time, so it is retried once its timer is done.
+fn job() -> Job<()> {
+    let a = Node::builder("A").producer(|_: Context<()>, (): ()| async { Ok(1u8) });
+    let b = |ctx: Context<()>, (a,): (u8,)| async move {
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Not yet", Duration::from_millis(5)));
+        }
+        Ok(u16::from(a) + 1)
+    };
+    let b = Node::builder("B").dep_on::<u8>(a).producer(b);
+    Job::builder().add_node(b).build().unwrap()
+}
+
+#[wasm_bindgen_test]
+async fn worker() {
+    let mut worker = Worker::new(job(), ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["B"], json!(2));
+}
+
+#[wasm_bindgen_test]
+async fn driver() {
+    let mut driver = Driver::new(job());
+    while !driver.is_done() {
+        for run in driver.ready() {
+            let id = run.id;
+            let result = run.run(()).await;
+            driver.complete(id, result).unwrap();
+        }
+    }
+    assert!(driver.failures().is_empty());
+    assert_eq!(driver.data()["B"], json!(2));
+}
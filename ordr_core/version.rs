@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+/// Upgrades data provided for a node from an older version (`None` if the data had no version)
+/// to the version the node has now, or describes why it could not. See
+/// [`crate::JobBuilder::migrate`].
+pub type Migrator = Arc<dyn Fn(Option<u32>, Value) -> Result<Value, String> + Send + Sync>;
+
+const VERSION: &str = "__v";
+const VALUE: &str = "value";
+
+/// Wraps the value of a versioned node, as it is in [`crate::Worker::data`].
+pub(crate) fn versioned(version: u32, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(VERSION.into(), version.into());
+    map.insert(VALUE.into(), value);
+    Value::Object(map)
+}
+
+/// Splits a versioned value into its version and the value itself. Anything else has no
+/// version, and is returned as is.
+pub(crate) fn unversioned(value: Value) -> (Option<u32>, Value) {
+    match value {
+        Value::Object(mut map) if is_versioned(&map) => {
+            let version = map[VERSION].as_u64().and_then(|v| u32::try_from(v).ok());
+            (version, map.remove(VALUE).unwrap_or_default())
+        }
+        value => (None, value),
+    }
+}
+
+/// Like [`unversioned`], but only looks at the value.
+pub(crate) fn unversioned_ref(value: &Value) -> &Value {
+    match value {
+        Value::Object(map) if is_versioned(map) => &map[VALUE],
+        value => value,
+    }
+}
+
+fn is_versioned(map: &Map<String, Value>) -> bool {
+    map.len() == 2 && map.get(VERSION).is_some_and(Value::is_u64) && map.contains_key(VALUE)
+}
@@ -1,67 +1,758 @@
 use std::{
-    any::TypeId,
-    collections::{HashMap, HashSet},
-    sync::Arc,
-    time::{Duration, Instant},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    pin::Pin,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime},
 };
 
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::{
-    sync::Mutex,
-    task::{JoinHandle, JoinSet},
+    runtime::Handle,
+    sync::{Mutex, broadcast, mpsc, oneshot, watch},
+    task::AbortHandle,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span, error, field, info, info_span, warn};
+
+use crate::{
+    AttemptInfo, BlobStore, Cache, Checkpoint, Context, DataDiff, Error, ExtractError, Format,
+    FromData, Hook, Job, JobReport, JobStore, NodeBuilder, NodeFailure, NodeId, Output, Payload,
+    Producer, Quarantine, Recorder, Replayer, Scratchpad, State, Task, blob, cache_key, diff,
+    dispatch::Dispatcher,
+    fair::PoolShare,
+    rate::RateLimit,
+    rt::{self, Instant, Tasks},
+    unversioned, versioned,
 };
-use tracing::{error, info, warn};
 
-use crate::{Context, Error, Job, Output, State};
+/// A request to start the next node. The scheduler answers with the set of nodes that were ready,
+/// or why the request was turned down.
+type Step = oneshot::Sender<Result<Vec<&'static str>, &'static str>>;
 
+#[allow(clippy::large_enum_variant)] // There is only one per worker
 enum Mode<S: State> {
-    Init { job: Job<S>, state: S },
-    Running(Instant, JoinHandle<Output>),
+    Init {
+        job: Job<S>,
+        state: S,
+        steps: mpsc::UnboundedReceiver<Step>,
+    },
+    Running(Instant, Task<Output>),
     Done(Output),
 }
 
+/// Settings for how the worker should run the job.
+#[derive(Clone, Default)]
+struct Config {
+    /// Only start one node at a time, and only when [`Worker::step`] is called.
+    stepping: bool,
+    /// Pause right before starting any of these nodes, until [`Worker::step`] is called.
+    breakpoints: HashSet<NodeId>,
+    /// Retries already spent on nodes, when restored from a [`Snapshot`].
+    retries: HashMap<NodeId, u32>,
+    /// Shared between workers, to stop running nodes that keep failing.
+    quarantine: Option<Quarantine>,
+    /// Where to log scheduling decisions, if asked to.
+    decisions: Option<Arc<std::sync::Mutex<Vec<Decision>>>>,
+    /// Where to persist node states, as they change.
+    store: Option<Arc<dyn JobStore>>,
+    /// Where to look for outputs from earlier runs, and keep new ones.
+    cache: Option<Arc<dyn Cache>>,
+    /// Where to keep outputs larger than the given number of bytes.
+    blobs: Option<(Arc<dyn BlobStore>, usize)>,
+    /// Sizes above which outputs of these nodes are kept in the blob store, instead of the
+    /// default one.
+    output_limits: HashMap<NodeId, usize>,
+    /// How many times a node may be retried, unless the node says otherwise.
+    max_retries: Option<u32>,
+    /// How many nodes may run at the same time.
+    max_concurrency: Option<usize>,
+    /// The node slots this job shares with the other jobs of a pool.
+    share: Option<PoolShare>,
+    /// How often nodes with each rate tag may be started.
+    rate_limits: HashMap<String, RateLimit>,
+    /// Where to run blocking nodes, instead of the blocking thread pool.
+    blocking_runtime: Option<Handle>,
+    /// Tells the job apart from others, in logs and metrics.
+    label: Option<Arc<str>>,
+    /// How the outputs of nodes are serialized.
+    format: Format,
+    /// What to do when a node fails.
+    failure_policy: FailurePolicy,
+    /// How long nodes are expected to take, for [`Worker::progress`].
+    duration_hints: Arc<DurationHints>,
+    /// Where to record attempts at running nodes, or replay them from.
+    journal: Option<Journal>,
+    /// The versions of the nodes that have one, for [`Worker::data`].
+    versions: Arc<HashMap<&'static str, u32>>,
+    /// Shared by the nodes of the job. See [`Worker::scratch`].
+    scratch: Scratchpad,
+}
+
+/// Records attempts at running nodes, or replays them. See [`Worker::record`] and
+/// [`Worker::replay`].
+#[derive(Clone)]
+enum Journal {
+    Record(Recorder),
+    Replay(Replayer),
+}
+
+/// How long nodes are expected to take, by name. See [`Worker::duration_hints`].
+pub type DurationHints = HashMap<String, Duration>;
+
+/// What a worker does when a node fails (after its retries) or panics. Set with
+/// [`Worker::on_failure`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FailurePolicy {
+    /// Stop the job right away. Whatever else is running is aborted.
+    #[default]
+    FailFast,
+    /// Keep running the nodes that do not depend on the failed node. The nodes that do are
+    /// [`NodeState::Blocked`]. Once nothing more can run, the job finishes with the first
+    /// failure as its output, and [`Worker::status`] has the result of every node.
+    ContinueUnaffected,
+    /// Like [`FailurePolicy::ContinueUnaffected`], but if any node failed, the job finishes with
+    /// [`Output::Finished`], listing all of them.
+    CollectAll,
+}
+
+/// Used to give every job its own id.
+pub(crate) static NEXT_JOB_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How many events a subscriber can fall behind, before it misses some.
+const EVENT_CAPACITY: usize = 1024;
+
+/// How long failures are remembered for [`Worker::health`].
+const MAX_FAILURE_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Counters behind [`Worker::health`]. Kept apart from the node states, so reading them never
+/// waits for the job.
+#[derive(Debug)]
+struct Counters {
+    created: Instant,
+    running_jobs: AtomicUsize,
+    running_nodes: AtomicUsize,
+    /// When nodes failed, oldest first.
+    failures: std::sync::Mutex<VecDeque<Instant>>,
+}
+
+impl Counters {
+    fn failed(&self) {
+        let now = rt::now();
+        let mut failures = self.failures.lock().unwrap();
+        while failures
+            .front()
+            .is_some_and(|t| now - *t > MAX_FAILURE_WINDOW)
+        {
+            failures.pop_front();
+        }
+        failures.push_back(now);
+    }
+}
+
 /// Runs [`crate::Job`]s.
 #[derive(Clone)]
 pub struct Worker<S: State> {
     out: Arc<Mutex<HashMap<&'static str, NodeState>>>,
     mode: Arc<Mutex<Option<Mode<S>>>>, // Option because of ownership fun
+    config: Config,
+    steps: mpsc::UnboundedSender<Step>,
+    /// Names of the nodes that the job will run, and the names of their dependencies.
+    deps: Arc<HashMap<&'static str, Vec<&'static str>>>,
+    /// Names of the nodes of the job, which differ from [`crate::Node::name`] if renamed with
+    /// [`crate::JobBuilder::rename`].
+    names: Arc<HashMap<NodeId, &'static str>>,
+    counters: Arc<Counters>,
+    /// Cancelled when the worker should drain (see [`Worker::drain`]).
+    draining: CancellationToken,
+    /// Cancelled when the job has finished running.
+    finished: CancellationToken,
+    /// Cancelled by [`Worker::stop`], which ends the job right away.
+    stopping: CancellationToken,
+    /// Cancelled when the job is stopped, drained or times out. Given to producers, see
+    /// [`Context::cancelled`].
+    cancellation: CancellationToken,
+    /// The output, once the job has finished. Watched by [`RunHandle`]s.
+    output: Arc<watch::Sender<Option<Output>>>,
+    events: broadcast::Sender<JobEvent>,
+    job_id: u64,
+    labels: Arc<BTreeMap<String, String>>,
+    /// Names of the nodes the job solves for, sorted.
+    targets: Arc<Vec<&'static str>>,
+    /// When the job was started. See [`Worker::started_at`].
+    started_at: Arc<OnceLock<SystemTime>>,
+    /// Called around every node. See [`Worker::add_hook`].
+    hooks: Vec<Arc<dyn Hook<S>>>,
+    /// The producer of each node: its name, or the name of the variant that was selected.
+    producers: Arc<HashMap<&'static str, &'static str>>,
+    /// Where values came from, when the node states can not tell: values restored from a
+    /// [`Snapshot`], and values set with [`Worker::set_value`]. See [`Worker::provenance`].
+    provenance: Arc<std::sync::Mutex<HashMap<String, Provenance>>>,
 }
 
 impl<S: State> Worker<S> {
     /// Create a new worker.
     pub fn new(job: Job<S>, state: S) -> Self {
+        let (tx, steps) = mpsc::unbounded_channel();
+        let name = |id| {
+            job.nodes
+                .get(id)
+                .map_or_else(|| job.provided[id].0, |n| n.name)
+        };
+        let deps = job
+            .adj
+            .iter()
+            .map(|(id, deps)| (name(id), deps.iter().map(name).collect()))
+            .collect();
+        let names = job.nodes.keys().chain(job.provided.keys());
+        let names = names.map(|id| (*id, name(id))).collect();
+        let labels = Arc::new(job.labels.clone());
+        let mut targets: Vec<_> = job.targets.iter().map(name).collect();
+        targets.sort_unstable();
+        let producers = job
+            .nodes
+            .iter()
+            .map(|(id, node)| (name(id), node.variant.unwrap_or(node.name)))
+            .collect();
+        let config = Config {
+            versions: Arc::new(job.versions.clone()),
+            ..Config::default()
+        };
         Self {
             out: Arc::new(Mutex::new(HashMap::new())),
-            mode: Arc::new(Mutex::new(Some(Mode::Init { job, state }))),
+            mode: Arc::new(Mutex::new(Some(Mode::Init { job, state, steps }))),
+            config,
+            steps: tx,
+            deps: Arc::new(deps),
+            names: Arc::new(names),
+            counters: Arc::new(Counters {
+                created: rt::now(),
+                running_jobs: AtomicUsize::new(0),
+                running_nodes: AtomicUsize::new(0),
+                failures: std::sync::Mutex::default(),
+            }),
+            draining: CancellationToken::new(),
+            finished: CancellationToken::new(),
+            stopping: CancellationToken::new(),
+            cancellation: CancellationToken::new(),
+            output: Arc::new(watch::channel(None).0),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            job_id: NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed),
+            labels,
+            targets: Arc::new(targets),
+            started_at: Arc::default(),
+            hooks: vec![],
+            producers: Arc::new(producers),
+            provenance: Arc::default(),
+        }
+    }
+
+    /// When the job was started, if it has been. The offsets in [`NodeState`] and [`Output`] are
+    /// from this time.
+    #[must_use]
+    pub fn started_at(&self) -> Option<SystemTime> {
+        self.started_at.get().copied()
+    }
+
+    /// The values the nodes of the job shared with [`Context::scratch`].
+    #[must_use]
+    pub fn scratch(&self) -> &Scratchpad {
+        &self.config.scratch
+    }
+
+    /// The labels set on the job with [`crate::JobBuilder::label`].
+    #[must_use]
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        &self.labels
+    }
+
+    /// Identifies the job, and is unique within the process. Everything the worker logs is in a
+    /// `job` span with this id, and producers get it as [`Context::job_id`].
+    #[must_use]
+    pub fn job_id(&self) -> u64 {
+        self.job_id
+    }
+
+    /// Create a worker that continues where a [`Snapshot`] left off. Values from the snapshot are
+    /// treated as provided data, but keep their [`Provenance`], and nodes that were retrying keep
+    /// their retry count.
+    pub fn restore(mut job: Job<S>, state: S, mut snapshot: Snapshot) -> Self {
+        let mut provenance = HashMap::new();
+        for (name, value) in snapshot.values {
+            let value = match (job.versions.get(name.as_str()), unversioned(value)) {
+                (None, (_, value)) => value,
+                (Some(version), (Some(found), value)) if found == *version => value,
+                (Some(version), (found, _)) => {
+                    warn!(
+                        name,
+                        ?found,
+                        version,
+                        "Snapshot has another version. Discarding."
+                    );
+                    continue;
+                }
+            };
+            if !job.provide(&name, value) {
+                warn!(name, "Did not find node from the snapshot. Discarding.");
+                continue;
+            }
+            if let Some(source) = snapshot.provenance.remove(&name) {
+                provenance.insert(name, source);
+            }
+        }
+        let retries = job
+            .nodes
+            .iter()
+            .filter_map(|(id, node)| Some((*id, *snapshot.retries.get(node.name)?)))
+            .collect();
+        let mut worker = Self::new(job, state);
+        worker.config.retries = retries;
+        worker.provenance = Arc::new(std::sync::Mutex::new(provenance));
+        worker
+    }
+
+    /// Run the job in step mode. At most one node runs at a time, and before each node is
+    /// started, the worker waits for a call to [`Worker::step`].
+    ///
+    /// Useful for debugging surprising execution orders.
+    #[must_use]
+    pub fn step_mode(mut self) -> Self {
+        self.config.stepping = true;
+        self
+    }
+
+    /// Pause the job right before node `N` starts. While paused, you can inspect
+    /// [`Worker::data`] and change values with [`Worker::set_value`]. Call [`Worker::step`] to
+    /// continue.
+    #[must_use]
+    pub fn break_on<N: NodeBuilder<S>>(mut self) -> Self {
+        self.config.breakpoints.insert(N::node().id);
+        self
+    }
+
+    /// Record node failures in `quarantine`, and refuse to run the job if any of its nodes are
+    /// quarantined. The job then fails right away with [`Output::NodeFailed`].
+    #[must_use]
+    pub fn with_quarantine(mut self, quarantine: Quarantine) -> Self {
+        self.config.quarantine = Some(quarantine);
+        self
+    }
+
+    /// Let a quarantined node run again (see [`Worker::with_quarantine`]). Returns `false` if the
+    /// node was not quarantined.
+    #[allow(clippy::must_use_candidate)]
+    pub fn unquarantine(&self, name: &str) -> bool {
+        self.config
+            .quarantine
+            .as_ref()
+            .is_some_and(|quarantine| quarantine.clear(name))
+    }
+
+    /// Retry a node at most `max_retries` times. After that, it fails, even if it asks to be
+    /// retried. Nodes can set their own limit (see [`crate::Node::max_retries`]), which wins over
+    /// this one. Without a limit, nodes are retried for as long as they ask to be.
+    #[must_use]
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.config.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Run at most `max_concurrency` nodes at the same time. Nodes that are ready wait for a
+    /// running node to finish. Nodes that are being retried go first.
+    ///
+    /// # Panics
+    /// If `max_concurrency` is `0`.
+    #[must_use]
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        assert!(max_concurrency > 0, "Max concurrency must be at least 1");
+        self.config.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// Take turns running nodes with the other jobs of a pool. See
+    /// [`crate::WorkerPool::max_nodes`].
+    #[must_use]
+    pub(crate) fn share(mut self, share: PoolShare) -> Self {
+        self.config.share = Some(share);
+        self
+    }
+
+    /// Start at most `starts` nodes tagged `tag` (see [`crate::NodeDef::rate`], or
+    /// `#[producer(rate = "openai")]`) within any `per`, say to stay within the quota of an
+    /// external API. Retries count as well. Nodes that would go over wait until they can start.
+    ///
+    /// # Panics
+    /// If `starts` is `0`.
+    #[must_use]
+    pub fn rate_limit(mut self, tag: &str, starts: u32, per: Duration) -> Self {
+        assert!(starts > 0, "A rate limit must allow at least 1 start");
+        let limit = RateLimit { starts, per };
+        self.config.rate_limits.insert(tag.to_string(), limit);
+        self
+    }
+
+    /// How long nodes are expected to take, say from an earlier run (see [`Worker::durations`]).
+    /// Used to estimate when the job will be done. See [`Worker::progress`].
+    #[must_use]
+    pub fn duration_hints(mut self, hints: DurationHints) -> Self {
+        self.config.duration_hints = Arc::new(hints);
+        self
+    }
+
+    /// Name the kind of job the worker runs, say `"import"`. It is put on the tracing span of the
+    /// job, and, with the `metrics` feature, on the metrics of its nodes.
+    #[must_use]
+    pub fn label(mut self, label: &str) -> Self {
+        self.config.label = Some(label.into());
+        self
+    }
+
+    /// Decide what happens when a node fails. Defaults to [`FailurePolicy::FailFast`].
+    #[must_use]
+    pub fn on_failure(mut self, policy: FailurePolicy) -> Self {
+        self.config.failure_policy = policy;
+        self
+    }
+
+    /// Serialize the outputs of nodes with `format`, instead of as JSON. See [`Format`].
+    #[must_use]
+    pub fn format(mut self, format: Format) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    /// Run blocking nodes (see [`crate::Node::blocking`]) on the tokio `runtime`, instead of the
+    /// blocking threads of the runtime the job runs on. Handy for keeping heavy nodes on a runtime
+    /// of their own, with a fixed number of threads.
+    #[must_use]
+    pub fn blocking_runtime(mut self, runtime: Handle) -> Self {
+        self.config.blocking_runtime = Some(runtime);
+        self
+    }
+
+    /// Persist every change to the state of a node in `store`. To resume a job that did not
+    /// finish, pass [`JobStore::load`] to [`Worker::restore`].
+    #[must_use]
+    pub fn with_store(mut self, store: impl JobStore) -> Self {
+        self.config.store = Some(Arc::new(store));
+        self
+    }
+
+    /// Look for the output of each node in `cache` before running it, and store outputs there
+    /// once they are done. Nodes with the same inputs as an earlier run are then not run again.
+    /// Meant for nodes that only depend on their inputs, not on the state or the outside world.
+    #[must_use]
+    pub fn with_cache(mut self, cache: impl Cache) -> Self {
+        self.config.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Keep outputs that serialize to more than `max_output_size` bytes in `store`, instead of in
+    /// memory. Only a reference to them is kept (see [`BlobStore`]), and dependents get them
+    /// loaded back. Override the size for single nodes with [`Worker::max_output_size`].
+    #[must_use]
+    pub fn with_blob_store(mut self, store: impl BlobStore, max_output_size: usize) -> Self {
+        self.config.blobs = Some((Arc::new(store), max_output_size));
+        self
+    }
+
+    /// Keep outputs of node `N` in the blob store if they serialize to more than `bytes` bytes.
+    /// Only has an effect with [`Worker::with_blob_store`].
+    #[must_use]
+    pub fn max_output_size<N: NodeBuilder<S>>(mut self, bytes: usize) -> Self {
+        self.config.output_limits.insert(N::node().id, bytes);
+        self
+    }
+
+    /// Keep a [`crate::Record`] of every attempt at running a node in `recorder`, so the job can
+    /// be replayed later (see [`Worker::replay`]).
+    #[must_use]
+    pub fn record(mut self, recorder: Recorder) -> Self {
+        self.config.journal = Some(Journal::Record(recorder));
+        self
+    }
+
+    /// Run the job from what was recorded earlier, instead of running the producers. Meant for
+    /// debugging a job with exactly the data that went through it. See [`Replayer`].
+    #[must_use]
+    pub fn replay(mut self, replayer: Replayer) -> Self {
+        self.config.journal = Some(Journal::Replay(replayer));
+        self
+    }
+
+    /// Call `hook` around every node the worker runs. Hooks are called in the order they were
+    /// added. See [`Hook`].
+    #[must_use]
+    pub fn add_hook(mut self, hook: impl Hook<S>) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Keep a log of why each node was, or was not, started at each scheduling step. Get it with
+    /// [`Worker::decisions`]. Meant for debugging, since the log grows with every step.
+    #[must_use]
+    pub fn log_decisions(mut self) -> Self {
+        self.config.decisions = Some(Arc::default());
+        self
+    }
+
+    /// The scheduling decisions made so far, in order. Empty unless
+    /// [`Worker::log_decisions`] was used.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn decisions(&self) -> Vec<Decision> {
+        self.config
+            .decisions
+            .as_ref()
+            .map(|decisions| decisions.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    /// Let the worker start the next node, when running in step mode or paused on a breakpoint.
+    ///
+    /// Returns the nodes that were ready to start at this step, sorted by name. The first one is
+    /// the one that was started. If the job has finished, the list is empty.
+    ///
+    /// # Errors
+    /// If the worker has not been started, since nothing would answer. Outside of step mode, if
+    /// the job was not paused when the step was asked for: it does not carry over to the next
+    /// breakpoint.
+    pub async fn step(&self) -> Result<Vec<&'static str>, &'static str> {
+        if matches!(*self.mode.lock().await, Some(Mode::Init { .. })) {
+            return Err("Has not been started");
+        }
+        let (tx, rx) = oneshot::channel();
+        if self.steps.send(tx).is_err() {
+            return Ok(vec![]);
+        }
+        // Dropped without an answer once the job has ended.
+        rx.await.unwrap_or(Ok(vec![]))
+    }
+
+    /// Like [`Worker::step`], but waits for the node that was started to finish (retries
+    /// included) before returning, so progress on the job can be interleaved with other work,
+    /// one node at a time. Meant for step mode (see [`Worker::step_mode`]), where nothing else
+    /// runs until the next step.
+    ///
+    /// Returns [`StepResult::Finished`] once the job has ended.
+    ///
+    /// # Errors
+    /// If the worker has not been started.
+    #[allow(clippy::missing_panics_doc)]
+    pub async fn step_node(&self) -> Result<StepResult, &'static str> {
+        // Subscribe first, so we do not miss the node finishing.
+        let mut events = self.subscribe();
+        let ready = self.step().await?;
+        let Some(&name) = ready.first() else {
+            let mut output = self.output.subscribe();
+            let output = output.wait_for(Option::is_some).await;
+            let output = output.ok().and_then(|output| output.clone());
+            return Ok(StepResult::Finished(output.expect("The job has ended")));
+        };
+        loop {
+            let finished = match events.recv().await {
+                Ok(JobEvent::NodeDone { name: n, .. } | JobEvent::NodeFailed { name: n, .. }) => {
+                    n == name
+                }
+                Ok(JobEvent::JobDone { output }) => return Ok(StepResult::Finished(output)),
+                Ok(_) => false,
+                // We may have missed it, so look for ourselves.
+                Err(broadcast::error::RecvError::Lagged(_)) => matches!(
+                    self.out.lock().await.get(name),
+                    Some(NodeState::Done { .. } | NodeState::Failed { .. })
+                ),
+                Err(broadcast::error::RecvError::Closed) => unreachable!("We hold a sender"),
+            };
+            if finished {
+                let state = self.out.lock().await[name].clone();
+                return Ok(StepResult::Ran { name, ready, state });
+            }
         }
     }
 
-    /// Start running the job.
+    /// Start running the job. The returned handle can be awaited for the [`Output`], and
+    /// aborted. See [`RunHandle`].
     ///
     /// # Errors
     /// If the worker has already started working.
     #[allow(clippy::missing_panics_doc)]
-    pub async fn run(&mut self) -> Result<(), &'static str> {
+    pub async fn run(&mut self) -> Result<RunHandle<S>, &'static str> {
+        self.start(None).await
+    }
+
+    /// Like [`Worker::run`], but stops the job if it has not finished within `timeout`. The
+    /// running nodes are aborted, and the output is [`Output::TimedOut`].
+    ///
+    /// # Errors
+    /// If the worker has already started working.
+    pub async fn run_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<RunHandle<S>, &'static str> {
+        self.start(Some(timeout)).await
+    }
+
+    async fn start(&mut self, timeout: Option<Duration>) -> Result<RunHandle<S>, &'static str> {
         let mut mode = self.mode.lock().await;
-        let Mode::Init { job, state } = std::mem::take(&mut *mode).unwrap() else {
+        let Mode::Init { job, state, steps } = std::mem::take(&mut *mode).unwrap() else {
             return Err("Has already been started");
         };
-        let t0 = Instant::now();
-        let fut = run_job(job, state, self.out.clone(), t0);
-        let handle = tokio::spawn(fut);
+        let t0 = rt::now();
+        let started_at = rt::system_now();
+        // Only ever set here, and the worker can only be started once.
+        let _ = self.started_at.set(started_at);
+        let config = self.config.clone();
+        let counters = self.counters.clone();
+        let fut = run_job(
+            job,
+            state,
+            config,
+            steps,
+            self.out.clone(),
+            counters.clone(),
+            self.draining.clone(),
+            self.cancellation.clone(),
+            self.events.clone(),
+            self.job_id,
+            self.labels.clone(),
+            self.hooks.clone().into(),
+            t0,
+            started_at,
+        )
+        .instrument(info_span!(
+            "job",
+            job_id = self.job_id,
+            label = self.config.label.as_deref(),
+            labels = ?self.labels,
+        ));
+        counters.running_jobs.fetch_add(1, Ordering::Relaxed);
+        let finished = self.finished.clone();
+        let stopping = self.stopping.clone();
+        let cancellation = self.cancellation.clone();
+        let events = self.events.clone();
+        let output_tx = self.output.clone();
+        let out = self.out.clone();
+        let versions = self.config.versions.clone();
+        let handle = rt::spawn(async move {
+            let run = async {
+                match timeout {
+                    // Dropping the job aborts whatever is running.
+                    Some(timeout) => {
+                        if let Some(output) = rt::timeout(timeout, fut).await {
+                            output
+                        } else {
+                            warn!(?timeout, "Job timed out");
+                            cancellation.cancel();
+                            Output::TimedOut {
+                                duration: t0.elapsed(),
+                                started_at,
+                                finished_at: rt::system_now(),
+                                data: Arc::new(data(&*out.lock().await, &versions)),
+                            }
+                        }
+                    }
+                    None => fut.await,
+                }
+            };
+            let output = tokio::select! {
+                output = run => output,
+                () = stopping.cancelled() => Output::Stopped {
+                    duration: t0.elapsed(),
+                    started_at,
+                    finished_at: rt::system_now(),
+                    data: Arc::new(data(&*out.lock().await, &versions)),
+                },
+            };
+            // Whatever was still running was aborted along with the job.
+            counters.running_nodes.store(0, Ordering::Relaxed);
+            counters.running_jobs.fetch_sub(1, Ordering::Relaxed);
+            finished.cancel();
+            // `stop` has already sent its own output.
+            output_tx.send_if_modified(|sent| {
+                if sent.is_some() {
+                    return false;
+                }
+                *sent = Some(output.clone());
+                true
+            });
+            let _ = events.send(JobEvent::JobDone {
+                output: output.clone(),
+            });
+            output
+        });
         *mode = Some(Mode::Running(t0, handle));
-        Ok(())
+        Ok(RunHandle {
+            worker: self.clone(),
+            output: self.output.subscribe(),
+        })
+    }
+
+    /// Get notified as nodes start, finish and fail, and when the job is done. Only events that
+    /// happen after subscribing are received, so subscribe before calling [`Worker::run`] to
+    /// get all of them.
+    ///
+    /// A subscriber that falls far behind misses events, and gets a
+    /// [`broadcast::error::RecvError::Lagged`].
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
     }
 
-    /// Stop the worker. All currently running nodes will be aborted.
+    /// Stop the worker. All currently running nodes will be aborted. Producers can see it
+    /// coming with [`Context::cancelled`].
     #[allow(clippy::missing_panics_doc)]
     pub async fn stop(&mut self) {
         let mut mode = self.mode.lock().await;
         let Mode::Running(t0, _) = mode.as_ref().unwrap() else {
             return;
         };
-        let duration = t0.elapsed();
-        *mode = Some(Mode::Done(Output::Stopped { duration }));
+        let output = Output::Stopped {
+            duration: t0.elapsed(),
+            started_at: *self.started_at.get().unwrap(),
+            finished_at: rt::system_now(),
+            data: Arc::new(self.data().await),
+        };
+        self.output.send_replace(Some(output.clone()));
+        *mode = Some(Mode::Done(output));
+        self.cancellation.cancel();
+        self.stopping.cancel();
+    }
+
+    /// Stop the worker gracefully. No new nodes are started (nor retried), but the running nodes
+    /// are allowed to finish. Once they have, the job stops, and a [`Snapshot`] is returned, that
+    /// can be continued with [`Worker::restore`].
+    ///
+    /// Running producers are told to wrap up through [`Context::cancelled`].
+    pub async fn drain(&mut self) -> Snapshot {
+        info!("Draining");
+        self.draining.cancel();
+        self.cancellation.cancel();
+        // Fails if the worker never ran, which is fine. There is nothing to wait for.
+        let _ = self.get_output().await;
+        self.snapshot().await
+    }
+
+    /// Drain the worker (see [`Worker::drain`]) once `signal` completes, say on
+    /// `tokio::signal::ctrl_c()`. The returned handle resolves to the snapshot taken after
+    /// draining, so it can be saved before the program exits.
+    ///
+    /// If the job finishes before the signal, the handle resolves to a snapshot of the finished
+    /// job right away.
+    pub fn attach_shutdown<F>(&self, signal: F) -> Task<Snapshot>
+    where
+        F: Future + Send + 'static,
+    {
+        let mut worker = self.clone();
+        rt::spawn(async move {
+            tokio::select! {
+                () = async { signal.await; } => worker.drain().await,
+                () = worker.finished.cancelled() => worker.snapshot().await,
+            }
+        })
     }
 
     /// Wait for the worker to finish and return the [`Output`].
@@ -89,20 +780,632 @@ impl<S: State> Worker<S> {
         Ok(output)
     }
 
+    /// The output, if the job has finished. Unlike [`Worker::get_output`], it does not wait.
+    #[must_use]
+    pub fn output(&self) -> Option<Output> {
+        self.output.borrow().clone()
+    }
+
     /// Return the data collected from running the job.
     pub async fn data(&self) -> HashMap<String, Value> {
-        let mut data = HashMap::new();
+        data(&*self.out.lock().await, &self.config.versions)
+    }
+
+    /// Pull the values of several nodes out of [`Worker::data`] at once, into a struct that
+    /// derives [`FromData`].
+    ///
+    /// # Errors
+    /// If a value is missing, or can not be deserialized.
+    pub async fn extract<T: FromData>(&self) -> Result<T, ExtractError> {
+        T::from_data(&self.data().await)
+    }
+
+    /// The value of node `T`, if it was provided or has finished.
+    ///
+    /// # Errors
+    /// If the value can not be deserialized into `T` (say, it was changed with
+    /// [`Worker::set_value`], or written by an older version of the node), or loaded from the
+    /// blob store (see [`Worker::with_blob_store`]).
+    pub async fn get<T: NodeBuilder<S>>(&self) -> Result<Option<T>, ExtractError> {
+        let name = self.name_of::<T>();
+        let Some(payload) = self.out.lock().await.get(name).and_then(payload) else {
+            return Ok(None);
+        };
+        self.decode(name, payload).map(Some)
+    }
+
+    /// Like [`Worker::get`], but removes the node from the worker. It is then no longer part of
+    /// [`Worker::data`] or [`Worker::status`].
+    ///
+    /// # Errors
+    /// If the value can not be deserialized into `T`, or loaded from the blob store. The node is
+    /// then left as it was.
+    pub async fn take<T: NodeBuilder<S>>(&self) -> Result<Option<T>, ExtractError> {
+        let name = self.name_of::<T>();
+        let mut out = self.out.lock().await;
+        let Some(payload) = out.get(name).and_then(payload) else {
+            return Ok(None);
+        };
+        let value = self.decode(name, payload)?;
+        out.remove(name);
+        Ok(Some(value))
+    }
+
+    /// Loads `payload` from the blob store, if it is kept there, and turns it into `T`.
+    fn decode<T: NodeBuilder<S>>(
+        &self,
+        name: &'static str,
+        payload: Payload,
+    ) -> Result<T, ExtractError> {
+        let payload = match &self.config.blobs {
+            Some((store, _)) => blob::load(&**store, vec![payload])
+                .map_err(|e| ExtractError::Invalid(name, e.to_string()))?
+                .remove(0),
+            None => payload,
+        };
+        T::try_decode(payload).map_err(|e| ExtractError::Invalid(name, e))
+    }
+
+    /// The name of node `T` in the job.
+    fn name_of<T: NodeBuilder<S>>(&self) -> &'static str {
+        let node = T::node();
+        self.names.get(&node.id).copied().unwrap_or(node.name)
+    }
+
+    /// Take a snapshot of where the job is at. It can be serialized, and later (or somewhere
+    /// else) be continued with [`Worker::restore`].
+    pub async fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::default();
         for (&name, state) in self.out.lock().await.iter() {
-            if let NodeState::Provided { value } | NodeState::Done { value, .. } = state {
-                data.insert(name.to_string(), value.clone());
+            match state {
+                NodeState::Provided { value } => {
+                    let value = with_version(name, value.clone(), &self.config.versions);
+                    snapshot.values.insert(name.to_string(), value);
+                }
+                NodeState::Done { value, .. } => {
+                    if let Some(value) = value.to_json() {
+                        let value = with_version(name, value, &self.config.versions);
+                        snapshot.values.insert(name.to_string(), value);
+                    }
+                }
+                NodeState::Retrying { retries, .. } | NodeState::Failed { retries, .. } => {
+                    snapshot.retries.insert(name.to_string(), *retries);
+                }
+                NodeState::Running { .. } | NodeState::Blocked { .. } => {}
             }
         }
-        data
+        snapshot.pending = self
+            .deps
+            .keys()
+            .filter(|name| !snapshot.values.contains_key(**name))
+            .map(ToString::to_string)
+            .collect();
+        snapshot.pending.sort();
+        snapshot.provenance = self.provenance().await;
+        snapshot
+            .provenance
+            .retain(|name, _| snapshot.values.contains_key(name));
+        snapshot
+    }
+
+    /// Where the value of each node that was provided or done came from: whether it was given
+    /// to the job, or computed by it, and by which producer, at which attempt, and when. Values
+    /// restored from a [`Snapshot`] keep the provenance they had, so a resumed job can tell
+    /// trusted inputs apart from values computed by an older version of the code.
+    ///
+    /// # Panics
+    /// If the lock is poisoned.
+    pub async fn provenance(&self) -> HashMap<String, Provenance> {
+        let known = self.provenance.lock().unwrap().clone();
+        let provided_at = self.started_at().unwrap_or_else(rt::system_now);
+        let mut provenance = HashMap::new();
+        for (&name, state) in self.out.lock().await.iter() {
+            let source = match (known.get(name), state) {
+                (Some(source), NodeState::Provided { .. } | NodeState::Done { .. }) => {
+                    source.clone()
+                }
+                (None, NodeState::Provided { .. }) => Provenance::Provided { provided_at },
+                (
+                    None,
+                    NodeState::Done {
+                        retries,
+                        finished_at,
+                        ..
+                    },
+                ) => Provenance::Computed {
+                    producer: self.producers.get(name).unwrap_or(&name).to_string(),
+                    attempt: *retries,
+                    computed_at: *finished_at,
+                    version: self.config.versions.get(name).copied(),
+                },
+                _ => continue,
+            };
+            provenance.insert(name.to_string(), source);
+        }
+        provenance
+    }
+
+    /// Compare the data of the job with that of an earlier run, say one saved from another
+    /// version of the code. Nodes only in `previous` are `removed`, and nodes only in this job
+    /// are `added`. See [`diff`].
+    pub async fn diff(&self, previous: &Snapshot) -> DataDiff {
+        diff(&previous.values, &self.data().await)
+    }
+
+    /// Capture the targets, labels and values of the job, so it can be saved, and later be run
+    /// again from here with [`Job::builder_from_checkpoint`]. Unlike a [`Snapshot`], it remembers
+    /// what the job was solving for.
+    pub async fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            targets: self.targets.iter().map(ToString::to_string).collect(),
+            labels: (*self.labels).clone(),
+            values: self.data().await,
+        }
+    }
+
+    /// Names of the provided nodes that no started node depended on. Meant to be called after the
+    /// job has finished; anything listed here may be stale resume data or a misnamed key.
+    /// They are also part of the [`Worker::report`].
+    pub async fn unused_inputs(&self) -> Vec<&'static str> {
+        crate::report::unused_inputs(&*self.out.lock().await, &self.deps)
+    }
+
+    /// Replace the value of a node that has already been provided or produced. Nodes that start
+    /// after this will get the new value. Meant to be used while paused (see
+    /// [`Worker::break_on`]).
+    ///
+    /// # Errors
+    /// If the node does not have a value yet.
+    ///
+    /// # Panics
+    /// If the lock is poisoned.
+    pub async fn set_value(&self, name: &str, value: Value) -> Result<(), &'static str> {
+        let mut out = self.out.lock().await;
+        match out.get_mut(name) {
+            Some(NodeState::Provided { value: v }) => *v = value,
+            Some(NodeState::Done {
+                value: v @ (Payload::Json(_) | Payload::Cbor(_)),
+                ..
+            }) => *v = Payload::Json(Arc::new(value)),
+            _ => return Err("Node has no value"),
+        }
+        // It is the user's value now.
+        let provided_at = rt::system_now();
+        self.provenance
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), Provenance::Provided { provided_at });
+        Ok(())
+    }
+
+    /// A cheap summary of what the worker is doing, for health checks. It never waits for the
+    /// job, so it is fine to call as often as needed.
+    ///
+    /// `window` is how far back to count failed nodes. Failures are remembered for an hour, so a
+    /// longer window is the same as an hour.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn health(&self, window: Duration) -> Health {
+        let now = rt::now();
+        let failures = self.counters.failures.lock().unwrap();
+        let recent_failures = failures.iter().filter(|t| now - **t <= window).count();
+        Health {
+            running_jobs: self.counters.running_jobs.load(Ordering::Relaxed),
+            running_nodes: self.counters.running_nodes.load(Ordering::Relaxed),
+            recent_failures,
+            uptime: self.counters.created.elapsed(),
+        }
     }
 
     pub async fn status(&self) -> HashMap<&'static str, NodeState> {
         self.out.lock().await.clone()
     }
+
+    /// How far the job has come, and when it is expected to be done. Meant for progress bars.
+    ///
+    /// The estimate assumes nodes run as soon as their dependencies are done. A node is expected
+    /// to take as long as its hint (see [`Worker::duration_hints`]), or else as long as the nodes
+    /// that are done took on average.
+    pub async fn progress(&self) -> Progress {
+        let now = match self.mode.lock().await.as_ref() {
+            Some(Mode::Running(t0, _)) => Some(t0.elapsed()),
+            _ => None,
+        };
+        let out = self.out.lock().await;
+        let mut progress = Progress {
+            total: self.deps.len(),
+            ..Progress::default()
+        };
+        let mut took = vec![];
+        for name in self.deps.keys() {
+            match out.get(name) {
+                Some(NodeState::Done { duration, .. }) => {
+                    progress.done += 1;
+                    took.push(*duration);
+                }
+                Some(NodeState::Running { .. } | NodeState::Retrying { .. }) => {
+                    progress.running += 1;
+                }
+                Some(NodeState::Failed { .. } | NodeState::Blocked { .. }) => progress.failed += 1,
+                Some(NodeState::Provided { .. }) | None => {}
+            }
+        }
+        let average = u32::try_from(took.len())
+            .ok()
+            .filter(|n| *n > 0)
+            .map(|n| took.iter().sum::<Duration>() / n);
+
+        // How much is left of each node that has not finished, if it can be guessed.
+        let remaining: HashMap<_, _> = self
+            .deps
+            .keys()
+            .filter_map(|&name| {
+                let started = match out.get(name) {
+                    Some(NodeState::Running { start, .. } | NodeState::Retrying { start, .. }) => {
+                        Some(*start)
+                    }
+                    None => None,
+                    Some(_) => return None,
+                };
+                let expected = self.config.duration_hints.get(name).copied().or(average);
+                let ran = started
+                    .zip(now)
+                    .map_or(Duration::ZERO, |(start, now)| now.saturating_sub(start));
+                Some((name, expected.map(|expected| expected.saturating_sub(ran))))
+            })
+            .collect();
+        let mut memo = HashMap::new();
+        progress.eta = remaining
+            .keys()
+            .map(|name| finishes_in(name, &self.deps, &remaining, &mut memo))
+            .try_fold(Duration::ZERO, |eta, finish| Some(eta.max(finish?)));
+        progress
+    }
+
+    /// A mermaid diagram of the job, like [`crate::mermaid`], but with each node given a class
+    /// for its current state: `provided`, `done`, `running`, `failed`, `blocked` or `pending`.
+    /// Meant for showing how far a job has come.
+    pub async fn mermaid(&self) -> String {
+        let out = self.out.lock().await;
+        let mut names: Vec<_> = self
+            .deps
+            .iter()
+            .flat_map(|(name, deps)| deps.iter().chain([name]))
+            .copied()
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        let idx: HashMap<_, _> = names.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+        let mut lines = vec!["flowchart LR".to_string()];
+        for (i, name) in names.iter().enumerate() {
+            lines.push(format!("n{i}[{name}]"));
+        }
+        for name in &names {
+            let Some(deps) = self.deps.get(name).filter(|deps| !deps.is_empty()) else {
+                continue;
+            };
+            let mut deps: Vec<_> = deps.iter().map(|dep| idx[dep]).collect();
+            deps.sort_unstable();
+            let deps: Vec<_> = deps.iter().map(|i| format!("n{i}")).collect();
+            lines.push(format!("{} --> n{}", deps.join(" & "), idx[name]));
+        }
+        lines.push("classDef provided fill:#eee,stroke-dasharray:5 5".into());
+        lines.push("classDef done fill:#cfc".into());
+        lines.push("classDef running fill:#ffc".into());
+        lines.push("classDef failed fill:#fcc".into());
+        lines.push("classDef blocked fill:#ddd".into());
+        lines.push("classDef pending fill:#fff".into());
+        for (i, name) in names.iter().enumerate() {
+            let class = match out.get(name) {
+                Some(NodeState::Provided { .. }) => "provided",
+                Some(NodeState::Done { .. }) => "done",
+                Some(NodeState::Running { .. } | NodeState::Retrying { .. }) => "running",
+                Some(NodeState::Failed { .. }) => "failed",
+                Some(NodeState::Blocked { .. }) => "blocked",
+                None => "pending",
+            };
+            lines.push(format!("class n{i} {class}"));
+        }
+        lines.join("\n    ")
+    }
+
+    /// A summary of the job and each of its nodes, in one serializable struct. Best taken once
+    /// the job is done (see [`Worker::get_output`]), but can be taken at any time.
+    pub async fn report(&self) -> JobReport {
+        JobReport::new(
+            self.job_id,
+            self.labels().clone(),
+            self.output().as_ref(),
+            self.started_at(),
+            self.config.scratch.values(),
+            &*self.out.lock().await,
+            &self.deps,
+        )
+    }
+
+    /// How long each node that is done took to run. Can be given to the next run of the job, as
+    /// [`Worker::duration_hints`].
+    pub async fn durations(&self) -> DurationHints {
+        self.out
+            .lock()
+            .await
+            .iter()
+            .filter_map(|(name, state)| match state {
+                NodeState::Done { duration, .. } => Some((name.to_string(), *duration)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// How long until node `name` is done: what is left of it, after what is left of its
+/// dependencies. `None` if any of that can not be guessed.
+fn finishes_in(
+    name: &'static str,
+    deps: &HashMap<&'static str, Vec<&'static str>>,
+    remaining: &HashMap<&'static str, Option<Duration>>,
+    memo: &mut HashMap<&'static str, Option<Duration>>,
+) -> Option<Duration> {
+    if let Some(finish) = memo.get(name) {
+        return *finish;
+    }
+    // The node only starts once its dependencies are done.
+    let mut start = Some(Duration::ZERO);
+    for dep in deps.get(name).into_iter().flatten() {
+        if remaining.contains_key(dep) {
+            let dep = finishes_in(dep, deps, remaining, memo);
+            start = start.zip(dep).map(|(start, dep)| start.max(dep));
+        }
+    }
+    let finish = start.zip(remaining[name]).map(|(start, own)| start + own);
+    memo.insert(name, finish);
+    finish
+}
+
+/// A running job. Returned by [`Worker::run`].
+///
+/// Await it (or [`RunHandle::output`]) to get the [`Output`] once the job has finished. Since it
+/// is just a future, it can be used in `tokio::select!`. Dropping it does not stop the job.
+#[derive(Clone)]
+pub struct RunHandle<S: State> {
+    worker: Worker<S>,
+    output: watch::Receiver<Option<Output>>,
+}
+
+impl<S: State> RunHandle<S> {
+    /// The worker running the job.
+    #[must_use]
+    pub fn worker(&self) -> &Worker<S> {
+        &self.worker
+    }
+
+    /// Returns `true` once the job has finished.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.output.borrow().is_some()
+    }
+
+    /// Wait for the job to finish, and return the [`Output`].
+    ///
+    /// # Panics
+    /// If the worker was dropped without finishing the job, which does not happen.
+    pub async fn output(&mut self) -> Output {
+        let output = self
+            .output
+            .wait_for(Option::is_some)
+            .await
+            .expect("Worker sends the output before it is dropped");
+        output.clone().expect("Waited for it")
+    }
+
+    /// Stop the job, and abort the running nodes. See [`Worker::stop`].
+    pub async fn abort(&mut self) {
+        self.worker.stop().await;
+    }
+
+    /// The state of every node. See [`Worker::status`].
+    pub async fn status(&self) -> HashMap<&'static str, NodeState> {
+        self.worker.status().await
+    }
+
+    /// The data collected from running the job. See [`Worker::data`].
+    pub async fn data(&self) -> HashMap<String, Value> {
+        self.worker.data().await
+    }
+}
+
+impl<S: State> IntoFuture for RunHandle<S> {
+    type Output = Output;
+    type IntoFuture = Pin<Box<dyn Future<Output = Output> + Send>>;
+
+    fn into_future(mut self) -> Self::IntoFuture {
+        Box::pin(async move { self.output().await })
+    }
+}
+
+/// The state of a worker at some point in time. Created with [`Worker::snapshot`] and continued
+/// with [`Worker::restore`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Values of the nodes that were provided or done.
+    pub values: HashMap<String, Value>,
+    /// Nodes that had not finished yet.
+    pub pending: Vec<String>,
+    /// Number of retries spent on nodes that had failed.
+    pub retries: HashMap<String, u32>,
+    /// Where the values came from. See [`Worker::provenance`].
+    #[serde(default)]
+    pub provenance: HashMap<String, Provenance>,
+}
+
+/// Where the value of a node came from. See [`Worker::provenance`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum Provenance {
+    /// Given to the job (see [`Job::builder_with_data`]), or set with [`Worker::set_value`].
+    Provided {
+        /// When the job started, or when the value was set.
+        provided_at: SystemTime,
+    },
+    /// Computed by a producer.
+    Computed {
+        /// The name of the node, or of the variant that was selected (see
+        /// [`crate::JobBuilder::select`]).
+        producer: String,
+        /// The retry count of the attempt that computed it.
+        attempt: u32,
+        /// When the node finished.
+        computed_at: SystemTime,
+        /// The version of the node, if it has one. See [`crate::NodeDef::version`].
+        version: Option<u32>,
+    },
+}
+
+/// A summary of what a worker is doing. Created with [`Worker::health`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Health {
+    /// Number of jobs currently running.
+    pub running_jobs: usize,
+    /// Number of nodes currently running, including retries.
+    pub running_nodes: usize,
+    /// Number of times a node failed within the window, including failures that will be retried.
+    pub recent_failures: usize,
+    /// Time since the worker was created.
+    pub uptime: Duration,
+}
+
+/// How far a job has come. Created with [`Worker::progress`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of nodes the job runs. Nodes with provided data are not counted.
+    pub total: usize,
+    /// Number of nodes that are done.
+    pub done: usize,
+    /// Number of nodes that are running, including retries.
+    pub running: usize,
+    /// Number of nodes that failed, or will not run because another node failed.
+    pub failed: usize,
+    /// When the job is expected to be done, from now. `None` if there is no way to tell, say
+    /// before any node is done, and without hints.
+    pub eta: Option<Duration>,
+}
+
+/// Why the scheduler did, or did not, start a node. See [`Worker::log_decisions`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Decision {
+    /// Which scheduling step the decision was made in. Starts at `1`.
+    pub step: usize,
+    /// Time since the job started.
+    pub at: Duration,
+    /// Name of the node.
+    pub name: &'static str,
+    /// What was decided.
+    pub kind: DecisionKind,
+}
+
+/// What was decided about a node. More kinds may be added as the scheduler learns new tricks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub enum DecisionKind {
+    /// The node was started.
+    Started,
+    /// Not started, because these dependencies are not done yet (sorted).
+    WaitingFor(Vec<&'static str>),
+    /// Ready, but in step mode, and either another node is running or another ready node was
+    /// picked.
+    Stepping,
+    /// Paused on a breakpoint, before being started.
+    Breakpoint,
+    /// Ready, but the worker is draining.
+    Draining,
+    /// Ready, but [`Worker::max_concurrency`] nodes are already running.
+    ConcurrencyLimit,
+    /// Ready, but another node using this resource is running (or was picked). See
+    /// [`Node::exclusive`].
+    Exclusive(&'static str),
+    /// Not started, since its output was found in the cache. See [`Worker::with_cache`].
+    Cached,
+    /// Ready, but starting it would go over the rate limit of its tag. See
+    /// [`Worker::rate_limit`].
+    RateLimited(&'static str),
+}
+
+/// What happened in a single step of a job. See [`Worker::step_node`].
+#[derive(Debug, Clone)]
+pub enum StepResult {
+    /// Node `name` was started, and has now finished.
+    Ran {
+        name: &'static str,
+        /// The nodes that were ready to start, sorted by name. The first one is `name`.
+        ready: Vec<&'static str>,
+        /// What became of the node. Either [`NodeState::Done`] or [`NodeState::Failed`].
+        state: NodeState,
+    },
+    /// The job has ended, and there is nothing more to step through.
+    Finished(Output),
+}
+
+/// Something that happened while running a job. See [`Worker::subscribe`].
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    /// A node was started for the first time.
+    NodeStarted {
+        name: &'static str,
+        /// The offset from the job start that the node was started.
+        start: Duration,
+    },
+    /// A node is being retried (or will be, when the job is resumed after draining).
+    NodeRetrying { name: &'static str, retries: u32 },
+    /// A node finished successfully.
+    NodeDone {
+        name: &'static str,
+        /// Time it took to run the node.
+        duration: Duration,
+        retries: u32,
+    },
+    /// A node failed, and will not be retried.
+    NodeFailed {
+        name: &'static str,
+        retries: u32,
+        error: String,
+    },
+    /// A node will not run, because node `by` failed. See [`FailurePolicy::ContinueUnaffected`].
+    NodeBlocked {
+        name: &'static str,
+        by: &'static str,
+    },
+    /// The job finished, one way or another.
+    JobDone { output: Output },
+}
+
+impl JobEvent {
+    /// The event for a node changing to `state`.
+    fn new(name: &'static str, state: &NodeState) -> Option<Self> {
+        Some(match state {
+            NodeState::Provided { .. } => return None,
+            NodeState::Running { start, .. } => Self::NodeStarted {
+                name,
+                start: *start,
+            },
+            NodeState::Retrying { retries, .. } => Self::NodeRetrying {
+                name,
+                retries: *retries,
+            },
+            NodeState::Done {
+                duration, retries, ..
+            } => Self::NodeDone {
+                name,
+                duration: *duration,
+                retries: *retries,
+            },
+            NodeState::Failed { retries, error, .. } => Self::NodeFailed {
+                name,
+                retries: *retries,
+                error: error.message.clone(),
+            },
+            NodeState::Blocked { by } => Self::NodeBlocked { name, by },
+        })
+    }
 }
 
 /// The current state of a single node in a job.
@@ -114,6 +1417,8 @@ pub enum NodeState {
     Running {
         /// The offset from the job start that this node was started.
         start: Duration,
+        /// When the node was started.
+        started_at: SystemTime,
     },
     /// Job has finished successfully.
     Done {
@@ -122,13 +1427,17 @@ pub enum NodeState {
         /// Number of retries to finish the node.
         retries: u32,
         /// The output of the node.
-        value: Value,
+        value: Payload,
+        /// When the node finished.
+        finished_at: SystemTime,
     },
     Retrying {
         /// Current retry start.
         start: Duration,
         /// Retry count.
         retries: u32,
+        /// When the current retry was started (or, while waiting for it, when it was decided).
+        started_at: SystemTime,
     },
     Failed {
         /// The node failed at this time.
@@ -137,147 +1446,788 @@ pub enum NodeState {
         retries: u32,
         /// Error returned from the node.
         error: Error,
+        /// When the node failed.
+        finished_at: SystemTime,
     },
+    /// Will not run, since a node it depends on failed. See
+    /// [`FailurePolicy::ContinueUnaffected`].
+    Blocked {
+        /// The node that failed.
+        by: &'static str,
+    },
+}
+
+/// The values of the nodes that were provided or done. See [`Worker::data`].
+fn data<T: std::hash::BuildHasher>(
+    out: &HashMap<&'static str, NodeState, T>,
+    versions: &HashMap<&'static str, u32>,
+) -> HashMap<String, Value> {
+    let mut data = HashMap::new();
+    for (&name, state) in out {
+        let value = match state {
+            NodeState::Provided { value } => Some(value.clone()),
+            NodeState::Done { value, .. } => value.to_json(),
+            _ => None,
+        };
+        if let Some(value) = value {
+            data.insert(name.to_string(), with_version(name, value, versions));
+        }
+    }
+    data
+}
+
+/// Wraps `value` in the version of node `name`, if it has one.
+pub(crate) fn with_version(
+    name: &str,
+    value: Value,
+    versions: &HashMap<&'static str, u32>,
+) -> Value {
+    match versions.get(name) {
+        Some(version) => versioned(*version, value),
+        None => value,
+    }
 }
 
-#[allow(clippy::too_many_lines)] // It's okay
+#[allow(clippy::too_many_lines, clippy::too_many_arguments)] // It's okay
 async fn run_job<S: State, T: ::std::hash::BuildHasher>(
-    job: Job<S>,
+    mut job: Job<S>,
     state: S,
+    config: Config,
+    mut steps: mpsc::UnboundedReceiver<Step>,
     out: Arc<Mutex<HashMap<&'static str, NodeState, T>>>,
+    counters: Arc<Counters>,
+    draining: CancellationToken,
+    cancellation: CancellationToken,
+    events: broadcast::Sender<JobEvent>,
+    job_id: u64,
+    labels: Arc<BTreeMap<String, String>>,
+    hooks: Arc<[Arc<dyn Hook<S>>]>,
     t0: Instant,
+    started_at: SystemTime,
 ) -> Output {
-    // Type for the JoinSet (or running tasks).
+    // Type for the set of running tasks.
     enum Node {
-        Done(TypeId, u32, Duration, Result<Value, Error>),
-        Retry(TypeId, u32),
+        /// Id, retry count, when it finished, how long it took, and the result.
+        Done(NodeId, u32, Duration, Duration, Result<Payload, Error>),
+        Retry(NodeId, u32),
     }
 
-    let nodes = job.nodes;
-    let adj = job.adj;
-    let mut results = HashMap::new();
-    let mut handles = JoinSet::new();
-    let mut abort_handles = HashMap::new();
-    let mut pending: HashSet<TypeId> = nodes.keys().copied().collect();
+    let mut dispatch = Dispatcher::new(&mut job, config.rate_limits.clone());
+    dispatch.max_concurrency = config.max_concurrency;
+    dispatch.max_retries = config.max_retries;
+    let mut handles = Tasks::new();
+    // The node (and retry) each task runs, to tell which one panicked.
+    let mut tasks = HashMap::new();
+    // Nodes that are waiting to be retried, with their retry count.
+    let mut sleeping = HashMap::new();
+    // A tracing span for each node that has been started. Its events are logged in it.
+    let mut spans: HashMap<NodeId, Span> = HashMap::new();
+    // The cache keys of the running nodes, so their outputs can be cached once they are done.
+    let mut cache_keys = HashMap::new();
 
-    let mut o = out.lock().await;
+    // Updates the state of a node, and persists it if there is a store.
+    let set_state = async |name: &'static str, state: NodeState| {
+        if let Some(store) = &config.store
+            && let Err(error) = store.save(name, &state)
+        {
+            warn!(name, %error, "Could not save node state");
+        }
+        if let Some(event) = JobEvent::new(name, &state) {
+            // Nobody listening is fine.
+            let _ = events.send(event);
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::record(config.label.as_deref().unwrap_or_default(), name, &state);
+        out.lock().await.insert(name, state);
+    };
+
+    let mut provided_ids = vec![];
     for (id, (name, data)) in job.provided {
+        provided_ids.push((name, id));
         info!(name, "Provided");
-        o.insert(
-            name,
-            NodeState::Provided {
-                value: data.clone(),
-            },
-        );
-        results.insert(id, data);
+        let value = data.clone();
+        set_state(name, NodeState::Provided { value }).await;
+        dispatch.results.insert(id, Payload::Json(Arc::new(data)));
+    }
+
+    // Fail before starting anything, if a node is known to be broken.
+    if let Some(quarantine) = &config.quarantine {
+        let mut names: Vec<_> = dispatch.nodes.values().map(|node| node.name).collect();
+        names.sort_unstable();
+        if let Some(name) = names
+            .into_iter()
+            .find(|name| quarantine.is_quarantined(name))
+        {
+            let error = Error::fatal(format!("Node {name} is quarantined"));
+            let state = NodeState::Failed {
+                duration: Duration::ZERO,
+                retries: 0,
+                error: error.clone(),
+                finished_at: rt::system_now(),
+            };
+            set_state(name, state).await;
+            error!(name, "Node quarantined");
+            return Output::NodeFailed {
+                duration: t0.elapsed(),
+                started_at,
+                finished_at: rt::system_now(),
+                name,
+                retries: 0,
+                error,
+                data: Arc::new(data(&*out.lock().await, &config.versions)),
+            };
+        }
+    }
+
+    // Do not start anything if the nodes will not be able to run anyway.
+    let unmet = crate::preflight(dispatch.nodes.values(), &state).await;
+    if !unmet.is_empty() {
+        return Output::Unmet {
+            duration: t0.elapsed(),
+            started_at,
+            finished_at: rt::system_now(),
+            unmet,
+            data: Arc::new(data(&*out.lock().await, &config.versions)),
+        };
     }
-    drop(o);
 
     // A helper to create a Context.
-    let ctx = |retry, start| Context {
-        retry,
-        start,
-        state: state.clone(),
-    };
+    let ctx =
+        |attempt, first_started, attempt_started: Duration, timeout: Option<Duration>| Context {
+            state: state.clone(),
+            attempt: AttemptInfo {
+                attempt,
+                first_started,
+                attempt_started,
+                deadline: timeout.map(|timeout| attempt_started + timeout),
+            },
+            job_id,
+            format: config.format,
+            labels: labels.clone(),
+            cancellation: cancellation.clone(),
+            scratchpad: config.scratch.clone(),
+        };
+
+    // Used to find nodes by name, when the user changes values while we are paused.
+    let names: HashMap<_, _> = dispatch
+        .nodes
+        .iter()
+        .map(|(id, node)| (*id, node.name))
+        .collect();
+    let ids: HashMap<_, _> = names
+        .iter()
+        .map(|(id, name)| (*name, *id))
+        .chain(provided_ids)
+        .collect();
 
+    let mut step = 0;
     loop {
-        // A few helper functions.
-        let is_done = |i| results.contains_key(i);
-        let get_payloads = |id| adj[&id].iter().map(|id| results[id].clone()).collect();
+        step += 1;
 
-        // Start the ready nodes.
-        let ready = pending.extract_if(|id| adj[id].iter().all(is_done));
-        for id in ready {
-            let payloads = get_payloads(id);
-            let node = &nodes[&id];
+        // Records a decision, if we are asked to.
+        let decide = |id: &NodeId, kind| {
+            if let Some(decisions) = &config.decisions {
+                let decision = Decision {
+                    step,
+                    at: t0.elapsed(),
+                    name: names[id],
+                    kind,
+                };
+                decisions.lock().unwrap().push(decision);
+            }
+        };
+
+        let mut ready = dispatch.ready();
+        if config.decisions.is_some() {
+            for (id, deps) in dispatch.waiting() {
+                decide(&id, DecisionKind::WaitingFor(deps));
+            }
+        }
+
+        // In step mode we only start a single node, and only once we are told to.
+        if config.stepping {
+            if handles.is_empty() && !ready.is_empty() {
+                let ready_names = ready.iter().map(|id| names[id]).collect();
+                wait_for_step(&mut steps, ready_names, &draining).await;
+                refresh(&mut dispatch.results, &ids, &out).await;
+                for id in ready.drain(1..) {
+                    decide(&id, DecisionKind::Stepping);
+                }
+            } else {
+                for id in ready.drain(..) {
+                    decide(&id, DecisionKind::Stepping);
+                }
+            }
+        }
+
+        // When draining, nothing new is started, and we stop once only retries are left.
+        if draining.is_cancelled() {
+            for id in ready.drain(..) {
+                decide(&id, DecisionKind::Draining);
+            }
+            for (id, retry) in dispatch.drain_retries() {
+                let state = NodeState::Retrying {
+                    start: t0.elapsed(),
+                    retries: retry,
+                    started_at: rt::system_now(),
+                };
+                set_state(names[&id], state).await;
+            }
+            if handles.len() == sleeping.len() {
+                for (id, retry) in sleeping {
+                    let state = NodeState::Retrying {
+                        start: t0.elapsed(),
+                        retries: retry + 1,
+                        started_at: rt::system_now(),
+                    };
+                    set_state(names[&id], state).await;
+                }
+                let duration = t0.elapsed();
+                info!(?duration, "Job drained");
+                return Output::Stopped {
+                    duration,
+                    started_at,
+                    finished_at: rt::system_now(),
+                    data: Arc::new(data(&*out.lock().await, &config.versions)),
+                };
+            }
+        }
+
+        // Only start as many nodes as we are allowed to. Retries go first.
+        let admitted = dispatch.admit(ready, rt::now());
+        for (id, kind) in admitted.held_back {
+            decide(&id, kind);
+        }
+        let wake_at = admitted.wake_at;
+        for (id, retry) in admitted.retries {
+            let node = &dispatch.nodes[&id];
+            let payloads = dispatch.payloads(id);
             let producer = node.producer.clone();
+            let timeout = node.timeout;
+            let placement = placement(node, &config);
             let start = t0.elapsed();
-            let context = ctx(0, start);
-            let state = NodeState::Running { start };
-            out.lock().await.insert(node.name, state);
-            info!(name = node.name, "Node start");
-            let abort_handle = handles.spawn(async move {
-                let result = producer(context, payloads).await;
-                Node::Done(id, 0, t0.elapsed(), result)
-            });
-            abort_handles.insert(abort_handle.id(), id);
+            let first_started = dispatch.first_started(id).duration_since(t0);
+            let context = ctx(retry, first_started, start, timeout);
+            let name = node.name;
+            let state = NodeState::Retrying {
+                start,
+                retries: retry,
+                started_at: rt::system_now(),
+            };
+            set_state(name, state).await;
+            let span = &spans[&id];
+            span.record("retry", retry);
+            span.in_scope(|| info!("Node retrying"));
+            counters.running_nodes.fetch_add(1, Ordering::Relaxed);
+            let hooks = hooks.clone();
+            let journal = config.journal.clone();
+            let blobs = config.blobs.as_ref().map(|(store, _)| store.clone());
+            let share = config.share.clone();
+            let run = async move {
+                // Wait for a turn, if the job shares its nodes with the other jobs of a pool.
+                let _slot = match &share {
+                    Some(share) => Some(share.acquire().await),
+                    None => None,
+                };
+                let (result, took) = run_node(
+                    &hooks, journal, blobs, name, placement, producer, context, payloads, timeout,
+                )
+                .await;
+                Node::Done(id, retry, t0.elapsed(), took, result)
+            };
+            let task = handles.spawn(run.instrument(span.clone()));
+            tasks.insert(task, (id, retry));
+        }
+
+        // Start the ready nodes.
+        let mut cached = false;
+        for id in admitted.ready {
+            let name = names[&id];
+            if !config.stepping && config.breakpoints.contains(&id) {
+                info!(name, "Breakpoint");
+                turn_down_steps(&mut steps);
+                // Logged once paused, so a step asked for after seeing it is not turned down.
+                decide(&id, DecisionKind::Breakpoint);
+                wait_for_step(&mut steps, vec![name], &draining).await;
+                refresh(&mut dispatch.results, &ids, &out).await;
+                if draining.is_cancelled() {
+                    decide(&id, DecisionKind::Draining);
+                    break;
+                }
+            }
+            let payloads = dispatch.payloads(id);
+            let retry = config.retries.get(&id).copied().unwrap_or_default();
+            let span = info_span!("node", name, retry, duration = field::Empty);
+            spans.insert(id, span.clone());
+            if let Some(cache) = &config.cache
+                && !dispatch.nodes[&id].transient
+                && let Some(key) = cache_key(&payloads)
+            {
+                match cache.get(name, &key) {
+                    Ok(Some(value)) => {
+                        decide(&id, DecisionKind::Cached);
+                        let value = Payload::Json(Arc::new(value));
+                        let value = spill(&config, id, name, value);
+                        dispatch.skip(id, value.clone());
+                        let state = NodeState::Done {
+                            duration: Duration::ZERO,
+                            retries: 0,
+                            value,
+                            finished_at: rt::system_now(),
+                        };
+                        set_state(name, state).await;
+                        span.in_scope(|| info!("Node cached"));
+                        cached = true;
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(error) => span.in_scope(|| warn!(%error, "Could not read from cache")),
+                }
+                cache_keys.insert(id, key);
+            }
+            decide(&id, DecisionKind::Started);
+            let now = rt::now();
+            dispatch.start(id, retry, now);
+            let node = &dispatch.nodes[&id];
+            let producer = node.producer.clone();
+            let timeout = node.timeout;
+            let placement = placement(node, &config);
+            let start = now.duration_since(t0);
+            let context = ctx(retry, start, start, timeout);
+            let state = NodeState::Running {
+                start,
+                started_at: rt::system_now(),
+            };
+            set_state(name, state).await;
+            span.in_scope(|| info!("Node start"));
+            counters.running_nodes.fetch_add(1, Ordering::Relaxed);
+            let hooks = hooks.clone();
+            let journal = config.journal.clone();
+            let blobs = config.blobs.as_ref().map(|(store, _)| store.clone());
+            let share = config.share.clone();
+            let run = async move {
+                let _slot = match &share {
+                    Some(share) => Some(share.acquire().await),
+                    None => None,
+                };
+                let (result, took) = run_node(
+                    &hooks, journal, blobs, name, placement, producer, context, payloads, timeout,
+                )
+                .await;
+                Node::Done(id, retry, t0.elapsed(), took, result)
+            };
+            let task = handles.spawn(run.instrument(span));
+            tasks.insert(task, (id, retry));
+        }
+        // Cached nodes may have made others ready.
+        if cached {
+            continue;
         }
 
-        let result = handles.join_next().await;
+        let result = tokio::select! {
+            // Nothing running is not the end of the job, if nodes wait for their rate limit.
+            result = handles.join_next(), if !handles.is_empty() || wake_at.is_none() => result,
+            // Wake up, so we can stop if only retries are left.
+            () = draining.cancelled(), if !draining.is_cancelled() => continue,
+            () = sleep_until(wake_at), if wake_at.is_some() => continue,
+        };
         let Some(result) = result else {
             let duration = t0.elapsed();
-            info!(?duration, "Job done");
-            return Output::Done { duration };
+            let data = || async { Arc::new(data(&*out.lock().await, &config.versions)) };
+            let mut failures = dispatch.take_failures();
+            if failures.is_empty() {
+                info!(?duration, "Job done");
+                return Output::Done {
+                    duration,
+                    started_at,
+                    finished_at: rt::system_now(),
+                };
+            }
+            if config.failure_policy == FailurePolicy::CollectAll {
+                return Output::Finished {
+                    duration,
+                    started_at,
+                    finished_at: rt::system_now(),
+                    failures,
+                    data: data().await,
+                };
+            }
+            let NodeFailure {
+                name,
+                retries,
+                error,
+                panicked,
+            } = failures.swap_remove(0);
+            return if panicked {
+                Output::NodePanic {
+                    duration,
+                    started_at,
+                    finished_at: rt::system_now(),
+                    name,
+                    error: error.message,
+                    data: data().await,
+                }
+            } else {
+                Output::NodeFailed {
+                    duration,
+                    started_at,
+                    finished_at: rt::system_now(),
+                    name,
+                    retries,
+                    error,
+                    data: data().await,
+                }
+            };
         };
+        let (task, result) = result;
+        // A task waiting to retry a node can panic too, in a hook. The node already gave back
+        // what it held when it failed.
+        let sleeper = result.is_err() && sleeping.contains_key(&tasks[&task].0);
+        if matches!(result, Ok(Node::Done(..))) || result.is_err() && !sleeper {
+            counters.running_nodes.fetch_sub(1, Ordering::Relaxed);
+        }
         let result = match result {
             Ok(result) => result,
             Err(e) => {
                 let duration = t0.elapsed();
-                let id = abort_handles[&e.id()];
-                let name = nodes[&id].name;
-                error!(name, "Node panicked");
-                return Output::NodePanic {
+                let (id, retry) = tasks[&task];
+                let name = names[&id];
+                if !sleeper {
+                    dispatch.finish(id);
+                }
+                sleeping.remove(&id);
+                spans[&id].in_scope(|| error!("Node panicked"));
+                let error = e.to_string();
+                if config.failure_policy == FailurePolicy::FailFast {
+                    return Output::NodePanic {
+                        duration,
+                        started_at,
+                        finished_at: rt::system_now(),
+                        name,
+                        error,
+                        data: Arc::new(data(&*out.lock().await, &config.versions)),
+                    };
+                }
+                let error = Error::fatal(error);
+                let state = NodeState::Failed {
                     duration,
+                    retries: retry,
+                    error: error.clone(),
+                    finished_at: rt::system_now(),
+                };
+                set_state(name, state).await;
+                let failure = NodeFailure {
                     name,
-                    error: format!("{e:?}"),
+                    retries: retry,
+                    error,
+                    panicked: true,
                 };
+                for blocked in dispatch.give_up(id, failure) {
+                    set_state(names[&blocked], NodeState::Blocked { by: name }).await;
+                }
+                continue;
             }
         };
         match result {
-            Node::Done(id, retry, time, Ok(payload)) => {
-                results.insert(id, payload.clone());
-                let name = nodes[&id].name;
+            Node::Done(id, retry, _, _, Ok(Payload::Streaming(stream, rest))) => {
+                // Dependents can start reading, while the rest of the producer keeps running.
+                let value = Payload::Transient(stream);
+                dispatch.stream(id, value.clone());
+                let rest = rest.lock().unwrap().take().expect("Only taken once");
+                let start = dispatch.first_started(id).duration_since(t0);
+                counters.running_nodes.fetch_add(1, Ordering::Relaxed);
+                let run = async move {
+                    // The emitted items can not be taken back, so it is never retried.
+                    let result = rest.await.map(|()| value).map_err(|e| Error {
+                        retry_in: None,
+                        ..e
+                    });
+                    let time = t0.elapsed();
+                    Node::Done(id, retry, time, time.saturating_sub(start), result)
+                };
+                let task = handles.spawn(run.instrument(spans[&id].clone()));
+                tasks.insert(task, (id, retry));
+            }
+            Node::Done(id, retry, _, took, Ok(payload)) => {
+                let name = names[&id];
+                if let Some(cache) = &config.cache
+                    && let Some(key) = cache_keys.remove(&id)
+                    && let Some(value) = payload.to_json()
+                    && let Err(error) = cache.put(name, &key, &value)
+                {
+                    spans[&id].in_scope(|| warn!(%error, "Could not write to cache"));
+                }
+                let payload = spill(&config, id, name, payload);
+                dispatch.done(id, payload.clone());
                 let state = NodeState::Done {
-                    duration: time,
+                    duration: took,
                     retries: retry,
                     value: payload,
+                    finished_at: rt::system_now(),
                 };
-                out.lock().await.insert(name, state);
-                info!(name, "Node done");
+                set_state(name, state).await;
+                if let Some(quarantine) = &config.quarantine {
+                    quarantine.succeeded(name);
+                }
+                let span = &spans[&id];
+                span.record("duration", field::debug(took));
+                span.in_scope(|| info!("Node done"));
             }
-            Node::Done(id, retry, time, Err(e)) => {
-                let name = nodes[&id].name;
-                if let Some(retry_in) = e.retry_in {
-                    warn!(name, retry, error = e.message, ?retry_in, "Node failed");
-                    handles.spawn(async move {
-                        tokio::time::sleep(time + retry_in).await;
+            Node::Done(id, retry, time, took, Err(e)) => {
+                let name = names[&id];
+                counters.failed();
+                if let Some(retry_in) = dispatch.failed(id, retry, &e, rt::now()) {
+                    spans[&id].in_scope(|| warn!(error = e.message, ?retry_in, "Node failed"));
+                    sleeping.insert(id, retry);
+                    let hooks = hooks.clone();
+                    let error = e.clone();
+                    let task = handles.spawn(async move {
+                        for hook in hooks.iter() {
+                            hook.on_retry(name, retry + 1, &error, retry_in).await;
+                        }
+                        rt::sleep(retry_in).await;
                         Node::Retry(id, retry)
                     });
+                    tasks.insert(task, (id, retry));
                 } else {
                     let duration = t0.elapsed();
-                    let msg = format!("Node {name} failed ({retry} retries): {}", e.message);
                     let state = NodeState::Failed {
                         duration: time,
                         retries: retry,
-                        error: e,
+                        error: e.clone(),
+                        finished_at: rt::system_now(),
                     };
-                    out.lock().await.insert(name, state);
-                    error!(name, "Node failed");
-                    return Output::NodeFailed {
-                        duration,
+                    set_state(name, state).await;
+                    if let Some(quarantine) = &config.quarantine {
+                        quarantine.failed(name);
+                    }
+                    let span = &spans[&id];
+                    span.record("duration", field::debug(took));
+                    span.in_scope(|| error!(error = e.message, "Node failed"));
+                    if config.failure_policy == FailurePolicy::FailFast {
+                        return Output::NodeFailed {
+                            duration,
+                            started_at,
+                            finished_at: rt::system_now(),
+                            name,
+                            retries: retry,
+                            error: e,
+                            data: Arc::new(data(&*out.lock().await, &config.versions)),
+                        };
+                    }
+                    let failure = NodeFailure {
                         name,
-                        error: msg,
+                        retries: retry,
+                        error: e,
+                        panicked: false,
                     };
+                    for blocked in dispatch.give_up(id, failure) {
+                        set_state(names[&blocked], NodeState::Blocked { by: name }).await;
+                    }
                 }
             }
             Node::Retry(id, mut retry) => {
+                sleeping.remove(&id);
                 retry += 1;
-                let payloads = get_payloads(id);
-                let producer = nodes[&id].producer.clone();
-                let start = t0.elapsed();
-                let context = ctx(retry, start);
-                let name = nodes[&id].name;
-                let state = NodeState::Retrying {
-                    start,
-                    retries: retry,
-                };
-                out.lock().await.insert(name, state);
-                info!(name, retry, "Node retrying");
-                handles.spawn(async move {
-                    let result = producer(context, payloads).await;
-                    Node::Done(id, retry, t0.elapsed(), result)
-                });
+                if draining.is_cancelled() {
+                    let state = NodeState::Retrying {
+                        start: t0.elapsed(),
+                        retries: retry,
+                        started_at: rt::system_now(),
+                    };
+                    set_state(names[&id], state).await;
+                    continue;
+                }
+                dispatch.retry(id, retry);
             }
         }
     }
 }
+
+/// The value of a node, if it has one.
+fn payload(state: &NodeState) -> Option<Payload> {
+    match state {
+        NodeState::Provided { value } => Some(Payload::Json(Arc::new(value.clone()))),
+        NodeState::Done { value, .. } => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// Where to run a producer.
+enum Placement {
+    /// Along with the other nodes.
+    Shared,
+    /// On the blocking threads of the runtime.
+    BlockingPool,
+    /// On a runtime of its own.
+    Runtime(Handle),
+}
+
+fn placement<S: State>(node: &crate::Node<S>, config: &Config) -> Placement {
+    match (&config.blocking_runtime, node.blocking) {
+        (_, false) => Placement::Shared,
+        (None, true) => Placement::BlockingPool,
+        (Some(runtime), true) => Placement::Runtime(runtime.clone()),
+    }
+}
+
+/// Aborts a task when dropped, so it is stopped along with the node that started it.
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Keeps `payload` in the blob store if it is too large (see [`Worker::with_blob_store`]), and
+/// returns what to keep in memory instead.
+fn spill(config: &Config, id: NodeId, name: &'static str, payload: Payload) -> Payload {
+    let Some((store, limit)) = &config.blobs else {
+        return payload;
+    };
+    let limit = config.output_limits.get(&id).unwrap_or(limit);
+    match blob::spill(&**store, *limit, name, &payload) {
+        Ok(Some(reference)) => reference,
+        Ok(None) => payload,
+        Err(error) => {
+            warn!(name, %error, "Could not write to blob store, keeping output in memory");
+            payload
+        }
+    }
+}
+
+/// Runs a producer, with the hooks around it. Returns the result, and how long the producer
+/// took.
+#[allow(clippy::too_many_arguments)] // It's okay
+async fn run_node<S: State>(
+    hooks: &[Arc<dyn Hook<S>>],
+    journal: Option<Journal>,
+    blobs: Option<Arc<dyn BlobStore>>,
+    name: &'static str,
+    placement: Placement,
+    producer: Producer<S>,
+    context: Context<S>,
+    payloads: Vec<Payload>,
+    timeout: Option<Duration>,
+) -> (Result<Payload, Error>, Duration) {
+    for hook in hooks {
+        hook.before_node(name, &context).await;
+    }
+    let t = rt::now();
+    let retry = context.retry();
+    let payloads = match blobs {
+        Some(store) => blob::load(&*store, payloads),
+        None => Ok(payloads),
+    };
+    let result = match (journal, payloads) {
+        (_, Err(e)) => Err(e),
+        (Some(Journal::Replay(replayer)), Ok(payloads)) => replayer.replay(name, retry, &payloads),
+        (Some(Journal::Record(recorder)), Ok(payloads)) => {
+            let inputs = payloads.iter().map(Payload::to_json).collect();
+            let result = produce_on(placement, producer, context, payloads, timeout).await;
+            recorder.record(name, retry, inputs, &result, t.elapsed());
+            result
+        }
+        (None, Ok(payloads)) => produce_on(placement, producer, context, payloads, timeout).await,
+    };
+    let took = t.elapsed();
+    for hook in hooks {
+        hook.after_node(name, &result, took).await;
+    }
+    (result, took)
+}
+
+/// Completes at `at`, or never if there is no `at`.
+async fn sleep_until(at: Option<Instant>) {
+    match at {
+        Some(at) => rt::sleep_until(at).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Runs a producer where it should be run. See [`produce`].
+async fn produce_on<S: State>(
+    placement: Placement,
+    producer: Producer<S>,
+    context: Context<S>,
+    payloads: Vec<Payload>,
+    timeout: Option<Duration>,
+) -> Result<Payload, Error> {
+    let run = async move { produce(&producer, context, payloads, timeout).await };
+    match placement {
+        Placement::Shared => run.await,
+        Placement::BlockingPool => rt::run_blocking_future(run).await,
+        Placement::Runtime(runtime) => {
+            let task = runtime.spawn(run);
+            let _abort = AbortOnDrop(task.abort_handle());
+            task.await.unwrap_or_else(|e| {
+                if e.is_panic() {
+                    std::panic::resume_unwind(e.into_panic())
+                }
+                Err(Error::fatal("The runtime running the node shut down"))
+            })
+        }
+    }
+}
+
+/// Runs a producer, and gives up if it takes longer than `timeout`.
+async fn produce<S: State>(
+    producer: &Producer<S>,
+    context: Context<S>,
+    payloads: Vec<Payload>,
+    timeout: Option<Duration>,
+) -> Result<Payload, Error> {
+    let fut = producer(context, payloads);
+    match timeout {
+        Some(timeout) => rt::timeout(timeout, fut)
+            .await
+            .unwrap_or_else(|| Err(Error::fatal(format!("Timed out after {timeout:?}")))),
+        None => fut.await,
+    }
+}
+
+/// Pick up values that the user may have changed (with [`Worker::set_value`]) while paused.
+async fn refresh<T: ::std::hash::BuildHasher>(
+    results: &mut HashMap<NodeId, Payload>,
+    ids: &HashMap<&'static str, NodeId>,
+    out: &Mutex<HashMap<&'static str, NodeState, T>>,
+) {
+    for (name, state) in out.lock().await.iter() {
+        match state {
+            NodeState::Provided { value } => {
+                results.insert(ids[name], Payload::Json(Arc::new(value.clone())));
+            }
+            NodeState::Done { value, .. } => {
+                results.insert(ids[name], value.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Turn down the steps that were asked for before the job paused on a breakpoint, so they do not
+/// let it past the breakpoint before anyone had a chance to look.
+fn turn_down_steps(steps: &mut mpsc::UnboundedReceiver<Step>) {
+    while let Ok(reply) = steps.try_recv() {
+        let _ = reply.send(Err("Was not paused"));
+    }
+}
+
+/// Wait until the user allows the next node to start, and tell them what was ready. Gives up if
+/// the worker starts draining.
+async fn wait_for_step(
+    steps: &mut mpsc::UnboundedReceiver<Step>,
+    ready: Vec<&'static str>,
+    draining: &CancellationToken,
+) {
+    info!(?ready, "Waiting for step");
+    tokio::select! {
+        // If every worker has been dropped, nobody can step anymore, so we just carry on.
+        Some(reply) = steps.recv() => {
+            let _ = reply.send(Ok(ready));
+        }
+        () = draining.cancelled() => {}
+        else => {}
+    }
+}
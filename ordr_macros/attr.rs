@@ -1,42 +1,228 @@
 //! Parse the attributes part of calling the `node` macro.
 
-use syn::{LitStr, Type, meta::ParseNestedMeta};
+use syn::{
+    LitInt, LitStr, Token, Type, bracketed, meta::ParseNestedMeta, parenthesized,
+    punctuated::Punctuated,
+};
 
 /// Describes the attibutes in a node(...) macro
 #[derive(Default)]
+#[allow(clippy::struct_excessive_bools)] // They are independent flags
 pub(super) struct Attr {
     /// The name of the node
     pub(super) name: Option<String>,
+    /// Include the module path in the name of the node, unless it is given one
+    pub(super) qualified: bool,
     /// The type of the output
     pub(super) out: Option<Type>,
     /// The type of the input state
     pub(super) state: Option<Type>,
+    /// Only keep the output in memory
+    pub(super) transient: bool,
+    /// Keep the output as bytes, instead of serializing it
+    pub(super) raw: bool,
+    /// Does heavy CPU work, so run it away from other nodes
+    pub(super) blocking: bool,
+    /// Timeout for a single attempt, in milliseconds
+    pub(super) timeout: Option<u64>,
+    /// Maximum number of retries
+    pub(super) max_retries: Option<u32>,
+    /// Start before ready nodes with a lower priority
+    pub(super) priority: Option<i32>,
+    /// Retry policy: its kind ("fixed" or "exponential"), delay in milliseconds and max retries
+    pub(super) retry: Option<(String, u64, u32)>,
+    /// Dependencies, when deriving `Node`
+    pub(super) deps: Option<Vec<Type>>,
+    /// The nodes produced at once, by a producer that returns a tuple of them
+    pub(super) outputs: Option<Vec<Type>>,
+    /// Run the producer once per item of this node
+    pub(super) map_over: Option<Type>,
+    /// Run after these nodes, if they are part of the job
+    pub(super) after: Vec<Type>,
+    /// Never run at the same time as other nodes using this resource
+    pub(super) exclusive: Option<String>,
+    /// Version of the shape of the output
+    pub(super) version: Option<u32>,
+    /// Pass dependencies that can not be deserialized as `None` or their default
+    pub(super) lenient: bool,
+    /// The output only marks that something was done, and is not serialized
+    pub(super) marker: bool,
+    /// Starts are limited by the rate limit of this tag
+    pub(super) rate: Option<String>,
+    /// Checks run once when the job starts, before any node runs
+    pub(super) requires: Vec<syn::Path>,
 }
 
 impl Attr {
     /// Parses the attributes on a node(...)
     pub(super) fn parse(&mut self, meta: &ParseNestedMeta) -> syn::Result<()> {
-        // name = "..."
-        if meta.path.is_ident("name") {
+        let ident = meta.path.get_ident().map(ToString::to_string);
+
+        // Types, like `output = Meta`, `state = State` or `map_over = Items`
+        let ty = match ident.as_deref() {
+            Some("output") => Some(&mut self.out),
+            Some("state") => Some(&mut self.state),
+            Some("map_over") => Some(&mut self.map_over),
+            _ => None,
+        };
+        if let Some(ty) = ty {
+            *ty = Some(meta.value()?.parse()?);
+            return Ok(());
+        }
+
+        // Strings, like `name = "..."`, `exclusive = "gpu"` or `rate = "openai"`
+        let string = match ident.as_deref() {
+            Some("name") => Some(&mut self.name),
+            Some("exclusive") => Some(&mut self.exclusive),
+            Some("rate") => Some(&mut self.rate),
+            _ => None,
+        };
+        if let Some(string) = string {
+            let lit: LitStr = meta.value()?.parse()?;
+            *string = Some(lit.value());
+            return Ok(());
+        }
+
+        // Flags, like `transient`
+        let flag = match ident.as_deref() {
+            Some("qualified") => Some(&mut self.qualified),
+            Some("transient") => Some(&mut self.transient),
+            Some("raw") => Some(&mut self.raw),
+            Some("blocking") => Some(&mut self.blocking),
+            Some("lenient") => Some(&mut self.lenient),
+            Some("marker") => Some(&mut self.marker),
+            _ => None,
+        };
+        if let Some(flag) = flag {
+            *flag = true;
+            return Ok(());
+        }
+
+        // timeout = "30s"
+        if meta.path.is_ident("timeout") {
             let lit: LitStr = meta.value()?.parse()?;
-            self.name = Some(lit.value());
+            let millis = parse_millis(&lit.value()).ok_or_else(|| {
+                syn::Error::new(
+                    lit.span(),
+                    "expected a duration like \"500ms\", \"30s\", \"5m\" or \"1h\"",
+                )
+            })?;
+            self.timeout = Some(millis);
+            return Ok(());
+        }
+
+        // deps(A, B) or outputs(A, B)
+        let types = match ident.as_deref() {
+            Some("deps") => Some(&mut self.deps),
+            Some("outputs") => Some(&mut self.outputs),
+            _ => None,
+        };
+        if let Some(types) = types {
+            let content;
+            parenthesized!(content in meta.input);
+            let parsed = Punctuated::<Type, Token![,]>::parse_terminated(&content)?;
+            *types = Some(parsed.into_iter().collect());
+            return Ok(());
+        }
+
+        // after = [A, B]
+        if meta.path.is_ident("after") {
+            let value = meta.value()?;
+            let content;
+            bracketed!(content in value);
+            let after = Punctuated::<Type, Token![,]>::parse_terminated(&content)?;
+            self.after.extend(after);
+            return Ok(());
+        }
+
+        // Numbers, like `version = 3` or `max_retries = 5`
+        let number = match ident.as_deref() {
+            Some("version") => Some(&mut self.version),
+            Some("max_retries") => Some(&mut self.max_retries),
+            _ => None,
+        };
+        if let Some(number) = number {
+            let lit: LitInt = meta.value()?.parse()?;
+            *number = Some(lit.base10_parse()?);
+            return Ok(());
+        }
+
+        // requires = check_s3_access, or requires = [check_s3_access, check_db]
+        if meta.path.is_ident("requires") {
+            self.requires.extend(parse_paths(meta.value()?)?);
             return Ok(());
         }
 
-        if meta.path.is_ident("output") {
-            let ty: syn::Type = meta.value()?.parse()?;
-            self.out = Some(ty);
+        // retry = "exponential(100ms, 5)"
+        if meta.path.is_ident("retry") {
+            let lit: LitStr = meta.value()?.parse()?;
+            let retry = parse_retry(&lit.value()).ok_or_else(|| {
+                syn::Error::new(
+                    lit.span(),
+                    "expected a retry policy like \"exponential(100ms, 5)\" or \"fixed(1s, 3)\"",
+                )
+            })?;
+            self.retry = Some(retry);
             return Ok(());
         }
 
-        if meta.path.is_ident("state") {
-            let ty: syn::Type = meta.value()?.parse()?;
-            self.state = Some(ty);
+        // priority = 10, or priority = -1
+        if meta.path.is_ident("priority") {
+            self.priority = Some(parse_signed(meta.value()?)?);
             return Ok(());
         }
 
-        Err(meta.error("unknown key in `node(...)`, expected one of: name or output"))
+        Err(meta.error(
+            "unknown key in `node(...)`, expected one of: name, qualified, output, state, transient, raw, blocking, timeout, max_retries, retry, priority, exclusive, rate, version, requires, deps, outputs, map_over or after",
+        ))
+    }
+}
+
+/// Parses a number that may be negative, like `10` or `-1`.
+fn parse_signed(input: syn::parse::ParseStream) -> syn::Result<i32> {
+    let negative = input.parse::<Option<Token![-]>>()?.is_some();
+    let lit: LitInt = input.parse()?;
+    let n: i32 = lit.base10_parse()?;
+    Ok(if negative { -n } else { n })
+}
+
+/// Parses a single path, like `check`, or a list of them, like `[check, other::check]`.
+fn parse_paths(input: syn::parse::ParseStream) -> syn::Result<Vec<syn::Path>> {
+    if !input.peek(syn::token::Bracket) {
+        return Ok(vec![input.parse()?]);
     }
+    let content;
+    bracketed!(content in input);
+    let paths = Punctuated::<syn::Path, Token![,]>::parse_terminated(&content)?;
+    Ok(paths.into_iter().collect())
+}
+
+/// Parses a retry policy like "exponential(100ms, 5)" into its kind, delay and max retries.
+fn parse_retry(s: &str) -> Option<(String, u64, u32)> {
+    let (kind, args) = s.strip_suffix(')')?.split_once('(')?;
+    let kind = kind.trim();
+    if kind != "fixed" && kind != "exponential" {
+        return None;
+    }
+    let (delay, max_retries) = args.split_once(',')?;
+    let delay = parse_millis(delay.trim())?;
+    let max_retries = max_retries.trim().parse().ok()?;
+    Some((kind.to_string(), delay, max_retries))
+}
+
+/// Parses a duration like "30s" into milliseconds.
+fn parse_millis(s: &str) -> Option<u64> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n.parse().ok()?;
+    let factor = match unit {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        _ => return None,
+    };
+    n.checked_mul(factor)
 }
 
 #[cfg(test)]
@@ -64,5 +250,155 @@ mod tests {
         assert_eq!(args.out.into_token_stream().to_string(), "Foo");
         assert_eq!(args.name.as_deref(), Some("foo"));
         assert_eq!(args.state.into_token_stream().to_string(), "State");
+        assert!(!args.transient);
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        let args = parse_args(parse_quote! { timeout = "30s" });
+        assert_eq!(args.timeout, Some(30_000));
+        let args = parse_args(parse_quote! { timeout = "250ms" });
+        assert_eq!(args.timeout, Some(250));
+
+        let mut attr = Attr::default();
+        let parser = syn::meta::parser(|meta: ParseNestedMeta| attr.parse(&meta));
+        assert!(parser.parse2(parse_quote! { timeout = "soon" }).is_err());
+    }
+
+    #[test]
+    fn test_parse_max_retries() {
+        let args = parse_args(parse_quote! { max_retries = 5 });
+        assert_eq!(args.max_retries, Some(5));
+    }
+
+    #[test]
+    fn test_parse_blocking() {
+        let args = parse_args(parse_quote! { blocking, timeout = "1s" });
+        assert!(args.blocking);
+        assert!(!args.transient);
+    }
+
+    #[test]
+    fn test_parse_retry() {
+        let args = parse_args(parse_quote! { retry = "exponential(100ms, 5)" });
+        assert_eq!(args.retry, Some(("exponential".to_string(), 100, 5)));
+        let args = parse_args(parse_quote! { retry = "fixed(2s,3)" });
+        assert_eq!(args.retry, Some(("fixed".to_string(), 2000, 3)));
+        assert!(super::parse_retry("linear(1s, 3)").is_none());
+    }
+
+    #[test]
+    fn test_parse_priority() {
+        let args = parse_args(parse_quote! { priority = 10 });
+        assert_eq!(args.priority, Some(10));
+        let args = parse_args(parse_quote! { priority = -3 });
+        assert_eq!(args.priority, Some(-3));
+    }
+
+    #[test]
+    fn test_parse_qualified() {
+        let args = parse_args(parse_quote! { qualified, transient });
+        assert!(args.qualified);
+        assert!(args.transient);
+        assert!(!parse_args(parse_quote! { transient }).qualified);
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        assert!(parse_args(parse_quote! { lenient }).lenient);
+        assert!(!parse_args(parse_quote! { blocking }).lenient);
+    }
+
+    #[test]
+    fn test_parse_marker() {
+        assert!(parse_args(parse_quote! { marker }).marker);
+        assert!(!parse_args(parse_quote! { raw }).marker);
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        let args = parse_args(parse_quote! { rate = "openai" });
+        assert_eq!(args.rate.as_deref(), Some("openai"));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let args = parse_args(parse_quote! { version = 3 });
+        assert_eq!(args.version, Some(3));
+    }
+
+    #[test]
+    fn test_parse_exclusive() {
+        let args = parse_args(parse_quote! { exclusive = "gpu", priority = 1 });
+        assert_eq!(args.exclusive.as_deref(), Some("gpu"));
+        assert_eq!(args.priority, Some(1));
+    }
+
+    #[test]
+    fn test_parse_deps() {
+        let args = parse_args(parse_quote! { deps(A, b::B) });
+        let deps: Vec<_> = args
+            .deps
+            .unwrap()
+            .iter()
+            .map(|ty| ty.to_token_stream().to_string())
+            .collect();
+        assert_eq!(deps, ["A", "b :: B"]);
+    }
+
+    #[test]
+    fn test_parse_outputs() {
+        let args = parse_args(parse_quote! { outputs(Doc, meta::Meta) });
+        let outputs: Vec<_> = args
+            .outputs
+            .unwrap()
+            .iter()
+            .map(|ty| ty.to_token_stream().to_string())
+            .collect();
+        assert_eq!(outputs, ["Doc", "meta :: Meta"]);
+        assert!(args.deps.is_none());
+    }
+
+    #[test]
+    fn test_parse_after() {
+        let args = parse_args(parse_quote! { after = [A, b::B], name = "C" });
+        let after: Vec<_> = args
+            .after
+            .iter()
+            .map(|ty| ty.to_token_stream().to_string())
+            .collect();
+        assert_eq!(after, ["A", "b :: B"]);
+        assert_eq!(args.name.as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn test_parse_requires() {
+        let args = parse_args(parse_quote! { requires = check_s3, requires = [a::b, c] });
+        let requires: Vec<_> = args
+            .requires
+            .iter()
+            .map(|path| path.to_token_stream().to_string())
+            .collect();
+        assert_eq!(requires, ["check_s3", "a :: b", "c"]);
+    }
+
+    #[test]
+    fn test_parse_map_over() {
+        let args = parse_args(parse_quote! { map_over = Pages, output = Summaries });
+        assert_eq!(args.map_over.into_token_stream().to_string(), "Pages");
+        assert_eq!(args.out.into_token_stream().to_string(), "Summaries");
+    }
+
+    #[test]
+    fn test_parse_transient() {
+        let args = parse_args(parse_quote! { transient });
+        assert!(args.transient);
+    }
+
+    #[test]
+    fn test_parse_raw() {
+        let args = parse_args(parse_quote! { raw, name = "Image" });
+        assert!(args.raw);
+        assert!(!args.transient);
     }
 }
@@ -13,6 +13,36 @@ pub(super) fn input(sig: &Signature) -> Vec<Type> {
         .collect::<Vec<_>>()
 }
 
+/// Removes `#[ordr(lenient)]` from the arguments, and returns which of them had it.
+pub(super) fn take_lenient(sig: &mut Signature) -> Vec<bool> {
+    sig.inputs
+        .iter_mut()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(p) => Some(p),
+            FnArg::Receiver(_) => None,
+        })
+        .map(|p| {
+            let (ordr, rest) = std::mem::take(&mut p.attrs)
+                .into_iter()
+                .partition(|a| a.path().is_ident("ordr"));
+            p.attrs = rest;
+            for a in &ordr {
+                let parsed = a.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("lenient") {
+                        Ok(())
+                    } else {
+                        Err(meta.error("Unknown option. Only `lenient` is supported"))
+                    }
+                });
+                if let Err(e) = parsed {
+                    panic!("{e}");
+                }
+            }
+            !ordr.is_empty()
+        })
+        .collect()
+}
+
 /// Given something like `Result<T, E>` or `Context<T>` this function will return `T`.
 pub(super) fn first_generic(ty: &Type) -> Type {
     let e = syn::Error::new(
@@ -37,6 +67,37 @@ pub(super) fn first_generic(ty: &Type) -> Type {
     inner_ty.clone()
 }
 
+/// Given `Option<T>`, returns `T`.
+pub(super) fn option_inner(ty: &Type) -> Option<Type> {
+    wrapped(ty, "Option")
+}
+
+/// Given `Arc<T>` or `&T`, returns `T`.
+pub(super) fn shared_inner(ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Reference(r) if r.mutability.is_none() => Some(*r.elem.clone()),
+        ty => wrapped(ty, "Arc"),
+    }
+}
+
+/// Given `Wrapper<T>`, where `Wrapper` is called `wrapper`, returns `T`.
+fn wrapped(ty: &Type, wrapper: &str) -> Option<Type> {
+    let Type::Path(TypePath { path, .. }) = ty else {
+        return None;
+    };
+    let seg = path.segments.last()?;
+    if seg.ident != wrapper {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &seg.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner.clone()),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use quote::ToTokens;
@@ -72,4 +133,41 @@ mod tests {
         let fn_args = input(&f.sig);
         assert_eq!(fn_args.len(), 3);
     }
+
+    #[test]
+    fn parse_lenient() {
+        let mut f: ItemFn = parse_quote! {
+            async fn exec(_ctx: (), #[ordr(lenient)] x: i32, #[allow(unused)] y: i32) -> Result<i32, ordr::Error> {
+                Ok(x + y)
+            }
+        };
+        assert_eq!(take_lenient(&mut f.sig), [false, true, false]);
+        let FnArg::Typed(x) = &f.sig.inputs[1] else {
+            panic!("x is typed");
+        };
+        assert!(x.attrs.is_empty());
+        let FnArg::Typed(y) = &f.sig.inputs[2] else {
+            panic!("y is typed");
+        };
+        assert_eq!(y.attrs.len(), 1);
+    }
+
+    #[test]
+    fn parse_option() {
+        let ty: Type = parse_quote! { Option<A> };
+        let inner = option_inner(&ty).unwrap();
+        assert_eq!(inner.to_token_stream().to_string(), "A");
+        assert!(option_inner(&parse_quote! { A }).is_none());
+    }
+
+    #[test]
+    fn parse_shared() {
+        let ty: Type = parse_quote! { std::sync::Arc<A> };
+        let inner = shared_inner(&ty).unwrap();
+        assert_eq!(inner.to_token_stream().to_string(), "A");
+        let inner = shared_inner(&parse_quote! { &b::B }).unwrap();
+        assert_eq!(inner.to_token_stream().to_string(), "b :: B");
+        assert!(shared_inner(&parse_quote! { &mut A }).is_none());
+        assert!(shared_inner(&parse_quote! { A }).is_none());
+    }
 }
@@ -3,41 +3,608 @@ mod input_output;
 
 use attr::Attr;
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{Ident, ItemFn, ReturnType, Type, parse_macro_input, spanned::Spanned};
+use quote::{format_ident, quote};
+use syn::{
+    Attribute, Ident, ImplItem, ItemFn, ItemImpl, Meta, ReturnType, Signature, Type,
+    parse_macro_input, spanned::Spanned,
+};
 
 /// Mark a function return a `Result<T, ordr::Error>` as a producer of `T`.
 ///
+/// The function can be `async`, or a plain function, which is then run on tokio's blocking thread
+/// pool (so CPU heavy work doesn't hold up other nodes).
+///
+/// Dependencies are the arguments after the context. `Option<A>` is an optional dependency on
+/// `A`. Taking `Arc<A>` or `&A` shares the output of a transient node with its other dependents,
+/// instead of cloning it for each of them.
+///
+/// A dependency marked `#[ordr(lenient)]` (or every dependency, with the `lenient` option) that
+/// can not be deserialized, say because it was saved by an older version of the node, is passed
+/// as `None` if it is an `Option`, and as its `Default` otherwise, instead of failing the node.
+///
+/// With `marker`, the output only marks that something was done (say, `DocUploaded`), and must be
+/// a unit struct, which does not need to implement `Serialize` or `Deserialize`.
+///
+/// The node is named after its output, say `"Meta"`. Pick another name with `name = "..."`, or
+/// include the module path with `qualified` (say `"my_crate::meta::Meta"`), so nodes from
+/// different crates do not collide.
+///
+/// A producer that returns a tuple, like `Result<(Doc, Meta)>`, produces both `Doc` and `Meta`,
+/// so one expensive operation (say, parsing a document) can feed nodes of both types. It is run
+/// once, as a transient node named after the function (or `name`), which the settings apply to.
+/// Spell the outputs out with `outputs(Doc, Meta)`, if the tuple is hidden behind a type alias.
+///
+/// With `requires = check_s3_access` (or `requires = [a, b]`), the async function
+/// `check_s3_access`, which takes the state and returns a `Result<()>`, is run once when the job
+/// starts. If it fails, no node is run at all.
+///
+/// With the `registry` feature, the node is registered under its name, so a job can be built
+/// from it without knowing its type (see `JobBuilder::add_by_name`).
+///
 /// # Panics
 /// There are several more or less implicit rules that the function (and output) needs to abide by.
 /// If any of them are violated, we panic with a hopefully good error message.
 #[proc_macro_attribute]
 pub fn producer(attrs: TokenStream, item: TokenStream) -> TokenStream {
-    let func = parse_macro_input!(item as ItemFn);
+    let mut func = parse_macro_input!(item as ItemFn);
+    let lenient = input_output::take_lenient(&mut func.sig);
     let func_ident = &func.sig.ident;
 
     let mut attr = Attr::default();
     let parser = syn::meta::parser(|meta| attr.parse(&meta));
     parse_macro_input!(attrs with parser);
 
-    let mut dep_tys = input_output::input(&func.sig);
+    assert!(
+        func.sig.receiver().is_none(),
+        "Put `#[ordr::service]` on the `impl` block to use methods as producers"
+    );
+
+    let node = producer_impl(attr, &func.sig, &lenient, &quote! { #func_ident });
+    quote! {
+        #func
+
+        #node
+    }
+    .into()
+}
+
+/// Lets methods be producers, so they can use the clients and config of the struct they are
+/// defined on. Put it on the `impl` block, and mark the methods with `#[producer]`, which takes the
+/// usual options. The methods take `&self`, followed by the usual arguments.
+///
+/// `self` is the state of the job, so the state must be the struct, or deref to it (like
+/// `Arc<MyService>`).
+///
+/// # Panics
+/// If any of the marked methods could not be a producer (see [`macro@producer`]), or does not
+/// take `&self`.
+#[proc_macro_attribute]
+pub fn service(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    assert!(attrs.is_empty(), "`service` takes no options");
+    let mut block = parse_macro_input!(item as ItemImpl);
+    let self_ty = &block.self_ty;
+    let mut nodes = vec![];
+    for item in &mut block.items {
+        let ImplItem::Fn(method) = item else {
+            continue;
+        };
+        let is_producer = |a: &Attribute| a.path().segments.last().unwrap().ident == "producer";
+        let Some(i) = method.attrs.iter().position(is_producer) else {
+            continue;
+        };
+        let marker = method.attrs.remove(i);
+        let mut attr = Attr::default();
+        if matches!(marker.meta, Meta::List(_))
+            && let Err(e) = marker.parse_nested_meta(|meta| attr.parse(&meta))
+        {
+            return e.to_compile_error().into();
+        }
+
+        let lenient = input_output::take_lenient(&mut method.sig);
+        let sig = &method.sig;
+        assert!(
+            matches!(sig.receiver(), Some(r) if r.reference.is_some() && r.mutability.is_none()),
+            "Producer methods must take `&self`"
+        );
+        let ident = &sig.ident;
+        let inputs = input_output::input(sig);
+        let context_ty = &inputs[0];
+        let args: Vec<_> = (1..inputs.len())
+            .map(|i| Ident::new(&format!("arg{i}"), ident.span()))
+            .collect();
+        // Calls the method on the state, which the future then has to own.
+        let call = quote! { <#self_ty>::#ident(&service, context, #(#args),*) };
+        let call = if sig.asyncness.is_some() {
+            quote! { async move { #call.await } }
+        } else {
+            call
+        };
+        let func = quote! {
+            (|context: #context_ty, #(#args),*| {
+                let service = context.state.clone();
+                #call
+            })
+        };
+        nodes.push(producer_impl(attr, sig, &lenient, &func));
+    }
+    quote! {
+        #block
+
+        #( #nodes )*
+    }
+    .into()
+}
+
+/// Implements `NodeBuilder` for the output of a producer with signature `sig`, where `func` is
+/// what gets called. `lenient` has the arguments marked `#[ordr(lenient)]`.
+fn producer_impl(
+    mut attr: Attr,
+    sig: &Signature,
+    lenient: &[bool],
+    func: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let mut dep_tys = input_output::input(sig);
     let context_ty = dep_tys.remove(0); // First one is the Context argument
+    let lenient: Vec<_> = lenient[1..].iter().map(|l| *l || attr.lenient).collect();
 
-    let node_ty = match (attr.out, &func.sig.output) {
-        (Some(ty), _) => ty,
-        (None, ReturnType::Default) => panic!("The producer function must return a Result<T>"),
-        (None, ReturnType::Type(_, box_ty)) => input_output::first_generic(box_ty),
-    };
+    assert!(
+        attr.deps.is_none(),
+        "`deps` is only for `derive(Node)`. Producers take their dependencies as arguments."
+    );
+
+    if attr.map_over.is_some() {
+        assert!(
+            attr.out.is_some(),
+            "A producer with `map_over` must set `output`, since it returns a single item"
+        );
+        assert!(
+            dep_tys.len() == 1,
+            "A producer with `map_over` takes the context and a single item"
+        );
+        assert!(!attr.transient, "`map_over` can not be transient");
+        assert!(!attr.raw, "`map_over` can not be raw");
+        assert!(!attr.marker, "`map_over` can not be a marker");
+        assert!(!lenient.contains(&true), "`map_over` can not be lenient");
+        assert!(
+            sig.asyncness.is_some(),
+            "A producer with `map_over` must be async"
+        );
+    }
 
     let state_ty = attr
         .state
+        .take()
         .unwrap_or_else(|| input_output::first_generic(&context_ty));
+    let plain_fn = sig.asyncness.is_none();
+
+    let returned = match &sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, box_ty) => Some(input_output::first_generic(box_ty)),
+    };
+    let outputs = match (attr.outputs.take(), &returned) {
+        (Some(outputs), _) => Some(outputs),
+        (None, Some(Type::Tuple(tuple))) if attr.out.is_none() && tuple.elems.len() > 1 => {
+            Some(tuple.elems.iter().cloned().collect())
+        }
+        _ => None,
+    };
+    if let Some(outputs) = outputs {
+        assert!(
+            attr.out.is_none(),
+            "A producer with several outputs can not also set `output`"
+        );
+        let ident = sig.ident.to_string();
+        return outputs_impl(
+            &attr, &ident, &outputs, &state_ty, &dep_tys, &lenient, func, plain_fn,
+        );
+    }
+
+    let node_ty = attr
+        .out
+        .take()
+        .or(returned)
+        .unwrap_or_else(|| panic!("The producer function must return a Result<T>"));
+
+    node_impl(
+        attr, &node_ty, &state_ty, &dep_tys, &lenient, func, plain_fn,
+    )
+}
+
+/// Declare a struct as a node, with a producer that is an associated function on it, called
+/// `produce`. Dependencies are listed with `#[node(deps(A, B))]`, and are passed to `produce` in
+/// that order.
+///
+/// Takes the same options as [`macro@producer`], except `output`, which is always the struct.
+/// Without `state`, the state is `()`. Unit structs are markers, unless they are `transient` or
+/// `raw`.
+///
+/// # Panics
+/// If the struct is generic, or the options can not be parsed.
+#[proc_macro_derive(Node, attributes(node))]
+pub fn derive_node(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    if !input.generics.params.is_empty() {
+        let e = syn::Error::new(input.generics.span(), "Nodes cannot be generic");
+        return e.to_compile_error().into();
+    }
+
+    let mut attr = Attr::default();
+    for a in input.attrs.iter().filter(|a| a.path().is_ident("node")) {
+        if let Err(e) = a.parse_nested_meta(|meta| attr.parse(&meta)) {
+            return e.to_compile_error().into();
+        }
+    }
+
+    // Unit structs carry nothing, so they do not have to be serializable.
+    let unit = matches!(
+        &input.data,
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Unit,
+            ..
+        })
+    );
+    attr.marker |= unit && !attr.transient && !attr.raw;
+
+    let ident = &input.ident;
+    let node_ty: Type = syn::parse_quote! { #ident };
+    let state_ty = attr
+        .state
+        .take()
+        .unwrap_or_else(|| syn::parse_quote! { () });
+    assert!(
+        attr.outputs.is_none(),
+        "`outputs` is only for producers. A derived node is its own output."
+    );
+    let dep_tys = attr.deps.take().unwrap_or_default();
+    let lenient = vec![attr.lenient; dep_tys.len()];
+    node_impl(
+        attr,
+        &node_ty,
+        &state_ty,
+        &dep_tys,
+        &lenient,
+        &quote! { #ident::produce },
+        false,
+    )
+    .into()
+}
+
+/// Derive `FromData` for a struct, to pull the values of several nodes out of the data of a job
+/// at once (see `Worker::extract`). Each field is the value of the node with the same name as
+/// the field, unless it is renamed with `#[ordr(name = "...")]`. Fields of type `Option<T>` are
+/// `None` if the node has no value.
+///
+/// # Panics
+/// If the item is not a struct with named fields, or the options can not be parsed.
+#[proc_macro_derive(FromData, attributes(ordr))]
+pub fn derive_from_data(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    let syn::Data::Struct(syn::DataStruct {
+        fields: syn::Fields::Named(fields),
+        ..
+    }) = &input.data
+    else {
+        let e = syn::Error::new(input.span(), "FromData needs a struct with named fields");
+        return e.to_compile_error().into();
+    };
+
+    let mut inits = vec![];
+    for field in &fields.named {
+        let ident = field.ident.as_ref().unwrap();
+        let mut name = ident.to_string();
+        for a in field.attrs.iter().filter(|a| a.path().is_ident("ordr")) {
+            let parsed = a.parse_nested_meta(|meta| {
+                if !meta.path.is_ident("name") {
+                    return Err(meta.error("Unknown option. Only `name` is supported"));
+                }
+                name = meta.value()?.parse::<syn::LitStr>()?.value();
+                Ok(())
+            });
+            if let Err(e) = parsed {
+                return e.to_compile_error().into();
+            }
+        }
+        let extract = if input_output::option_inner(&field.ty).is_some() {
+            quote! { ordr::extract_optional_field }
+        } else {
+            quote! { ordr::extract_field }
+        };
+        inits.push(quote! { #ident: #extract(data, #name)? });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ordr::FromData for #ident #ty_generics #where_clause {
+            fn from_data(
+                data: &::std::collections::HashMap<::std::string::String, ordr::serde_json::Value>,
+            ) -> ::std::result::Result<Self, ordr::ExtractError> {
+                Ok(Self { #(#inits),* })
+            }
+        }
+    }
+    .into()
+}
+
+/// Implements `NodeBuilder` for `node_ty`, with `func` as the producer. `plain_fn` is set if
+/// `func` is a plain function, rather than an async one. `lenient` has the dependencies that are
+/// passed as `None` or their default if they can not be deserialized.
+fn node_impl(
+    attr: Attr,
+    node_ty: &Type,
+    state_ty: &Type,
+    dep_tys: &[Type],
+    lenient: &[bool],
+    func: &proc_macro2::TokenStream,
+    plain_fn: bool,
+) -> proc_macro2::TokenStream {
+    let node_name = node_name(&attr, node_ty);
+    let after = &attr.after;
+    let settings = settings(&attr, plain_fn);
+    let register = register(node_ty, state_ty);
+
+    // The producer runs once per item, so it does not get the dependencies as they are.
+    if let Some(items) = attr.map_over {
+        return quote! {
+            impl ordr::NodeBuilder<#state_ty> for #node_ty {
+                fn node() -> ordr::Node<#state_ty> {
+                    ordr::Node::builder::<#node_ty>(#node_name)
+                        .dep::<#items>()
+                        .validate_data()
+                        #( .after::<#after>() )*
+                        #settings
+                        .map_producer(#func)
+                }
 
-    let node_name = attr.name.unwrap_or_else(|| ty_to_string(&node_ty));
+                fn try_decode(payload: ordr::Payload) -> ::std::result::Result<Self, String> {
+                    payload.try_deserialize()
+                }
+            }
 
-    let mut dep_idents = vec![];
-    for ty in &dep_tys {
-        let Type::Path(type_path) = ty else {
+            #register
+        };
+    }
+
+    assert!(
+        [attr.transient, attr.raw, attr.marker]
+            .iter()
+            .filter(|flag| **flag)
+            .count()
+            <= 1,
+        "A producer can only be one of transient, raw and marker"
+    );
+
+    // Transient outputs are passed on as they are, raw ones as bytes, markers are not passed on
+    // at all, and everything else is serialized.
+    let (kind, decode) = match (attr.transient, attr.raw, attr.marker) {
+        (true, _, _) => ("transient_", quote! { Ok(payload.from_transient()) }),
+        (_, true, _) => ("raw_", quote! { Ok(payload.from_raw()) }),
+        (_, _, true) => ("marker_", quote! { Ok(#node_ty) }),
+        _ => ("", quote! { payload.try_deserialize() }),
+    };
+    let blocking = if plain_fn { "blocking_" } else { "" };
+    let producer = format_ident!("{kind}{blocking}producer");
+
+    // Only serialized outputs are checked when provided as JSON.
+    let validate = kind.is_empty().then(|| quote! { .validate_data() });
+
+    // Transient outputs can be shared with dependents that take them as `Arc<T>` or `&T`.
+    let decode_shared = attr.transient.then(|| {
+        quote! {
+            fn try_decode_shared(
+                payload: ordr::Payload,
+            ) -> ::std::result::Result<::std::sync::Arc<Self>, String> {
+                Ok(payload.from_transient_shared())
+            }
+        }
+    });
+
+    let DepArgs {
+        adds: deps,
+        idents: dep_idents,
+        args,
+        borrowed,
+    } = dep_args(dep_tys, lenient);
+    // A future that borrows the dependencies has to own them.
+    let call = if borrowed && !plain_fn {
+        quote! { async move { #func(context, #(#args),* ).await } }
+    } else {
+        quote! { #func(context, #(#args),* ) }
+    };
+
+    quote! {
+        impl ordr::NodeBuilder<#state_ty> for #node_ty {
+            fn node() -> ordr::Node<#state_ty> {
+                ordr::Node::builder(#node_name)
+                    #( #deps )*
+                    #validate
+                    #( .after::<#after>() )*
+                    #settings
+                    .#producer(|context, ( #(#dep_idents,)* )| {
+                        #call
+                    })
+            }
+
+            fn try_decode(payload: ordr::Payload) -> ::std::result::Result<Self, String> {
+                #decode
+            }
+
+            #decode_shared
+        }
+
+        #register
+    }
+}
+
+/// Implements `NodeBuilder` for each of `outputs`, produced all at once by `func`. The producer
+/// is a transient node, called `ident` (unless it is named), with the outputs as a tuple, and
+/// each output is picked out of it by a node of its own.
+#[allow(clippy::too_many_arguments)]
+fn outputs_impl(
+    attr: &Attr,
+    ident: &str,
+    outputs: &[Type],
+    state_ty: &Type,
+    dep_tys: &[Type],
+    lenient: &[bool],
+    func: &proc_macro2::TokenStream,
+    plain_fn: bool,
+) -> proc_macro2::TokenStream {
+    assert!(
+        attr.map_over.is_none() && !attr.transient && !attr.raw && !attr.marker,
+        "A producer with several outputs can not be map_over, transient, raw or a marker"
+    );
+    assert!(
+        attr.version.is_none(),
+        "A producer with several outputs can not set a version"
+    );
+
+    let whole_name = match &attr.name {
+        Some(name) => quote! { #name },
+        None if attr.qualified => {
+            let name = format!("::{ident}");
+            quote! { concat!(module_path!(), #name) }
+        }
+        None => quote! { #ident },
+    };
+    let whole_ty = quote! { ( #( #outputs, )* ) };
+    let after = &attr.after;
+    let settings = settings(attr, plain_fn);
+    let producer = if plain_fn {
+        quote! { transient_blocking_producer }
+    } else {
+        quote! { transient_producer }
+    };
+    let DepArgs {
+        adds: deps,
+        idents: dep_idents,
+        args,
+        borrowed,
+    } = dep_args(dep_tys, lenient);
+    let call = if borrowed && !plain_fn {
+        quote! { async move { #func(context, #(#args),* ).await } }
+    } else {
+        quote! { #func(context, #(#args),* ) }
+    };
+    let whole = quote! {
+        ordr::Node::builder::<#whole_ty>(#whole_name)
+            #( #deps )*
+            #( .after::<#after>() )*
+            #settings
+            .#producer(|context, ( #(#dep_idents,)* )| {
+                #call
+            })
+    };
+
+    // The outputs are named after their types, since the name is taken by the producer.
+    let part_attr = Attr {
+        qualified: attr.qualified,
+        ..Attr::default()
+    };
+    let parts = outputs.iter().enumerate().map(|(i, node_ty)| {
+        let i = syn::Index::from(i);
+        let node_name = node_name(&part_attr, node_ty);
+        let register = register(node_ty, state_ty);
+        quote! {
+            impl ordr::NodeBuilder<#state_ty> for #node_ty {
+                fn node() -> ordr::Node<#state_ty> {
+                    ordr::Node::builder::<#node_ty>(#node_name)
+                        .validate_data()
+                        .output_of(#whole, |whole: &#whole_ty| &whole.#i)
+                }
+
+                fn try_decode(payload: ordr::Payload) -> ::std::result::Result<Self, String> {
+                    payload.try_deserialize()
+                }
+            }
+
+            #register
+        }
+    });
+    quote! { #( #parts )* }
+}
+
+/// Registers the node, so it can be added to a job by name, if the `registry` feature is on.
+fn register(node_ty: &Type, state_ty: &Type) -> Option<proc_macro2::TokenStream> {
+    cfg!(feature = "registry").then(|| {
+        quote! {
+            ordr::inventory::submit! {
+                ordr::RegisteredNode::new::<#state_ty, #node_ty>()
+            }
+        }
+    })
+}
+
+/// The settings of the node, like its timeout, as calls on its `NodeDef`. `plain_fn` is set if
+/// the producer is a plain function, rather than an async one.
+fn settings(attr: &Attr, plain_fn: bool) -> proc_macro2::TokenStream {
+    let timeout = attr
+        .timeout
+        .map(|millis| quote! { .timeout(::std::time::Duration::from_millis(#millis)) });
+    let max_retries = attr.max_retries.map(|max| quote! { .max_retries(#max) });
+    let priority = attr.priority.map(|p| quote! { .priority(#p) });
+    let exclusive = attr
+        .exclusive
+        .as_ref()
+        .map(|resource| quote! { .exclusive(#resource) });
+    let version = attr.version.map(|v| quote! { .version(#v) });
+    let rate = attr.rate.as_ref().map(|tag| quote! { .rate(#tag) });
+    let requires = attr.requires.iter().map(|check| {
+        let name = check.segments.last().unwrap().ident.to_string();
+        quote! { .requires(#name, #check) }
+    });
+    // Plain functions run on the blocking thread pool anyway.
+    let run_blocking = (attr.blocking && !plain_fn).then(|| quote! { .blocking() });
+    let retry = attr.retry.as_ref().map(|(kind, millis, max)| {
+        let kind = Ident::new(kind, proc_macro2::Span::call_site());
+        quote! {
+            .retry_policy(ordr::RetryPolicy::#kind(::std::time::Duration::from_millis(#millis), #max))
+        }
+    });
+    quote! {
+        #timeout #max_retries #priority #exclusive #rate #version #retry #run_blocking
+        #( #requires )*
+    }
+}
+
+/// How a producer takes its dependencies.
+struct DepArgs {
+    /// Adds each dependency to the node.
+    adds: Vec<proc_macro2::TokenStream>,
+    /// What each dependency is called, once decoded.
+    idents: Vec<Ident>,
+    /// How each dependency is passed to the producer.
+    args: Vec<proc_macro2::TokenStream>,
+    /// Whether any of them are passed by reference.
+    borrowed: bool,
+}
+
+fn dep_args(dep_tys: &[Type], lenient: &[bool]) -> DepArgs {
+    let mut deps = DepArgs {
+        adds: vec![],
+        idents: vec![],
+        args: vec![],
+        borrowed: false,
+    };
+    for (ty, &lenient) in dep_tys.iter().zip(lenient) {
+        // `Option<A>` is an optional dependency on `A`, and `Arc<A>` or `&A` a shared one.
+        let borrowed = matches!(ty, Type::Reference(_));
+        let (ty, add) = if let Some(inner) = input_output::option_inner(ty) {
+            let add = if lenient {
+                quote! { lenient_optional_dep }
+            } else {
+                quote! { optional_dep }
+            };
+            (inner, add)
+        } else if let Some(inner) = input_output::shared_inner(ty) {
+            assert!(!lenient, "Shared dependencies can not be lenient");
+            (inner, quote! { shared_dep })
+        } else if lenient {
+            (ty.clone(), quote! { lenient_dep })
+        } else {
+            (ty.clone(), quote! { dep })
+        };
+        let Type::Path(type_path) = &ty else {
             panic!("{ty:?} has no path")
         };
         for seg in &type_path.path.segments {
@@ -52,45 +619,31 @@ pub fn producer(attrs: TokenStream, item: TokenStream) -> TokenStream {
         let seg = type_path.path.segments.last().unwrap();
         let str = seg.ident.to_string().to_lowercase();
         let ident = Ident::new(&str, seg.ident.span());
-        dep_idents.push(ident);
+        deps.args.push(if borrowed {
+            quote! { &*#ident }
+        } else {
+            quote! { #ident }
+        });
+        deps.borrowed |= borrowed;
+        deps.idents.push(ident);
+        deps.adds.push(quote! { .#add::<#ty>() });
     }
+    deps
+}
 
-    quote! {
-        #func
-
-        impl ordr::NodeBuilder<#state_ty> for #node_ty {
-            fn node() -> ordr::Node<#state_ty> {
-                ordr::Node {
-                    id: std::any::TypeId::of::<#node_ty>(),
-                    name: #node_name,
-                    deps: std::sync::Arc::new(|| {
-                        vec![
-                            #(
-                                #dep_tys::node()
-                            ),*
-                        ]
-                    }),
-                    producer: std::sync::Arc::new(|context, payloads| {
-                        let [ #(#dep_idents),* ] = payloads.try_into().unwrap();
-                        let ( #(#dep_idents),* ) = (
-                            #(
-                                ordr::serde_json::from_value(#dep_idents).unwrap()
-                            ),*
-                        );
-                        Box::pin(async move {
-                            let result = match #func_ident(context, #(#dep_idents),* ).await {
-                                Ok(result) => result,
-                                Err(e) => return Err(e),
-                            };
-                            let v = ordr::serde_json::to_value(result).unwrap();
-                            Ok(v)
-                        })
-                    })
-                }
-            }
+/// The name of the node: as given, or the name of its output, optionally with the module path.
+fn node_name(attr: &Attr, node_ty: &Type) -> proc_macro2::TokenStream {
+    match &attr.name {
+        Some(name) => quote! { #name },
+        None if attr.qualified => {
+            let name = format!("::{}", ty_to_string(node_ty));
+            quote! { concat!(module_path!(), #name) }
+        }
+        None => {
+            let name = ty_to_string(node_ty);
+            quote! { #name }
         }
     }
-    .into()
 }
 
 fn ty_to_string(ty: &Type) -> String {
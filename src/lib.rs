@@ -97,8 +97,12 @@
 //!
 //! * All nodes and the context must implement `Clone` and Serde's `Serialize` and `Deserialize`.
 //! * All producers must return a `ordr::Result` (which is a `Result<T, ordr::Error>`.
-//! * All producers must be async and take `ordr::Context<State>` as the first parameter.
+//! * All producers must take `ordr::Context<State>` as the first parameter.
 //!     * `State` is your state. Whatever you need.
+//! * Producers are usually async. A plain (non-async) function is run on a blocking thread pool,
+//!   which is handy for CPU heavy work.
+//! * Jobs run on tokio. Enable the `async-std` or `smol` feature to run them outside of a tokio
+//!   runtime as well.
 //!
 //!
 //! # Mermaid diagram
@@ -161,6 +165,293 @@
 //! # };
 //! ```
 //!
+//! When the output of a node changes shape, give its producer a version, like
+//! `#[ordr::producer(version = 2)]`. Its data then carries the version, and data from another
+//! version is rejected when the job is built, unless [`JobBuilder::migrate`] says how to upgrade
+//! it.
+//!
+//!
+//! # Typed results
+//!
+//! Instead of deserializing the values in [`Worker::data`] one by one, derive [`macro@FromData`]
+//! for a struct of them, and get it with [`Worker::extract`]. Fields are looked up by their name,
+//! unless given another with `#[ordr(name = "...")]`.
+//!
+//! ```
+//! # async {
+//! # #[derive(Clone, serde::Serialize, serde::Deserialize)]
+//! # struct A(i32);
+//! # #[ordr::producer]
+//! # async fn a(_ctx: ordr::Context<()>) -> ordr::Result<A> { Ok(A(123)) }
+//! # let job = ordr::Job::builder().add::<A>().build().unwrap();
+//! # let worker = ordr::Worker::new(job, ());
+//! #[derive(ordr::FromData)]
+//! struct Results {
+//!     #[ordr(name = "A")]
+//!     a: A,
+//! }
+//!
+//! let results: Results = worker.extract().await.unwrap();
+//! # };
+//! ```
+//!
+//!
+//! # Nodes without the macro
+//!
+//! Nodes can also be defined at runtime with [`Node::builder`]. Dependencies are added one at a
+//! time, and given to the producer as a tuple.
+//!
+//! ```
+//! # #[derive(Clone, serde::Serialize, serde::Deserialize)]
+//! # struct A(i32);
+//! # #[ordr::producer]
+//! # async fn a(_ctx: ordr::Context<()>) -> ordr::Result<A> { Ok(A(1)) }
+//! #[derive(Clone, serde::Serialize, serde::Deserialize)]
+//! struct B(i32);
+//!
+//! let b = ordr::Node::builder("B")
+//!     .dep::<A>()
+//!     .producer(|_ctx: ordr::Context<()>, (a,): (A,)| async move { Ok(B(a.0 + 1)) });
+//!
+//! let job = ordr::Job::builder().add_node(b).build().unwrap();
+//! ```
+//!
+//! Use [`NodeDef::dep_on`] to depend on another node that was defined at runtime.
+//!
+//!
+//! # Producers as methods
+//!
+//! Instead of a free function, the producer can live next to its type. Derive [`macro@Node`],
+//! list the dependencies on the struct, and write an associated function called `produce`:
+//!
+//! ```
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Clone, Serialize, Deserialize)]
+//! # struct A(i32);
+//! # #[ordr::producer]
+//! # async fn a(_ctx: ordr::Context<()>) -> ordr::Result<A> { Ok(A(1)) }
+//! #[derive(Clone, Serialize, Deserialize, ordr::Node)]
+//! #[node(deps(A), timeout = "10s")]
+//! struct B(i32);
+//!
+//! impl B {
+//!     async fn produce(_ctx: ordr::Context<()>, a: A) -> ordr::Result<B> {
+//!         Ok(B(a.0 + 1))
+//!     }
+//! }
+//!
+//! let job = ordr::Job::builder().add::<B>().build().unwrap();
+//! ```
+//!
+//! The state defaults to `()`. Set it with `#[node(state = MyState)]`.
+//!
+//! Producers can also be methods on the state, so they can use the clients and config it holds.
+//! Put [`macro@service`] on the `impl` block, and mark the methods with `#[producer]`:
+//!
+//! ```
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Clone, Serialize, Deserialize)]
+//! # struct A(String);
+//! #[derive(Clone)]
+//! struct Service {
+//!     base_url: String,
+//! }
+//!
+//! #[ordr::service]
+//! impl Service {
+//!     #[producer]
+//!     async fn make_a(&self, _ctx: ordr::Context<Service>) -> ordr::Result<A> {
+//!         Ok(A(self.base_url.clone()))
+//!     }
+//! }
+//!
+//! let job = ordr::Job::<Service>::builder().add::<A>().build().unwrap();
+//! ```
+//!
+//! The state can also be something that derefs to the struct, like `Arc<Service>`.
+//!
+//!
+//! # Fan-out
+//!
+//! A producer can run once per item of a node, in parallel, with `map_over`. The node being
+//! mapped over must implement `IntoIterator`, and the `output` must implement `FromIterator`
+//! of what the producer returns. The results keep the order of the items.
+//!
+//! ```
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Clone, Serialize, Deserialize)]
+//! struct Urls(Vec<String>);
+//! # impl IntoIterator for Urls {
+//! #     type Item = String;
+//! #     type IntoIter = std::vec::IntoIter<String>;
+//! #     fn into_iter(self) -> Self::IntoIter { self.0.into_iter() }
+//! # }
+//! # #[ordr::producer]
+//! # async fn urls(_ctx: ordr::Context<()>) -> ordr::Result<Urls> { Ok(Urls(vec![])) }
+//!
+//! #[derive(Clone, Serialize, Deserialize)]
+//! struct Pages(Vec<String>);
+//! # impl FromIterator<String> for Pages {
+//! #     fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> Self { Self(iter.into_iter().collect()) }
+//! # }
+//!
+//! #[ordr::producer(map_over = Urls, output = Pages)]
+//! async fn fetch(_ctx: ordr::Context<()>, url: String) -> ordr::Result<String> {
+//!     Ok(format!("Contents of {url}"))
+//! }
+//! ```
+//!
+//! At runtime, use [`NodeDef::map_producer`].
+//!
+//!
+//! # Several outputs
+//!
+//! A producer that returns a tuple produces each of its elements, so one expensive operation can
+//! feed nodes of several types. It runs once, as a transient node named after the function, and
+//! the settings of the producer (timeout, retries and so on) apply to it.
+//!
+//! ```
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Clone, Serialize, Deserialize)]
+//! struct Text(String);
+//! #[derive(Clone, Serialize, Deserialize)]
+//! struct Title(String);
+//!
+//! #[ordr::producer(timeout = "10s")]
+//! fn parse(_ctx: ordr::Context<()>) -> ordr::Result<(Text, Title)> {
+//!     Ok((Text("...".into()), Title("Hello".into())))
+//! }
+//! ```
+//!
+//! At runtime, use [`NodeDef::output_of`].
+//!
+//!
+//! # Transient nodes
+//!
+//! If the output of a node is big, or can't be serialized, and is not needed outside the job, you
+//! can mark the producer as `transient`. The output is then only kept in memory and passed on as
+//! is to the nodes that depend on it. It is not part of [`Worker::data`], and it can not be
+//! provided.
+//!
+//! ```
+//! # #[derive(Clone)]
+//! # struct Pixels(Vec<u8>);
+//! #[ordr::producer(transient)]
+//! async fn pixels(_ctx: ordr::Context<()>) -> ordr::Result<Pixels> {
+//!     Ok(Pixels(vec![0; 1024 * 1024]))
+//! }
+//! ```
+//!
+//!
+//! # Raw nodes
+//!
+//! Large binary outputs (images, archives) can skip serialization too, by marking the producer as
+//! `raw`. The output must turn into and from a `Vec<u8>`, and is passed on as bytes. Like a
+//! transient output, it is not part of [`Worker::data`], but it does show up in
+//! [`Worker::status`], and can be read with [`Worker::get`].
+//!
+//! ```
+//! #[derive(Clone)]
+//! struct Archive(Vec<u8>);
+//! # impl From<Vec<u8>> for Archive {
+//! #     fn from(bytes: Vec<u8>) -> Self { Archive(bytes) }
+//! # }
+//! # impl From<Archive> for Vec<u8> {
+//! #     fn from(archive: Archive) -> Self { archive.0 }
+//! # }
+//!
+//! #[ordr::producer(raw)]
+//! async fn archive(_ctx: ordr::Context<()>) -> ordr::Result<Archive> {
+//!     Ok(Archive(vec![0; 1024 * 1024]))
+//! }
+//! ```
+//!
+//! At runtime, use [`NodeDef::raw_producer`] and [`NodeDef::raw_dep_on`].
+//!
+//!
+//! # Streaming nodes
+//!
+//! A node defined at runtime can emit its output as a stream of items, with
+//! [`NodeDef::stream_producer`]. Its dependents start right away, and read the items as they come,
+//! so they never have to be held in memory all at once. Depend on it with
+//! [`NodeDef::stream_dep_on`].
+//!
+//! ```
+//! use ordr::{Context, Emitter, Node, Stream};
+//!
+//! let lines = Node::builder::<Stream<String>>("Lines").stream_producer(
+//!     |_: Context<()>, (), emitter: Emitter<String>| async move {
+//!         for i in 0..1000 {
+//!             emitter.emit(format!("Line {i}")).await?;
+//!         }
+//!         Ok(())
+//!     },
+//! );
+//! let count = Node::builder::<usize>("Count")
+//!     .stream_dep_on::<String>(lines)
+//!     .producer(|_: Context<()>, (lines,): (Stream<String>,)| async move {
+//!         let mut count = 0;
+//!         while lines.next().await.is_some() {
+//!             count += 1;
+//!         }
+//!         Ok(count)
+//!     });
+//! ```
+//!
+//!
+//! # Subgraphs
+//!
+//! A group of nodes that is needed more than once in a job (say, generate + upload + record) can
+//! be put in a [`Subgraph`], and mounted under a namespace of its own each time. Mounted nodes are
+//! named `"{namespace}.{name}"`, so each mount can be given its own data.
+//!
+//! ```
+//! # use std::collections::HashMap;
+//! # #[derive(Clone, serde::Serialize, serde::Deserialize)]
+//! # struct File(String);
+//! # #[ordr::producer]
+//! # async fn file(_ctx: ordr::Context<()>) -> ordr::Result<File> { Ok(File("".into())) }
+//! # #[derive(Clone, serde::Serialize, serde::Deserialize)]
+//! # struct Upload(String);
+//! # #[ordr::producer]
+//! # async fn upload(_ctx: ordr::Context<()>, file: File) -> ordr::Result<Upload> { Ok(Upload(file.0)) }
+//! let upload = ordr::Subgraph::new().add::<File>().add::<Upload>();
+//! let data = HashMap::from([
+//!     ("small.File".to_string(), "small.txt".into()),
+//!     ("large.File".to_string(), "large.txt".into()),
+//! ]);
+//! let job = ordr::Job::builder_with_data(data)
+//!     .mount("small", &upload)
+//!     .mount("large", &upload)
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//!
+//! # Batches
+//!
+//! To run the same job over many inputs (say, a backfill), use [`batch::run_all`]. It builds a job
+//! per input, runs a limited number of them at a time, and returns a report.
+//!
+//! ```
+//! # async {
+//! # #[derive(Clone, serde::Serialize, serde::Deserialize)]
+//! # struct A(i32);
+//! # #[ordr::producer]
+//! # async fn a(_ctx: ordr::Context<()>) -> ordr::Result<A> { Ok(A(123)) }
+//! # let documents: Vec<(String, std::collections::HashMap<String, serde_json::Value>)> = vec![];
+//! let template = ordr::Job::builder().add::<A>();
+//! let options = ordr::batch::Options {
+//!     concurrency: 8,
+//!     // Lets a rerun skip the inputs that already completed.
+//!     manifest: Some("backfill.manifest".into()),
+//!     ..Default::default()
+//! };
+//! let report = ordr::batch::run_all(template, documents, (), options).await;
+//! println!("{:.0}% succeeded", report.stats.success_rate * 100.0);
+//! # };
+//! ```
+//!
 //!
 //! # Stopping a job
 //!
@@ -173,18 +464,36 @@
 //! let mut worker = ordr::Worker::new(job, state);
 //!
 //! // Starts the worker
-//! worker.run();
+//! let mut handle = worker.run().await.unwrap();
 //!
 //! // Stops it and cancels all running nodes.
-//! worker.stop();
+//! handle.abort().await;
 //!
 //! // You can still get the ouput.
-//! let output = worker.get_output().await;
+//! let output = handle.await;
 //!
 //! // And whatever data was done before you stopped it.
 //! let data = worker.data().await;
 //! # };
 //! ```
+//!
+//! To stop gracefully, let the running nodes finish with [`Worker::drain`]. For programs that
+//! should do that on shutdown, there is [`Worker::attach_shutdown`]:
+//!
+//! ```
+//! # async {
+//! # let job = ordr::Job::builder().build().unwrap();
+//! # let state = ();
+//! # let ctrl_c = std::future::pending::<()>;
+//! let mut worker = ordr::Worker::new(job, state);
+//! // Usually `tokio::signal::ctrl_c()`.
+//! let snapshot = worker.attach_shutdown(ctrl_c());
+//! worker.run().await.unwrap();
+//!
+//! // Resolves once the job finished, or has been drained after ctrl-c.
+//! let snapshot = snapshot.await.unwrap();
+//! # };
+//! ```
 
 pub use ordr_core::*;
-pub use ordr_macros::producer;
+pub use ordr_macros::{FromData, Node, producer, service};
@@ -1,7 +1,10 @@
 use std::{sync::Arc, time::Duration};
 
 use ordr::{
-    Context, Error, Job, NodeBuilder, Result, Worker, producer,
+    AttemptInfo, CancellationToken, Context, DecisionKind, Error, Explanation, FileCache,
+    FileStore, Format, FromData, InMemoryCache, Job, JobEvent, JobStore, NodeBuilder, NodeInfo,
+    Overlap, Priority, Provenance, Quarantine, Recorder, Replayer, Result, RetryPolicy, Scheduler,
+    StoredState, Subgraph, When, Worker, WorkerPool, producer,
     serde::{Deserialize, Serialize},
     serde_json,
 };
@@ -30,21 +33,25 @@ async fn make_b(_ctx: Context<State>, a: A) -> Result<B> {
 async fn producer() {
     let ctx = Context {
         state: State,
-        retry: 0,
-        start: Duration::from_secs(0),
+        attempt: AttemptInfo::default(),
+        job_id: 0,
+        format: Format::Json,
+        labels: Arc::default(),
+        cancellation: CancellationToken::new(),
+        scratchpad: ordr::Scratchpad::default(),
     };
 
     // Call A
     let node = A::node();
     let data = (node.producer)(ctx.clone(), vec![]).await.unwrap();
-    let A(n): A = serde_json::from_value(data.clone()).unwrap();
+    let A(n) = A::decode(data.clone());
     assert_eq!(node.name, "A");
     assert_eq!(n, 1);
 
     // Call B (with output of A)
     let node = B::node();
     let data = (node.producer)(ctx, vec![data.clone()]).await.unwrap();
-    let B(n): B = serde_json::from_value(data).unwrap();
+    let B(n) = B::decode(data);
     assert_eq!(node.name, "BB");
     assert_eq!(n, 2);
 }
@@ -71,6 +78,259 @@ fn create_job_with_data_from_str() {
     assert_eq!(job.len(), 1);
 }
 
+#[test]
+fn create_job_with_input() {
+    let job = Job::builder().with_input(A(1)).add::<B>().build().unwrap();
+    assert_eq!(job.len(), 1);
+    assert_eq!(job.explain::<A>(), Explanation::Provided);
+}
+
+#[tokio::test]
+async fn create_job_with_input_as() {
+    // What an external service calls `A`. There is no `From` to turn it into one.
+    let fetched = serde_json::json!({ "count": "3" });
+    let job = Job::builder()
+        .provide_as::<A, _>(fetched, |fetched| {
+            A(fetched["count"].as_str().unwrap().parse().unwrap())
+        })
+        .add::<B>()
+        .build()
+        .unwrap();
+    assert_eq!(job.len(), 1);
+    assert_eq!(job.explain::<A>(), Explanation::Provided);
+
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["A"], serde_json::json!(3));
+    assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, 4);
+}
+
+#[test]
+fn create_job_with_targets() {
+    let registered = Job::builder().add::<A>().add::<B>();
+    let job = registered.clone().build().unwrap();
+    assert_eq!(job.len(), 2);
+
+    let job = registered.clone().target::<A>().build().unwrap();
+    assert_eq!(job.len(), 1);
+    assert_eq!(job.explain::<B>(), Explanation::Unreachable);
+
+    // Targets do not have to be registered first.
+    let job = Job::builder().target::<B>().build().unwrap();
+    assert_eq!(job.len(), 2);
+}
+
+#[test]
+fn create_job_with_forced_node() {
+    let a = serde_json::to_value(A(1)).unwrap();
+    let b = serde_json::to_value(B(2)).unwrap();
+    let data = [("A".to_string(), a), ("BB".to_string(), b)]
+        .into_iter()
+        .collect();
+    let job = Job::builder_with_data(data)
+        .add::<B>()
+        .force::<B>()
+        .build()
+        .unwrap();
+    assert_eq!(job.len(), 1); // B runs, A is still provided
+}
+
+#[test]
+fn invalid_provided_data() {
+    let data = [("A".to_string(), serde_json::json!("not a number"))]
+        .into_iter()
+        .collect();
+    let err = Job::builder_with_data(data).add::<B>().build().err();
+    assert!(matches!(
+        err,
+        Some(ordr::JobError::InvalidProvidedData("A", _))
+    ));
+}
+
+#[test]
+fn strict() {
+    let build = |data: &[(&str, i32)], strict: bool| {
+        let data = data
+            .iter()
+            .map(|(name, value)| (name.to_string(), serde_json::json!(value)))
+            .collect();
+        let builder = Job::builder_with_data(data).add::<B>();
+        if strict { builder.strict() } else { builder }.build()
+    };
+    assert!(build(&[("Typo", 1)], false).is_ok());
+    let err = build(&[("Typo", 1)], true).err();
+    assert!(matches!(err, Some(ordr::JobError::UnknownData(name)) if name == "Typo"));
+    // A is only needed by B, which is provided.
+    let err = build(&[("A", 1), ("BB", 2)], true).err();
+    assert!(matches!(err, Some(ordr::JobError::UnreachableData(name)) if name == "A"));
+    let err = build(&[("BB", 2)], true).err();
+    assert!(matches!(err, Some(ordr::JobError::ProvidedTarget("BB"))));
+    assert_eq!(build(&[("A", 1)], true).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn invalidate() {
+    let data = || {
+        [
+            ("A".to_string(), serde_json::json!(10)),
+            ("BB".to_string(), serde_json::json!(99)),
+        ]
+        .into_iter()
+        .collect()
+    };
+
+    // Everything downstream of A runs again.
+    let job = Job::builder_with_data(data())
+        .add::<B>()
+        .invalidate::<A>()
+        .build()
+        .unwrap();
+    assert_eq!(job.len(), 2);
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    assert_eq!(worker.data().await["BB"], 2);
+
+    // Only B runs again, from the provided A.
+    let job = Job::builder_with_data(data())
+        .add::<B>()
+        .invalidate::<B>()
+        .build()
+        .unwrap();
+    assert_eq!(job.len(), 1);
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    assert_eq!(worker.data().await["BB"], 11);
+}
+
+#[test]
+fn explain() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+    #[producer]
+    async fn c(_: Context<State>) -> Result<C> {
+        Ok(C)
+    }
+
+    let job = Job::builder().add::<B>().build().unwrap();
+    assert_eq!(
+        job.explain::<A>(),
+        Explanation::Pending {
+            waiting_for: vec![]
+        }
+    );
+    assert_eq!(
+        job.explain::<B>(),
+        Explanation::Pending {
+            waiting_for: vec!["A"]
+        }
+    );
+    assert_eq!(job.explain::<C>(), Explanation::Unreachable);
+
+    let v = serde_json::to_value(B(1)).unwrap();
+    let data = [("BB".to_string(), v)].into_iter().collect();
+    let job = Job::builder_with_data(data).add::<B>().build().unwrap();
+    assert_eq!(job.explain::<A>(), Explanation::Pruned { by: vec!["BB"] });
+    assert_eq!(job.explain::<B>(), Explanation::Provided);
+    assert_eq!(job.pruned(), vec![("A", vec!["BB"])]);
+}
+
+#[test]
+fn inspect_graph() {
+    let job = Job::builder().add::<B>().build().unwrap();
+    let nodes = job.nodes();
+    assert_eq!(
+        nodes,
+        [
+            NodeInfo {
+                name: "A",
+                deps: vec![],
+                provided: false,
+                target: false,
+            },
+            NodeInfo {
+                name: "BB",
+                deps: vec!["A"],
+                provided: false,
+                target: true,
+            },
+        ]
+    );
+    assert_eq!(job.dependencies_of("BB"), Some(vec!["A"]));
+    assert_eq!(job.dependencies_of("C"), None);
+
+    let job = Job::builder().with_input(A(1)).add::<B>().build().unwrap();
+    assert!(job.nodes()[0].provided);
+    assert_eq!(job.dependencies_of("A"), Some(vec![]));
+    assert_eq!(job.dependencies_of("BB"), Some(vec!["A"]));
+}
+
+#[test]
+fn execution_plan() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+    #[producer]
+    async fn c(_: Context<State>, _: A) -> Result<C> {
+        Ok(C)
+    }
+
+    let job = Job::builder().add::<B>().add::<C>().build().unwrap();
+    assert_eq!(job.execution_plan(), [vec!["A"], vec!["BB", "C"]]);
+
+    let job = Job::builder()
+        .with_input(A(1))
+        .add::<B>()
+        .add::<C>()
+        .build()
+        .unwrap();
+    assert_eq!(job.execution_plan(), [vec!["BB", "C"]]);
+}
+
+#[test]
+fn plan() {
+    let data = [
+        ("A".to_string(), serde_json::json!(1)),
+        ("Typo".to_string(), serde_json::json!(2)),
+    ]
+    .into();
+    let job = Job::<State>::builder_with_data(data)
+        .add::<B>()
+        .build()
+        .unwrap();
+    let plan = job.plan();
+    assert_eq!(plan.waves, [vec!["BB"]]);
+    assert_eq!(plan.provided, ["A"]);
+    assert!(plan.pruned.is_empty());
+    assert_eq!(plan.discarded, ["Typo"]);
+    let json = serde_json::to_value(&plan).unwrap();
+    assert_eq!(json["discarded"], serde_json::json!(["Typo"]));
+}
+
+#[tokio::test]
+async fn unused_inputs() {
+    let v = serde_json::to_value(A(1)).unwrap();
+    let data: std::collections::HashMap<_, _> = [("A".to_string(), v)].into_iter().collect();
+
+    let job = Job::builder_with_data(data.clone())
+        .add::<A>()
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.unused_inputs().await, vec!["A"]);
+    assert_eq!(worker.report().await.unused_inputs, ["A"]);
+
+    let job = Job::builder_with_data(data).add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert!(worker.unused_inputs().await.is_empty());
+    assert!(worker.report().await.unused_inputs.is_empty());
+}
+
 /// Tests that two jobs actually are started concurrently
 #[tokio::test]
 async fn concurrent() {
@@ -174,6 +434,107 @@ async fn node_panic() {
     }
 }
 
+#[tokio::test]
+async fn continue_unaffected() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Fails;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Blocked;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Bomb;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Slow(u8);
+    #[producer]
+    async fn fails(_: Context<()>) -> Result<Fails> {
+        Err(Error::fatal("Nope"))
+    }
+    #[producer]
+    async fn blocked(_: Context<()>, _: Fails) -> Result<Blocked> {
+        Ok(Blocked)
+    }
+    #[producer]
+    async fn bomb(_: Context<()>) -> Result<Bomb> {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        panic!("boom!")
+    }
+    #[producer]
+    async fn slow(_: Context<()>) -> Result<Slow> {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        Ok(Slow(1))
+    }
+
+    let job = Job::builder()
+        .add::<Blocked>()
+        .add::<Bomb>()
+        .add::<Slow>()
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, ()).on_failure(ordr::FailurePolicy::ContinueUnaffected);
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    let ordr::Output::NodeFailed { name, data, .. } = output else {
+        panic!("Expected the first failure, got {output:?}");
+    };
+    assert_eq!(name, "Fails");
+    assert_eq!(data["Slow"], 1);
+
+    let status = worker.status().await;
+    assert!(matches!(
+        status["Blocked"],
+        ordr::NodeState::Blocked { by: "Fails" }
+    ));
+    assert!(matches!(status["Bomb"], ordr::NodeState::Failed { .. }));
+    assert!(matches!(status["Slow"], ordr::NodeState::Done { .. }));
+}
+
+#[tokio::test]
+async fn collect_all() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Fails;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct AlsoFails;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Works(u8);
+    #[producer]
+    async fn fails(_: Context<()>) -> Result<Fails> {
+        Err(Error::fatal("Nope"))
+    }
+    #[producer]
+    async fn also_fails(_: Context<()>) -> Result<AlsoFails> {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        Err(Error::fatal("Nope again"))
+    }
+    #[producer]
+    async fn works(_: Context<()>) -> Result<Works> {
+        Ok(Works(1))
+    }
+
+    let job = Job::builder()
+        .add::<Fails>()
+        .add::<AlsoFails>()
+        .add::<Works>()
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, ()).on_failure(ordr::FailurePolicy::CollectAll);
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    let ordr::Output::Finished { failures, data, .. } = output else {
+        panic!("Expected the job to finish, got {output:?}");
+    };
+    let failed: Vec<_> = failures
+        .iter()
+        .map(|f| (f.name, f.error.message()))
+        .collect();
+    assert_eq!(failed, [("Fails", "Nope"), ("AlsoFails", "Nope again")]);
+    assert_eq!(data["Works"], 1);
+
+    // Without failures, the job is just done.
+    let job = Job::builder().add::<Works>().build().unwrap();
+    let mut worker = Worker::new(job, ()).on_failure(ordr::FailurePolicy::CollectAll);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+}
+
 #[tokio::test]
 async fn readme_example() {
     #[derive(Clone)]
@@ -235,12 +596,13 @@ async fn retrying() {
 
     #[producer]
     async fn a(ctx: Context<()>) -> Result<A> {
-        if ctx.retry < 3 {
-            let msg = format!("Boom {}", ctx.retry);
+        if ctx.retry() < 3 {
+            let msg = format!("Boom {}", ctx.retry());
             let retry_in = Duration::from_millis(10);
             return Err(Error::with_retry(msg, retry_in));
         }
-        Ok(A(ctx.retry))
+        assert!(ctx.attempt.first_started <= ctx.start());
+        Ok(A(ctx.retry()))
     }
 
     let job = Job::builder().add::<A>().build().unwrap();
@@ -280,4 +642,3052 @@ async fn can_stop() {
     let data = worker.data().await;
     assert!(data.contains_key("A"));
     assert!(!data.contains_key("B"));
+    let ordr::Output::Stopped {
+        data: collected, ..
+    } = output
+    else {
+        panic!("Expected the job to be stopped");
+    };
+    assert_eq!(*collected, data);
+}
+
+#[tokio::test]
+async fn step_mode() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+    #[producer]
+    async fn a(_: Context<()>) -> Result<A> {
+        Ok(A)
+    }
+    #[producer]
+    async fn b(_: Context<()>) -> Result<B> {
+        Ok(B)
+    }
+    #[producer]
+    async fn c(_: Context<()>, _: A, _: B) -> Result<C> {
+        Ok(C)
+    }
+
+    let job = Job::builder().add::<C>().build().unwrap();
+    let mut worker = Worker::new(job, ()).step_mode();
+    // Nothing would answer yet.
+    assert!(worker.step().await.is_err());
+    worker.run().await.unwrap();
+    assert_eq!(worker.step().await.unwrap(), vec!["A", "B"]);
+    assert_eq!(worker.step().await.unwrap(), vec!["B"]);
+    assert_eq!(worker.step().await.unwrap(), vec!["C"]);
+    let output = worker.get_output().await.unwrap();
+    assert!(output.is_done());
+    assert!(worker.step().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn step_node() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B(u8);
+    #[producer]
+    async fn a(_: Context<()>) -> Result<A> {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        Ok(A(1))
+    }
+    #[producer]
+    async fn b(ctx: Context<()>, a: A) -> Result<B> {
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Not yet", Duration::from_millis(1)));
+        }
+        Ok(B(a.0 + 1))
+    }
+
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, ()).step_mode();
+    worker.run().await.unwrap();
+    let ordr::StepResult::Ran { name, ready, state } = worker.step_node().await.unwrap() else {
+        panic!("Expected A to run");
+    };
+    assert_eq!((name, ready), ("A", vec!["A"]));
+    assert!(matches!(state, ordr::NodeState::Done { .. }));
+    // A is done, and nothing else has started.
+    assert_eq!(worker.data().await.len(), 1);
+
+    // Retries are part of the step.
+    let ordr::StepResult::Ran { name, state, .. } = worker.step_node().await.unwrap() else {
+        panic!("Expected B to run");
+    };
+    assert_eq!(name, "B");
+    assert!(matches!(state, ordr::NodeState::Done { retries: 1, .. }));
+
+    let ordr::StepResult::Finished(output) = worker.step_node().await.unwrap() else {
+        panic!("Expected the job to have finished");
+    };
+    assert!(output.is_done());
+}
+
+/// Waits until `worker` is paused on the breakpoint of node `name`.
+async fn paused_on(worker: &Worker<()>, name: &str) {
+    let paused = || {
+        worker
+            .decisions()
+            .iter()
+            .any(|decision| decision.name == name && decision.kind == DecisionKind::Breakpoint)
+    };
+    while !paused() {
+        tokio::task::yield_now().await;
+    }
+}
+
+#[tokio::test]
+async fn breakpoint() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B(u8);
+    #[producer]
+    async fn a(_: Context<()>) -> Result<A> {
+        Ok(A(1))
+    }
+    #[producer]
+    async fn b(_: Context<()>, a: A) -> Result<B> {
+        Ok(B(a.0 + 1))
+    }
+
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, ()).break_on::<B>().log_decisions();
+    worker.run().await.unwrap();
+    paused_on(&worker, "B").await;
+
+    // Paused right before B.
+    let data = worker.data().await;
+    assert!(data.contains_key("A"));
+    assert!(!data.contains_key("B"));
+    assert!(worker.set_value("B", serde_json::json!(1)).await.is_err());
+    worker.set_value("A", serde_json::json!(10)).await.unwrap();
+
+    assert_eq!(worker.step().await.unwrap(), vec!["B"]);
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["B"], serde_json::json!(11));
+}
+
+#[tokio::test]
+async fn breakpoint_ignores_early_steps() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+    #[producer]
+    async fn a(_: Context<()>) -> Result<A> {
+        Ok(A)
+    }
+    #[producer]
+    async fn b(_: Context<()>, _: A) -> Result<B> {
+        Ok(B)
+    }
+    #[producer]
+    async fn c(_: Context<()>, _: B) -> Result<C> {
+        Ok(C)
+    }
+
+    let job = Job::builder().add::<C>().build().unwrap();
+    let mut worker = Worker::new(job, ())
+        .break_on::<B>()
+        .break_on::<C>()
+        .log_decisions();
+    worker.run().await.unwrap();
+    paused_on(&worker, "B").await;
+
+    // The first step lets B start. The second was not asked for at a breakpoint, so it does not
+    // let C start.
+    let (first, second) = tokio::join!(worker.step(), worker.step());
+    assert_eq!(first.unwrap(), vec!["B"]);
+    assert!(second.is_err());
+    paused_on(&worker, "C").await;
+    assert!(worker.data().await.contains_key("B"));
+    assert!(!worker.data().await.contains_key("C"));
+
+    assert_eq!(worker.step().await.unwrap(), vec!["C"]);
+    assert!(worker.get_output().await.unwrap().is_done());
+}
+
+#[tokio::test]
+async fn snapshot_and_restore() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C(u8);
+    #[producer]
+    async fn a(ctx: Context<bool>) -> Result<A> {
+        assert!(!ctx.state, "A should not run again");
+        Ok(A(1))
+    }
+    #[producer]
+    async fn b(ctx: Context<bool>, a: A) -> Result<B> {
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Not yet", Duration::from_millis(1)));
+        }
+        if !ctx.state {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+        Ok(B(a.0 + u8::try_from(ctx.retry()).unwrap()))
+    }
+    #[producer]
+    async fn c(_: Context<bool>, b: B) -> Result<C> {
+        Ok(C(b.0 + 1))
+    }
+
+    let job = Job::builder().add::<C>().build().unwrap();
+    let mut worker = Worker::new(job.clone(), false);
+    worker.run().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let snapshot = worker.snapshot().await;
+    worker.stop().await;
+    assert_eq!(snapshot.pending, vec!["B", "C"]);
+    assert_eq!(snapshot.retries["B"], 1);
+
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let snapshot = serde_json::from_str(&json).unwrap();
+    let mut worker = Worker::restore(job, true, snapshot);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["C"], serde_json::json!(3));
+}
+
+#[tokio::test]
+async fn provenance() {
+    let data = [("A".to_string(), serde_json::json!(1))].into();
+    let job = Job::builder_with_data(data).add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let provenance = worker.provenance().await;
+    assert!(matches!(provenance["A"], Provenance::Provided { .. }));
+    let Provenance::Computed {
+        producer, attempt, ..
+    } = &provenance["BB"]
+    else {
+        panic!("BB should be computed");
+    };
+    assert_eq!((producer.as_str(), *attempt), ("BB", 0));
+
+    // A resumed job knows which values were computed.
+    let json = serde_json::to_string(&worker.snapshot().await).unwrap();
+    let snapshot = serde_json::from_str(&json).unwrap();
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::restore(job, State, snapshot);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    assert_eq!(worker.provenance().await["BB"], provenance["BB"]);
+
+    worker.set_value("BB", serde_json::json!(5)).await.unwrap();
+    let provenance = worker.provenance().await;
+    assert!(matches!(provenance["BB"], Provenance::Provided { .. }));
+}
+
+#[tokio::test]
+async fn transient() {
+    /// Not serializable.
+    #[derive(Clone)]
+    struct Big(Arc<Vec<u8>>);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Len(usize);
+
+    #[producer(transient)]
+    async fn big(_: Context<()>) -> Result<Big> {
+        Ok(Big(Arc::new(vec![0; 1000])))
+    }
+    #[producer]
+    async fn len(_: Context<()>, big: Big) -> Result<Len> {
+        Ok(Len(big.0.len()))
+    }
+
+    let job = Job::builder().add::<Len>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    let data = worker.data().await;
+    assert!(!data.contains_key("Big"));
+    assert_eq!(data["Len"], serde_json::json!(1000));
+}
+
+#[tokio::test]
+async fn shared_deps() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Not serializable, and expensive to clone.
+    struct Big(Vec<u8>);
+    impl Clone for Big {
+        fn clone(&self) -> Self {
+            CLONES.fetch_add(1, Ordering::Relaxed);
+            Self(self.0.clone())
+        }
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Len(usize);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct First(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Last(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Sum(usize);
+    struct Service;
+
+    #[producer(transient)]
+    async fn big(_: Context<Arc<Service>>) -> Result<Big> {
+        Ok(Big((0..100).collect()))
+    }
+    #[producer]
+    async fn len(_: Context<Arc<Service>>, big: &Big) -> Result<Len> {
+        tokio::task::yield_now().await;
+        Ok(Len(big.0.len()))
+    }
+    #[producer]
+    fn first(_: Context<Arc<Service>>, big: Arc<Big>) -> Result<First> {
+        Ok(First(big.0[0]))
+    }
+    #[ordr::service]
+    impl Service {
+        #[producer]
+        async fn last(&self, _: Context<Arc<Service>>, big: &Big) -> Result<Last> {
+            Ok(Last(*big.0.last().unwrap()))
+        }
+    }
+    #[producer]
+    async fn sum(_: Context<Arc<Service>>, len: Len, first: First, last: Last) -> Result<Sum> {
+        Ok(Sum(len.0 + usize::from(first.0) + usize::from(last.0)))
+    }
+
+    let job = Job::builder().add::<Sum>().build().unwrap();
+    let mut worker = Worker::new(job, Arc::new(Service));
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<Sum>().await.unwrap().unwrap().0, 100 + 99);
+    assert_eq!(CLONES.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn sync_producer() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Sum(u64);
+    #[producer]
+    fn sum(_: Context<State>, a: A) -> Result<Sum> {
+        std::thread::sleep(Duration::from_millis(1));
+        Ok(Sum((0..=u64::from(a.0) * 100).sum()))
+    }
+
+    let job = Job::builder().add::<Sum>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<Sum>().await.unwrap().unwrap().0, 5050);
+}
+
+#[tokio::test]
+async fn blocking_producer() {
+    type Order = Arc<std::sync::Mutex<Vec<&'static str>>>;
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B;
+    #[producer(blocking)]
+    async fn a(ctx: Context<Order>) -> Result<A> {
+        // Would hold up B, if it was run along with it.
+        std::thread::sleep(Duration::from_millis(100));
+        ctx.state.lock().unwrap().push("A");
+        Ok(A)
+    }
+    #[producer]
+    async fn b(ctx: Context<Order>) -> Result<B> {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        ctx.state.lock().unwrap().push("B");
+        Ok(B)
+    }
+
+    let job = Job::builder().add::<A>().add::<B>().build().unwrap();
+    let order = Order::default();
+    let mut worker = Worker::new(job.clone(), order.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(*order.lock().unwrap(), ["B", "A"]);
+
+    // On a runtime of its own.
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+    let order = Order::default();
+    let mut worker = Worker::new(job, order.clone()).blocking_runtime(runtime.handle().clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(*order.lock().unwrap(), ["B", "A"]);
+    runtime.shutdown_background();
+}
+
+#[tokio::test]
+async fn after() {
+    type Order = Arc<std::sync::Mutex<Vec<&'static str>>>;
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Upload;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Notify;
+    #[producer]
+    async fn upload(ctx: Context<Order>) -> Result<Upload> {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        ctx.state.lock().unwrap().push("Upload");
+        Ok(Upload)
+    }
+    #[producer(after = [Upload])]
+    async fn notify(ctx: Context<Order>) -> Result<Notify> {
+        ctx.state.lock().unwrap().push("Notify");
+        Ok(Notify)
+    }
+
+    let job = Job::builder()
+        .add::<Notify>()
+        .add::<Upload>()
+        .build()
+        .unwrap();
+    assert_eq!(job.execution_plan(), [vec!["Upload"], vec!["Notify"]]);
+    let order = Order::default();
+    let mut worker = Worker::new(job, order.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(*order.lock().unwrap(), ["Upload", "Notify"]);
+
+    // Upload is not added just because Notify runs after it.
+    let job = Job::builder().add::<Notify>().build().unwrap();
+    assert_eq!(job.execution_plan(), [vec!["Notify"]]);
+    let order = Order::default();
+    let mut worker = Worker::new(job, order.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(*order.lock().unwrap(), ["Notify"]);
+}
+
+#[tokio::test]
+async fn runtime_nodes() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct D(u8);
+
+    let c = ordr::Node::builder("C")
+        .dep::<A>()
+        .dep::<B>()
+        .producer(|_: Context<State>, (a, b): (A, B)| async move { Ok(C(a.0 + b.0)) });
+    let d = ordr::Node::builder("D")
+        .dep_on::<C>(c)
+        .producer(|_: Context<State>, (c,): (C,)| async move { Ok(D(c.0 * 2)) });
+
+    let job = Job::builder().add_node(d).build().unwrap();
+    assert_eq!(job.len(), 4);
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["D"], serde_json::json!(6));
+}
+
+#[tokio::test]
+async fn dependents_share_outputs() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Left(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Right(u8);
+
+    // The output of A, as each dependent was given it.
+    let given: Arc<std::sync::Mutex<Vec<Arc<serde_json::Value>>>> = Arc::default();
+    let spy = |mut node: ordr::Node<State>| {
+        let producer = node.producer.clone();
+        let given = given.clone();
+        node.producer = Arc::new(move |ctx, payloads: Vec<ordr::Payload>| {
+            if let ordr::Payload::Json(value) = &payloads[0] {
+                given.lock().unwrap().push(value.clone());
+            }
+            producer(ctx, payloads)
+        });
+        node
+    };
+    let left = ordr::Node::builder("Left")
+        .dep::<A>()
+        .producer(|_: Context<State>, (a,): (A,)| async move { Ok(Left(a.0)) });
+    let right = ordr::Node::builder("Right")
+        .dep::<A>()
+        .producer(|_: Context<State>, (a,): (A,)| async move { Ok(Right(a.0)) });
+
+    let job = Job::builder()
+        .add_node(spy(left))
+        .add_node(spy(right))
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+
+    // Both were given the same value, not a copy each.
+    let given = given.lock().unwrap();
+    assert_eq!(given.len(), 2);
+    assert!(Arc::ptr_eq(&given[0], &given[1]));
+}
+
+#[tokio::test]
+async fn serde_error() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+
+    // A is a number, not a string.
+    let c = ordr::Node::builder("C")
+        .dep_on::<String>(A::node())
+        .producer(|_: Context<State>, _: (String,)| async move { Ok(C) });
+    let job = Job::builder().add_node(c).build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    let ordr::Output::NodeFailed {
+        name,
+        retries,
+        error,
+        ..
+    } = output
+    else {
+        panic!("Expected C to fail, got {output:?}");
+    };
+    assert_eq!(name, "C");
+    assert_eq!(retries, 0);
+    assert_eq!(error.kind(), ordr::ErrorKind::Serde { node: "A" });
+}
+
+#[tokio::test]
+async fn streaming() {
+    use ordr::{Emitter, Stream};
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Sum(u32);
+
+    let numbers = |fail: bool| {
+        ordr::Node::builder::<Stream<u32>>("Numbers").stream_producer(
+            move |_: Context<()>, (), emitter: Emitter<u32>| async move {
+                // More than fits in the channel, so the dependent has to read while we emit.
+                for i in 1..=100 {
+                    emitter.emit(i).await?;
+                }
+                if fail {
+                    return Err(Error::with_retry("Broken", Duration::from_millis(1)));
+                }
+                Ok(())
+            },
+        )
+    };
+    let sum = |numbers| {
+        ordr::Node::builder("Sum")
+            .stream_dep_on::<u32>(numbers)
+            .producer(|_: Context<()>, (numbers,): (Stream<u32>,)| async move {
+                let mut sum = 0;
+                while let Some(n) = numbers.next().await {
+                    sum += n;
+                }
+                Ok(Sum(sum))
+            })
+    };
+
+    let job = Job::builder()
+        .add_node(sum(numbers(false)))
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, ());
+    worker
+        .run_with_timeout(Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["Sum"], 5050);
+    let status = worker.status().await;
+    assert!(matches!(status["Numbers"], ordr::NodeState::Done { .. }));
+
+    // The job fails with the producer, and it is not retried.
+    let job = Job::builder().add_node(sum(numbers(true))).build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker
+        .run_with_timeout(Duration::from_secs(1))
+        .await
+        .unwrap();
+    let ordr::Output::NodeFailed { name, retries, .. } = worker.get_output().await.unwrap() else {
+        panic!("Expected the node to fail");
+    };
+    assert_eq!((name, retries), ("Numbers", 0));
+}
+
+#[tokio::test]
+async fn runtime_nodes_with_captured_config() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Greeting(String);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Shout(String);
+
+    // Pretend this came from a config file.
+    let config = ("Hello".to_string(), 3);
+
+    let greeting = ordr::Node::builder("Greeting")
+        .producer_with(config.0, |word, _: Context<()>, ()| async move {
+            Ok(Greeting(format!("{word}, World")))
+        });
+    let shout = ordr::Node::builder("Shout")
+        .dep_on::<Greeting>(greeting)
+        .producer(move |_: Context<()>, (g,): (Greeting,)| async move {
+            Ok(Shout(format!("{}{}", g.0, "!".repeat(config.1))))
+        });
+
+    let job = Job::builder().add_node(shout).build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["Shout"], "Hello, World!!!");
+}
+
+#[test]
+fn mermaid_is_stable() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+    #[producer]
+    async fn c(_: Context<State>, _: B, _: A) -> Result<C> {
+        Ok(C)
+    }
+
+    let job = Job::builder().add::<C>().build().unwrap();
+    let expected = "flowchart LR
+    n0[A]
+    n1[BB]
+    n2[C]
+    n0 --> n1
+    n0 & n1 --> n2
+    classDef target stroke-width:3px
+    classDef provided fill:#eee,stroke-dasharray:5 5
+    classDef pending fill:#fff
+    class n0 pending
+    class n1 pending
+    class n2 target";
+    for _ in 0..10 {
+        assert_eq!(ordr::mermaid(&job.clone()), expected);
+    }
+
+    let v = serde_json::to_value(A(1)).unwrap();
+    let data = [("A".to_string(), v)].into_iter().collect();
+    let job = Job::builder_with_data(data).add::<C>().build().unwrap();
+    let diagram = ordr::mermaid(&job);
+    assert!(diagram.contains("n0[A]"));
+    assert!(diagram.contains("class n0 provided"));
+    assert!(diagram.contains("n0 --> n1"));
+}
+
+#[tokio::test]
+async fn health() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[producer]
+    async fn a(ctx: Context<()>) -> Result<A> {
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Not yet", Duration::from_millis(1)));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(A)
+    }
+
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    let health = worker.health(Duration::from_secs(60));
+    assert_eq!(health.running_jobs, 0);
+    assert_eq!(health.running_nodes, 0);
+
+    worker.run().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let health = worker.health(Duration::from_secs(60));
+    assert_eq!(health.running_jobs, 1);
+    assert_eq!(health.running_nodes, 1);
+    assert_eq!(health.recent_failures, 1);
+
+    worker.get_output().await.unwrap();
+    let health = worker.health(Duration::from_secs(60));
+    assert_eq!(health.running_jobs, 0);
+    assert_eq!(health.running_nodes, 0);
+    assert_eq!(health.recent_failures, 1);
+    assert_eq!(worker.health(Duration::ZERO).recent_failures, 0);
+}
+
+#[tokio::test]
+async fn progress() {
+    let job = || Job::builder().add::<B>().build().unwrap();
+    let hints = [
+        ("A".to_string(), Duration::from_secs(2)),
+        ("BB".to_string(), Duration::from_secs(3)),
+    ];
+
+    let mut worker = Worker::new(job(), State).duration_hints(hints.into_iter().collect());
+    let progress = worker.progress().await;
+    assert_eq!(progress.total, 2);
+    assert_eq!(progress.done, 0);
+    assert_eq!(progress.eta, Some(Duration::from_secs(5)));
+
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let progress = worker.progress().await;
+    assert_eq!(progress.done, 2);
+    assert_eq!(progress.eta, Some(Duration::ZERO));
+
+    // Without hints there is nothing to go by until a node is done.
+    let durations = worker.durations().await;
+    assert_eq!(durations.len(), 2);
+    let worker = Worker::new(job(), State);
+    assert_eq!(worker.progress().await.eta, None);
+    let worker = Worker::new(job(), State).duration_hints(durations);
+    assert!(worker.progress().await.eta.is_some());
+}
+
+#[tokio::test]
+async fn drain_on_shutdown() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C(u8);
+
+    #[producer]
+    async fn a(_: Context<()>) -> Result<A> {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(A(1))
+    }
+    #[producer]
+    async fn b(ctx: Context<()>) -> Result<B> {
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Later", Duration::from_secs(60)));
+        }
+        Ok(B(2))
+    }
+    #[producer]
+    async fn c(_: Context<()>, a: A, b: B) -> Result<C> {
+        Ok(C(a.0 + b.0))
+    }
+
+    let job = Job::builder().add::<C>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    let shutdown = worker.attach_shutdown(rx);
+    worker.run().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    tx.send(()).unwrap();
+
+    // A is allowed to finish, and B is not retried.
+    let snapshot = shutdown.await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_stopped());
+    assert_eq!(snapshot.values["A"], serde_json::json!(1));
+    assert_eq!(snapshot.retries["B"], 1);
+    assert_eq!(snapshot.pending, ["B", "C"]);
+
+    // Restoring picks up where it left off.
+    let job = Job::builder().add::<C>().build().unwrap();
+    let mut worker = Worker::restore(job, (), snapshot);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["C"], serde_json::json!(3));
+}
+
+#[tokio::test]
+async fn cancelled() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Pages(u32);
+
+    /// Fetches pages until told to stop.
+    #[producer]
+    async fn pages(ctx: Context<()>) -> Result<Pages> {
+        let mut pages = 0;
+        while !ctx.is_cancelled() {
+            pages += 1;
+            tokio::select! {
+                () = ctx.cancelled() => {}
+                () = tokio::time::sleep(Duration::from_millis(5)) => {}
+            }
+        }
+        Ok(Pages(pages))
+    }
+
+    let job = Job::builder().add::<Pages>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let snapshot = tokio::time::timeout(Duration::from_secs(1), worker.drain())
+        .await
+        .unwrap();
+    assert!(snapshot.values["Pages"].as_u64().unwrap() > 0);
+    assert!(snapshot.pending.is_empty());
+}
+
+#[tokio::test]
+async fn quarantine() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[producer]
+    async fn a(ctx: Context<bool>) -> Result<A> {
+        if ctx.state {
+            Ok(A)
+        } else {
+            Err(Error::fatal("Broken"))
+        }
+    }
+
+    let quarantine = Quarantine::new(2);
+    let run = async |works: bool| {
+        let job = Job::builder().add::<A>().build().unwrap();
+        let mut worker = Worker::new(job, works).with_quarantine(quarantine.clone());
+        worker.run().await.unwrap();
+        (worker.get_output().await.unwrap(), worker)
+    };
+
+    // A success in between resets the count.
+    assert!(run(false).await.0.is_node_failed());
+    assert!(run(true).await.0.is_done());
+    assert!(run(false).await.0.is_node_failed());
+    assert!(quarantine.quarantined().is_empty());
+    assert!(run(false).await.0.is_node_failed());
+    assert_eq!(quarantine.quarantined(), ["A"]);
+
+    // Fails even though A would work now.
+    let (output, worker) = run(true).await;
+    assert!(output.is_node_failed());
+    assert!(worker.unquarantine("A"));
+    assert!(!worker.unquarantine("A"));
+    assert!(run(true).await.0.is_done());
+}
+
+#[tokio::test]
+async fn decision_log() {
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State).log_decisions();
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+
+    let decisions: Vec<_> = worker
+        .decisions()
+        .into_iter()
+        .map(|d| (d.step, d.name, d.kind))
+        .collect();
+    assert_eq!(
+        decisions,
+        [
+            (1, "BB", DecisionKind::WaitingFor(vec!["A"])),
+            (1, "A", DecisionKind::Started),
+            (2, "BB", DecisionKind::Started),
+        ]
+    );
+
+    // Nothing is logged unless asked for.
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    assert!(worker.decisions().is_empty());
+}
+
+#[tokio::test]
+async fn file_store() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B(u8);
+    #[producer]
+    async fn a(_: Context<bool>) -> Result<A> {
+        Ok(A(1))
+    }
+    #[producer]
+    async fn b(ctx: Context<bool>, a: A) -> Result<B> {
+        if ctx.state {
+            Ok(B(a.0 + 1))
+        } else {
+            Err(Error::fatal("Crash"))
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!("ordr-store-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let job = Job::builder().add::<B>().build().unwrap();
+    let store = FileStore::open(&path).unwrap();
+    let mut worker = Worker::new(job, false).with_store(store);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_node_failed());
+
+    // The file is picked up again, as if the process had crashed.
+    let store = FileStore::open(&path).unwrap();
+    let nodes = store.nodes();
+    assert_eq!(
+        nodes["A"],
+        StoredState::Done {
+            retries: 0,
+            value: Some(serde_json::json!(1))
+        }
+    );
+    assert!(matches!(nodes["B"], StoredState::Failed { .. }));
+
+    let snapshot = store.load().unwrap();
+    assert_eq!(snapshot.pending, ["B"]);
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::restore(job, true, snapshot).with_store(store);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["B"], serde_json::json!(2));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn timeout() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[producer(timeout = "20ms")]
+    async fn a(ctx: Context<()>) -> Result<A> {
+        assert!(ctx.attempt.deadline.is_some_and(|d| d > ctx.start()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok(A)
+    }
+
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    let ordr::Output::NodeFailed { name, error, .. } = output else {
+        panic!("Expected the node to fail");
+    };
+    assert_eq!(name, "A");
+    assert!(error.message().contains("Timed out after 20ms"), "{error}");
+
+    // The job can override it.
+    let job = Job::builder()
+        .add::<A>()
+        .timeout_for::<A>(Duration::from_secs(1))
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+}
+
+#[tokio::test]
+async fn max_retries() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B;
+    #[producer]
+    async fn a(_: Context<()>) -> Result<A> {
+        Err(Error::with_retry("Again", Duration::from_millis(1)))
+    }
+    #[producer(max_retries = 1)]
+    async fn b(_: Context<()>) -> Result<B> {
+        Err(Error::with_retry("Again", Duration::from_millis(1)))
+    }
+
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut worker = Worker::new(job, ()).max_retries(3);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_node_failed());
+    let status = worker.status().await;
+    assert!(matches!(
+        status["A"],
+        ordr::NodeState::Failed { retries: 3, .. }
+    ));
+
+    // The node's own limit wins.
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, ()).max_retries(3);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_node_failed());
+    let status = worker.status().await;
+    assert!(matches!(
+        status["B"],
+        ordr::NodeState::Failed { retries: 1, .. }
+    ));
+}
+
+#[tokio::test]
+async fn cache() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B(u8);
+    #[producer]
+    async fn a(ctx: Context<Arc<AtomicUsize>>) -> Result<A> {
+        ctx.state.fetch_add(1, Ordering::Relaxed);
+        Ok(A(1))
+    }
+    #[producer]
+    async fn b(ctx: Context<Arc<AtomicUsize>>, a: A) -> Result<B> {
+        ctx.state.fetch_add(1, Ordering::Relaxed);
+        Ok(B(a.0 + 1))
+    }
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let cache = Arc::new(InMemoryCache::new());
+    let job = Job::builder().add::<B>().build().unwrap();
+    for _ in 0..2 {
+        let mut worker = Worker::new(job.clone(), runs.clone()).with_cache(cache.clone());
+        worker.run().await.unwrap();
+        assert!(worker.get_output().await.unwrap().is_done());
+        assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, 2);
+    }
+    assert_eq!(runs.load(Ordering::Relaxed), 2);
+    assert_eq!(cache.len(), 2);
+
+    // Different inputs are not the same.
+    let job = Job::builder().with_input(A(5)).add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, runs.clone()).with_cache(cache.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, 6);
+    assert_eq!(runs.load(Ordering::Relaxed), 3);
+
+    // Outputs survive in files.
+    let dir = std::env::temp_dir().join(format!("ordr-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let job = Job::builder().add::<B>().build().unwrap();
+    for _ in 0..2 {
+        let cache = FileCache::open(&dir).unwrap();
+        let mut worker = Worker::new(job.clone(), runs.clone()).with_cache(cache);
+        worker.run().await.unwrap();
+        assert!(worker.get_output().await.unwrap().is_done());
+        assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, 2);
+    }
+    assert_eq!(runs.load(Ordering::Relaxed), 5);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn several_outputs() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Doc(String);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Meta(usize);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Summary(String);
+    #[producer]
+    async fn parse(ctx: Context<Arc<AtomicUsize>>) -> Result<(Doc, Meta)> {
+        ctx.state.fetch_add(1, Ordering::Relaxed);
+        Ok((Doc("hello".into()), Meta(5)))
+    }
+    #[producer]
+    async fn summary(_: Context<Arc<AtomicUsize>>, doc: Doc, meta: Meta) -> Result<Summary> {
+        Ok(Summary(format!("{}: {}", doc.0, meta.0)))
+    }
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let job = Job::builder().add::<Summary>().build().unwrap();
+    let mut worker = Worker::new(job, runs.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(
+        worker.get::<Summary>().await.unwrap().unwrap().0,
+        "hello: 5"
+    );
+    assert_eq!(runs.load(Ordering::Relaxed), 1);
+    let data = worker.data().await;
+    assert_eq!(data["Doc"], serde_json::json!("hello"));
+    assert_eq!(data["Meta"], serde_json::json!(5));
+    assert!(!data.contains_key("parse"));
+
+    // With both provided, it does not run at all.
+    let job = Job::builder()
+        .with_input(Doc("hi".into()))
+        .with_input(Meta(2))
+        .add::<Summary>()
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, runs.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<Summary>().await.unwrap().unwrap().0, "hi: 2");
+    assert_eq!(runs.load(Ordering::Relaxed), 1);
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Head(u8);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Tail(Vec<u8>);
+    type Split = (Head, Tail);
+    #[producer(outputs(Head, Tail), name = "split")]
+    fn split(_: Context<Arc<AtomicUsize>>) -> Result<Split> {
+        Ok((Head(1), Tail(vec![2, 3])))
+    }
+
+    let job = Job::builder().add::<Head>().add::<Tail>().build().unwrap();
+    let mut worker = Worker::new(job, runs);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<Tail>().await.unwrap().unwrap().0, [2, 3]);
+    assert_eq!(worker.get::<Head>().await.unwrap().unwrap().0, 1);
+}
+
+#[tokio::test]
+async fn record_and_replay() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u32);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B(u32);
+    #[producer]
+    async fn a(ctx: Context<Arc<AtomicU32>>) -> Result<A> {
+        let run = ctx.state.fetch_add(1, Ordering::Relaxed);
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Not yet", Duration::from_millis(1)));
+        }
+        Ok(A(run * 10))
+    }
+    #[producer]
+    async fn b(ctx: Context<Arc<AtomicU32>>, a: A) -> Result<B> {
+        ctx.state.fetch_add(1, Ordering::Relaxed);
+        Ok(B(a.0 + 1))
+    }
+
+    let job = Job::builder().add::<B>().build().unwrap();
+    let recorder = Recorder::new();
+    let runs = Arc::new(AtomicU32::new(0));
+    let mut worker = Worker::new(job.clone(), runs.clone()).record(recorder.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, 11);
+    assert_eq!(runs.load(Ordering::Relaxed), 3);
+    let records = recorder.records();
+    let attempts: Vec<_> = records
+        .iter()
+        .map(|r| (r.name.as_str(), r.retry, r.error.is_some()))
+        .collect();
+    assert_eq!(attempts, [("A", 0, true), ("A", 1, false), ("B", 0, false)]);
+    assert_eq!(records[2].inputs, [Some(serde_json::json!(10))]);
+
+    // The same thing happens again, without running any producer.
+    let path = std::env::temp_dir().join(format!("ordr-journal-{}.json", std::process::id()));
+    recorder.save(&path).unwrap();
+    let replayer = Replayer::open(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let runs = Arc::new(AtomicU32::new(0));
+    let mut worker = Worker::new(job.clone(), runs.clone()).replay(replayer);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, 11);
+    assert!(matches!(
+        worker.status().await["A"],
+        ordr::NodeState::Done { retries: 1, .. }
+    ));
+    assert_eq!(runs.load(Ordering::Relaxed), 0);
+
+    // Attempts that were not recorded fail.
+    let mut worker = Worker::new(job, runs).replay(Replayer::new(records.into_iter().take(2)));
+    worker.run().await.unwrap();
+    let ordr::Output::NodeFailed { name, error, .. } = worker.get_output().await.unwrap() else {
+        panic!("B was not recorded");
+    };
+    assert_eq!(name, "B");
+    assert_eq!(error.message(), "Retry 0 of node B was not recorded");
+}
+
+#[tokio::test]
+async fn priority() {
+    type Order = Arc<std::sync::Mutex<Vec<&'static str>>>;
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+    #[producer]
+    async fn a(ctx: Context<Order>) -> Result<A> {
+        ctx.state.lock().unwrap().push("A");
+        Ok(A)
+    }
+    #[producer]
+    async fn b(ctx: Context<Order>) -> Result<B> {
+        ctx.state.lock().unwrap().push("B");
+        Ok(B)
+    }
+    #[producer(priority = 10)]
+    async fn c(ctx: Context<Order>) -> Result<C> {
+        ctx.state.lock().unwrap().push("C");
+        Ok(C)
+    }
+
+    let job = Job::builder()
+        .add::<A>()
+        .add::<B>()
+        .add::<C>()
+        .priority::<B>(5)
+        .build()
+        .unwrap();
+    let order = Order::default();
+    let mut worker = Worker::new(job, order.clone()).max_concurrency(1);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(*order.lock().unwrap(), ["C", "B", "A"]);
+}
+
+#[tokio::test]
+async fn worker_pool() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Currently running, and the most that ever ran at once.
+    type Running = Arc<(AtomicUsize, AtomicUsize)>;
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u8);
+    #[producer]
+    async fn a(ctx: Context<Running>) -> Result<A> {
+        let (running, max) = &*ctx.state;
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        Ok(A(1))
+    }
+
+    let running = Running::default();
+    let pool = WorkerPool::new(running.clone(), 2);
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut handles: Vec<_> = (0..5).map(|_| pool.submit(job.clone())).collect();
+    assert_eq!(pool.stats().submitted, 5);
+    for (i, handle) in handles.iter_mut().enumerate() {
+        assert_eq!(handle.id(), i);
+        assert!(handle.output().await.is_done());
+        assert!(handle.is_finished());
+        assert_eq!(handle.data().await["A"], 1);
+    }
+    assert_eq!(running.1.load(Ordering::SeqCst), 2);
+    let stats = pool.stats();
+    assert_eq!(stats.succeeded, 5);
+    assert_eq!(stats.queued + stats.running + stats.failed, 0);
+}
+
+#[tokio::test]
+async fn pool_priority() {
+    type Started = Arc<std::sync::Mutex<Vec<&'static str>>>;
+
+    macro_rules! outputs {
+        ($($name:ident)*) => {$(
+            #[derive(Clone, Default, Serialize, Deserialize)]
+            struct $name;
+        )*};
+    }
+    outputs!(N1 N2 N3 N4 N5 N6);
+
+    fn node<T: Default + Serialize + Send + 'static>(name: &'static str) -> ordr::Node<Started> {
+        ordr::Node::builder::<T>(name).producer(move |ctx: Context<Started>, ()| {
+            ctx.state.lock().unwrap().push(name);
+            async move {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                Ok(T::default())
+            }
+        })
+    }
+
+    fn job(names: [&'static str; 6]) -> Job<Started> {
+        Job::builder()
+            .add_node(node::<N1>(names[0]))
+            .add_node(node::<N2>(names[1]))
+            .add_node(node::<N3>(names[2]))
+            .add_node(node::<N4>(names[3]))
+            .add_node(node::<N5>(names[4]))
+            .add_node(node::<N6>(names[5]))
+            .build()
+            .unwrap()
+    }
+
+    let started = Started::default();
+    let pool = WorkerPool::new(started.clone(), 2).max_nodes(1);
+    let mut low =
+        pool.submit_with_priority(job(["L1", "L2", "L3", "L4", "L5", "L6"]), Priority::Low);
+    let mut high =
+        pool.submit_with_priority(job(["H1", "H2", "H3", "H4", "H5", "H6"]), Priority::High);
+    assert!(low.output().await.is_done());
+    assert!(high.output().await.is_done());
+
+    // Both jobs had all of their nodes waiting from the start, but the one with the higher
+    // priority got four turns for every one of the other.
+    let started = started.lock().unwrap();
+    assert_eq!(started.len(), 12);
+    let high_first = started[..6]
+        .iter()
+        .filter(|name| name.starts_with('H'))
+        .count();
+    assert!(high_first >= 4, "{started:?}");
+}
+
+#[tokio::test]
+async fn scheduler() {
+    let scheduler = Scheduler::new(2);
+    let job = Job::builder().add::<B>().build().unwrap();
+
+    // Nothing runs before it is due.
+    let start = std::time::Instant::now();
+    let mut later = scheduler.enqueue(job.clone(), State, When::In(Duration::from_millis(30)));
+    let mut now = scheduler.enqueue(job.clone(), State, When::Now);
+    assert_eq!((now.id(), later.id()), (1, 0));
+    let mut worker = now.next_run().await.unwrap();
+    assert!(start.elapsed() < Duration::from_millis(30));
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["BB"], 2);
+    assert!(now.next_run().await.is_none());
+    let mut worker = later.next_run().await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert!(worker.get_output().await.unwrap().is_done());
+
+    // Recurring jobs run until cancelled.
+    let mut every = scheduler.enqueue(job.clone(), State, When::Every(Duration::from_millis(5)));
+    for _ in 0..3 {
+        let mut worker = every.next_run().await.unwrap();
+        assert!(worker.get_output().await.unwrap().is_done());
+    }
+    every.cancel();
+    assert!(every.is_cancelled());
+    assert!(every.next_run().await.is_none());
+
+    // Shutting down cancels whatever has not run yet.
+    let mut never = scheduler.enqueue(job, State, When::In(Duration::from_secs(60)));
+    scheduler.shutdown();
+    assert!(never.is_cancelled());
+    assert!(never.next_run().await.is_none());
+}
+
+#[tokio::test]
+async fn recurring() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Each run carries on from the data of the one before it.
+    let scheduler = Scheduler::new(4);
+    let interval = Duration::from_millis(20);
+    let mut job = scheduler.recurring(interval, Overlap::Queue, State, |previous| {
+        let data = previous
+            .map(|data| [("A".to_string(), data["BB"].clone())].into())
+            .unwrap_or_default();
+        Job::builder_with_data(data).add::<B>().build()
+    });
+    for expected in 2..5 {
+        let worker = job.next_run().await.unwrap();
+        assert_eq!(worker.data().await["BB"], expected);
+    }
+    job.cancel();
+    assert!(job.next_run().await.is_none());
+
+    /// Currently running, and the most that ever ran at once.
+    type Running = Arc<(AtomicUsize, AtomicUsize)>;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Slow;
+    #[producer]
+    async fn slow(ctx: Context<Running>) -> Result<Slow> {
+        let (running, max) = &*ctx.state;
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        Ok(Slow)
+    }
+
+    // Runs only overlap if they are allowed to.
+    let interval = Duration::from_millis(5);
+    for (overlap, overlapped) in [(Overlap::Skip, false), (Overlap::Concurrent, true)] {
+        let running = Running::default();
+        let mut job = scheduler.recurring(interval, overlap, running.clone(), |_| {
+            Job::builder().add::<Slow>().build()
+        });
+        for _ in 0..2 {
+            job.next_run().await.unwrap();
+        }
+        job.cancel();
+        assert_eq!(running.1.load(Ordering::SeqCst) > 1, overlapped);
+    }
+}
+
+#[tokio::test]
+async fn job_id() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u64);
+    #[producer]
+    async fn a(ctx: Context<()>) -> Result<A> {
+        Ok(A(ctx.job_id))
+    }
+
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut worker = Worker::new(job.clone(), ());
+    assert_ne!(worker.job_id(), Worker::new(job, ()).job_id());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<A>().await.unwrap().unwrap().0, worker.job_id());
+}
+
+#[tokio::test]
+async fn run_handle() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Slow;
+    static DROPPED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DROPPED.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+    #[producer]
+    async fn slow(_: Context<()>) -> Result<Slow> {
+        let _guard = Guard;
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(Slow)
+    }
+
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    let handle = worker.run().await.unwrap();
+    assert!(worker.run().await.is_err());
+    assert!(handle.clone().await.is_done());
+    assert!(handle.is_finished());
+    assert_eq!(handle.data().await["A"], 1);
+
+    let job = Job::builder().add::<Slow>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    let mut handle = worker.run().await.unwrap();
+    tokio::select! {
+        _ = handle.clone() => panic!("The job should not finish"),
+        () = tokio::time::sleep(Duration::from_millis(10)) => handle.abort().await,
+    }
+    assert!(handle.output().await.is_stopped());
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(DROPPED.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn checkpoint() {
+    let path = std::env::temp_dir().join(format!("ordr-checkpoint-{}.json", std::process::id()));
+
+    let data = [("A".to_string(), serde_json::json!(10))].into();
+    let job = Job::builder_with_data(data)
+        .add::<B>()
+        .label("tenant", "acme")
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let checkpoint = worker.checkpoint().await;
+    assert_eq!(checkpoint.targets, ["BB"]);
+    checkpoint.save(&path).unwrap();
+
+    let checkpoint = ordr::Checkpoint::load(&path).unwrap();
+    let job = Job::builder_from_checkpoint(checkpoint.clone(), &[A::node(), B::node()])
+        .unwrap()
+        .build()
+        .unwrap();
+    assert!(job.execution_plan().is_empty());
+    assert_eq!(job.labels()["tenant"], "acme");
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["BB"], 11);
+
+    let err = Job::builder_from_checkpoint(checkpoint, &[A::node()]).err();
+    assert!(matches!(err, Some(ordr::JobError::UnknownTarget(name)) if name == "BB"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn variants() {
+    let fixture = ordr::Node::builder("fixture")
+        .variant("fixture")
+        .producer(|_: Context<State>, (): ()| async move { Ok(A(41)) });
+    let builder = || Job::builder().add::<B>().add_variant(fixture.clone());
+
+    let job = builder().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    assert_eq!(worker.data().await["BB"], 2);
+
+    let job = builder().select::<A>("fixture").build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let data = worker.data().await;
+    assert_eq!(data["A"], 41);
+    assert_eq!(data["BB"], 42);
+
+    let err = builder().select::<A>("live").build().err();
+    assert!(matches!(err, Some(ordr::JobError::UnknownVariant("A", variant)) if variant == "live"));
+}
+
+#[tokio::test]
+async fn hooks() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Flaky;
+    #[producer]
+    async fn flaky(ctx: Context<()>) -> Result<Flaky> {
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Again", Duration::from_millis(1)));
+        }
+        Ok(Flaky)
+    }
+
+    #[derive(Default)]
+    struct Recorder(std::sync::Mutex<Vec<String>>);
+    impl ordr::Hook<()> for Recorder {
+        fn before_node<'a>(
+            &'a self,
+            name: &'static str,
+            ctx: &'a Context<()>,
+        ) -> ordr::HookFuture<'a> {
+            let retry = ctx.retry();
+            Box::pin(async move {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("before {name} {retry}"))
+            })
+        }
+
+        fn after_node<'a>(
+            &'a self,
+            name: &'static str,
+            result: &'a Result<ordr::Payload>,
+            _: Duration,
+        ) -> ordr::HookFuture<'a> {
+            let ok = result.is_ok();
+            Box::pin(async move { self.0.lock().unwrap().push(format!("after {name} {ok}")) })
+        }
+
+        fn on_retry<'a>(
+            &'a self,
+            name: &'static str,
+            retry: u32,
+            error: &'a Error,
+            _: Duration,
+        ) -> ordr::HookFuture<'a> {
+            Box::pin(async move {
+                let event = format!("retry {name} {retry} {}", error.message());
+                self.0.lock().unwrap().push(event);
+            })
+        }
+    }
+
+    let recorder = Arc::new(Recorder::default());
+    let job = Job::builder().add::<Flaky>().build().unwrap();
+    let mut worker = Worker::new(job, ()).add_hook(recorder.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        [
+            "before Flaky 0",
+            "after Flaky false",
+            "retry Flaky 1 Again",
+            "before Flaky 1",
+            "after Flaky true",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn panicking_hook() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Flaky;
+    #[producer]
+    async fn flaky(ctx: Context<()>) -> Result<Flaky> {
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Again", Duration::from_millis(1)));
+        }
+        Ok(Flaky)
+    }
+
+    struct Panics;
+    impl ordr::Hook<()> for Panics {
+        fn on_retry<'a>(
+            &'a self,
+            _: &'static str,
+            _: u32,
+            _: &'a Error,
+            _: Duration,
+        ) -> ordr::HookFuture<'a> {
+            Box::pin(async { panic!("Hook failed") })
+        }
+    }
+
+    let job = Job::builder().add::<Flaky>().build().unwrap();
+    let mut worker = Worker::new(job.clone(), ()).add_hook(Panics);
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    assert!(matches!(
+        output,
+        ordr::Output::NodePanic { name: "Flaky", .. }
+    ));
+
+    // The node fails like a producer that panics, and the rest of the job carries on.
+    let mut worker = Worker::new(job, ())
+        .add_hook(Panics)
+        .on_failure(ordr::FailurePolicy::CollectAll);
+    worker.run().await.unwrap();
+    let ordr::Output::Finished { failures, .. } = worker.get_output().await.unwrap() else {
+        panic!("Expected the job to finish");
+    };
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0].panicked);
+    assert!(matches!(
+        worker.status().await["Flaky"],
+        ordr::NodeState::Failed { retries: 0, .. }
+    ));
+}
+
+#[tokio::test]
+async fn service() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Base(u32);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Scaled(u32);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Summed(u32);
+    struct Service {
+        factor: u32,
+    }
+
+    #[ordr::service]
+    impl Service {
+        #[producer(name = "Base")]
+        async fn base(&self, _: Context<Arc<Service>>) -> Result<Base> {
+            Ok(Base(self.factor))
+        }
+
+        #[producer]
+        fn scaled(&self, _: Context<Arc<Service>>, base: Base) -> Result<Scaled> {
+            Ok(Scaled(base.0 * self.factor))
+        }
+
+        #[producer(max_retries = 1)]
+        async fn summed(&self, _: Context<Arc<Service>>, b: Base, s: Scaled) -> Result<Summed> {
+            Ok(Summed(b.0 + s.0 + self.factor))
+        }
+
+        // Not a producer.
+        fn helper(&self) -> u32 {
+            self.factor
+        }
+    }
+
+    let service = Arc::new(Service { factor: 3 });
+    assert_eq!(service.helper(), 3);
+    let job = Job::builder().add::<Summed>().build().unwrap();
+    let mut worker = Worker::new(job, service);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<Summed>().await.unwrap().unwrap().0, 3 + 9 + 3);
+}
+
+#[tokio::test]
+async fn labels() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Tenant(String);
+    #[producer]
+    async fn tenant(ctx: Context<()>) -> Result<Tenant> {
+        Ok(Tenant(ctx.label("tenant").unwrap_or_default().to_string()))
+    }
+
+    let job = Job::builder()
+        .add::<Tenant>()
+        .label("tenant", "acme")
+        .build()
+        .unwrap();
+    assert_eq!(job.labels()["tenant"], "acme");
+    let mut worker = Worker::new(job, ());
+    assert_eq!(worker.labels().len(), 1);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<Tenant>().await.unwrap().unwrap().0, "acme");
+}
+
+#[tokio::test]
+async fn structured_error() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[producer(max_retries = 1)]
+    async fn a(_: Context<()>) -> Result<A> {
+        let error = Error::with_retry("Rate limited", Duration::from_millis(1));
+        Err(error.with_details(serde_json::json!({ "code": 429 })))
+    }
+
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    let ordr::Output::NodeFailed {
+        name,
+        retries,
+        error,
+        ..
+    } = worker.get_output().await.unwrap()
+    else {
+        panic!("Expected the node to fail");
+    };
+    assert_eq!(name, "A");
+    assert_eq!(retries, 1);
+    assert_eq!(error.message(), "Rate limited");
+    assert_eq!(error.retry_in(), Some(Duration::from_millis(1)));
+    assert_eq!(error.details().unwrap()["code"], 429);
+}
+
+#[tokio::test]
+async fn cbor_format() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A(u64);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B(u64);
+    #[producer]
+    async fn a(_: Context<()>) -> Result<A> {
+        Ok(A(u64::MAX))
+    }
+    #[producer]
+    async fn b(_: Context<()>, a: A) -> Result<B> {
+        Ok(B(a.0 - 1))
+    }
+
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, ()).format(Format::Cbor);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    let status = worker.status().await;
+    let ordr::NodeState::Done { value, .. } = &status["B"] else {
+        panic!("Expected B to be done");
+    };
+    assert!(matches!(value, ordr::Payload::Cbor(_)));
+    assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, u64::MAX - 1);
+    assert_eq!(worker.data().await["A"], u64::MAX);
+}
+
+#[tokio::test]
+async fn raw_producer() {
+    #[derive(Clone)]
+    struct Image(Vec<u8>);
+    impl From<Vec<u8>> for Image {
+        fn from(bytes: Vec<u8>) -> Self {
+            Image(bytes)
+        }
+    }
+    impl From<Image> for Vec<u8> {
+        fn from(image: Image) -> Self {
+            image.0
+        }
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Size(usize);
+    #[producer(raw)]
+    async fn image(_: Context<()>) -> Result<Image> {
+        Ok(Image(vec![7; 1000]))
+    }
+    #[producer]
+    async fn size(_: Context<()>, image: Image) -> Result<Size> {
+        Ok(Size(image.0.len()))
+    }
+
+    let job = Job::builder().add::<Size>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(
+        worker.get::<Image>().await.unwrap().unwrap().0,
+        vec![7; 1000]
+    );
+    let data = worker.data().await;
+    assert_eq!(data["Size"], 1000);
+    assert!(!data.contains_key("Image"));
+}
+
+#[tokio::test]
+async fn subgraph() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Shared(u32);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Input(u32);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Sum(u32);
+    static RUNS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+    #[producer]
+    async fn shared(_: Context<()>) -> Result<Shared> {
+        RUNS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(Shared(100))
+    }
+    #[producer]
+    async fn input(_: Context<()>) -> Result<Input> {
+        Ok(Input(0))
+    }
+    #[producer]
+    async fn sum(_: Context<()>, input: Input, shared: Shared) -> Result<Sum> {
+        Ok(Sum(input.0 + shared.0))
+    }
+
+    let subgraph = Subgraph::new().add::<Input>().add::<Sum>();
+    let data = [("a.Input".to_string(), serde_json::json!(1))].into();
+    let job = Job::builder_with_data(data)
+        .mount("a", &subgraph)
+        .mount("b", &subgraph)
+        .build()
+        .unwrap();
+    assert_eq!(job.dependencies_of("b.Sum").unwrap(), ["Shared", "b.Input"]);
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    let data = worker.data().await;
+    assert_eq!(data["a.Sum"], 101);
+    assert_eq!(data["b.Sum"], 100);
+    assert_eq!(RUNS.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[test]
+fn retry_policy_delays() {
+    let ms = Duration::from_millis;
+    let policy = RetryPolicy::exponential(ms(100), 3);
+    let delays: Vec<_> = (1..=4).map(|retry| policy.delay(retry, ms(0))).collect();
+    assert_eq!(delays, [Some(ms(100)), Some(ms(200)), Some(ms(400)), None]);
+
+    let policy = policy.max_delay(ms(150)).max_elapsed(ms(1000));
+    assert_eq!(policy.delay(3, ms(0)), Some(ms(150)));
+    assert_eq!(policy.delay(3, ms(900)), None);
+
+    let policy = RetryPolicy::fixed(ms(100), 3).with_jitter();
+    let delay = policy.delay(2, ms(0)).unwrap();
+    assert!(delay >= ms(50) && delay <= ms(100), "{delay:?}");
+}
+
+#[tokio::test]
+async fn retry_policy() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[producer(retry = "exponential(1ms, 3)")]
+    async fn a(_: Context<()>) -> Result<A> {
+        // Ignored, since the node has a policy.
+        Err(Error::with_retry("Again", Duration::from_secs(3600)))
+    }
+
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker
+        .run_with_timeout(Duration::from_secs(1))
+        .await
+        .unwrap();
+    let ordr::Output::NodeFailed { retries, .. } = worker.get_output().await.unwrap() else {
+        panic!("Expected the node to fail");
+    };
+    assert_eq!(retries, 3);
+}
+
+#[tokio::test]
+async fn max_concurrency() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Currently running, and the most that ever ran at once.
+    type Running = Arc<(AtomicUsize, AtomicUsize)>;
+
+    async fn work(running: &Running) {
+        let now = running.0.fetch_add(1, Ordering::SeqCst) + 1;
+        running.1.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        running.0.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct D;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct E;
+    #[producer]
+    async fn a(ctx: Context<Running>) -> Result<A> {
+        work(&ctx.state).await;
+        Ok(A)
+    }
+    #[producer]
+    async fn b(ctx: Context<Running>) -> Result<B> {
+        work(&ctx.state).await;
+        Ok(B)
+    }
+    #[producer]
+    async fn c(ctx: Context<Running>) -> Result<C> {
+        work(&ctx.state).await;
+        Ok(C)
+    }
+    #[producer]
+    async fn d(ctx: Context<Running>) -> Result<D> {
+        work(&ctx.state).await;
+        Ok(D)
+    }
+    #[producer]
+    async fn e(_: Context<Running>, _: A, _: B, _: C, _: D) -> Result<E> {
+        Ok(E)
+    }
+
+    let running = Running::default();
+    let job = Job::builder().add::<E>().build().unwrap();
+    let mut worker = Worker::new(job, running.clone())
+        .max_concurrency(2)
+        .log_decisions();
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(running.1.load(Ordering::SeqCst), 2);
+    let limited: Vec<_> = worker
+        .decisions()
+        .into_iter()
+        .filter(|d| d.step == 1 && d.kind == DecisionKind::ConcurrencyLimit)
+        .map(|d| d.name)
+        .collect();
+    assert_eq!(limited, ["C", "D"]);
+}
+
+#[tokio::test]
+async fn exclusive() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Currently using the gpu, and the most that ever did at once.
+    type Gpu = Arc<(AtomicUsize, AtomicUsize)>;
+
+    async fn work(gpu: &Gpu) {
+        let now = gpu.0.fetch_add(1, Ordering::SeqCst) + 1;
+        gpu.1.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        gpu.0.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct B;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct D;
+    #[producer(exclusive = "gpu")]
+    async fn a(ctx: Context<Gpu>) -> Result<A> {
+        work(&ctx.state).await;
+        // The gpu is free for others while waiting to retry.
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Not yet", Duration::from_millis(1)));
+        }
+        Ok(A)
+    }
+    #[producer(exclusive = "gpu")]
+    async fn b(ctx: Context<Gpu>) -> Result<B> {
+        work(&ctx.state).await;
+        Ok(B)
+    }
+    #[producer]
+    async fn c(_: Context<Gpu>) -> Result<C> {
+        Ok(C)
+    }
+    #[producer]
+    async fn d(_: Context<Gpu>, _: A, _: B, _: C) -> Result<D> {
+        Ok(D)
+    }
+
+    let gpu = Gpu::default();
+    let job = Job::builder().add::<D>().build().unwrap();
+    let mut worker = Worker::new(job, gpu.clone()).log_decisions();
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(gpu.1.load(Ordering::SeqCst), 1);
+    let decisions = worker.decisions();
+    let started = |step| {
+        decisions
+            .iter()
+            .filter(|d| d.step == step && d.kind == DecisionKind::Started)
+            .map(|d| d.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(started(1), ["A", "C"]);
+    assert!(
+        decisions
+            .iter()
+            .any(|d| d.name == "B" && d.kind == DecisionKind::Exclusive("gpu"))
+    );
+}
+
+#[tokio::test]
+async fn subscribe() {
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    let mut events = worker.subscribe();
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+
+    let mut seen = vec![];
+    while let Ok(event) = events.try_recv() {
+        seen.push(match event {
+            JobEvent::NodeStarted { name, .. } => format!("started {name}"),
+            JobEvent::NodeDone { name, .. } => format!("done {name}"),
+            JobEvent::JobDone { output } => format!("job done {}", output.is_done()),
+            e => panic!("Unexpected event {e:?}"),
+        });
+    }
+    assert_eq!(
+        seen,
+        [
+            "started A",
+            "done A",
+            "started BB",
+            "done BB",
+            "job done true"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn derive_node() {
+    #[derive(Clone, Serialize, Deserialize, ordr::Node)]
+    #[node(deps(A, B), state = State, name = "Sum")]
+    struct C(u8);
+
+    impl C {
+        async fn produce(_: Context<State>, a: A, b: B) -> Result<C> {
+            Ok(C(a.0 + b.0))
+        }
+    }
+
+    let job = Job::builder().add::<C>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.data().await["Sum"], serde_json::json!(3));
+}
+
+#[tokio::test]
+async fn optional_dependency() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Enriched(Option<u8>);
+    #[producer]
+    async fn enrich(_: Context<State>, b: Option<B>) -> Result<Enriched> {
+        Ok(Enriched(b.map(|b| b.0)))
+    }
+
+    let run = async |job: Job<State>| {
+        let mut worker = Worker::new(job, State);
+        worker.run().await.unwrap();
+        assert!(worker.get_output().await.unwrap().is_done());
+        worker.data().await
+    };
+
+    // B is not forced into the job.
+    let job = Job::builder().add::<Enriched>().build().unwrap();
+    assert_eq!(job.len(), 1);
+    let data = run(job).await;
+    assert_eq!(data["Enriched"], serde_json::json!(null));
+
+    // But it is used when something else needs it.
+    let job = Job::builder().add::<Enriched>().add::<B>().build().unwrap();
+    let data = run(job).await;
+    assert_eq!(data["Enriched"], serde_json::json!(2));
+
+    // Or when it is provided.
+    let data = [("BB".to_string(), serde_json::json!(5))].into();
+    let job = Job::builder_with_data(data)
+        .add::<Enriched>()
+        .build()
+        .unwrap();
+    let data = run(job).await;
+    assert_eq!(data["Enriched"], serde_json::json!(5));
+}
+
+#[tokio::test]
+async fn map_over() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Pages(Vec<u32>);
+    impl IntoIterator for Pages {
+        type Item = u32;
+        type IntoIter = std::vec::IntoIter<u32>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.0.into_iter()
+        }
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Lengths(Vec<u32>);
+    impl FromIterator<u32> for Lengths {
+        fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+            Self(iter.into_iter().collect())
+        }
+    }
+
+    #[producer]
+    async fn pages(_: Context<()>) -> Result<Pages> {
+        Ok(Pages(vec![3, 1, 2]))
+    }
+    #[producer(map_over = Pages, output = Lengths)]
+    async fn length(_: Context<()>, page: u32) -> Result<u32> {
+        // Later pages finish first, but the order is kept.
+        tokio::time::sleep(Duration::from_millis(u64::from(page))).await;
+        if page == 0 {
+            return Err(Error::fatal("Empty page"));
+        }
+        Ok(page * 10)
+    }
+
+    let job = Job::builder().add::<Lengths>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(
+        worker.data().await["Lengths"],
+        serde_json::json!([30, 10, 20])
+    );
+
+    // One failing item fails the node.
+    let data = [("Pages".to_string(), serde_json::json!([1, 0]))].into();
+    let job = Job::builder_with_data(data)
+        .add::<Lengths>()
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_node_failed());
+}
+
+#[tokio::test]
+async fn typed_data() {
+    let data = [("A".to_string(), serde_json::json!(4))].into();
+    let job = Job::builder_with_data(data).add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    assert!(worker.get::<A>().await.unwrap().is_none());
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+
+    assert_eq!(worker.get::<A>().await.unwrap().unwrap().0, 4);
+    assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, 5);
+    assert_eq!(worker.take::<B>().await.unwrap().unwrap().0, 5);
+    assert!(worker.get::<B>().await.unwrap().is_none());
+    assert!(!worker.data().await.contains_key("BB"));
+}
+
+#[tokio::test]
+async fn typed_data_that_does_not_fit() {
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+
+    // Say, a value written by an older version of the node.
+    worker
+        .set_value("A", serde_json::json!({"old": "shape"}))
+        .await
+        .unwrap();
+    assert!(matches!(
+        worker.get::<A>().await,
+        Err(ordr::ExtractError::Invalid("A", _))
+    ));
+    assert!(worker.take::<A>().await.is_err());
+    assert!(worker.data().await.contains_key("A"));
+}
+
+#[tokio::test]
+async fn job_timeout() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct A;
+    #[producer]
+    async fn a(_: Context<()>) -> Result<A> {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(A)
+    }
+
+    let job = Job::builder().add::<A>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker
+        .run_with_timeout(Duration::from_millis(10))
+        .await
+        .unwrap();
+    let output = worker.get_output().await.unwrap();
+    assert!(output.is_timed_out());
+    assert!(output.duration() < Duration::from_secs(1));
+    assert_eq!(worker.health(Duration::ZERO).running_nodes, 0);
+}
+
+#[tokio::test]
+async fn diff() {
+    use serde_json::json;
+
+    let a = [
+        ("Same".to_string(), json!(1)),
+        ("Gone".to_string(), json!("x")),
+        (
+            "Report".to_string(),
+            json!({ "total": 3, "items": [1, 2], "a/b": null }),
+        ),
+    ]
+    .into();
+    let b = [
+        ("Same".to_string(), json!(1)),
+        ("New".to_string(), json!(true)),
+        (
+            "Report".to_string(),
+            json!({ "total": 4, "items": [1, 2, 3], "a/b": null }),
+        ),
+    ]
+    .into();
+    let diff = ordr::diff(&a, &b);
+    assert_eq!(diff.added.keys().collect::<Vec<_>>(), ["New"]);
+    assert_eq!(diff.removed.keys().collect::<Vec<_>>(), ["Gone"]);
+    let changes = &diff.changed["Report"];
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].path, "/items/2");
+    assert_eq!(changes[0].before, None);
+    assert_eq!(changes[0].after, Some(json!(3)));
+    assert_eq!(changes[1].path, "/total");
+    assert_eq!(changes[1].before, Some(json!(3)));
+    assert!(!diff.changed.contains_key("Same"));
+    assert!(ordr::diff(&a, &a).is_empty());
+
+    // Against an earlier run, where BB came out differently.
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let previous = ordr::Snapshot {
+        values: [("A".to_string(), json!(1)), ("BB".to_string(), json!(3))].into(),
+        ..ordr::Snapshot::default()
+    };
+    let diff = worker.diff(&previous).await;
+    assert!(diff.added.is_empty() && diff.removed.is_empty());
+    assert_eq!(diff.changed.keys().collect::<Vec<_>>(), ["BB"]);
+    assert_eq!(diff.changed["BB"][0].path, "");
+    assert_eq!(diff.changed["BB"][0].after, Some(json!(2)));
+}
+
+#[tokio::test]
+async fn from_data() {
+    #[derive(ordr::FromData)]
+    struct Results {
+        #[ordr(name = "A")]
+        a: A,
+        #[ordr(name = "BB")]
+        b: B,
+        #[ordr(name = "C")]
+        c: Option<u8>,
+    }
+
+    #[derive(Debug, ordr::FromData)]
+    struct Missing {
+        #[allow(dead_code)]
+        #[ordr(name = "C")]
+        c: u8,
+    }
+
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+
+    let results: Results = worker.extract().await.unwrap();
+    assert_eq!((results.a.0, results.b.0, results.c), (1, 2, None));
+    let error = worker.extract::<Missing>().await.unwrap_err();
+    assert!(matches!(error, ordr::ExtractError::Missing("C")));
+
+    let mut data = worker.data().await;
+    data.insert("C".into(), serde_json::json!("three"));
+    let error = Results::from_data(&data).err().unwrap();
+    assert!(matches!(error, ordr::ExtractError::Invalid("C", _)));
+}
+
+#[tokio::test]
+async fn merge() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct C(u8);
+    #[producer]
+    async fn c(_: Context<State>, a: A) -> Result<C> {
+        Ok(C(a.0 * 3))
+    }
+
+    let ours = Job::builder()
+        .add::<B>()
+        .label("team", "a")
+        .build()
+        .unwrap();
+    let data = [("A".to_string(), serde_json::json!(10))].into();
+    let theirs = Job::builder_with_data(data)
+        .add::<C>()
+        .label("team", "b")
+        .build()
+        .unwrap();
+    // A is provided by the other job, so it does not run.
+    let job = ours.merge(theirs).unwrap();
+    assert_eq!(job.len(), 2);
+    assert_eq!(job.labels()["team"], "a");
+    assert_eq!(job.plan().provided, ["A"]);
+    assert_eq!(job.pruned(), []);
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    let data = worker.data().await;
+    assert_eq!(data["BB"], serde_json::json!(11));
+    assert_eq!(data["C"], serde_json::json!(30));
+
+    // A node called "A" with another output conflicts.
+    let other_a = ordr::Node::builder("A").producer(|_: Context<State>, ()| async { Ok(1u8) });
+    let conflicting = Job::builder().add_node(other_a).build().unwrap();
+    let ours = Job::builder().add::<B>().build().unwrap();
+    let error = ours.merge(conflicting).unwrap_err();
+    assert!(matches!(error, ordr::JobError::DuplicateName("A")));
+
+    // Builders can be combined before building too.
+    let job = Job::builder()
+        .add::<B>()
+        .extend(
+            Job::builder()
+                .add::<C>()
+                .timeout_for::<C>(Duration::from_secs(1)),
+        )
+        .build()
+        .unwrap();
+    assert_eq!(job.len(), 3);
+    let other_a = ordr::Node::builder("A").producer(|_: Context<State>, ()| async { Ok(1u8) });
+    let error = Job::builder()
+        .add::<B>()
+        .extend(Job::builder().add_node(other_a))
+        .build()
+        .unwrap_err();
+    assert!(matches!(error, ordr::JobError::DuplicateName("A")));
+}
+
+#[tokio::test]
+async fn versions() {
+    use serde_json::json;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct V(u8);
+    #[producer(version = 2)]
+    async fn v(_: Context<()>) -> Result<V> {
+        Ok(V(5))
+    }
+
+    let job = Job::builder().add::<V>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let data = worker.data().await;
+    assert_eq!(data["V"], json!({ "__v": 2, "value": 5 }));
+    assert_eq!(worker.snapshot().await.values, data);
+
+    // Data of the same version is used as is.
+    let job = Job::builder_with_data(data.clone())
+        .add::<V>()
+        .build()
+        .unwrap();
+    assert!(job.is_empty());
+    let snapshot = worker.snapshot().await;
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    assert_eq!(worker.get::<V>().await.unwrap(), Some(V(5)));
+    assert_eq!(worker.data().await, data);
+
+    // So is a snapshot.
+    let job = Job::builder().add::<V>().build().unwrap();
+    let mut worker = Worker::restore(job, (), snapshot);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    assert_eq!(worker.durations().await.len(), 0);
+    assert_eq!(worker.get::<V>().await.unwrap(), Some(V(5)));
+
+    // Data without a version, or of another one, is rejected.
+    let stale = [("V".to_string(), json!(5))].into();
+    let error = Job::builder_with_data(stale)
+        .add::<V>()
+        .build()
+        .unwrap_err();
+    assert!(matches!(error, ordr::JobError::StaleData("V", None, 2)));
+    let old = || [("V".to_string(), json!({ "__v": 1, "value": { "v": 4 } }))].into();
+    let error = Job::builder_with_data(old())
+        .add::<V>()
+        .build()
+        .unwrap_err();
+    assert!(matches!(error, ordr::JobError::StaleData("V", Some(1), 2)));
+
+    // Unless it can be migrated.
+    let job = Job::builder_with_data(old())
+        .add::<V>()
+        .migrate::<V, _>(|from, value| match from {
+            Some(1) => Ok(json!(value["v"].as_u64().unwrap() + 1)),
+            _ => Err(format!("Can not migrate from {from:?}")),
+        })
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    assert_eq!(worker.get::<V>().await.unwrap(), Some(V(5)));
+    let error = Job::builder_with_data([("V".to_string(), json!(5))].into())
+        .add::<V>()
+        .migrate::<V, _>(|from, _| Err(format!("Can not migrate from {from:?}")))
+        .build()
+        .unwrap_err();
+    assert!(matches!(error, ordr::JobError::InvalidProvidedData("V", _)));
+
+    // Typed results look past the version.
+    #[derive(FromData)]
+    struct Results {
+        #[ordr(name = "V")]
+        v: V,
+    }
+    assert_eq!(worker.extract::<Results>().await.unwrap().v, V(5));
+}
+
+mod first {
+    use ordr::{Context, Result, producer};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Meta(pub u8);
+
+    #[producer(qualified)]
+    async fn meta(_: Context<()>) -> Result<Meta> {
+        Ok(Meta(1))
+    }
+}
+
+mod second {
+    use ordr::{Context, Result, producer};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Meta(pub u8);
+
+    #[producer]
+    async fn meta(_: Context<()>) -> Result<Meta> {
+        Ok(Meta(2))
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Other(pub u8);
+
+    #[producer(name = "Meta")]
+    async fn other(_: Context<()>) -> Result<Other> {
+        Ok(Other(3))
+    }
+}
+
+#[tokio::test]
+async fn rename() {
+    assert_eq!(
+        <first::Meta as NodeBuilder<()>>::node().name,
+        "base::first::Meta"
+    );
+    let job = Job::builder()
+        .add::<first::Meta>()
+        .add::<second::Meta>()
+        .build()
+        .unwrap();
+    assert_eq!(job.len(), 2);
+
+    let job = Job::builder()
+        .add::<second::Meta>()
+        .add::<second::Other>()
+        .build();
+    assert!(matches!(job, Err(ordr::JobError::DuplicateName("Meta"))));
+
+    let data = [("Other".to_string(), serde_json::json!(4))].into();
+    let job = Job::builder_with_data(data)
+        .add::<second::Meta>()
+        .add::<second::Other>()
+        .rename::<second::Other>("Other")
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let data = worker.data().await;
+    assert_eq!(data["Meta"], serde_json::json!(2));
+    assert_eq!(data["Other"], serde_json::json!(4));
+    assert_eq!(worker.get::<second::Other>().await.unwrap().unwrap().0, 4);
+}
+
+#[tokio::test]
+async fn timestamps() {
+    let before = std::time::SystemTime::now();
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    assert!(worker.started_at().is_none());
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    let after = std::time::SystemTime::now();
+
+    let started_at = worker.started_at().unwrap();
+    assert_eq!(output.started_at(), started_at);
+    assert!(before <= started_at);
+    assert!(started_at <= output.finished_at());
+    assert!(output.finished_at() <= after);
+
+    let status = worker.status().await;
+    let ordr::NodeState::Done { finished_at, .. } = status["BB"] else {
+        panic!("Expected BB to be done, got {:?}", status["BB"]);
+    };
+    assert!(started_at <= finished_at);
+    assert!(finished_at <= output.finished_at());
+}
+
+#[tokio::test]
+async fn report() {
+    let data = [("A".to_string(), serde_json::json!(1))].into();
+    let job = Job::builder_with_data(data)
+        .add::<B>()
+        .label("team", "data")
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, State);
+    let report = worker.report().await;
+    assert_eq!(report.outcome, None);
+    assert_eq!(report.nodes["BB"].state, ordr::NodeStatus::Pending);
+
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let report = worker.report().await;
+    assert_eq!(report.job_id, worker.job_id());
+    assert_eq!(report.labels["team"], "data");
+    assert_eq!(report.outcome, Some(ordr::Outcome::Done));
+    assert_eq!(report.started_at, worker.started_at());
+    assert!(report.duration.is_some());
+
+    let a = &report.nodes["A"];
+    assert_eq!(a.state, ordr::NodeStatus::Provided);
+    assert!(a.provided);
+    assert_eq!(a.duration(), None);
+    let b = &report.nodes["BB"];
+    assert_eq!(b.state, ordr::NodeStatus::Done);
+    assert!(!b.provided);
+    assert_eq!(b.retries, 0);
+    assert!(b.start.unwrap() <= b.end.unwrap());
+    let ran = report
+        .finished_at
+        .unwrap()
+        .duration_since(report.started_at.unwrap());
+    assert!(b.end.unwrap() <= ran.unwrap());
+
+    let json = serde_json::to_string(&report).unwrap();
+    assert!(json.contains(r#""outcome":"done""#));
+    let parsed: ordr::JobReport = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, report);
+}
+
+#[tokio::test]
+async fn critical_path() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Quick;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Long;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Join;
+
+    #[producer]
+    async fn quick(_ctx: Context<State>, _: A) -> Result<Quick> {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        Ok(Quick)
+    }
+    #[producer]
+    async fn long(_ctx: Context<State>, _: A) -> Result<Long> {
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        Ok(Long)
+    }
+    #[producer]
+    async fn join(_ctx: Context<State>, _: Quick, _: Long) -> Result<Join> {
+        Ok(Join)
+    }
+
+    let job = Job::builder().add::<Join>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let report = worker.report().await;
+    assert_eq!(report.nodes["Join"].inputs, ["Long", "Quick"]);
+
+    let path = report.critical_path();
+    let names: Vec<_> = path.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, ["A", "Long", "Join"]);
+    assert!(path[1].1 >= Duration::from_millis(80));
+
+    let slack = report.slack();
+    assert_eq!(slack.len(), 4);
+    assert_eq!(slack["Join"], Duration::ZERO);
+    assert!(slack["Long"] < Duration::from_millis(20));
+    assert!(slack["Quick"] >= Duration::from_millis(40));
+}
+
+#[tokio::test]
+async fn mermaid_gantt() {
+    let data = [("A".to_string(), serde_json::json!(1))].into();
+    let job = Job::builder_with_data(data).add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    worker.get_output().await.unwrap();
+    let mut report = worker.report().await;
+    let node = report.nodes.get_mut("BB").unwrap();
+    node.start = Some(Duration::from_millis(2));
+    node.end = Some(Duration::from_millis(30));
+    report.job_id = 7;
+
+    let expected = "\
+gantt
+    title Job 7
+    dateFormat x
+    axisFormat %M:%S.%L
+    BB :crit, done, 2, 30";
+    assert_eq!(ordr::mermaid_gantt(&report), expected);
+}
+
+#[tokio::test]
+async fn lenient() {
+    #[derive(Clone, Default, Serialize, Deserialize)]
+    struct Pages(u32);
+    #[producer]
+    async fn pages(_ctx: Context<State>) -> Result<Pages> {
+        Ok(Pages(3))
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Count(u32);
+    #[producer(lenient)]
+    async fn count(_ctx: Context<State>, pages: Pages) -> Result<Count> {
+        Ok(Count(pages.0))
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Maybe(Option<u32>);
+    #[producer]
+    async fn maybe(_ctx: Context<State>, #[ordr(lenient)] pages: Option<Pages>) -> Result<Maybe> {
+        Ok(Maybe(pages.map(|p| p.0)))
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Strict(u32);
+    #[producer]
+    async fn strict(_ctx: Context<State>, pages: Pages) -> Result<Strict> {
+        Ok(Strict(pages.0))
+    }
+
+    // Saved by an older version, where `Pages` was a list.
+    let snapshot = ordr::Snapshot {
+        values: [("Pages".to_string(), serde_json::json!([1, 2, 3]))].into(),
+        ..Default::default()
+    };
+    let job = Job::builder()
+        .add::<Count>()
+        .add::<Maybe>()
+        .build()
+        .unwrap();
+    let mut worker = Worker::restore(job, State, snapshot.clone());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<Count>().await.unwrap().unwrap().0, 0);
+    assert_eq!(worker.get::<Maybe>().await.unwrap().unwrap().0, None);
+
+    let job = Job::builder().add::<Strict>().build().unwrap();
+    let mut worker = Worker::restore(job, State, snapshot);
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    assert!(matches!(
+        output,
+        ordr::Output::NodeFailed { name: "Strict", .. }
+    ));
+}
+
+#[tokio::test]
+async fn blob_store() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Image(Vec<u8>);
+    #[producer]
+    async fn image(_ctx: Context<State>) -> Result<Image> {
+        Ok(Image(vec![7; 1000]))
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Size(usize);
+    #[producer]
+    async fn size(_ctx: Context<State>, image: Image) -> Result<Size> {
+        Ok(Size(image.0.len()))
+    }
+
+    let dir = std::env::temp_dir().join(format!("ordr-blobs-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let job = Job::builder().add::<Size>().build().unwrap();
+    let store = ordr::FileBlobStore::open(&dir).unwrap();
+    let mut worker = Worker::new(job.clone(), State).with_blob_store(store, 100);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<Size>().await.unwrap().unwrap().0, 1000);
+    assert_eq!(worker.get::<Image>().await.unwrap().unwrap().0.len(), 1000);
+
+    // Only the image is kept in the store.
+    let data = worker.data().await;
+    let reference = data["Image"]["$blob"].as_str().unwrap();
+    assert!(std::path::Path::new(reference).starts_with(&dir));
+    assert_eq!(data["Size"], serde_json::json!(1000));
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    // A limit for a single node overrides the default.
+    let store = ordr::FileBlobStore::open(&dir).unwrap();
+    let mut worker = Worker::new(job, State)
+        .with_blob_store(store, 100)
+        .max_output_size::<Image>(10_000);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert!(worker.data().await["Image"].is_array());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn scratch() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Left;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Right;
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Both;
+    fn count(ctx: &Context<State>) {
+        ctx.scratch().update("pages", |pages| {
+            serde_json::json!(pages.and_then(|p| p.as_u64()).unwrap_or(0) + 2)
+        });
+    }
+    #[producer]
+    async fn left(ctx: Context<State>) -> Result<Left> {
+        count(&ctx);
+        Ok(Left)
+    }
+    #[producer]
+    async fn right(ctx: Context<State>) -> Result<Right> {
+        count(&ctx);
+        ctx.scratch().insert("flag", serde_json::json!(true));
+        Ok(Right)
+    }
+    #[producer]
+    async fn both(ctx: Context<State>, _: Left, _: Right) -> Result<Both> {
+        assert_eq!(ctx.scratch().get("flag"), Some(serde_json::json!(true)));
+        ctx.scratch().remove("flag");
+        Ok(Both)
+    }
+
+    let job = Job::builder().add::<Both>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.scratch().get("pages"), Some(serde_json::json!(4)));
+    let report = worker.report().await;
+    assert_eq!(
+        report.scratch,
+        [("pages".into(), serde_json::json!(4))].into()
+    );
+}
+
+#[tokio::test]
+async fn marker() {
+    struct Uploaded;
+    #[producer(marker)]
+    async fn upload(_ctx: Context<State>, _: A) -> Result<Uploaded> {
+        Ok(Uploaded)
+    }
+    #[derive(ordr::Node)]
+    #[node(deps(Uploaded), state = State)]
+    struct Notified;
+    impl Notified {
+        async fn produce(_: Context<State>, _: Uploaded) -> Result<Notified> {
+            Ok(Notified)
+        }
+    }
+
+    let job = Job::builder().add::<Notified>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    let data = worker.data().await;
+    assert_eq!(data["Uploaded"], serde_json::Value::Null);
+    assert_eq!(data["Notified"], serde_json::Value::Null);
+    assert!(worker.get::<Uploaded>().await.unwrap().is_some());
+
+    // Any value will do, when provided.
+    let data = [("Uploaded".to_string(), serde_json::json!({"at": 1}))].into();
+    let job = Job::builder_with_data(data)
+        .add::<Notified>()
+        .build()
+        .unwrap();
+    assert_eq!(job.len(), 1);
+}
+
+#[tokio::test]
+async fn requires() {
+    static CHECKS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+    async fn has_credentials(_: State) -> Result<()> {
+        CHECKS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Err(Error::fatal("AWS_ACCESS_KEY_ID is not set"))
+    }
+    async fn has_database(_: State) -> Result<()> {
+        Ok(())
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Uploaded;
+    #[producer(requires = [has_credentials, has_database])]
+    async fn upload(_ctx: Context<State>, _: A) -> Result<Uploaded> {
+        unreachable!("Requirements are not met")
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Archived;
+    #[producer(requires = has_credentials)]
+    async fn archive(_ctx: Context<State>, _: Uploaded) -> Result<Archived> {
+        unreachable!("Requirements are not met")
+    }
+
+    let job = Job::builder().add::<Archived>().build().unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    let ordr::Output::Unmet { unmet, .. } = worker.get_output().await.unwrap() else {
+        panic!("Expected unmet requirements");
+    };
+    assert_eq!(CHECKS.load(std::sync::atomic::Ordering::Relaxed), 1);
+    assert_eq!(unmet.len(), 1);
+    assert_eq!(unmet[0].name, "has_credentials");
+    assert_eq!(unmet[0].nodes, ["Archived", "Uploaded"]);
+    assert_eq!(unmet[0].error.message(), "AWS_ACCESS_KEY_ID is not set");
+    // Nothing was started.
+    assert!(worker.data().await.is_empty());
+
+    // Nodes that are not run do not need their requirements.
+    let data = [("Archived".to_string(), serde_json::json!(null))].into();
+    let job = Job::builder_with_data(data)
+        .add::<Archived>()
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, State);
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+}
+
+#[tokio::test]
+async fn rate_limit() {
+    static STARTS: std::sync::Mutex<Vec<std::time::Instant>> = std::sync::Mutex::new(vec![]);
+    fn call_api() {
+        STARTS.lock().unwrap().push(std::time::Instant::now());
+    }
+    macro_rules! api_node {
+        ($name:ident, $output:ident) => {
+            #[derive(Clone, Serialize, Deserialize)]
+            struct $output;
+            #[producer(rate = "api")]
+            async fn $name(_ctx: Context<State>) -> Result<$output> {
+                call_api();
+                Ok($output)
+            }
+        };
+    }
+    api_node!(first, First);
+    api_node!(second, Second);
+    api_node!(third, Third);
+    #[derive(Clone, Serialize, Deserialize)]
+    struct All;
+    #[producer]
+    async fn all(_ctx: Context<State>, _: First, _: Second, _: Third) -> Result<All> {
+        Ok(All)
+    }
+
+    let job = Job::builder().add::<All>().build().unwrap();
+    let mut worker = Worker::new(job, State)
+        .rate_limit("api", 2, Duration::from_millis(200))
+        .log_decisions();
+    let t0 = std::time::Instant::now();
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    let starts = STARTS.lock().unwrap().clone();
+    assert_eq!(starts.len(), 3);
+    assert!(starts[1] - t0 < Duration::from_millis(100));
+    assert!(starts[2] - starts[0] >= Duration::from_millis(200));
+    let decisions = worker.decisions();
+    assert!(
+        decisions
+            .iter()
+            .any(|d| d.kind == DecisionKind::RateLimited("api"))
+    );
+}
+
+#[test]
+fn driver() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Flaky(u8);
+    #[producer]
+    async fn flaky(ctx: Context<State>, a: A) -> Result<Flaky> {
+        if ctx.retry() == 0 {
+            return Err(Error::with_retry("Not yet", Duration::from_millis(5)));
+        }
+        Ok(Flaky(a.0 + 10))
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Both(u8);
+    #[producer]
+    async fn both(_ctx: Context<State>, b: B, flaky: Flaky) -> Result<Both> {
+        Ok(Both(b.0 + flaky.0))
+    }
+
+    let job = Job::builder().add::<Both>().build().unwrap();
+    let mut driver = ordr::Driver::new(job);
+    let mut order = vec![];
+    // No tokio here.
+    futures::executor::block_on(async {
+        while !driver.is_done() {
+            let runs = driver.ready();
+            assert!(!runs.is_empty());
+            for run in runs {
+                order.push((run.name, run.retry, run.delay));
+                let id = run.id;
+                let result = run.run(State).await;
+                driver.complete(id, result).unwrap();
+            }
+        }
+    });
+    assert_eq!(
+        order,
+        [
+            ("A", 0, Duration::ZERO),
+            ("BB", 0, Duration::ZERO),
+            ("Flaky", 0, Duration::ZERO),
+            ("Flaky", 1, Duration::from_millis(5)),
+            ("Both", 0, Duration::ZERO),
+        ]
+    );
+    assert!(driver.failures().is_empty());
+    assert_eq!(driver.data()["Both"], serde_json::json!(13));
+    // A node can only be completed once.
+    let result = Ok(ordr::Payload::Missing);
+    assert!(driver.complete(A::node().id, result).is_err());
+    assert_eq!(driver.data()["A"], serde_json::json!(1));
+
+    // Dependents of a failed node are given up on.
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Broken;
+    #[producer]
+    async fn broken(_ctx: Context<State>, _: A) -> Result<Broken> {
+        Err(Error::fatal("Broken"))
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct After;
+    #[producer]
+    async fn after(_ctx: Context<State>, _: Broken) -> Result<After> {
+        Ok(After)
+    }
+    let job = Job::builder().add::<After>().build().unwrap();
+    let mut driver = ordr::Driver::new(job);
+    while !driver.is_done() {
+        for run in driver.ready() {
+            let id = run.id;
+            driver
+                .complete(id, futures::executor::block_on(run.run(State)))
+                .unwrap();
+        }
+    }
+    assert_eq!(driver.failures().len(), 1);
+    assert_eq!(driver.failures()[0].name, "Broken");
+    assert!(!driver.data().contains_key("After"));
+}
+
+#[test]
+fn driver_context() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Slow(bool);
+    #[producer(timeout = "1s")]
+    async fn slow(ctx: Context<State>) -> Result<Slow> {
+        assert_eq!(ctx.format, Format::Cbor);
+        let deadline = ctx.start() + Duration::from_secs(1);
+        assert_eq!(ctx.attempt.deadline, Some(deadline));
+        Ok(Slow(ctx.is_cancelled()))
+    }
+
+    let job = Job::builder().add::<Slow>().build().unwrap();
+    let mut driver = ordr::Driver::new(job).format(Format::Cbor);
+    let runs = driver.ready();
+    assert_eq!(runs[0].timeout, Some(Duration::from_secs(1)));
+    driver.cancel();
+    for run in runs {
+        let id = run.id;
+        let result = futures::executor::block_on(run.run(State));
+        assert!(matches!(result, Ok(ordr::Payload::Cbor(_))));
+        driver.complete(id, result).unwrap();
+    }
+    assert_eq!(driver.data()["Slow"], serde_json::json!(true));
+}
+
+#[test]
+fn driver_limits() {
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Left;
+    #[producer(exclusive = "gpu")]
+    async fn left(_: Context<State>) -> Result<Left> {
+        Ok(Left)
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Right;
+    #[producer(exclusive = "gpu")]
+    async fn right(_: Context<State>) -> Result<Right> {
+        Ok(Right)
+    }
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Both;
+    #[producer]
+    async fn both(_: Context<State>, _: A, _: Left, _: Right) -> Result<Both> {
+        Ok(Both)
+    }
+
+    // The names of the nodes handed out together.
+    let batches = |mut driver: ordr::Driver<State>| {
+        let mut batches = vec![];
+        while !driver.is_done() {
+            let runs = driver.ready();
+            batches.push(runs.iter().map(|run| run.name).collect::<Vec<_>>());
+            for run in runs {
+                let id = run.id;
+                driver
+                    .complete(id, futures::executor::block_on(run.run(State)))
+                    .unwrap();
+            }
+        }
+        batches
+    };
+    let job = Job::builder().add::<Both>().build().unwrap();
+    // Only one node uses the gpu at a time.
+    assert_eq!(
+        batches(ordr::Driver::new(job.clone())),
+        [vec!["A", "Left"], vec!["Right"], vec!["Both"]]
+    );
+    assert_eq!(
+        batches(ordr::Driver::new(job).max_concurrency(1)),
+        [vec!["A"], vec!["Left"], vec!["Right"], vec!["Both"]]
+    );
+
+    // A retry policy gives up once its time is up, even with retries left.
+    #[derive(Clone, Serialize, Deserialize)]
+    struct Slow;
+    let policy = RetryPolicy::fixed(Duration::ZERO, 100).max_elapsed(Duration::from_millis(30));
+    let slow = ordr::Node::builder("Slow").retry_policy(policy).producer(
+        |_: Context<State>, (): ()| async move {
+            std::thread::sleep(Duration::from_millis(20));
+            Err::<Slow, _>(Error::with_retry("Slow", Duration::ZERO))
+        },
+    );
+    let job = Job::builder().add_node(slow).build().unwrap();
+    let mut driver = ordr::Driver::new(job);
+    while !driver.is_done() {
+        for run in driver.ready() {
+            let id = run.id;
+            driver
+                .complete(id, futures::executor::block_on(run.run(State)))
+                .unwrap();
+        }
+    }
+    assert_eq!(driver.failures().len(), 1);
+    assert_eq!(driver.failures()[0].retries, 1);
 }
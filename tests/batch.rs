@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use ordr::{
+    Context, Error, Job, Result,
+    batch::{self, Options},
+    producer,
+    serde::{Deserialize, Serialize},
+    serde_json::{self, json},
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Input(u32);
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Double(u32);
+
+/// Always provided by the batch.
+#[producer]
+async fn input(_: Context<()>) -> Result<Input> {
+    Err(Error::fatal("Input must be provided"))
+}
+
+#[producer]
+async fn double(_: Context<()>, input: Input) -> Result<Double> {
+    if input.0 == 0 {
+        return Err(Error::fatal("Zero"));
+    }
+    Ok(Double(input.0 * 2))
+}
+
+fn make_input(n: u32) -> (String, HashMap<String, serde_json::Value>) {
+    let data = [("Input".to_string(), json!(n))].into_iter().collect();
+    (format!("input-{n}"), data)
+}
+
+#[tokio::test]
+async fn run_all() {
+    let template = Job::builder().add::<Double>();
+    let inputs = (0..10).map(make_input);
+    let options = Options {
+        concurrency: 3,
+        ..Default::default()
+    };
+    let report = batch::run_all(template, inputs, (), options).await;
+
+    assert_eq!(report.items.len(), 10);
+    assert_eq!(report.stats.total, 10);
+    assert_eq!(report.stats.succeeded, 9);
+    assert!((report.stats.success_rate - 0.9).abs() < f64::EPSILON);
+    assert_eq!(report.stats.slowest.unwrap().0, "Double");
+
+    let item = &report.items[0];
+    assert_eq!(item.key, "input-0");
+    assert!(item.output.as_ref().unwrap().is_node_failed());
+    let item = &report.items[4];
+    assert_eq!(item.key, "input-4");
+    assert_eq!(item.data["Double"], json!(8));
+}
+
+#[tokio::test]
+async fn resume_with_manifest() {
+    let path = std::env::temp_dir().join(format!("ordr-manifest-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let options = Options {
+        concurrency: 3,
+        manifest: Some(path.clone()),
+        ..Default::default()
+    };
+
+    let template = Job::builder().add::<Double>();
+    let report = batch::run_all(
+        template.clone(),
+        (0..5).map(make_input),
+        (),
+        options.clone(),
+    )
+    .await;
+    assert_eq!(report.stats.total, 5);
+    assert_eq!(report.stats.succeeded, 4);
+
+    // Only the failed input, and the new one, are run again.
+    let report = batch::run_all(template, (0..6).map(make_input), (), options).await;
+    let keys: Vec<_> = report.items.iter().map(|item| item.key.as_str()).collect();
+    assert_eq!(keys, ["input-0", "input-5"]);
+    assert_eq!(report.stats.skipped, 4);
+    assert_eq!(report.stats.succeeded, 1);
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "test-util")]
+
+use std::time::Duration;
+
+use ordr::{
+    ChaosConfig, Context, Job, Output, Result, Worker, producer,
+    serde::{Deserialize, Serialize},
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct A(u8);
+
+#[producer]
+async fn make_a(_ctx: Context<()>) -> Result<A> {
+    Ok(A(1))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct B(u8);
+
+#[producer]
+async fn make_b(_ctx: Context<()>, a: A) -> Result<B> {
+    Ok(B(a.0 + 1))
+}
+
+fn new_job() -> Job<()> {
+    Job::builder().add::<B>().build().unwrap()
+}
+
+#[tokio::test]
+async fn fail() {
+    let job = ChaosConfig::fail("B").wrap(new_job());
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    let Output::NodeFailed {
+        name,
+        retries,
+        error,
+        ..
+    } = worker.get_output().await.unwrap()
+    else {
+        panic!("Expected B to fail");
+    };
+    assert_eq!(name, "B");
+    assert_eq!(retries, 0);
+    assert_eq!(error.message(), "Injected failure in B");
+}
+
+#[tokio::test]
+async fn fail_and_retry() {
+    let job = ChaosConfig::fail("B")
+        .retry_in(Duration::ZERO)
+        .times(2)
+        .wrap(new_job());
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.get::<B>().await.unwrap().unwrap().0, 2);
+    let report = worker.report().await;
+    assert_eq!(report.nodes["B"].retries, 2);
+
+    // Only the second attempt fails.
+    let job = ChaosConfig::fail("B")
+        .retry_in(Duration::ZERO)
+        .after_attempts(1)
+        .times(1)
+        .wrap(new_job());
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_done());
+    assert_eq!(worker.report().await.nodes["B"].retries, 0);
+}
+
+#[tokio::test]
+async fn panic_and_delay() {
+    let job = ChaosConfig::delay("A", Duration::from_millis(30))
+        .and(ChaosConfig::panic("B"))
+        .wrap(new_job());
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    let Output::NodePanic { name, error, .. } = &output else {
+        panic!("Expected B to panic, got {output:?}");
+    };
+    assert_eq!(*name, "B");
+    assert!(error.contains("Injected panic in B"));
+    assert!(output.duration() >= Duration::from_millis(30));
+}
+
+#[test]
+#[should_panic(expected = "C is not run by the job")]
+fn unknown_node() {
+    let _ = ChaosConfig::fail("C").wrap(new_job());
+}
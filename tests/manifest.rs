@@ -0,0 +1,98 @@
+use std::fs;
+
+use ordr::{
+    Context, Error, Job, JobError, NodeBuilder, Output, Result, Worker,
+    manifest::{self, JobManifest, Manifest, ManifestError, NodeRegistry},
+    producer,
+    serde::{Deserialize, Serialize},
+    serde_json::{self, json},
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Input(u32);
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Double(u32);
+
+#[producer]
+async fn input(_: Context<()>) -> Result<Input> {
+    Err(Error::fatal("Input must be provided"))
+}
+
+#[producer]
+async fn double(_: Context<()>, input: Input) -> Result<Double> {
+    Ok(Double(input.0 * 2))
+}
+
+#[tokio::test]
+async fn run_from_manifest() {
+    let dir = std::env::temp_dir().join(format!("ordr-run-manifest-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("input.json"), r#"{"Input": 21}"#).unwrap();
+    let contents = json!({
+        "targets": ["Double"],
+        "data": "input.json",
+        "output": "output.json",
+        "timeout_secs": 5,
+        "max_concurrency": 2,
+        "labels": { "tenant": "acme" },
+    });
+    fs::write(dir.join("manifest.json"), contents.to_string()).unwrap();
+
+    let manifest = Manifest::open(dir.join("manifest.json")).unwrap();
+    assert_eq!(manifest.data, Some(dir.join("input.json")));
+    let nodes = [Input::node(), Double::node()];
+    let output = manifest::run_from_manifest(&manifest, &nodes, ())
+        .await
+        .unwrap();
+    assert!(matches!(output, Output::Done { .. }));
+    let written: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.join("output.json")).unwrap()).unwrap();
+    assert_eq!(written, json!({ "Input": 21, "Double": 42 }));
+
+    let unknown = Manifest {
+        targets: vec!["Triple".into()],
+        ..Manifest::default()
+    };
+    let result = manifest::run_from_manifest(&unknown, &nodes, ()).await;
+    assert!(matches!(
+        result,
+        Err(ManifestError::Job(JobError::UnknownTarget(name))) if name == "Triple"
+    ));
+
+    let missing = Manifest {
+        targets: vec!["Double".into()],
+        data: Some(dir.join("missing.json")),
+        ..Manifest::default()
+    };
+    let result = manifest::run_from_manifest(&missing, &nodes, ()).await;
+    assert!(matches!(result, Err(ManifestError::Io(_))));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn job_manifest() {
+    let job = Job::builder_with_data([("Input".to_string(), json!(21))].into())
+        .add::<Double>()
+        .label("tenant", "acme")
+        .build()
+        .unwrap();
+    let saved = serde_json::to_string(&job.to_manifest()).unwrap();
+    let manifest: JobManifest = serde_json::from_str(&saved).unwrap();
+    assert_eq!(manifest.nodes, ["Double"]);
+    assert_eq!(manifest.targets, ["Double"]);
+    assert_eq!(manifest.provided["Input"], json!(21));
+
+    // Input is registered along with Double.
+    let registry = NodeRegistry::new().register::<Double>();
+    let job = Job::from_manifest(manifest.clone(), &registry).unwrap();
+    assert_eq!(job.labels()["tenant"], "acme");
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(matches!(worker.get_output().await, Ok(Output::Done { .. })));
+    assert_eq!(worker.data().await["Double"], json!(42));
+
+    let result = Job::from_manifest(manifest, &NodeRegistry::<()>::new());
+    assert!(matches!(result, Err(JobError::UnknownNode(name)) if name == "Double"));
+}
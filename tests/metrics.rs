@@ -0,0 +1,79 @@
+#![cfg(feature = "metrics")]
+
+use std::time::Duration;
+
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use ordr::{
+    Context, Error, Job, Result, Worker, producer,
+    serde::{Deserialize, Serialize},
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct A(u8);
+
+#[producer]
+async fn make_a(_ctx: Context<()>) -> Result<A> {
+    Ok(A(1))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct B(u8);
+
+#[producer]
+async fn make_b(ctx: Context<()>, a: A) -> Result<B> {
+    if ctx.retry() == 0 {
+        return Err(Error::with_retry("Not yet", Duration::from_millis(1)));
+    }
+    Ok(B(a.0 + 1))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct C(u8);
+
+#[producer]
+async fn make_c(_ctx: Context<()>, b: B) -> Result<C> {
+    Err(Error::fatal(format!("No C for {}", b.0)))
+}
+
+#[tokio::test]
+async fn node_metrics() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    recorder.install().unwrap();
+
+    let job = Job::builder().add::<C>().build().unwrap();
+    let mut worker = Worker::new(job, ()).label("import");
+    worker.run().await.unwrap();
+    assert!(worker.get_output().await.unwrap().is_node_failed());
+
+    let mut counters = vec![];
+    let mut durations = vec![];
+    for (key, _, _, value) in snapshotter.snapshot().into_vec() {
+        let key = key.key();
+        let labels: Vec<_> = key.labels().map(|l| (l.key(), l.value())).collect();
+        assert_eq!(labels[0], ("job", "import"));
+        let node = labels[1].1.to_string();
+        match value {
+            DebugValue::Counter(count) => counters.push((key.name().to_string(), node, count)),
+            DebugValue::Histogram(values) => durations.push((node, values.len())),
+            DebugValue::Gauge(_) => panic!("No gauges"),
+        }
+    }
+    counters.sort();
+    durations.sort();
+
+    let counter = |name: &str, node: &str, count| (name.to_string(), node.to_string(), count);
+    assert_eq!(
+        counters,
+        vec![
+            counter("ordr_node_done_total", "A", 1),
+            counter("ordr_node_done_total", "B", 1),
+            counter("ordr_node_failures_total", "C", 1),
+            counter("ordr_node_retries_total", "B", 1),
+            counter("ordr_node_started_total", "A", 1),
+            counter("ordr_node_started_total", "B", 1),
+            counter("ordr_node_started_total", "C", 1),
+        ]
+    );
+    assert_eq!(durations, vec![("A".to_string(), 1), ("B".to_string(), 1)]);
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "registry")]
+
+use ordr::{
+    Context, Job, JobError, Output, Result, Worker,
+    manifest::NodeRegistry,
+    producer,
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct DocId(u32);
+
+#[producer]
+async fn make_doc_id(_ctx: Context<()>) -> Result<DocId> {
+    Ok(DocId(7))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct DocText(String);
+
+#[producer]
+async fn make_doc_text(_ctx: Context<()>, id: DocId) -> Result<DocText> {
+    Ok(DocText(format!("doc {}", id.0)))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Other(u8);
+
+#[producer]
+async fn make_other(_ctx: Context<u8>) -> Result<Other> {
+    Ok(Other(1))
+}
+
+#[tokio::test]
+async fn add_by_name() {
+    let job = Job::builder()
+        .add_by_name("DocText")
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(job.len(), 2);
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(matches!(worker.get_output().await, Ok(Output::Done { .. })));
+    assert_eq!(worker.data().await["DocText"], json!("doc 7"));
+
+    let result = Job::<()>::builder().add_by_name("Missing");
+    assert!(matches!(result, Err(JobError::UnknownNode(name)) if name == "Missing"));
+    // Registered, but for another state.
+    let result = Job::<()>::builder().add_by_name("Other");
+    assert!(matches!(result, Err(JobError::UnknownNode(name)) if name == "Other"));
+}
+
+#[test]
+fn registered() {
+    let registry = NodeRegistry::<()>::registered();
+    assert!(registry.get("DocId").is_some());
+    assert!(registry.get("DocText").is_some());
+    assert!(registry.get("Other").is_none());
+}
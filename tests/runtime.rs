@@ -0,0 +1,65 @@
+#![cfg(any(feature = "async-std", feature = "smol"))]
+
+use std::time::Duration;
+
+use ordr::{
+    Context, Error, Job, Output, Result, Worker, producer,
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct A(u8);
+
+#[producer]
+async fn make_a(ctx: Context<()>) -> Result<A> {
+    if ctx.retry() == 0 {
+        return Err(Error::with_retry("Not yet", Duration::from_millis(5)));
+    }
+    Ok(A(1))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct B(u8);
+
+#[producer]
+fn make_b(_ctx: Context<()>, a: A) -> Result<B> {
+    Ok(B(a.0 + 1))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct C(u8);
+
+#[producer(timeout = "1ms")]
+async fn make_c(_ctx: Context<()>) -> Result<C> {
+    std::future::pending().await
+}
+
+#[cfg(feature = "async-std")]
+#[test]
+fn runs_on_async_std() {
+    async_std::task::block_on(run_jobs());
+}
+
+/// With `async-std` enabled too, the jobs still run on async-std, so run with only `smol` to
+/// cover it.
+#[cfg(feature = "smol")]
+#[test]
+fn runs_on_smol() {
+    smol::block_on(run_jobs());
+}
+
+/// A retry, a plain (blocking) producer, and a timeout, none of which are run by tokio.
+async fn run_jobs() {
+    let job = Job::builder().add::<B>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    assert!(matches!(worker.get_output().await, Ok(Output::Done { .. })));
+    assert_eq!(worker.data().await["B"], json!(2));
+
+    let job = Job::builder().add::<C>().build().unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    let output = worker.get_output().await.unwrap();
+    assert!(matches!(output, Output::NodeFailed { name: "C", .. }));
+}
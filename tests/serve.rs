@@ -0,0 +1,112 @@
+#![cfg(feature = "serve")]
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use ordr::{
+    Context, Job, Result, Server, Worker, producer,
+    serde::{Deserialize, Serialize},
+    serde_json::{self, Value},
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct A(u8);
+
+#[producer]
+async fn make_a(_ctx: Context<()>) -> Result<A> {
+    Ok(A(1))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Slow(u8);
+
+#[producer]
+async fn make_slow(ctx: Context<()>, a: A) -> Result<Slow> {
+    ctx.cancelled().await;
+    Ok(Slow(a.0))
+}
+
+/// Send a request, and return the status line and body of the response.
+async fn request(addr: SocketAddr, method: &str, path: &str) -> (String, String) {
+    let request =
+        format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    tokio::task::spawn_blocking(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serve() {
+    let job = Job::builder()
+        .add::<Slow>()
+        .label("team", "data")
+        .build()
+        .unwrap();
+    let mut worker = Worker::new(job, ());
+    worker.run().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let server = Server::new();
+    server.add(&worker);
+    let serving = server.serve("127.0.0.1:0").await.unwrap();
+    let addr = serving.addr();
+    let id = worker.job_id();
+
+    // A client that connects, and sends nothing, does not hold up anyone else.
+    let _idle = TcpStream::connect(addr).unwrap();
+
+    let (status, body) = request(addr, "GET", "/jobs").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body, format!("[{id}]"));
+
+    let (_, body) = request(addr, "GET", &format!("/jobs/{id}")).await;
+    let job: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(job["labels"]["team"], "data");
+    assert_eq!(job["progress"]["total"], 2);
+    assert_eq!(job["progress"]["done"], 1);
+    assert_eq!(job["progress"]["running"], 1);
+    assert_eq!(job["output"], Value::Null);
+
+    let (_, body) = request(addr, "GET", &format!("/jobs/{id}/nodes")).await;
+    let nodes: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(nodes["A"]["state"], "done");
+    assert_eq!(nodes["Slow"]["state"], "running");
+
+    let (_, body) = request(addr, "GET", &format!("/jobs/{id}/data")).await;
+    assert_eq!(body, r#"{"A":1}"#);
+
+    let (_, body) = request(addr, "GET", &format!("/jobs/{id}/mermaid")).await;
+    assert!(body.starts_with("flowchart LR"));
+    assert!(body.contains("class n0 done"));
+    assert!(body.contains("class n1 running"));
+
+    let (status, _) = request(addr, "GET", "/jobs/12345").await;
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+    let (status, _) = request(addr, "GET", &format!("/jobs/{id}/stop")).await;
+    assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+
+    let (_, body) = request(addr, "POST", &format!("/jobs/{id}/stop")).await;
+    let job: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(job["output"]["state"], "stopped");
+    assert!(matches!(
+        worker.get_output().await,
+        Ok(ordr::Output::Stopped { .. })
+    ));
+
+    assert!(server.remove(id));
+    let (status, _) = request(addr, "GET", &format!("/jobs/{id}")).await;
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+    serving.stop().await;
+    assert!(TcpStream::connect(addr).is_err());
+}